@@ -0,0 +1,112 @@
+use crate::math::{Point, Real};
+use crate::object::FluidListener;
+use std::sync::{Arc, Mutex};
+
+/// The handle of a spring attaching a fluid particle to an anchor, see `LiquidWorld::attach_particle`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AttachmentHandle(pub(crate) u64);
+
+// A damped spring pulling a fluid particle toward an anchor point.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct ParticleAttachment {
+    pub handle: AttachmentHandle,
+    // The current index of the attached particle on its fluid.
+    pub particle: usize,
+    pub anchor: Point<Real>,
+    pub stiffness: Real,
+    pub damping: Real,
+}
+
+// The attachments of a fluid, shared with the `AttachmentTracker` registered on this fluid.
+pub(crate) type SharedAttachments = Arc<Mutex<Vec<ParticleAttachment>>>;
+
+// Keeps the particle indices of the attachments of a fluid in sync with its particles, and drops
+// the attachments of the removed particles.
+pub(crate) struct AttachmentTracker {
+    pub attachments: SharedAttachments,
+}
+
+impl FluidListener for AttachmentTracker {
+    fn on_particles_removed(&mut self, indices: &[usize]) {
+        let mut attachments = self.attachments.lock().unwrap();
+        attachments.retain(|a| indices.binary_search(&a.particle).is_err());
+
+        for attachment in attachments.iter_mut() {
+            attachment.particle -= indices.partition_point(|i| *i < attachment.particle);
+        }
+    }
+
+    fn on_particles_permuted(&mut self, permutation: &[usize]) {
+        let mut new_indices = vec![0; permutation.len()];
+
+        for (i, old) in permutation.iter().enumerate() {
+            new_indices[*old] = i;
+        }
+
+        for attachment in self.attachments.lock().unwrap().iter_mut() {
+            attachment.particle = new_indices[attachment.particle];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kernel::CubicSplineKernel;
+    use crate::math::{Point, Real, Vector};
+    use crate::object::Fluid;
+    use crate::LiquidWorld;
+
+    #[test]
+    fn attachments_follow_their_particles_and_accumulate() {
+        use crate::solver::DFSPHSolver;
+
+        let particle_radius = 0.05;
+        // Isolated particles, in reverse Morton order so that `z_sort` reverses them.
+        let positions: Vec<_> = (0..3)
+            .rev()
+            .map(|i| Point::from(Vector::repeat(i as Real)))
+            .collect();
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        let fluid = world.add_fluid(Fluid::new(positions, particle_radius, 1000.0));
+        let stiffness = 0.01;
+        let offset = Vector::x() * 0.1;
+        let target = world.fluids()[fluid].positions[2];
+        let removed = world
+            .attach_particle(fluid, 0, Point::origin(), stiffness, 0.0)
+            .unwrap();
+        let attachments = [
+            world.attach_particle(fluid, 2, target + offset, stiffness, 0.0),
+            world.attach_particle(fluid, 2, target + offset * 2.0, stiffness, 0.0),
+        ]
+        .map(Option::unwrap);
+        assert!(world
+            .attach_particle(fluid, 3, target, stiffness, 0.0)
+            .is_none());
+
+        world.fluids_mut()[fluid].z_sort();
+        assert_eq!(world.attached_particle(attachments[0]), Some((fluid, 0)));
+        assert_eq!(world.attached_particle(removed), Some((fluid, 2)));
+
+        world.fluids_mut()[fluid].delete_particle_at_next_timestep(2);
+        let dt = 1.0e-3;
+        world.step(dt, &Vector::zeros());
+        assert_eq!(world.attached_particle(removed), None);
+        assert!(!world.detach_particle(removed));
+
+        // Both springs pull the particle toward their anchors.
+        let fluid_ref = &world.fluids()[fluid];
+        let inv_mass = fluid_ref.particle_inv_mass(0);
+        let expected = offset * (3.0 * stiffness * inv_mass * dt * dt);
+        let displacement = fluid_ref.positions[0] - target;
+        assert!((displacement - expected).norm() < expected.norm() * 1.0e-2);
+        assert_eq!(fluid_ref.positions[1], Point::from(Vector::repeat(1.0)));
+
+        assert!(world.detach_particle(attachments[0]));
+        assert!(world.set_attachment_anchor(attachments[1], target));
+        assert_eq!(world.attached_particle(attachments[0]), None);
+    }
+}
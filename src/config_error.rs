@@ -0,0 +1,47 @@
+use crate::math::Real;
+use std::fmt;
+
+/// Errors detected when validating the parameters of a liquid world.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    /// The particle radius is zero, negative, or not finite.
+    InvalidParticleRadius(Real),
+    /// The smoothing factor is smaller than one, or not finite.
+    ///
+    /// Below one, the kernel radius is smaller than the particle spacing, so the particles of a
+    /// regular lattice do not see their neighbors.
+    InvalidSmoothingFactor(Real),
+    /// The kernel radius computed from the particle radius and the smoothing factor is not finite.
+    InvalidKernelRadius(Real),
+    /// A pressure solver was assigned to a fluid, but either this solver or the pressure solver of
+    /// the world can't be restricted to a subset of the fluids.
+    ///
+    /// Both solvers must implement `PartialPressureSolver`.
+    UnsupportedFluidSolver,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::InvalidParticleRadius(radius) => write!(
+                f,
+                "the particle radius must be positive and finite, got {}",
+                radius
+            ),
+            ConfigError::InvalidSmoothingFactor(factor) => write!(
+                f,
+                "the smoothing factor must be finite and at least 1, got {}",
+                factor
+            ),
+            ConfigError::InvalidKernelRadius(h) => {
+                write!(f, "the kernel radius must be finite, got {}", h)
+            }
+            ConfigError::UnsupportedFluidSolver => write!(
+                f,
+                "the pressure solvers assigned to specific fluids require solvers that can be restricted to a subset of the fluids"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
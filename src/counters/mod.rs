@@ -17,6 +17,8 @@ mod timer;
 pub struct Counters {
     /// Total number of substeps performed.
     pub nsubsteps: usize,
+    /// Number of substeps that were retried with a smaller length by the stability guard.
+    pub nsubstep_retries: usize,
     /// Timer for a whole timestep.
     pub step_time: Timer,
     /// Timer used for debugging.
@@ -34,6 +36,7 @@ impl Counters {
     pub fn new() -> Self {
         Counters {
             nsubsteps: 0,
+            nsubstep_retries: 0,
             step_time: Timer::new(),
             custom: Timer::new(),
             stages: StagesCounters::new(),
@@ -45,6 +48,7 @@ impl Counters {
     /// Resets to zero all the counters.
     pub fn reset(&mut self) {
         self.nsubsteps = 0;
+        self.nsubstep_retries = 0;
         self.step_time.reset();
         self.custom.reset();
         self.stages.reset();
@@ -75,6 +79,7 @@ impl Display for Counters {
     fn fmt(&self, f: &mut Formatter) -> Result {
         writeln!(f, "Total timestep time: {}", self.step_time)?;
         writeln!(f, "Num substeps: {}", self.nsubsteps)?;
+        writeln!(f, "Num substep retries: {}", self.nsubstep_retries)?;
         self.stages.fmt(f)?;
         self.cd.fmt(f)?;
         self.solver.fmt(f)?;
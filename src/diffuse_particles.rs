@@ -0,0 +1,492 @@
+use crate::geometry::Contact;
+use crate::helper;
+use crate::math::{Point, Real, Vector};
+use crate::object::Fluid;
+use crate::LiquidWorld;
+use approx::AbsDiffEq;
+#[cfg(feature = "dim3")]
+use na::RealField;
+
+/// The kind of a diffuse particle, depending on the number of fluid particles around it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DiffuseParticleKind {
+    /// A particle with few fluid neighbors, following a ballistic trajectory.
+    Spray,
+    /// A particle at the fluid surface, advected by the velocity of the fluid.
+    Foam,
+    /// A particle inside of the fluid, rising because of buoyancy while being dragged by the fluid.
+    Bubble,
+}
+
+/// Spray, foam, and bubble particles generated from the fluids of a liquid world.
+///
+/// This implements the method from Ihmsen et al. 2012, "Unified spray, foam and bubbles for
+/// particle-based fluids". At each call to `self.step`, diffuse particles are generated around the
+/// fluid particles likely to trap air or to be at a wave crest, depending on their kinetic energy.
+/// The diffuse particles are then advected by the velocity of the fluids, without affecting them,
+/// and are removed once their lifetime is over.
+pub struct DiffuseParticles {
+    /// The positions of the diffuse particles.
+    pub positions: Vec<Point<Real>>,
+    /// The velocities of the diffuse particles.
+    pub velocities: Vec<Vector<Real>>,
+    /// The remaining lifetime of each diffuse particle, in seconds.
+    pub lifetimes: Vec<Real>,
+    /// The kind of each diffuse particle, updated at each step.
+    pub kinds: Vec<DiffuseParticleKind>,
+    /// The number of diffuse particles generated per second by a fluid particle with maximal trapped-air and kinetic energy potentials.
+    pub trapped_air_rate: Real,
+    /// The number of diffuse particles generated per second by a fluid particle with maximal wave-crest and kinetic energy potentials.
+    pub wave_crest_rate: Real,
+    /// The trapped-air potentials mapped to `0.0` and `1.0` respectively, the values in-between being linearly interpolated.
+    ///
+    /// If both bounds are equal, the potentials below them are mapped to `0.0` and the others to `1.0`.
+    /// This is the same for the other ranges.
+    pub trapped_air_range: (Real, Real),
+    /// The wave-crest potentials mapped to `0.0` and `1.0` respectively, the values in-between being linearly interpolated.
+    pub wave_crest_range: (Real, Real),
+    /// The kinetic energies per unit mass mapped to `0.0` and `1.0` respectively, the values in-between being linearly interpolated.
+    pub kinetic_energy_range: (Real, Real),
+    /// The lifetime given to each new diffuse particle, in seconds.
+    pub lifetime: Real,
+    /// Diffuse particles with less fluid neighbors than this are spray particles.
+    pub spray_max_neighbors: usize,
+    /// Diffuse particles with more fluid neighbors than this are bubble particles.
+    pub bubble_min_neighbors: usize,
+    /// The factor of the gravity opposed to the motion of bubble particles.
+    pub buoyancy: Real,
+    /// How much bubble particles are dragged by the fluid velocity at each step, between `0.0` and `1.0`.
+    pub drag: Real,
+    /// The maximum number of diffuse particles. No particle is generated once it is reached.
+    pub max_particles: usize,
+    rng_state: u64,
+}
+
+impl DiffuseParticles {
+    /// Initializes an empty set of diffuse particles with default parameters.
+    pub fn new() -> Self {
+        Self {
+            positions: Vec::new(),
+            velocities: Vec::new(),
+            lifetimes: Vec::new(),
+            kinds: Vec::new(),
+            trapped_air_rate: na::convert::<_, Real>(100.0),
+            wave_crest_rate: na::convert::<_, Real>(100.0),
+            trapped_air_range: (na::convert::<_, Real>(5.0), na::convert::<_, Real>(20.0)),
+            wave_crest_range: (na::convert::<_, Real>(2.0), na::convert::<_, Real>(8.0)),
+            kinetic_energy_range: (na::convert::<_, Real>(2.0), na::convert::<_, Real>(20.0)),
+            lifetime: na::convert::<_, Real>(2.0),
+            #[cfg(feature = "dim2")]
+            spray_max_neighbors: 3,
+            #[cfg(feature = "dim2")]
+            bubble_min_neighbors: 10,
+            #[cfg(feature = "dim3")]
+            spray_max_neighbors: 6,
+            #[cfg(feature = "dim3")]
+            bubble_min_neighbors: 20,
+            buoyancy: na::convert::<_, Real>(2.0),
+            drag: na::convert::<_, Real>(0.5),
+            max_particles: 100_000,
+            rng_state: 0x853c_49e6_748f_ea9b,
+        }
+    }
+
+    /// The number of diffuse particles.
+    pub fn num_particles(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Advects and ages the existing diffuse particles, then generates new ones from the fluids of `world`.
+    ///
+    /// This should be called after each step of `world`, with the same `dt` and `gravity`. The
+    /// potentials of the fluid particles are computed from the contacts detected during the last
+    /// step of `world`.
+    pub fn step(&mut self, world: &LiquidWorld, dt: Real, gravity: &Vector<Real>) {
+        self.advect(world, dt, gravity);
+        self.generate(world, dt);
+    }
+
+    fn advect(&mut self, world: &LiquidWorld, dt: Real, gravity: &Vector<Real>) {
+        let mut expired = Vec::with_capacity(self.num_particles());
+
+        for i in 0..self.num_particles() {
+            let (num_neighbors, fluid_velocity) = self.sample_fluid(world, &self.positions[i]);
+            let kind = self.classify(num_neighbors);
+            let velocity = &mut self.velocities[i];
+
+            match kind {
+                DiffuseParticleKind::Spray => *velocity += gravity * dt,
+                DiffuseParticleKind::Foam => *velocity = fluid_velocity.unwrap_or(*velocity),
+                DiffuseParticleKind::Bubble => {
+                    *velocity -= gravity * (self.buoyancy * dt);
+
+                    if let Some(fluid_velocity) = fluid_velocity {
+                        *velocity += (fluid_velocity - *velocity) * self.drag;
+                    }
+                }
+            }
+
+            self.positions[i] += *velocity * dt;
+            self.lifetimes[i] -= dt;
+            self.kinds[i] = kind;
+            expired.push(self.lifetimes[i] <= na::zero::<Real>());
+        }
+
+        helper::filter_from_mask(&expired, &mut self.positions);
+        helper::filter_from_mask(&expired, &mut self.velocities);
+        helper::filter_from_mask(&expired, &mut self.lifetimes);
+        helper::filter_from_mask(&expired, &mut self.kinds);
+    }
+
+    fn generate(&mut self, world: &LiquidWorld, dt: Real) {
+        let fluids = world.fluids().as_slice();
+        let contacts = &world.contact_manager().fluid_fluid_contacts;
+        let h = world.h();
+
+        // The normals are needed for all the fluids before computing the wave-crest potentials.
+        let normals: Vec<_> = fluids
+            .iter()
+            .zip(contacts.iter())
+            .map(|(fluid, contacts)| {
+                if contacts.contacts().len() != fluid.num_particles() {
+                    return Vec::new();
+                }
+
+                (0..fluid.num_particles())
+                    .map(|i| {
+                        let mut normal = Vector::zeros();
+
+                        for c in contacts.iter_particle(i) {
+                            normal -= c.gradient * fluids[c.j_model].volumes[c.j];
+                        }
+
+                        normal
+                            .try_normalize(Real::default_epsilon())
+                            .unwrap_or_else(Vector::zeros)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        for (fluid_id, fluid) in fluids.iter().enumerate() {
+            if normals[fluid_id].is_empty() {
+                continue;
+            }
+
+            for i in 0..fluid.num_particles() {
+                if self.num_particles() >= self.max_particles {
+                    return;
+                }
+
+                let kinetic_energy = clamp_potential(
+                    fluid.velocities[i].norm_squared() * na::convert::<_, Real>(0.5),
+                    self.kinetic_energy_range,
+                );
+
+                if kinetic_energy == na::zero::<Real>() {
+                    continue;
+                }
+
+                let (trapped_air, wave_crest) =
+                    Self::potentials(fluids, &normals, contacts[fluid_id].iter_particle(i), h);
+                let rate = self.trapped_air_rate
+                    * clamp_potential(trapped_air, self.trapped_air_range)
+                    + self.wave_crest_rate * clamp_potential(wave_crest, self.wave_crest_range);
+                let num_generated = (kinetic_energy * rate * dt + self.random()).floor() as usize;
+
+                for _ in 0..num_generated.min(self.max_particles - self.num_particles()) {
+                    self.emit(world, fluid, i, dt);
+                }
+            }
+        }
+    }
+
+    // The trapped-air and wave-crest potentials of the fluid particle with the given contacts.
+    fn potentials(
+        fluids: &[Fluid],
+        normals: &[Vec<Vector<Real>>],
+        contacts: impl Iterator<Item = Contact>,
+        h: Real,
+    ) -> (Real, Real) {
+        let mut trapped_air = na::zero::<Real>();
+        let mut curvature = na::zero::<Real>();
+        let mut velocity_i = Vector::zeros();
+        let mut normal_i = Vector::zeros();
+
+        for c in contacts {
+            let fluid_i = &fluids[c.i_model];
+            let fluid_j = &fluids[c.j_model];
+
+            if c.is_same_particle_contact() {
+                continue;
+            }
+
+            let dpos = fluid_i.positions[c.i] - fluid_j.positions[c.j];
+            let dvel = fluid_i.velocities[c.i] - fluid_j.velocities[c.j];
+            let dist = dpos.norm();
+            let weight = (na::one::<Real>() - dist / h).max(na::zero::<Real>());
+            velocity_i = fluid_i.velocities[c.i];
+            normal_i = normals[c.i_model][c.i];
+
+            if let (Some(dir), Some(vel_dir)) = (
+                dpos.try_normalize(Real::default_epsilon()),
+                dvel.try_normalize(Real::default_epsilon()),
+            ) {
+                trapped_air += dvel.norm() * (na::one::<Real>() - vel_dir.dot(&dir)) * weight;
+
+                // Only the neighbors behind the particle contribute to its convexity.
+                if dir.dot(&normal_i) > na::zero::<Real>() {
+                    curvature +=
+                        (na::one::<Real>() - normal_i.dot(&normals[c.j_model][c.j])) * weight;
+                }
+            }
+        }
+
+        // Wave crests are only where the fluid moves along the surface normal.
+        let moves_outward = velocity_i
+            .try_normalize(Real::default_epsilon())
+            .is_some_and(|dir| dir.dot(&normal_i) >= na::convert::<_, Real>(0.6));
+        let wave_crest = if moves_outward {
+            curvature
+        } else {
+            na::zero::<Real>()
+        };
+
+        (trapped_air, wave_crest)
+    }
+
+    // Emits a diffuse particle in the cylinder swept by the `i`-th particle of `fluid` during `dt`.
+    fn emit(&mut self, world: &LiquidWorld, fluid: &Fluid, i: usize, dt: Real) {
+        let velocity = fluid.velocities[i];
+        let speed = velocity.norm();
+        let dir = velocity / speed;
+        let radius = fluid.particle_radius();
+
+        #[cfg(feature = "dim2")]
+        let offset = {
+            let tangent = Vector::new(-dir.y, dir.x);
+            tangent * (radius * (self.random() * na::convert::<_, Real>(2.0) - na::one::<Real>()))
+        };
+
+        #[cfg(feature = "dim3")]
+        let offset = {
+            let axis = if dir.x.abs() < na::convert::<_, Real>(0.9) {
+                Vector::x()
+            } else {
+                Vector::y()
+            };
+            let u = dir.cross(&axis).normalize();
+            let v = dir.cross(&u);
+            let r = radius * self.random().sqrt();
+            let angle = Real::two_pi() * self.random();
+            u * (r * angle.cos()) + v * (r * angle.sin())
+        };
+
+        let position = fluid.positions[i] + offset + dir * (speed * dt * self.random());
+        let (num_neighbors, _) = self.sample_fluid(world, &position);
+
+        self.positions.push(position);
+        self.velocities.push(velocity);
+        self.lifetimes.push(self.lifetime);
+        self.kinds.push(self.classify(num_neighbors));
+    }
+
+    // The number of fluid particles around `point`, and the fluid velocity there.
+    fn sample_fluid(
+        &self,
+        world: &LiquidWorld,
+        point: &Point<Real>,
+    ) -> (usize, Option<Vector<Real>>) {
+        let mut num_neighbors = 0;
+        let mut velocity = Vector::zeros();
+        let mut total_weight = na::zero::<Real>();
+
+        world.for_each_fluid_neighbor(point, |fluid, i, weight| {
+            num_neighbors += 1;
+            velocity += fluid.velocities[i] * weight;
+            total_weight += weight;
+        });
+
+        if total_weight > na::zero::<Real>() {
+            (num_neighbors, Some(velocity / total_weight))
+        } else {
+            (num_neighbors, None)
+        }
+    }
+
+    fn classify(&self, num_neighbors: usize) -> DiffuseParticleKind {
+        if num_neighbors < self.spray_max_neighbors {
+            DiffuseParticleKind::Spray
+        } else if num_neighbors > self.bubble_min_neighbors {
+            DiffuseParticleKind::Bubble
+        } else {
+            DiffuseParticleKind::Foam
+        }
+    }
+
+    // A pseudo-random number in `[0, 1)`, from a xorshift64* generator.
+    fn random(&mut self) -> Real {
+        self.rng_state ^= self.rng_state >> 12;
+        self.rng_state ^= self.rng_state << 25;
+        self.rng_state ^= self.rng_state >> 27;
+        let bits = self.rng_state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 40;
+        (bits as Real) / ((1u64 << 24) as Real)
+    }
+}
+
+impl Default for DiffuseParticles {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Maps linearly `potential` from `[range.0, range.1]` to `[0, 1]`, clamping the result. An empty
+// range is a step from `0` to `1` at `range.1`.
+fn clamp_potential(potential: Real, range: (Real, Real)) -> Real {
+    if range.1 <= range.0 {
+        return if potential >= range.1 {
+            na::one::<Real>()
+        } else {
+            na::zero::<Real>()
+        };
+    }
+
+    (potential.min(range.1) - potential.min(range.0)) / (range.1 - range.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DiffuseParticleKind, DiffuseParticles};
+    use crate::kernel::CubicSplineKernel;
+    use crate::math::{Point, Real, Vector};
+    use crate::object::Fluid;
+    use crate::LiquidWorld;
+
+    #[test]
+    fn diffuse_particles_are_generated_by_collisions_and_culled_after_their_lifetime() {
+        use crate::solver::DFSPHSolver;
+        use crate::testbed::particle_block;
+
+        let particle_radius = 0.05;
+        let spacing = particle_radius * 2.0;
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        // Two blocks of fluid colliding head-on.
+        let left = particle_block(4, 4, spacing, Vector::zeros());
+        let right = particle_block(4, 4, spacing, Vector::x() * (spacing * 5.0));
+        let num_left = left.len();
+        let mut fluid = Fluid::new(
+            left.into_iter().chain(right).collect(),
+            particle_radius,
+            1000.0,
+        );
+        for (i, vel) in fluid.velocities.iter_mut().enumerate() {
+            *vel = Vector::x() * if i < num_left { 3.0 } else { -3.0 };
+        }
+        let _ = world.add_fluid(fluid);
+
+        let mut diffuse = DiffuseParticles::new();
+        diffuse.lifetime = 0.05;
+        let mut disabled = DiffuseParticles::new();
+        disabled.trapped_air_rate = 0.0;
+        disabled.wave_crest_rate = 0.0;
+        let dt = 0.005;
+        let mut num_generated = 0;
+
+        for step in 0..20 {
+            world.step(dt, &Vector::zeros());
+            let num_particles = diffuse.num_particles();
+            diffuse.step(&world, dt, &Vector::zeros());
+            disabled.step(&world, dt, &Vector::zeros());
+            num_generated += diffuse.num_particles().saturating_sub(num_particles);
+
+            // The blocks moving uniformly don't trap any air before they meet.
+            if step == 0 {
+                assert_eq!(diffuse.num_particles(), 0);
+            }
+
+            assert!(diffuse.lifetimes.iter().all(|lifetime| *lifetime <= 0.05));
+        }
+
+        assert!(num_generated > 0);
+        assert_eq!(disabled.num_particles(), 0);
+
+        // Without generation, all the particles expire after their lifetime.
+        diffuse.trapped_air_rate = 0.0;
+        diffuse.wave_crest_rate = 0.0;
+        for _ in 0..11 {
+            diffuse.step(&world, dt, &Vector::zeros());
+        }
+        assert_eq!(diffuse.num_particles(), 0);
+    }
+
+    #[test]
+    fn degenerate_potential_ranges_are_steps() {
+        use super::clamp_potential;
+
+        assert_eq!(clamp_potential(1.0, (0.0, 4.0)), 0.25);
+        assert_eq!(clamp_potential(-1.0, (0.0, 4.0)), 0.0);
+        assert_eq!(clamp_potential(5.0, (0.0, 4.0)), 1.0);
+        assert_eq!(clamp_potential(1.0, (2.0, 2.0)), 0.0);
+        assert_eq!(clamp_potential(2.0, (2.0, 2.0)), 1.0);
+        assert_eq!(clamp_potential(1.0, (3.0, 2.0)), 0.0);
+    }
+
+    #[test]
+    fn diffuse_particles_are_advected_depending_on_their_kind() {
+        use crate::solver::DFSPHSolver;
+        use crate::testbed::particle_block;
+
+        let particle_radius = 0.05;
+        let spacing = particle_radius * 2.0;
+        let gravity = Vector::y() * -9.81;
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        // A block of fluid moving uniformly, without gravity so that it stays uniform.
+        let mut fluid = Fluid::new(
+            particle_block(7, 7, spacing, Vector::zeros()),
+            particle_radius,
+            1000.0,
+        );
+        fluid
+            .velocities
+            .iter_mut()
+            .for_each(|vel| *vel = Vector::x());
+        let _ = world.add_fluid(fluid);
+        let dt = 0.01;
+        world.step(dt, &Vector::zeros());
+
+        // A particle inside of the fluid, and one far away from it.
+        let mut diffuse = DiffuseParticles::new();
+        diffuse.trapped_air_rate = 0.0;
+        diffuse.wave_crest_rate = 0.0;
+        let positions = &world.fluids().as_slice()[0].positions;
+        let inside = Point::from(
+            positions.iter().map(|pt| pt.coords).sum::<Vector<Real>>() / positions.len() as Real,
+        );
+        for pt in [inside, Point::from(Vector::repeat(10.0))] {
+            diffuse.positions.push(pt);
+            diffuse.velocities.push(Vector::zeros());
+            diffuse.lifetimes.push(1.0);
+            diffuse.kinds.push(DiffuseParticleKind::Foam);
+        }
+        diffuse.step(&world, dt, &gravity);
+
+        // The bubble rises while being dragged by the fluid, and the spray falls.
+        assert_eq!(
+            diffuse.kinds,
+            vec![DiffuseParticleKind::Bubble, DiffuseParticleKind::Spray]
+        );
+        let fluid_velocity = world.sample_velocity(&inside).unwrap();
+        assert!((diffuse.velocities[0].x - fluid_velocity.x * diffuse.drag).abs() < 1.0e-4);
+        assert!(diffuse.velocities[0].y > 0.0);
+        assert_eq!(diffuse.velocities[1], gravity * dt);
+        assert_eq!(diffuse.lifetimes, vec![1.0 - dt; 2]);
+    }
+}
@@ -0,0 +1,93 @@
+use crate::math::{Real, Vector, DIM};
+
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+
+// The radially-averaged kinetic energy spectrum of velocities sampled on a periodic regular grid with
+// `resolution` cells along each axis, the first axis varying the fastest.
+//
+// The `k`-th element is the energy of the Fourier modes with an integer wavenumber norm rounded to
+// `k`, normalized so that the spectrum sums to the mean of `|v|² / 2` over the grid.
+pub(crate) fn radial_energy_spectrum(velocities: &[Vector<Real>], resolution: usize) -> Vec<Real> {
+    let num_cells = velocities.len();
+    assert_eq!(
+        num_cells,
+        resolution.pow(DIM as u32),
+        "The velocities must cover the whole grid."
+    );
+
+    let fft = FftPlanner::<Real>::new().plan_fft_forward(resolution);
+    let shell = |i: usize| {
+        let wavenumber2: usize = (0..DIM)
+            .map(|axis| {
+                let k = (i / resolution.pow(axis as u32)) % resolution;
+                let k = k.min(resolution - k);
+                k * k
+            })
+            .sum();
+        (wavenumber2 as Real).sqrt().round() as usize
+    };
+
+    let normalization = na::convert::<_, Real>(0.5) / (num_cells as Real).powi(2);
+    let max_wavenumber2 = DIM * (resolution / 2).pow(2);
+    let max_shell = (max_wavenumber2 as Real).sqrt().round() as usize;
+    let mut spectrum = vec![na::zero::<Real>(); max_shell + 1];
+    let mut line = vec![Complex::default(); resolution];
+
+    for component in 0..DIM {
+        let mut values: Vec<_> = velocities
+            .iter()
+            .map(|v| Complex::new(v[component], na::zero::<Real>()))
+            .collect();
+
+        // The multidimensional transform is a sequence of 1D transforms along each axis.
+        for axis in 0..DIM {
+            let stride = resolution.pow(axis as u32);
+
+            for start in (0..num_cells).filter(|i| (i / stride).is_multiple_of(resolution)) {
+                for (k, value) in line.iter_mut().enumerate() {
+                    *value = values[start + k * stride];
+                }
+
+                fft.process(&mut line);
+
+                for (k, value) in line.iter().enumerate() {
+                    values[start + k * stride] = *value;
+                }
+            }
+        }
+
+        for (i, value) in values.iter().enumerate() {
+            spectrum[shell(i)] += value.norm_sqr() * normalization;
+        }
+    }
+
+    spectrum
+}
+
+#[test]
+fn energy_spectrum_of_a_single_mode() {
+    let resolution: usize = 16;
+    let num_cells = resolution.pow(DIM as u32);
+    let velocities: Vec<_> = (0..num_cells)
+        .map(|i| {
+            let y = ((i / resolution) % resolution) as Real / resolution as Real;
+            Vector::x() * (y * 3.0 * std::f32::consts::TAU).sin()
+        })
+        .collect();
+
+    let spectrum = radial_energy_spectrum(&velocities, resolution);
+    let mean_energy = velocities
+        .iter()
+        .map(|v| v.norm_squared() / 2.0)
+        .sum::<Real>()
+        / num_cells as Real;
+
+    for (k, energy) in spectrum.iter().enumerate() {
+        if k == 3 {
+            assert!((energy - mean_energy).abs() < 1.0e-5);
+        } else {
+            assert!(energy.abs() < 1.0e-5);
+        }
+    }
+}
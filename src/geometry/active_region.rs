@@ -0,0 +1,33 @@
+use crate::math::{Point, Real, DIM};
+
+/// An axis-aligned region of interest restricting where contacts are computed.
+///
+/// Particles outside of this region (enlarged by a halo) are considered frozen: they
+/// still act as neighbors for the particles inside of the region, but they are not
+/// moved by the solver. See `ContactManager::set_active_region` for details.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ActiveRegion {
+    /// The lower corner of the region.
+    pub mins: Point<Real>,
+    /// The upper corner of the region.
+    pub maxs: Point<Real>,
+}
+
+impl ActiveRegion {
+    /// Creates a new active region from its lower and upper corners.
+    pub fn new(mins: Point<Real>, maxs: Point<Real>) -> Self {
+        Self { mins, maxs }
+    }
+
+    /// Checks if `point` is inside of this region enlarged by `margin` along each axis.
+    #[inline]
+    pub fn contains_point(&self, point: &Point<Real>, margin: Real) -> bool {
+        (0..DIM).all(|i| point[i] >= self.mins[i] - margin && point[i] <= self.maxs[i] + margin)
+    }
+
+    /// Checks if the box with corners `mins` and `maxs` intersects this region enlarged by `margin`.
+    #[inline]
+    pub fn intersects_aabb(&self, mins: &Point<Real>, maxs: &Point<Real>, margin: Real) -> bool {
+        (0..DIM).all(|i| maxs[i] >= self.mins[i] - margin && mins[i] <= self.maxs[i] + margin)
+    }
+}
@@ -1,6 +1,8 @@
 use crate::counters::Counters;
-use crate::geometry::{self, HGrid, HGridEntry, ParticlesContacts};
-use crate::math::Real;
+use crate::geometry::{
+    self, ActiveRegion, ContactOptions, ContactPredicate, HGrid, HGridEntry, ParticlesContacts,
+};
+use crate::math::{Point, Real, Vector};
 use crate::object::Boundary;
 use crate::object::Fluid;
 
@@ -12,6 +14,11 @@ pub struct ContactManager {
     pub fluid_boundary_contacts: Vec<ParticlesContacts>,
     /// All contacts detected between two boundary particles.
     pub boundary_boundary_contacts: Vec<ParticlesContacts>,
+    active_region: Option<ActiveRegion>,
+    cell_size_factor: Real,
+    kernel_anisotropy: Vector<Real>,
+    contact_predicate: Option<Box<ContactPredicate>>,
+    max_neighbors: Option<usize>,
 }
 
 impl ContactManager {
@@ -21,9 +28,133 @@ impl ContactManager {
             fluid_fluid_contacts: Vec::new(),
             fluid_boundary_contacts: Vec::new(),
             boundary_boundary_contacts: Vec::new(),
+            active_region: None,
+            cell_size_factor: na::one::<Real>(),
+            kernel_anisotropy: Vector::repeat(na::one::<Real>()),
+            contact_predicate: None,
+            max_neighbors: None,
         }
     }
 
+    /// The width of the cells of the spacial grid, as a multiple of the kernel radius.
+    pub fn cell_size_factor(&self) -> Real {
+        self.cell_size_factor
+    }
+
+    /// Sets the width of the cells of the spacial grid, as a multiple of the kernel radius.
+    ///
+    /// Smaller cells contain less particles but more cells have to be visited by the neighborhood
+    /// search. The default value is 1.0, i.e., the cell width is equal to the kernel radius.
+    pub fn set_cell_size_factor(&mut self, factor: Real) {
+        assert!(
+            factor > na::zero::<Real>(),
+            "The cell size factor must be positive."
+        );
+        self.cell_size_factor = factor;
+    }
+
+    /// The width of the cells of the spacial grid for the given kernel radius `h`.
+    ///
+    /// If the kernel is anisotropic, this is the width of the cells along the axes where the
+    /// kernel support is not stretched. See `self.cell_widths` for the width along each axis.
+    pub fn cell_width(&self, h: Real) -> Real {
+        h * self.cell_size_factor
+    }
+
+    /// The width of the cells of the spacial grid along each axis for the given kernel radius `h`.
+    ///
+    /// The cells are stretched along each axis like the kernel support.
+    pub fn cell_widths(&self, h: Real) -> Vector<Real> {
+        self.kernel_anisotropy * self.cell_width(h)
+    }
+
+    /// The factor by which the kernel support is stretched along each axis.
+    pub fn kernel_anisotropy(&self) -> &Vector<Real> {
+        &self.kernel_anisotropy
+    }
+
+    /// Sets the factor by which the kernel support is stretched along each axis.
+    ///
+    /// The kernel support then becomes the ellipsoid with the semi-axes `h * anisotropy[i]`, where
+    /// `h` is the kernel radius, e.g., to simulate thin sheets of fluid with a kernel wider
+    /// horizontally than vertically. Both the neighborhood search and the kernels evaluated by
+    /// the pressure solvers account for this stretching, and the kernels are rescaled to still
+    /// integrate to one. The default value is 1 along all the axes, i.e., a spherical support.
+    ///
+    /// Note that the particle volumes are not affected, so the particle spacing along each axis
+    /// must remain small enough for each particle to have enough neighbors. In particular, the
+    /// pressure solvers may become unstable if the support covers less than about three particle
+    /// spacings along its shortest axis.
+    pub fn set_kernel_anisotropy(&mut self, anisotropy: Vector<Real>) {
+        assert!(
+            anisotropy.iter().all(|e| *e > na::zero::<Real>()),
+            "The kernel anisotropy must be positive along all the axes."
+        );
+        self.kernel_anisotropy = anisotropy;
+    }
+
+    /// The region outside of which particles are frozen, if any.
+    pub fn active_region(&self) -> Option<&ActiveRegion> {
+        self.active_region.as_ref()
+    }
+
+    /// Restricts contact computation to the given region.
+    ///
+    /// Only the fluid particles inside of `region` enlarged by the kernel radius are moved by the
+    /// pressure solvers. The other fluid particles are frozen: those within one more kernel radius
+    /// keep all their contacts, so that the moving particles see their right densities and
+    /// pressures, and the farther ones only get a contact with themselves. Set this to `None` to
+    /// simulate the whole domain.
+    pub fn set_active_region(&mut self, region: Option<ActiveRegion>) {
+        self.active_region = region;
+    }
+
+    /// Checks if the given fluid particle lies outside of the active region and should be left frozen.
+    ///
+    /// The pressure solvers must not modify the positions and velocities of the frozen particles.
+    pub fn is_frozen(&self, h: Real, particle: &Point<Real>) -> bool {
+        self.active_region
+            .is_some_and(|region| !region.contains_point(particle, h))
+    }
+
+    /// The predicate filtering the contacts between fluid particles, if any.
+    pub fn contact_predicate(&self) -> Option<&ContactPredicate> {
+        self.contact_predicate.as_deref()
+    }
+
+    /// Sets the predicate filtering the contacts between fluid particles.
+    ///
+    /// The predicate is called as `predicate(i_model, i, j_model, j)` for each contact of the fluid
+    /// particle `i` of the fluid `i_model` with a distinct fluid particle `j` of the fluid `j_model`
+    /// closer than the kernel radius. The contact is discarded if it returns `false`, so that the
+    /// particle `i` is no longer affected by the particle `j`. Because each contact is filtered
+    /// independently from its flipped version, this can be used for one-directional interactions.
+    /// Contacts with boundary particles are not affected.
+    pub fn set_contact_predicate(&mut self, predicate: Option<Box<ContactPredicate>>) {
+        self.contact_predicate = predicate;
+    }
+
+    /// The maximum number of neighbors of each fluid particle, if any.
+    pub fn max_neighbors(&self) -> Option<usize> {
+        self.max_neighbors
+    }
+
+    /// Limits the number of neighbors of each fluid particle.
+    ///
+    /// When a fluid particle has more than `max_neighbors` fluid (resp. boundary) neighbors, only its
+    /// contacts with the `max_neighbors` closest fluid (resp. boundary) particles are kept. Two fluid
+    /// particles only keep their contacts if each one is among the closest neighbors of the other,
+    /// so a particle is never affected by a neighbor without affecting it, and some particles end
+    /// up with less than `max_neighbors` neighbors. This is meant as a safety net against
+    /// pathological particle clumping and is disabled by default.
+    pub fn set_max_neighbors(&mut self, max_neighbors: Option<usize>) {
+        assert!(
+            max_neighbors != Some(0),
+            "The maximum number of neighbors must be positive."
+        );
+        self.max_neighbors = max_neighbors;
+    }
+
     /// The total number of contacts detected by this manager.
     ///
     /// Note that there will be two contact for each pair of distinct particles.
@@ -44,6 +175,69 @@ impl ContactManager {
                 .sum::<usize>()
     }
 
+    /// Checks that the contacts between fluid particles are symmetric, in debug builds only.
+    ///
+    /// For each contact from a particle `i` to a particle `j`, there must be a contact from `j` to
+    /// `i` with the same weight and the opposite gradient, otherwise the forces computed from these
+    /// contacts are not symmetric. This panics with the offending pair of particles if the check
+    /// fails, and is compiled out in release builds.
+    ///
+    /// This should be called after the contact weights have been computed, e.g., after a step.
+    /// Note that the contacts are not expected to be symmetric with an active region or an
+    /// asymmetric contact predicate.
+    pub fn debug_assert_symmetric_fluid_contacts(&self) {
+        #[cfg(debug_assertions)]
+        for contacts in &self.fluid_fluid_contacts {
+            for c in contacts.iter_pairs() {
+                if c.is_same_particle_contact() {
+                    continue;
+                }
+
+                let symmetric = self
+                    .fluid_fluid_contacts
+                    .get(c.j_model)
+                    .and_then(|contacts| contacts.contacts().get(c.j))
+                    .and_then(|contacts| {
+                        let contacts = contacts.read().unwrap();
+                        contacts
+                            .iter()
+                            .find(|s| s.j_model == c.i_model && s.j == c.i)
+                            .copied()
+                    });
+
+                let symmetric = symmetric.unwrap_or_else(|| {
+                    panic!(
+                        "The contact from particle {} of fluid {} to particle {} of fluid {} has no symmetric contact.",
+                        c.i, c.i_model, c.j, c.j_model
+                    )
+                });
+
+                assert!(
+                    approx::relative_eq!(c.weight, symmetric.weight)
+                        && approx::relative_eq!(c.gradient, -symmetric.gradient),
+                    "The contact from particle {} of fluid {} to particle {} of fluid {} is not symmetric: weights {} and {}, gradients {:?} and {:?}.",
+                    c.i,
+                    c.i_model,
+                    c.j,
+                    c.j_model,
+                    c.weight,
+                    symmetric.weight,
+                    c.gradient,
+                    symmetric.gradient,
+                );
+            }
+        }
+    }
+
+    /// Releases the capacity of all the contact buffers that exceeds their number of contacts.
+    pub fn shrink_to_fit(&mut self) {
+        self.fluid_fluid_contacts
+            .iter_mut()
+            .chain(self.fluid_boundary_contacts.iter_mut())
+            .chain(self.boundary_boundary_contacts.iter_mut())
+            .for_each(|contacts| contacts.shrink_to_fit());
+    }
+
     /// Computes all the contacts between the particles inserted on the provided spacial grid.
     pub fn update_contacts(
         &mut self,
@@ -53,9 +247,16 @@ impl ContactManager {
         boundaries: &[Boundary],
         hgrid: &HGrid<HGridEntry>,
     ) {
+        let options = ContactOptions {
+            active_region: self.active_region.as_ref(),
+            contact_predicate: self.contact_predicate.as_deref(),
+            max_neighbors: self.max_neighbors,
+            ..ContactOptions::new(h, &self.kernel_anisotropy)
+        };
+
         geometry::compute_contacts(
             counters,
-            h,
+            &options,
             &fluids,
             &boundaries,
             &mut self.fluid_fluid_contacts,
@@ -65,3 +266,238 @@ impl ContactManager {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ContactManager;
+    use crate::counters::Counters;
+    use crate::geometry::{self, HGrid};
+    use crate::kernel::CubicSplineKernel;
+    use crate::math::{Point, Real, Vector, DIM};
+    use crate::object::Fluid;
+    use crate::LiquidWorld;
+
+    #[test]
+    fn max_neighbors_keeps_the_contacts_symmetric() {
+        let particle_radius = 0.05;
+        let h = particle_radius * 4.0;
+
+        // A lattice compressed toward the origin, so neighboring particles have different
+        // numbers of neighbors, and their closest neighbors differ.
+        let dims = [10usize; DIM];
+        let num_particles: usize = dims.iter().product();
+        let positions = (0..num_particles).map(|mut i| {
+            let coords = Vector::from_fn(|k, _| {
+                let coord = (i % dims[k]) as Real;
+                i /= dims[k];
+                coord
+            });
+            Point::from(coords.map(|e| e.powf(1.5)) * particle_radius)
+        });
+        let fluid = Fluid::from_positions(positions, particle_radius, 1000.0);
+
+        let mut manager = ContactManager::new();
+        manager.set_max_neighbors(Some(8));
+        let mut grid = HGrid::with_cell_widths(manager.cell_widths(h));
+        geometry::insert_fluids_to_grid(std::slice::from_ref(&fluid), &mut grid);
+        manager.update_contacts(
+            &mut Counters::new(),
+            h,
+            std::slice::from_ref(&fluid),
+            &[],
+            &grid,
+        );
+
+        let contacts = &manager.fluid_fluid_contacts[0];
+        for i in 0..fluid.num_particles() {
+            assert!(contacts.particle_contacts(i).read().unwrap().len() <= 8);
+        }
+
+        for c in contacts.iter_pairs() {
+            assert!(
+                contacts.iter_particle(c.j).any(|s| s.j == c.i),
+                "The contact from {} to {} has no symmetric contact.",
+                c.i,
+                c.j
+            );
+        }
+
+        manager.debug_assert_symmetric_fluid_contacts();
+    }
+
+    #[test]
+    fn anisotropic_kernel_stretches_the_neighborhood() {
+        use crate::solver::DFSPHSolver;
+
+        let particle_radius = 0.05;
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        let h = world.h();
+
+        // Stretch the kernel support along every axis but the vertical one.
+        let mut anisotropy = Vector::repeat(2.0);
+        anisotropy[crate::math::UP_AXIS] = 1.0;
+        world
+            .contact_manager_mut()
+            .set_kernel_anisotropy(anisotropy);
+
+        let center = Point::origin();
+        let horizontal = center + Vector::x() * (h * 1.5);
+        let vertical = center + *crate::math::up() * (h * 1.5);
+        let _ = world.add_fluid(Fluid::new(
+            vec![center, horizontal, vertical],
+            particle_radius,
+            1000.0,
+        ));
+        world.step(0.001, &Vector::zeros());
+
+        let contacts = &world.contact_manager().fluid_fluid_contacts[0];
+        let neighbors: Vec<_> = contacts.iter_particle(0).map(|c| c.j).collect();
+        assert!(neighbors.contains(&1));
+        assert!(!neighbors.contains(&2));
+    }
+
+    #[test]
+    fn frozen_particles_next_to_the_active_region_keep_their_densities() {
+        use crate::geometry::ActiveRegion;
+        use crate::solver::DFSPHSolver;
+        use crate::testbed::particle_block;
+
+        let particle_radius = 0.05;
+        let positions = particle_block(12, 4, particle_radius * 2.0, Vector::zeros());
+        let velocity = Vector::x() * 0.5;
+        let world = |region: Option<ActiveRegion>| {
+            let mut world = LiquidWorld::new(
+                DFSPHSolver::<CubicSplineKernel>::new(),
+                particle_radius,
+                2.0,
+            );
+            let mut fluid = Fluid::new(positions.clone(), particle_radius, 1000.0);
+            fluid.velocities.iter_mut().for_each(|v| *v = velocity);
+            let _ = world.add_fluid(fluid);
+            world.contact_manager_mut().set_active_region(region);
+            world
+        };
+        let mut whole = world(None);
+        let h = whole.h();
+        let region = ActiveRegion::new(
+            Point::from(Vector::repeat(-1.0)),
+            Point::from(Vector::repeat(1.0) + Vector::x() * (0.3 - 1.0)),
+        );
+        let mut partial = world(Some(region));
+
+        // The frozen neighbors of the moving particles have the same densities as in the whole domain.
+        for world in [&mut whole, &mut partial] {
+            world.set_paused(true);
+            world.step(0.01, &Vector::zeros());
+        }
+
+        let whole_densities = whole.fluid_densities(whole.fluids().iter().next().unwrap().0);
+        let partial_densities = partial.fluid_densities(partial.fluids().iter().next().unwrap().0);
+        let mut num_halo_particles = 0;
+
+        for (i, (d1, d2)) in whole_densities
+            .unwrap()
+            .iter()
+            .zip(partial_densities.unwrap())
+            .enumerate()
+        {
+            if region.contains_point(&positions[i], h * 2.0) {
+                num_halo_particles += !region.contains_point(&positions[i], h) as usize;
+                assert!((d1 - d2).abs() <= d1 * 1.0e-4, "{} != {}", d1, d2);
+            }
+        }
+
+        assert!(num_halo_particles > 0);
+
+        // Only the particles inside of the region enlarged by the kernel radius are moved.
+        partial.set_paused(false);
+        partial.step(0.01, &Vector::zeros());
+        let fluid = partial.fluids().iter().next().unwrap().1;
+
+        for (i, p) in positions.iter().enumerate() {
+            if region.contains_point(p, h) {
+                assert!(fluid.positions[i].x > p.x);
+            } else {
+                assert_eq!(fluid.positions[i], *p);
+                assert_eq!(fluid.velocities[i], velocity);
+            }
+        }
+    }
+
+    #[test]
+    fn contact_predicates_only_filter_the_candidate_pairs() {
+        use crate::testbed::particle_block;
+        use std::sync::{Arc, Mutex};
+
+        let particle_radius = 0.05;
+        let h = particle_radius * 4.0;
+        let spacing = particle_radius * 2.0;
+        // A fluid, and a ghost fluid interleaved with it, far enough apart for some pairs to be
+        // farther than the kernel radius.
+        let fluids = [
+            Fluid::new(
+                particle_block(6, 3, spacing, Vector::zeros()),
+                particle_radius,
+                1000.0,
+            ),
+            Fluid::new(
+                particle_block(6, 3, spacing, Vector::repeat(particle_radius)),
+                particle_radius,
+                1000.0,
+            ),
+        ];
+        let compute_contacts = |manager: &mut ContactManager| {
+            let mut grid = HGrid::with_cell_widths(manager.cell_widths(h));
+            geometry::insert_fluids_to_grid(&fluids, &mut grid);
+            manager.update_contacts(&mut Counters::new(), h, &fluids, &[], &grid);
+        };
+
+        let mut unfiltered = ContactManager::new();
+        compute_contacts(&mut unfiltered);
+
+        // The ghost fluid feels the other fluid, but isn't felt by it.
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let recorded_calls = calls.clone();
+        let mut filtered = ContactManager::new();
+        filtered.set_contact_predicate(Some(Box::new(move |i_model, i, j_model, j| {
+            recorded_calls
+                .lock()
+                .unwrap()
+                .push((i_model, i, j_model, j));
+            !(i_model == 0 && j_model == 1)
+        })));
+        compute_contacts(&mut filtered);
+
+        let calls = calls.lock().unwrap();
+        let num_neighbor_contacts = |manager: &ContactManager| {
+            manager
+                .fluid_fluid_contacts
+                .iter()
+                .flat_map(|contacts| contacts.iter_pairs())
+                .filter(|c| !c.is_same_particle_contact())
+                .count()
+        };
+        assert_eq!(calls.len(), num_neighbor_contacts(&unfiltered));
+        assert!(calls.iter().all(|(i_model, i, j_model, j)| {
+            na::distance(
+                &fluids[*i_model].positions[*i],
+                &fluids[*j_model].positions[*j],
+            ) <= h
+        }));
+
+        let felt_by = |manager: &ContactManager, i_model: usize, j_model: usize| {
+            manager.fluid_fluid_contacts[i_model]
+                .iter_pairs()
+                .filter(|c| c.j_model == j_model)
+                .count()
+        };
+        assert!(felt_by(&unfiltered, 0, 1) > 0);
+        assert_eq!(felt_by(&filtered, 0, 1), 0);
+        assert_eq!(felt_by(&filtered, 1, 0), felt_by(&unfiltered, 1, 0));
+        assert_eq!(felt_by(&filtered, 0, 0), felt_by(&unfiltered, 0, 0));
+    }
+}
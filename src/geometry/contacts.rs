@@ -1,14 +1,20 @@
 use crate::counters::Counters;
-use crate::geometry::HGrid;
+use crate::geometry::{ActiveRegion, HGrid};
 use crate::math::{Point, Real, Vector};
 use crate::object::Boundary;
 use crate::object::Fluid;
 
-use std::sync::RwLock;
+use std::sync::{RwLock, RwLockReadGuard};
 
 #[cfg(feature = "parallel")]
 use rayon::prelude::*;
 
+/// A predicate deciding if a contact between two fluid particles should be kept.
+///
+/// Its arguments are, in order, the fluid index and particle index of the particle owning the
+/// contact, and the fluid index and particle index of its neighbor.
+pub type ContactPredicate = dyn Fn(usize, usize, usize, usize) -> bool + Send + Sync;
+
 #[derive(Copy, Clone, Debug)]
 /// A particle inserted on a spacial grid.
 pub enum HGridEntry {
@@ -52,6 +58,10 @@ pub struct Contact {
     /// The kernel gradient evaluated at `xi - xj` where `xi` is the position of the
     /// particle `i`, and `xj` is the position of the particle `j`.
     pub gradient: Vector<Real>,
+    /// The distance between the positions `xi` and `xj` of the particles `i` and `j`.
+    ///
+    /// This must be kept consistent with `gradient`, see `Self::distance`.
+    pub distance: Real,
 }
 
 impl Contact {
@@ -64,9 +74,19 @@ impl Contact {
             j_model: self.i_model,
             weight: self.weight,
             gradient: -self.gradient,
+            distance: self.distance,
         }
     }
 
+    /// The distance between the positions `xi` and `xj` of the particles `i` and `j`.
+    ///
+    /// This is computed during the contact detection, and updated with the weight and the
+    /// gradient each time the kernels are evaluated, so that it matches the positions used to
+    /// compute the gradient.
+    pub fn distance(&self) -> Real {
+        self.distance
+    }
+
     /// Returns `true` if this contact involves a single particle with itself.
     pub fn is_same_particle_contact(&self) -> bool {
         self.i_model == self.j_model && self.i == self.j
@@ -84,6 +104,9 @@ pub struct ParticlesContacts {
     // All the particle contact for one model.
     // `self.contacts[i]` contains all the contacts involving the particle `i`.
     contacts: Vec<RwLock<Vec<Contact>>>,
+    // The radius of the kernel support used by the last contact detection, or the largest
+    // semi-axis of this support if it is anisotropic.
+    support_radius: Real,
 }
 
 impl ParticlesContacts {
@@ -91,6 +114,7 @@ impl ParticlesContacts {
     pub fn new() -> Self {
         Self {
             contacts: Vec::new(),
+            support_radius: Real::MAX,
         }
     }
 
@@ -118,17 +142,161 @@ impl ParticlesContacts {
         &mut self.contacts[..]
     }
 
+    /// Iterates through all the contacts in this set, for all the particles.
+    ///
+    /// The contacts are yielded by copy because each particle's contacts are stored behind a lock.
+    /// The lock of a particle is held until all its contacts have been yielded.
+    pub fn iter_pairs(&self) -> ContactsIter<'_> {
+        ContactsIter::new(&self.contacts)
+    }
+
+    /// Iterates through all the contacts affecting the particle `i`.
+    ///
+    /// This is a shortcut for reading `self.particle_contacts(i)`.
+    pub fn iter_particle(&self, i: usize) -> ContactsIter<'_> {
+        ContactsIter::new(std::slice::from_ref(&self.contacts[i]))
+    }
+
+    /// Iterates through the contacts of the particle `i` with other particles, skipping its contact
+    /// with itself.
+    ///
+    /// The self-contact only contributes to the density of the particle, so this should be used by
+    /// computations that are undefined for two particles at the same position, e.g., normalizing the
+    /// direction between two particles.
+    pub fn iter_particle_neighbors(&self, i: usize) -> impl Iterator<Item = Contact> + '_ {
+        self.iter_particle(i)
+            .filter(|c| !c.is_same_particle_contact())
+    }
+
+    /// Iterates through the contacts of the particle `i` with the particles at a distance smaller
+    /// than or equal to `radius`, e.g., for forces with a support smaller than the kernel radius.
+    ///
+    /// This relies on `Contact::distance`, stored by the contact detection, instead of recomputing
+    /// the distances. All the contacts are yielded if `radius` is at least the kernel radius `h`
+    /// used to detect them, even if the particles moved since.
+    pub fn particle_contacts_within(
+        &self,
+        i: usize,
+        radius: Real,
+    ) -> impl Iterator<Item = Contact> + '_ {
+        let all = radius >= self.support_radius;
+        self.iter_particle(i)
+            .filter(move |c| all || c.distance <= radius)
+    }
+
     /// The total number of contacts in this set.
     pub fn len(&self) -> usize {
         self.contacts.iter().map(|c| c.read().unwrap().len()).sum()
     }
 
+    /// Releases the capacity of the contact buffers that exceeds their number of contacts.
+    pub fn shrink_to_fit(&mut self) {
+        self.contacts.shrink_to_fit();
+
+        for contacts in &mut self.contacts {
+            contacts.get_mut().unwrap().shrink_to_fit();
+        }
+    }
+
+    /// Replaces this set by the contacts of the particles of `other` with a `true` in `mask`.
+    ///
+    /// The particles with a `false` in `mask` are left without any contact. The buffers of this
+    /// set are reused, so this only copies the contacts of the selected particles.
+    pub fn copy_masked_from(&mut self, other: &ParticlesContacts, mask: &[bool]) {
+        assert_eq!(
+            mask.len(),
+            other.contacts.len(),
+            "The mask must have one element per particle."
+        );
+
+        self.contacts
+            .resize_with(other.contacts.len(), || RwLock::new(Vec::new()));
+        self.support_radius = other.support_radius;
+
+        par_iter_mut!(self.contacts)
+            .zip(par_iter!(other.contacts))
+            .zip(par_iter!(mask))
+            .for_each(|((contacts, other_contacts), selected)| {
+                let contacts = contacts.get_mut().unwrap();
+                contacts.clear();
+
+                if *selected {
+                    contacts.extend_from_slice(&other_contacts.read().unwrap());
+                }
+            })
+    }
+
     /// Apply a permutation to this set of contacts.
     pub fn apply_permutation(&mut self, _permutation: &[usize]) {
         unimplemented!()
     }
 }
 
+/// An iterator through the contacts of a `ParticlesContacts`.
+pub struct ContactsIter<'a> {
+    particles: std::slice::Iter<'a, RwLock<Vec<Contact>>>,
+    current: Option<RwLockReadGuard<'a, Vec<Contact>>>,
+    next: usize,
+}
+
+impl<'a> ContactsIter<'a> {
+    fn new(particles: &'a [RwLock<Vec<Contact>>]) -> Self {
+        Self {
+            particles: particles.iter(),
+            current: None,
+            next: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for ContactsIter<'a> {
+    type Item = Contact;
+
+    #[inline]
+    fn next(&mut self) -> Option<Contact> {
+        loop {
+            if let Some(contact) = self.current.as_ref().and_then(|c| c.get(self.next)) {
+                self.next += 1;
+                return Some(*contact);
+            }
+
+            // Release the current lock before acquiring the next one.
+            self.current = None;
+            self.current = Some(self.particles.next()?.read().unwrap());
+            self.next = 0;
+        }
+    }
+}
+
+/// The options of the contact detection performed by `compute_contacts`.
+#[derive(Copy, Clone)]
+pub struct ContactOptions<'a> {
+    /// The kernel radius.
+    pub h: Real,
+    /// The factors stretching the kernel support along each axis.
+    pub anisotropy: &'a Vector<Real>,
+    /// The region outside of which the fluid particles are only given a contact with themselves.
+    pub active_region: Option<&'a ActiveRegion>,
+    /// The predicate filtering the contacts between distinct fluid particles.
+    pub contact_predicate: Option<&'a ContactPredicate>,
+    /// The maximum number of fluid (resp. boundary) neighbors of each fluid particle.
+    pub max_neighbors: Option<usize>,
+}
+
+impl<'a> ContactOptions<'a> {
+    /// The options of an isotropic contact detection with the kernel radius `h`, without any
+    /// active region, contact predicate, or maximum number of neighbors.
+    pub fn new(h: Real, anisotropy: &'a Vector<Real>) -> Self {
+        Self {
+            h,
+            anisotropy,
+            active_region: None,
+            contact_predicate: None,
+            max_neighbors: None,
+        }
+    }
+}
+
 /// Insert all the particles from the given fluids into the `grid`.
 pub fn insert_fluids_to_grid(fluids: &[Fluid], grid: &mut HGrid<HGridEntry>) {
     for (fluid_id, fluid) in fluids.iter().enumerate() {
@@ -151,9 +319,42 @@ pub fn insert_boundaries_to_grid(boundaries: &[Boundary], grid: &mut HGrid<HGrid
 }
 
 /// Compute all the contacts between the particles inserted in `grid`.
+///
+/// Two particles are in contact if one lies inside of the kernel support of the other, i.e., the
+/// ellipsoid with semi-axes `h * anisotropy` centered at this particle, where `h` and `anisotropy`
+/// are given by the `options`.
+///
+/// If an `active_region` is given, only the fluid particles inside of this region enlarged by `2h`
+/// (and the boundary particles inside of this region enlarged by `3h`) will have their contacts
+/// computed. All the other particles will only be given a contact with themselves. The fluid
+/// particles inside of the region enlarged by `h` are moved by the solvers, and the other fluid
+/// particles with contacts are their frozen neighbors, which need all their contacts for their
+/// densities and pressures to be right.
+///
+/// If a `contact_predicate` is given, it is called for each contact between two distinct fluid
+/// particles closer than `h`, and the contacts for which it returns `false` are discarded.
+///
+/// The contacts between a fluid and a boundary are only computed if `Boundary::interacts_with_fluid`
+/// returns `true` for this fluid.
+///
+/// If `max_neighbors` is given, the fluid-fluid and the fluid-boundary contacts of each fluid
+/// particle are each limited to the `max_neighbors` contacts with the closest particles. The
+/// contact between two fluid particles is only kept if each one is among the `max_neighbors`
+/// closest fluid neighbors of the other, so the fluid-fluid contacts remain symmetric.
+///
+/// Each fluid (resp. boundary) particle is given exactly one contact with itself, which accounts
+/// for its own contribution to its density and has a zero gradient, and at most one contact with
+/// each other particle: a pair of distinct particles results in two flipped contacts, one stored
+/// for each particle, so that the solvers can gather the forces applied to each particle in parallel.
+///
+/// NOTE: there is no compact storage of the `i < j` pairs only. Every pressure solver and
+/// non-pressure force reads the contact list of each particle to compute its own forces, so
+/// applying each stored pair to both of its particles would require rewriting all of them as
+/// scatters synchronizing their writes. The memory of the contacts can be bounded with
+/// `ContactManager::set_max_neighbors` instead.
 pub fn compute_contacts(
     counters: &mut Counters,
-    h: Real,
+    options: &ContactOptions,
     fluids: &[Fluid],
     boundaries: &[Boundary],
     fluid_fluid_contacts: &mut Vec<ParticlesContacts>,
@@ -161,8 +362,13 @@ pub fn compute_contacts(
     boundary_boundary_contacts: &mut Vec<ParticlesContacts>,
     grid: &HGrid<HGridEntry>,
 ) {
-    // Needed so the loop in -1..=1 bellow works.
-    assert_eq!(h, grid.cell_width());
+    let ContactOptions {
+        h,
+        anisotropy,
+        active_region,
+        max_neighbors,
+        ..
+    } = *options;
     counters.cd.neighborhood_search_time.resume();
 
     fluid_fluid_contacts.resize_with(fluids.len(), || ParticlesContacts::new());
@@ -199,41 +405,40 @@ pub fn compute_contacts(
             .resize_with(boundary.num_particles(), || RwLock::new(Vec::new()))
     }
 
-    #[cfg(feature = "dim2")]
-    let neighbours: [(i64, i64); 5] = [(0, 0), (0, 1), (1, -1), (1, 0), (1, 1)];
-    #[cfg(feature = "dim3")]
-    let neighbours: [(i64, i64, i64); 14] = [
-        (0, 0, 0),
-        (0, 0, 1),
-        (0, 1, -1),
-        (0, 1, 0),
-        (0, 1, 1),
-        (1, -1, -1),
-        (1, -1, 0),
-        (1, -1, 1),
-        (1, 0, -1),
-        (1, 0, 0),
-        (1, 0, 1),
-        (1, 1, -1),
-        (1, 1, 0),
-        (1, 1, 1),
-    ];
+    let support_radius = h * anisotropy.max();
+
+    for contacts in fluid_fluid_contacts
+        .iter_mut()
+        .chain(fluid_boundary_contacts.iter_mut())
+        .chain(boundary_boundary_contacts.iter_mut())
+    {
+        contacts.support_radius = support_radius;
+    }
+
+    let neighbours = grid.half_neighborhood_with_radii(&(anisotropy * h));
 
     par_iter!(grid.inner_table()).for_each(|(curr_cell, curr_particles)| {
-        for &val in neighbours.iter() {
-            #[cfg(feature = "dim2")]
-            let neighbor_cell = {
-                let (i, j) = val;
-                curr_cell + Vector::new(i, j)
-            };
-            #[cfg(feature = "dim3")]
-            let neighbor_cell = {
-                let (i, j, k) = val;
-                curr_cell + Vector::new(i, j, k)
-            };
+        for offset in neighbours.iter() {
+            let neighbor_cell = curr_cell + offset;
             if let Some(neighbor_particles) = grid.cell(&neighbor_cell) {
+                let only_self_contacts = active_region.is_some_and(|region| {
+                    !cell_is_active(region, grid.cell_widths(), curr_cell, h)
+                        && !cell_is_active(region, grid.cell_widths(), &neighbor_cell, h)
+                });
+
+                if only_self_contacts {
+                    if *curr_cell == neighbor_cell {
+                        compute_self_contacts_for_cell(
+                            fluid_fluid_contacts,
+                            boundary_boundary_contacts,
+                            curr_particles,
+                        );
+                    }
+                    continue;
+                }
+
                 compute_contacts_for_pair_of_cells(
-                    h,
+                    options,
                     fluids,
                     boundaries,
                     fluid_fluid_contacts,
@@ -248,11 +453,138 @@ pub fn compute_contacts(
         }
     });
 
+    if let Some(max_neighbors) = max_neighbors {
+        keep_closest_fluid_contacts(fluid_fluid_contacts, max_neighbors);
+
+        for contacts in fluid_boundary_contacts.iter_mut() {
+            par_iter_mut!(contacts.contacts).for_each(|contacts| {
+                let _ = keep_closest_contacts(contacts.get_mut().unwrap(), max_neighbors);
+            })
+        }
+    }
+
     counters.cd.neighborhood_search_time.pause();
 }
 
-fn compute_contacts_for_pair_of_cells(
+// Orders the contacts of a particle by distance to its neighbors, the ties being broken by the
+// neighbor indices so that the closest neighbors of a particle are always the same.
+fn compare_neighbors(a: &Contact, b: &Contact) -> std::cmp::Ordering {
+    a.distance
+        .partial_cmp(&b.distance)
+        .unwrap_or(std::cmp::Ordering::Equal)
+        .then(a.j_model.cmp(&b.j_model))
+        .then(a.j.cmp(&b.j))
+}
+
+// Removes all but the `max_neighbors` contacts with the closest neighbors from `contacts`, and
+// returns the contact with the farthest neighbor kept if any contact was removed.
+fn keep_closest_contacts(contacts: &mut Vec<Contact>, max_neighbors: usize) -> Option<Contact> {
+    if contacts.len() <= max_neighbors {
+        return None;
+    }
+
+    if max_neighbors == 0 {
+        contacts.clear();
+        return None;
+    }
+
+    let (_, farthest, _) = contacts.select_nth_unstable_by(max_neighbors - 1, compare_neighbors);
+    let farthest = *farthest;
+    contacts.truncate(max_neighbors);
+    Some(farthest)
+}
+
+// Limits the contacts of each fluid particle to its `max_neighbors` closest neighbors.
+//
+// A pair of fluid particles keeps its contacts only if each particle is among the closest
+// neighbors of the other, so the contacts remain symmetric.
+fn keep_closest_fluid_contacts(contacts: &mut [ParticlesContacts], max_neighbors: usize) {
+    let farthest: Vec<Vec<Option<Contact>>> = contacts
+        .iter_mut()
+        .map(|contacts| {
+            par_iter_mut!(contacts.contacts)
+                .map(|contacts| keep_closest_contacts(contacts.get_mut().unwrap(), max_neighbors))
+                .collect()
+        })
+        .collect();
+
+    for contacts in contacts.iter_mut() {
+        par_iter_mut!(contacts.contacts).for_each(|contacts| {
+            contacts.get_mut().unwrap().retain(|c| {
+                farthest[c.j_model][c.j].is_none_or(|farthest| {
+                    compare_neighbors(&c.flip(), &farthest) != std::cmp::Ordering::Greater
+                })
+            })
+        })
+    }
+}
+
+fn fluid_particle_is_active(region: Option<&ActiveRegion>, pt: &Point<Real>, h: Real) -> bool {
+    region.is_none_or(|region| region.contains_point(pt, h * na::convert::<_, Real>(2.0)))
+}
+
+fn fluid_contact_is_allowed(predicate: Option<&ContactPredicate>, contact: &Contact) -> bool {
+    contact.is_same_particle_contact()
+        || predicate.is_none_or(|predicate| {
+            predicate(contact.i_model, contact.i, contact.j_model, contact.j)
+        })
+}
+
+fn boundary_particle_is_active(region: Option<&ActiveRegion>, pt: &Point<Real>, h: Real) -> bool {
+    region.is_none_or(|region| region.contains_point(pt, h * na::convert::<_, Real>(3.0)))
+}
+
+// Checks if the given cell may contain a fluid or boundary particle that is active.
+fn cell_is_active(
+    region: &ActiveRegion,
+    cell_widths: &Vector<Real>,
+    cell: &Point<i64>,
+    h: Real,
+) -> bool {
+    let mins = Point::from(cell.coords.map(|e| e as Real).component_mul(cell_widths));
+    let maxs = mins + cell_widths;
+    region.intersects_aabb(&mins, &maxs, h * na::convert::<_, Real>(3.0))
+}
+
+fn compute_self_contacts_for_cell(
+    fluid_fluid_contacts: &[ParticlesContacts],
+    boundary_boundary_contacts: &[ParticlesContacts],
+    particles: &[HGridEntry],
+) {
+    for entry in particles {
+        let (model, particle, is_boundary) = entry.into_tuple();
+        let contact = Contact {
+            i_model: model,
+            j_model: model,
+            i: particle,
+            j: particle,
+            weight: na::zero::<Real>(),
+            gradient: Vector::zeros(),
+            distance: na::zero::<Real>(),
+        };
+
+        let contacts = if is_boundary {
+            &boundary_boundary_contacts[model]
+        } else {
+            &fluid_fluid_contacts[model]
+        };
+
+        contacts.contacts[particle].write().unwrap().push(contact);
+    }
+}
+
+// Checks if `pj` is inside of the support, stretched by `anisotropy`, of the kernel centered at `pi`.
+fn is_in_kernel_support(
+    pi: &Point<Real>,
+    pj: &Point<Real>,
     h: Real,
+    anisotropy: &Vector<Real>,
+) -> bool {
+    (pj - pi).component_div(anisotropy).norm_squared() <= h * h
+}
+
+fn compute_contacts_for_pair_of_cells(
+    options: &ContactOptions,
     fluids: &[Fluid],
     boundaries: &[Boundary],
     fluid_fluid_contacts: &[ParticlesContacts],
@@ -263,6 +595,14 @@ fn compute_contacts_for_pair_of_cells(
     neighbor_cell: &Point<i64>,
     neighbor_particles: &[HGridEntry],
 ) {
+    let ContactOptions {
+        h,
+        anisotropy,
+        active_region,
+        contact_predicate,
+        ..
+    } = *options;
+
     for entry in curr_particles {
         match entry {
             HGridEntry::BoundaryParticle(boundary_i, particle_i) => {
@@ -274,7 +614,7 @@ fn compute_contacts_for_pair_of_cells(
                             let pi = &boundaries[*boundary_i].positions[*particle_i];
                             let pj = &boundaries[*boundary_j].positions[*particle_j];
 
-                            if na::distance_squared(pi, pj) <= h * h {
+                            if is_in_kernel_support(pi, pj, h, anisotropy) {
                                 let contact = Contact {
                                     i_model: *boundary_i,
                                     j_model: *boundary_j,
@@ -282,14 +622,21 @@ fn compute_contacts_for_pair_of_cells(
                                     j: *particle_j,
                                     weight: na::zero::<Real>(),
                                     gradient: Vector::zeros(),
+                                    distance: na::distance(pi, pj),
                                 };
 
-                                boundary_boundary_contacts[*boundary_i].contacts[*particle_i]
-                                    .write()
-                                    .unwrap()
-                                    .push(contact);
+                                if contact.is_same_particle_contact()
+                                    || boundary_particle_is_active(active_region, pi, h)
+                                {
+                                    boundary_boundary_contacts[*boundary_i].contacts[*particle_i]
+                                        .write()
+                                        .unwrap()
+                                        .push(contact);
+                                }
 
-                                if *curr_cell != *neighbor_cell {
+                                if *curr_cell != *neighbor_cell
+                                    && boundary_particle_is_active(active_region, pj, h)
+                                {
                                     boundary_boundary_contacts[*boundary_j].contacts[*particle_j]
                                         .write()
                                         .unwrap()
@@ -307,7 +654,10 @@ fn compute_contacts_for_pair_of_cells(
                             let pi = &boundaries[*boundary_i].positions[*particle_i];
                             let pj = &fluids[*fluid_j].positions[*particle_j];
 
-                            if na::distance_squared(pi, pj) <= h * h {
+                            if is_in_kernel_support(pi, pj, h, anisotropy)
+                                && fluid_particle_is_active(active_region, pj, h)
+                                && boundaries[*boundary_i].interacts_with_fluid(*fluid_j)
+                            {
                                 let contact = Contact {
                                     i_model: *fluid_j,
                                     j_model: *boundary_i,
@@ -315,6 +665,7 @@ fn compute_contacts_for_pair_of_cells(
                                     j: *particle_i,
                                     weight: na::zero::<Real>(),
                                     gradient: Vector::zeros(),
+                                    distance: na::distance(pi, pj),
                                 };
 
                                 fluid_boundary_contacts[*fluid_j].contacts[*particle_j]
@@ -336,8 +687,8 @@ fn compute_contacts_for_pair_of_cells(
                         fluids[fluid_j].positions[particle_j]
                     };
 
-                    if na::distance_squared(&pi, &pj) <= h * h {
-                        assert!(na::distance_squared(&pj, &pi) <= h * h);
+                    if is_in_kernel_support(&pi, &pj, h, anisotropy) {
+                        assert!(is_in_kernel_support(&pj, &pi, h, anisotropy));
                         let contact = Contact {
                             i_model: *fluid_i,
                             j_model: fluid_j,
@@ -345,24 +696,38 @@ fn compute_contacts_for_pair_of_cells(
                             j: particle_j,
                             weight: na::zero::<Real>(),
                             gradient: Vector::zeros(),
+                            distance: na::distance(&pi, &pj),
                         };
 
+                        let i_is_active = fluid_particle_is_active(active_region, &pi, h);
+
                         if is_boundary_j {
-                            fluid_boundary_contacts[*fluid_i].contacts[*particle_i]
-                                .write()
-                                .unwrap()
-                                .push(contact);
+                            if i_is_active && boundaries[fluid_j].interacts_with_fluid(*fluid_i) {
+                                fluid_boundary_contacts[*fluid_i].contacts[*particle_i]
+                                    .write()
+                                    .unwrap()
+                                    .push(contact);
+                            }
                         } else {
-                            fluid_fluid_contacts[*fluid_i].contacts[*particle_i]
-                                .write()
-                                .unwrap()
-                                .push(contact);
+                            if (i_is_active || contact.is_same_particle_contact())
+                                && fluid_contact_is_allowed(contact_predicate, &contact)
+                            {
+                                fluid_fluid_contacts[*fluid_i].contacts[*particle_i]
+                                    .write()
+                                    .unwrap()
+                                    .push(contact);
+                            }
+
+                            let flipped = contact.flip();
 
-                            if *curr_cell != *neighbor_cell {
+                            if *curr_cell != *neighbor_cell
+                                && fluid_particle_is_active(active_region, &pj, h)
+                                && fluid_contact_is_allowed(contact_predicate, &flipped)
+                            {
                                 fluid_fluid_contacts[fluid_j].contacts[particle_j]
                                     .write()
                                     .unwrap()
-                                    .push(contact.flip());
+                                    .push(flipped);
                             }
                         }
                     }
@@ -374,6 +739,7 @@ fn compute_contacts_for_pair_of_cells(
 
 /// Compute all the contacts between the particles of a single fluid object.
 pub fn compute_self_contacts(h: Real, fluid: &Fluid, contacts: &mut ParticlesContacts) {
+    contacts.support_radius = h;
     contacts
         .contacts
         .iter_mut()
@@ -405,6 +771,7 @@ pub fn compute_self_contacts(h: Real, fluid: &Fluid, contacts: &mut ParticlesCon
                             j: *particle_j,
                             weight: na::zero::<Real>(),
                             gradient: Vector::zeros(),
+                            distance: na::distance(&pi, &pj),
                         };
 
                         contacts.contacts[*particle_i]
@@ -417,3 +784,138 @@ pub fn compute_self_contacts(h: Real, fluid: &Fluid, contacts: &mut ParticlesCon
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::kernel::CubicSplineKernel;
+    use crate::math::{Point, Real, Vector};
+    use crate::object::{Boundary, Fluid};
+    use crate::LiquidWorld;
+
+    #[test]
+    fn contact_distances_match_the_particle_positions() {
+        use crate::solver::DFSPHSolver;
+
+        let particle_radius = 0.05;
+        let positions: Vec<_> = (0..20)
+            .map(|i| Point::from(Vector::repeat(0.3) + Vector::x() * (i as Real * 0.037)))
+            .collect();
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        let _ = world.add_fluid(Fluid::new(positions, particle_radius, 1000.0));
+        let _ = world.add_boundary(Boundary::new(vec![Point::from(Vector::repeat(0.25))]));
+        world.set_paused(true);
+        world.step(0.01, &Vector::zeros());
+
+        let fluid = &world.fluids().as_slice()[0];
+        let boundary = &world.boundaries().as_slice()[0];
+        let contacts = world.contact_manager();
+        let mut num_boundary_contacts = 0;
+
+        for particle_contacts in contacts.fluid_fluid_contacts[0].contacts() {
+            for c in particle_contacts.read().unwrap().iter() {
+                let expected = na::distance(&fluid.positions[c.i], &fluid.positions[c.j]);
+                assert!((c.distance() - expected).abs() < 1.0e-6);
+                assert!(c.distance() <= world.h());
+            }
+        }
+
+        for particle_contacts in contacts.fluid_boundary_contacts[0].contacts() {
+            for c in particle_contacts.read().unwrap().iter() {
+                let expected = na::distance(&fluid.positions[c.i], &boundary.positions[c.j]);
+                assert!((c.distance() - expected).abs() < 1.0e-6);
+                num_boundary_contacts += 1;
+            }
+        }
+
+        assert!(num_boundary_contacts > 0);
+    }
+
+    #[test]
+    fn fluid_contacts_have_one_self_contact_and_no_duplicates() {
+        use crate::solver::DFSPHSolver;
+
+        let particle_radius = 0.05;
+        let positions: Vec<_> = (0..60)
+            .map(|i| {
+                let t = i as Real * 0.37;
+                Point::from(Vector::repeat(t.sin() * 0.2) + Vector::x() * (t.cos() * 0.15))
+            })
+            .collect();
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        let _ = world.add_fluid(Fluid::new(positions.clone(), particle_radius, 1000.0));
+        world.set_paused(true);
+        world.step(0.01, &Vector::zeros());
+
+        let contacts = &world.contact_manager().fluid_fluid_contacts[0];
+
+        for (i, pi) in positions.iter().enumerate() {
+            let num_self_contacts = contacts
+                .iter_particle(i)
+                .filter(|c| c.is_same_particle_contact())
+                .count();
+            assert_eq!(num_self_contacts, 1);
+
+            let mut neighbors: Vec<_> = contacts.iter_particle_neighbors(i).map(|c| c.j).collect();
+            neighbors.sort_unstable();
+            let expected: Vec<_> = (0..positions.len())
+                .filter(|j| *j != i && na::distance(pi, &positions[*j]) <= world.h())
+                .collect();
+            assert_eq!(neighbors, expected);
+        }
+    }
+
+    #[test]
+    fn contacts_within_a_radius_use_the_stored_distances() {
+        use crate::solver::DFSPHSolver;
+
+        let particle_radius = 0.05;
+        let positions: Vec<_> = (0..40)
+            .map(|i| {
+                let t = i as Real * 0.61;
+                Point::from(Vector::repeat(t.cos() * 0.1) + Vector::y() * (t.sin() * 0.12))
+            })
+            .collect();
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        let handle = world.add_fluid(Fluid::new(positions.clone(), particle_radius, 1000.0));
+        world.set_paused(true);
+        world.step(0.01, &Vector::zeros());
+
+        // Moving the particles does not change the contacts detected during the step.
+        for pos in &mut world.fluids_mut()[handle].positions {
+            pos.x *= 3.0;
+        }
+
+        let contacts = &world.contact_manager().fluid_fluid_contacts[0];
+        let radius = particle_radius * 3.0;
+
+        for (i, pi) in positions.iter().enumerate() {
+            let mut within: Vec<_> = contacts
+                .particle_contacts_within(i, radius)
+                .map(|c| c.j)
+                .collect();
+            within.sort_unstable();
+            let expected: Vec<_> = (0..positions.len())
+                .filter(|j| na::distance(pi, &positions[*j]) <= radius)
+                .collect();
+            assert_eq!(within, expected);
+
+            let num_contacts = contacts.iter_particle(i).count();
+            assert_eq!(
+                contacts.particle_contacts_within(i, world.h()).count(),
+                num_contacts
+            );
+        }
+    }
+}
@@ -21,21 +21,33 @@ impl BuildHasher for DeterministicState {
 #[derive(PartialEq, Debug, Clone)]
 pub struct HGrid<T> {
     cells: HashMap<Point<i64>, Vec<T>, DeterministicState>,
-    cell_width: Real,
+    cell_widths: Vector<Real>,
 }
 
 impl<T> HGrid<T> {
     /// Initialize a grid where each cell has the width `cell_width`.
     pub fn new(cell_width: Real) -> Self {
+        Self::with_cell_widths(Vector::repeat(cell_width))
+    }
+
+    /// Initialize a grid where each cell has the width `cell_widths[i]` along the `i`-th axis.
+    pub fn with_cell_widths(cell_widths: Vector<Real>) -> Self {
         Self {
             cells: HashMap::with_hasher(DeterministicState),
-            cell_width,
+            cell_widths,
         }
     }
 
     /// The width of a cell of this spacial grid.
+    ///
+    /// If the cells do not have the same width along all the axes, this is their largest width.
     pub fn cell_width(&self) -> Real {
-        self.cell_width
+        self.cell_widths.max()
+    }
+
+    /// The width of a cell of this spacial grid along each axis.
+    pub fn cell_widths(&self) -> &Vector<Real> {
+        &self.cell_widths
     }
 
     fn quantify(value: Real, cell_width: Real) -> i64 {
@@ -48,7 +60,7 @@ impl<T> HGrid<T> {
 
     /// Computes the logical grid cell containing `point`.
     pub fn key(&self, point: &Point<Real>) -> Point<i64> {
-        Point::from(point.coords.map(|e| Self::quantify(e, self.cell_width)))
+        Point::from(point.coords.zip_map(&self.cell_widths, Self::quantify))
     }
 
     /// Removes all elements from this grid.
@@ -89,19 +101,60 @@ impl<T> HGrid<T> {
 
     /// An iterator through all the neighbors of the given cell.
     ///
-    /// The given cell itself will be yielded by this iterator too.
+    /// The given cell itself will be yielded by this iterator too. See `self.neighbor_cells_with_radii`
+    /// for the order of the cells.
     pub fn neighbor_cells(
         &self,
         cell: &Point<i64>,
         radius: Real,
+    ) -> impl Iterator<Item = (Point<i64>, &Vec<T>)> {
+        self.neighbor_cells_with_radii(cell, &Vector::repeat(radius))
+    }
+
+    /// An iterator through all the neighbors of the given cell, within `radii[i]` along the `i`-th axis.
+    ///
+    /// The given cell itself will be yielded by this iterator too. The non-empty cells are yielded in
+    /// a fixed order of their offsets from the given cell, with the first axis varying the fastest,
+    /// independently of the order in which the elements were inserted into this grid. This keeps the
+    /// order of the neighbors found from these cells reproducible.
+    pub fn neighbor_cells_with_radii(
+        &self,
+        cell: &Point<i64>,
+        radii: &Vector<Real>,
     ) -> impl Iterator<Item = (Point<i64>, &Vec<T>)> {
         let cells = &self.cells;
-        let quantified_radius = Self::quantify_ceil(radius, self.cell_width);
 
-        CellRangeIterator::with_center(*cell, quantified_radius)
+        CellRangeIterator::with_center(*cell, self.quantify_radii(radii))
             .filter_map(move |cell| cells.get(&cell).map(|c| (cell, c)))
     }
 
+    /// The offsets of the cells to visit for finding all the pairs of points closer than `radius`.
+    ///
+    /// Only half of the neighborhood is returned: if a non-zero offset `o` is returned, then `-o`
+    /// is not. Visiting these offsets from each cell will visit each pair of neighbor cells exactly once.
+    /// The offsets are sorted in lexicographic order.
+    pub fn half_neighborhood(&self, radius: Real) -> Vec<Vector<i64>> {
+        self.half_neighborhood_with_radii(&Vector::repeat(radius))
+    }
+
+    /// The offsets of the cells to visit for finding all the pairs of points closer than `radii[i]` along the `i`-th axis.
+    ///
+    /// See `self.half_neighborhood` for details.
+    pub fn half_neighborhood_with_radii(&self, radii: &Vector<Real>) -> Vec<Vector<i64>> {
+        let mut offsets: Vec<_> =
+            CellRangeIterator::with_center(Point::origin(), self.quantify_radii(radii))
+                .map(|cell| cell.coords)
+                .filter(|offset| offset.iter().find(|e| **e != 0).is_none_or(|e| *e > 0))
+                .collect();
+        offsets.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+        offsets
+    }
+
+    // The number of cells covered by the given radii along each axis.
+    fn quantify_radii(&self, radii: &Vector<Real>) -> Vector<i64> {
+        radii.zip_map(&self.cell_widths, Self::quantify_ceil)
+    }
+
     //    pub fn elements_close_to_point<'a>(
     //        &'a self,
     //        point: &Point<Real>,
@@ -132,11 +185,100 @@ impl<T> HGrid<T> {
             .filter_map(move |cell| cells.get(&cell).map(|c| (cell, c)))
     }
 
+    /// An iterator through the keys of the cells traversed by the segment from `origin` to
+    /// `origin + dir * max_toi`, in the order they are traversed.
+    ///
+    /// Each key is yielded with the time of impact at which the segment enters its cell, in multiples
+    /// of the length of `dir`. Empty cells are yielded too.
+    pub fn cells_along_ray(
+        &self,
+        origin: &Point<Real>,
+        dir: &Vector<Real>,
+        max_toi: Real,
+    ) -> impl Iterator<Item = (Point<i64>, Real)> {
+        RayCellIterator::new(self.key(origin), origin, dir, &self.cell_widths, max_toi)
+    }
+
     //    pub fn elements_containing_point(&self, point: &Point<Real>) -> impl Iterator<Item = &T> {
     //        std::iter::empty()
     //    }
 }
 
+// Walks the cells traversed by a segment, following Amanatides and Woo, "A fast voxel traversal
+// algorithm for ray tracing".
+struct RayCellIterator {
+    curr: Point<i64>,
+    toi: Real,
+    max_toi: Real,
+    steps: Vector<i64>,
+    // The time of impact at which the segment crosses the next cell boundary along each axis.
+    next_tois: Vector<Real>,
+    // The time of impact needed to cross a whole cell along each axis.
+    delta_tois: Vector<Real>,
+    done: bool,
+}
+
+impl RayCellIterator {
+    fn new(
+        start: Point<i64>,
+        origin: &Point<Real>,
+        dir: &Vector<Real>,
+        cell_widths: &Vector<Real>,
+        max_toi: Real,
+    ) -> Self {
+        let mut steps = Vector::zeros();
+        let mut next_tois = Vector::repeat(Real::MAX);
+        let mut delta_tois = Vector::repeat(Real::MAX);
+
+        for i in 0..DIM {
+            let width = cell_widths[i];
+
+            if dir[i] > na::zero::<Real>() {
+                steps[i] = 1;
+                next_tois[i] = ((start[i] + 1) as Real * width - origin[i]) / dir[i];
+                delta_tois[i] = width / dir[i];
+            } else if dir[i] < na::zero::<Real>() {
+                steps[i] = -1;
+                next_tois[i] = (start[i] as Real * width - origin[i]) / dir[i];
+                delta_tois[i] = -width / dir[i];
+            }
+        }
+
+        Self {
+            curr: start,
+            toi: na::zero::<Real>(),
+            max_toi,
+            steps,
+            next_tois,
+            delta_tois,
+            done: false,
+        }
+    }
+}
+
+impl Iterator for RayCellIterator {
+    type Item = (Point<i64>, Real);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let result = (self.curr, self.toi);
+        let axis = self.next_tois.imin();
+
+        if self.steps[axis] == 0 || self.next_tois[axis] > self.max_toi {
+            self.done = true;
+        } else {
+            self.curr[axis] += self.steps[axis];
+            self.toi = self.next_tois[axis];
+            self.next_tois[axis] += self.delta_tois[axis];
+        }
+
+        Some(result)
+    }
+}
+
 struct CellRangeIterator {
     start: Point<i64>,
     end: Point<i64>,
@@ -154,11 +296,11 @@ impl CellRangeIterator {
         }
     }
 
-    fn with_center(center: Point<i64>, radius: i64) -> Self {
-        let start = center - Vector::repeat(radius as i64);
+    fn with_center(center: Point<i64>, radii: Vector<i64>) -> Self {
+        let start = center - radii;
         Self {
             start,
-            end: center + Vector::repeat(radius as i64),
+            end: center + radii,
             curr: start,
             done: false,
         }
@@ -200,7 +342,7 @@ mod test {
     #[cfg(feature = "dim2")]
     fn grid_neighbor_iterator() {
         use super::CellRangeIterator;
-        use crate::math::Point;
+        use crate::math::{Point, Vector};
 
         let expected = [
             Point::new(-1, 0),
@@ -225,8 +367,118 @@ mod test {
             Point::new(3, 3),
         ];
 
-        let iter = CellRangeIterator::with_center(Point::new(1, 2), 2);
+        let iter = CellRangeIterator::with_center(Point::new(1, 2), Vector::repeat(2));
 
         assert!(iter.zip(expected.iter()).all(|(a, b)| a == *b))
     }
+
+    #[test]
+    fn grid_cells_along_ray() {
+        use super::HGrid;
+        use crate::math::{Point, Real, Vector};
+
+        let grid = HGrid::<()>::new(0.5);
+        let origin = Point::from(Vector::repeat(0.25));
+        let mut dir = Vector::zeros();
+        dir.x = 1.0;
+        dir.y = -0.5;
+        let cells: Vec<_> = grid.cells_along_ray(&origin, &dir, 1.0).collect();
+
+        // The segment ends at x = 1.25, y = -0.25, after crossing y = 0 at x = 0.75.
+        let cell = |x: i64, y: i64| {
+            let mut key = Point::origin();
+            key.x = x;
+            key.y = y;
+            key
+        };
+        let expected = [
+            (cell(0, 0), 0.0),
+            (cell(1, 0), 0.25),
+            (cell(1, -1), 0.5),
+            (cell(2, -1), 0.75),
+        ];
+        assert_eq!(cells.len(), expected.len());
+
+        for ((key, toi), (expected_key, expected_toi)) in cells.iter().zip(expected.iter()) {
+            assert_eq!(key, expected_key);
+            assert!((toi - expected_toi).abs() < 1.0e-5 as Real);
+        }
+    }
+
+    #[test]
+    fn grid_neighbor_cells_order() {
+        use super::HGrid;
+        use crate::math::{Point, Real, Vector};
+
+        let points: Vec<_> = (0..27)
+            .map(|i| {
+                Point::from(Vector::from_fn(|k, _| {
+                    ((i / 3usize.pow(k as u32)) % 3) as Real
+                }))
+            })
+            .collect();
+        let mut grid = HGrid::new(1.0);
+        let mut reversed_grid = HGrid::new(1.0);
+
+        for (i, pt) in points.iter().enumerate() {
+            grid.insert(pt, i);
+        }
+
+        for (i, pt) in points.iter().enumerate().rev() {
+            reversed_grid.insert(pt, i);
+        }
+
+        let center = grid.key(&Point::from(Vector::repeat(1.5)));
+        let cells: Vec<_> = grid.neighbor_cells(&center, 1.0).map(|e| e.0).collect();
+        let reversed_cells: Vec<_> = reversed_grid
+            .neighbor_cells(&center, 1.0)
+            .map(|e| e.0)
+            .collect();
+        assert_eq!(cells, reversed_cells);
+
+        // The first axis varies the fastest.
+        let mut sorted_cells = cells.clone();
+        sorted_cells.sort_by(|a, b| a.iter().rev().cmp(b.iter().rev()));
+        assert_eq!(cells, sorted_cells);
+    }
+
+    #[test]
+    fn grid_half_neighborhood() {
+        use super::HGrid;
+        use crate::math::{Vector, DIM};
+
+        let grid = HGrid::<()>::new(0.5);
+        let offsets = grid.half_neighborhood(1.0);
+
+        // Half of the 5^DIM cells around the origin, plus the origin itself.
+        assert_eq!(offsets.len(), 5usize.pow(DIM as u32).div_ceil(2));
+        assert_eq!(offsets[0], Vector::zeros());
+        assert!(offsets.iter().all(|o| o.amax() <= 2));
+        assert!(offsets
+            .iter()
+            .all(|o| *o == Vector::zeros() || !offsets.contains(&-o)));
+    }
+
+    #[test]
+    fn grid_anisotropic_half_neighborhood() {
+        use super::HGrid;
+        use crate::math::{Vector, DIM};
+
+        // Cells stretched along the first axis like the radii give the neighborhood of a cubic grid.
+        let mut widths = Vector::repeat(0.5);
+        widths[0] = 2.0;
+        let grid = HGrid::<()>::with_cell_widths(widths);
+        let mut radii = Vector::repeat(1.0);
+        radii[0] = 4.0;
+
+        assert_eq!(
+            grid.half_neighborhood_with_radii(&radii),
+            HGrid::<()>::new(0.5).half_neighborhood(1.0)
+        );
+
+        // Cubic radii span fewer cells along the stretched axis.
+        let offsets = grid.half_neighborhood(1.0);
+        assert_eq!(offsets.len(), (3 * 5usize.pow(DIM as u32 - 1)).div_ceil(2));
+        assert!(offsets.iter().all(|o| o[0].abs() <= 1));
+    }
 }
@@ -1,12 +1,16 @@
 //! Acceleration data structures for collision detection.
 
+pub use self::active_region::ActiveRegion;
 pub use self::contact_manager::ContactManager;
 pub use self::contacts::{
     compute_contacts, compute_self_contacts, insert_boundaries_to_grid, insert_fluids_to_grid,
-    HGridEntry, ParticlesContacts,
+    Contact, ContactOptions, ContactPredicate, ContactsIter, HGridEntry, ParticlesContacts,
 };
 pub use self::hgrid::HGrid;
+pub use self::surface_heightfield::{compute_surface_heightfield, SurfaceHeightfield};
 
+mod active_region;
 mod contact_manager;
 mod contacts;
 mod hgrid;
+mod surface_heightfield;
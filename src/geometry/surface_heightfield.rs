@@ -0,0 +1,169 @@
+use crate::geometry::{HGrid, HGridEntry};
+use crate::math::{Point, Real, DIM};
+use crate::object::Fluid;
+
+/// The height of the free surface of the fluids, sampled on a regular horizontal grid.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SurfaceHeightfield {
+    /// The index of the vertical axis.
+    pub up_axis: usize,
+    /// The lower corner of the sampled horizontal region.
+    ///
+    /// Its coordinate along the vertical axis is zero.
+    pub origin: Point<Real>,
+    /// The width of each column of this heightfield.
+    pub cell_width: Real,
+    /// The number of columns along each horizontal axis, in increasing axis order.
+    pub num_columns: [usize; DIM - 1],
+    /// The height of the highest fluid particle of each column, or `None` if the column is empty.
+    ///
+    /// The columns are stored in row-major order, i.e., the index of the column with coordinates
+    /// `(i, j)` is `i * num_columns[1] + j`.
+    pub heights: Vec<Option<Real>>,
+}
+
+impl SurfaceHeightfield {
+    /// The index into `self.heights` of the column containing the horizontal projection of `point`.
+    ///
+    /// Returns `None` if `point` is outside of the sampled region.
+    pub fn column_index(&self, point: &Point<Real>) -> Option<usize> {
+        let mut index = 0;
+
+        for (k, axis) in horizontal_axes(self.up_axis).enumerate() {
+            let coord = ((point[axis] - self.origin[axis]) / self.cell_width).floor();
+
+            if coord < na::zero::<Real>() || coord >= self.num_columns[k] as Real {
+                return None;
+            }
+
+            index = index * self.num_columns[k] + coord as usize;
+        }
+
+        Some(index)
+    }
+
+    /// The height of the column containing the horizontal projection of `point`.
+    ///
+    /// Returns `None` if this column is empty or outside of the sampled region.
+    pub fn height_at(&self, point: &Point<Real>) -> Option<Real> {
+        self.heights[self.column_index(point)?]
+    }
+}
+
+fn horizontal_axes(up_axis: usize) -> impl Iterator<Item = usize> {
+    (0..DIM).filter(move |axis| *axis != up_axis)
+}
+
+/// Computes the height of the free surface of `fluids` over columns of width `cell_width`.
+///
+/// The sampled region is the horizontal extent of the cells of `grid` containing fluid particles.
+/// The fluid particles are retrieved from `grid`, so it must be up-to-date with `fluids`.
+pub fn compute_surface_heightfield(
+    grid: &HGrid<HGridEntry>,
+    fluids: &[Fluid],
+    cell_width: Real,
+    up_axis: usize,
+) -> SurfaceHeightfield {
+    assert!(up_axis < DIM, "The up axis must be smaller than {}.", DIM);
+    assert!(
+        cell_width > na::zero::<Real>(),
+        "The heightfield cell width must be positive."
+    );
+
+    let is_fluid_entry = |entry: &HGridEntry| matches!(entry, HGridEntry::FluidParticle(..));
+    let mut any_fluid = false;
+    let mut mins = Point::from([i64::MAX; DIM]);
+    let mut maxs = Point::from([i64::MIN; DIM]);
+
+    for (key, entries) in grid.cells() {
+        if entries.iter().any(is_fluid_entry) {
+            any_fluid = true;
+            mins = mins.inf(key);
+            maxs = maxs.sup(key);
+        }
+    }
+
+    let mut result = SurfaceHeightfield {
+        up_axis,
+        origin: Point::origin(),
+        cell_width,
+        num_columns: [0; DIM - 1],
+        heights: Vec::new(),
+    };
+
+    if !any_fluid {
+        return result;
+    }
+
+    for (k, axis) in horizontal_axes(up_axis).enumerate() {
+        let extent = ((maxs[axis] - mins[axis] + 1) as Real) * grid.cell_widths()[axis];
+        result.origin[axis] = (mins[axis] as Real) * grid.cell_widths()[axis];
+        result.num_columns[k] = (extent / cell_width).ceil() as usize;
+    }
+
+    result.heights = vec![None; result.num_columns.iter().product()];
+
+    for (_, entries) in grid.cells() {
+        for entry in entries {
+            if let HGridEntry::FluidParticle(fluid_id, particle_id) = entry {
+                let Some(pos) = fluids
+                    .get(*fluid_id)
+                    .and_then(|fluid| fluid.positions.get(*particle_id))
+                else {
+                    continue;
+                };
+
+                if let Some(index) = result.column_index(pos) {
+                    let height = result.heights[index].get_or_insert(pos[up_axis]);
+                    *height = height.max(pos[up_axis]);
+                }
+            }
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kernel::CubicSplineKernel;
+    use crate::math::{Point, Real, Vector};
+    use crate::object::Fluid;
+    use crate::LiquidWorld;
+
+    #[test]
+    fn heightfields_follow_the_highest_particle_of_each_column() {
+        use crate::solver::DFSPHSolver;
+        use crate::testbed::particle_block;
+
+        let particle_radius = 0.05;
+        let spacing = particle_radius * 2.0;
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        // A tall column of fluid and a shallow puddle, with an empty gap between them.
+        let column = particle_block(3, 5, spacing, Vector::zeros());
+        let puddle = particle_block(3, 2, spacing, Vector::x() * 1.0);
+        let _ = world.add_fluid(Fluid::new(column, particle_radius, 1000.0));
+        let _ = world.add_fluid(Fluid::new(puddle, particle_radius, 1000.0));
+
+        // The spacial grid is only built by the first step.
+        assert!(world.surface_heightfield(0.1, 1).heights.is_empty());
+        world.step(1.0e-4, &Vector::zeros());
+
+        let heightfield = world.surface_heightfield(0.1, 1);
+        let at = |x: Real| {
+            let mut point = Point::from(Vector::repeat(0.1));
+            point.x = x;
+            heightfield.height_at(&point)
+        };
+        assert!((at(0.1).unwrap() - 0.4).abs() < 1.0e-3);
+        assert!((at(1.1).unwrap() - 0.1).abs() < 1.0e-3);
+        assert_eq!(at(0.6), None);
+        assert_eq!(at(-10.0), None);
+        assert_eq!(heightfield.up_axis, 1);
+        assert!(heightfield.heights.iter().any(|height| height.is_none()));
+    }
+}
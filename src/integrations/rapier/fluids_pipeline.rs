@@ -8,7 +8,7 @@ use approx::AbsDiffEq;
 use na::Unit;
 use rapier::dynamics::RigidBodySet;
 use rapier::geometry::{ColliderHandle, ColliderSet};
-use rapier::math::{Point, Vector};
+use rapier::math::{AngVector, Point, Vector};
 use rapier::parry::bounding_volume::BoundingVolume;
 use rapier::parry::shape::FeatureId;
 use std::collections::HashMap;
@@ -75,12 +75,15 @@ pub struct ColliderCouplingEntry {
     pub sampling_method: ColliderSampling,
     pub boundary: BoundaryHandle,
     features: Vec<FeatureId>,
+    // The smoothed force and torque transmitted to the body, see `ColliderCouplingSet::set_force_smoothing`.
+    smoothed_force: Option<(Vector<f32>, AngVector<f32>)>,
 }
 
 /// Structure managing all the coupling between colliders from rapier with boundaries and fluids from salva.
 pub struct ColliderCouplingSet {
     /// The hashmap containing mappings from ColliderHandle to ColliderCouplingEntry
     pub entries: HashMap<ColliderHandle, ColliderCouplingEntry>,
+    force_smoothing: f32,
 }
 
 impl ColliderCouplingSet {
@@ -88,9 +91,33 @@ impl ColliderCouplingSet {
     pub fn new() -> Self {
         Self {
             entries: HashMap::new(),
+            force_smoothing: 0.0,
         }
     }
 
+    /// The smoothing factor of the forces transmitted to the coupled bodies.
+    pub fn force_smoothing(&self) -> f32 {
+        self.force_smoothing
+    }
+
+    /// Sets the smoothing factor, in `[0, 1)`, of the forces transmitted to the coupled bodies.
+    ///
+    /// With a non-zero `factor`, the force and torque applied to a body by each coupled collider are
+    /// an exponential moving average of the force and torque exerted by the fluids at each substep:
+    /// `smoothed = factor * smoothed + (1 - factor) * raw`. The sum of the forces is then applied at
+    /// the center of mass of the body, together with the sum of their torques. This damps the noise
+    /// of the per-particle pressure forces, e.g., the jitter of floating objects, at the cost of a
+    /// delay of about `factor / (1 - factor)` substeps in the response of the bodies, so factors
+    /// around 0.5 are usually enough. The default value is 0, i.e., the raw forces are applied at
+    /// the boundary particles.
+    pub fn set_force_smoothing(&mut self, factor: f32) {
+        assert!(
+            (0.0..1.0).contains(&factor),
+            "The force smoothing factor must be in [0, 1)."
+        );
+        self.force_smoothing = factor;
+    }
+
     /// Register a coupling between a boundary and a collider.
     /// There can be only up to one coupling between a collider and a boundary object. If a coupling
     /// already exists for this collider when calling this function, the handle of the previously coupled
@@ -107,6 +134,7 @@ impl ColliderCouplingSet {
                 sampling_method,
                 boundary,
                 features: Vec::new(),
+                smoothed_force: None,
             },
         );
 
@@ -174,6 +202,7 @@ impl<'a> CouplingManager for ColliderCouplingManager<'a> {
                 boundary.positions.clear();
                 boundary.velocities.clear();
                 boundary.volumes.clear();
+                boundary.normals = None;
                 coupling.features.clear();
 
                 match &coupling.sampling_method {
@@ -261,12 +290,15 @@ impl<'a> CouplingManager for ColliderCouplingManager<'a> {
     }
 
     fn transmit_forces(&mut self, timestep: &TimestepManager, boundaries: &BoundarySet) {
-        for (collider, coupling) in &self.coupling.entries {
+        let smoothing = self.coupling.force_smoothing;
+
+        for (collider, coupling) in &mut self.coupling.entries {
             if let (Some(collider), Some(boundary)) = (
                 self.colliders.get(*collider),
                 boundaries.get(coupling.boundary),
             ) {
-                if boundary.positions.is_empty() {
+                // NOTE: the smoothed forces keep being applied, and decay, while the boundary has no particle.
+                if boundary.positions.is_empty() && coupling.smoothed_force.is_none() {
                     continue;
                 }
 
@@ -274,11 +306,43 @@ impl<'a> CouplingManager for ColliderCouplingManager<'a> {
                     let forces = forces.read().unwrap();
                     if let Some(parent) = collider.parent() {
                         if let Some(body) = self.bodies.get_mut(parent) {
-                            for (pos, force) in
-                                boundary.positions.iter().zip(forces.iter().cloned())
-                            {
-                                body.apply_impulse_at_point(force * timestep.dt(), *pos, true)
+                            if smoothing == 0.0 {
+                                coupling.smoothed_force = None;
+
+                                for (pos, force) in
+                                    boundary.positions.iter().zip(forces.iter().cloned())
+                                {
+                                    body.apply_impulse_at_point(force * timestep.dt(), *pos, true)
+                                }
+
+                                continue;
+                            }
+
+                            let center_of_mass = *body.center_of_mass();
+                            let mut force = Vector::zeros();
+                            let mut torque = na::zero::<AngVector<f32>>();
+
+                            for (pos, f) in boundary.positions.iter().zip(forces.iter()) {
+                                force += f;
+                                #[cfg(feature = "dim2")]
+                                {
+                                    torque += (pos - center_of_mass).perp(f);
+                                }
+                                #[cfg(feature = "dim3")]
+                                {
+                                    torque += (pos - center_of_mass).cross(f);
+                                }
                             }
+
+                            let (smoothed_force, smoothed_torque) =
+                                coupling.smoothed_force.get_or_insert((force, torque));
+                            *smoothed_force =
+                                *smoothed_force * smoothing + force * (1.0 - smoothing);
+                            *smoothed_torque =
+                                *smoothed_torque * smoothing + torque * (1.0 - smoothing);
+
+                            body.apply_impulse(*smoothed_force * timestep.dt(), true);
+                            body.apply_torque_impulse(*smoothed_torque * timestep.dt(), true);
                         }
                     }
                 }
@@ -286,3 +350,235 @@ impl<'a> CouplingManager for ColliderCouplingManager<'a> {
         }
     }
 }
+
+#[cfg(feature = "dim3")]
+#[test]
+fn pressure_boundaries_improve_the_draft_of_a_floating_block() {
+    use crate::object::Boundary;
+    use crate::solver::BoundaryMethod;
+    use rapier::prelude::*;
+
+    let particle_radius = 0.05;
+    let spacing = particle_radius * 2.0;
+    let (width, depth) = (14, 7);
+    let water_level = depth as f32 * spacing;
+    let center = width as f32 * spacing / 2.0;
+    // A block with half the density of the water floats with half of its height submerged.
+    let half = 0.2;
+    let expected_draft = half;
+
+    let draft = |method: BoundaryMethod| {
+        let mut pipeline = FluidsPipeline::new(particle_radius, 2.0);
+        pipeline.liquid_world.set_boundary_method(method);
+        let mut bodies = RigidBodySet::new();
+        let mut colliders = ColliderSet::new();
+        let body = bodies.insert(
+            RigidBodyBuilder::dynamic()
+                .translation(vector![center, water_level, center])
+                .lock_rotations()
+                .linear_damping(2.0),
+        );
+        let collider = colliders.insert_with_parent(
+            ColliderBuilder::cuboid(half, half, half).density(500.0),
+            body,
+            &mut bodies,
+        );
+        let samples =
+            Boundary::cuboid(&Point::origin(), &Vector::repeat(half), particle_radius, 1).positions;
+        let boundary = pipeline
+            .liquid_world
+            .add_boundary(Boundary::new(Vec::new()));
+        let _ = pipeline.coupling.register_coupling(
+            boundary,
+            collider,
+            ColliderSampling::StaticSampling(samples),
+        );
+        let tank_half_extents = vector![center, 1.0, center];
+        let _ = pipeline.liquid_world.add_boundary(Boundary::cuboid(
+            &Point::from(tank_half_extents),
+            &tank_half_extents.add_scalar(particle_radius),
+            particle_radius,
+            2,
+        ));
+
+        // The water, around and below the block.
+        let mut positions = Vec::new();
+        for i in 0..width {
+            for j in 0..=depth {
+                for k in 0..width {
+                    let p = point![i as f32 + 0.5, j as f32 + 0.5, k as f32 + 0.5] * spacing;
+                    if (p.x - center).abs() > half + spacing
+                        || (p.z - center).abs() > half + spacing
+                        || p.y < water_level - half * 2.0
+                    {
+                        positions.push(p);
+                    }
+                }
+            }
+        }
+        let _ = pipeline
+            .liquid_world
+            .add_fluid(Fluid::new(positions, particle_radius, 1000.0));
+
+        let mut physics = PhysicsPipeline::new();
+        let mut islands = IslandManager::new();
+        let mut broad_phase = DefaultBroadPhase::new();
+        let mut narrow_phase = NarrowPhase::new();
+        let mut impulse_joints = ImpulseJointSet::new();
+        let mut multibody_joints = MultibodyJointSet::new();
+        let mut ccd_solver = CCDSolver::new();
+        let gravity = vector![0.0, -9.81, 0.0];
+        let dt = 0.01;
+        let params = IntegrationParameters {
+            dt,
+            ..IntegrationParameters::default()
+        };
+        let mut mean_draft = 0.0;
+
+        for step in 0..400 {
+            pipeline.step(&gravity, dt, &colliders, &mut bodies);
+            physics.step(
+                &gravity,
+                &params,
+                &mut islands,
+                &mut broad_phase,
+                &mut narrow_phase,
+                &mut bodies,
+                &mut colliders,
+                &mut impulse_joints,
+                &mut multibody_joints,
+                &mut ccd_solver,
+                None,
+                &(),
+                &(),
+            );
+
+            // Average the draft once the block oscillates around its equilibrium.
+            if step >= 300 {
+                let fluid = &pipeline.liquid_world.fluids().as_slice()[0];
+                let level = fluid
+                    .positions
+                    .iter()
+                    .filter(|p| {
+                        (p.x - center).abs() > half * 2.0 || (p.z - center).abs() > half * 2.0
+                    })
+                    .map(|p| p.y)
+                    .fold(0.0, f32::max)
+                    + particle_radius;
+                mean_draft += (level - bodies[body].translation().y + half) / 100.0;
+            }
+        }
+
+        mean_draft
+    };
+
+    let akinci_error = (draft(BoundaryMethod::Akinci) - expected_draft).abs();
+    let pressure_boundaries_error =
+        (draft(BoundaryMethod::PressureBoundaries) - expected_draft).abs();
+    assert!(pressure_boundaries_error < akinci_error);
+}
+
+// A dynamic ball coupled to a boundary with two particles, the forces of which are set by the
+// caller before transmitting them.
+#[cfg(test)]
+fn coupled_ball() -> (
+    ColliderCouplingSet,
+    ColliderSet,
+    RigidBodySet,
+    BoundarySet,
+    rapier::dynamics::RigidBodyHandle,
+) {
+    use crate::object::Boundary;
+    use rapier::prelude::{ColliderBuilder, RigidBodyBuilder};
+
+    let mut bodies = RigidBodySet::new();
+    let mut colliders = ColliderSet::new();
+    let body = bodies.insert(RigidBodyBuilder::dynamic().translation(Vector::x()));
+    let collider = colliders.insert_with_parent(ColliderBuilder::ball(0.5), body, &mut bodies);
+
+    let mut boundaries = BoundarySet::new();
+    let mut boundary = Boundary::new(vec![
+        Point::from(Vector::x() * 1.5),
+        Point::from(Vector::x() * 0.5),
+    ]);
+    boundary.forces = Some(RwLock::new(Vec::new()));
+    boundary.clear_forces(true);
+    let boundary = boundaries.insert(boundary);
+
+    let mut coupling = ColliderCouplingSet::new();
+    let _ = coupling.register_coupling(
+        boundary,
+        collider,
+        ColliderSampling::StaticSampling(Vec::new()),
+    );
+
+    (coupling, colliders, bodies, boundaries, body)
+}
+
+#[test]
+fn unsmoothed_forces_are_applied_at_the_boundary_particles() {
+    let (mut coupling, colliders, mut bodies, boundaries, body) = coupled_ball();
+    let mut expected = bodies[body].clone();
+    let timestep = TimestepManager::new(0.05).with_dt(0.01);
+    let forces = [Vector::y() * 3.0, -Vector::x() - Vector::y()];
+    let boundary = boundaries.iter().next().unwrap().1;
+    boundary
+        .forces
+        .as_ref()
+        .unwrap()
+        .write()
+        .unwrap()
+        .copy_from_slice(&forces);
+
+    coupling
+        .as_manager_mut(&colliders, &mut bodies)
+        .transmit_forces(&timestep, &boundaries);
+
+    // The same impulses as the ones applied before the smoothing was introduced.
+    for (pos, force) in boundary.positions.iter().zip(forces) {
+        expected.apply_impulse_at_point(force * timestep.dt(), *pos, true);
+    }
+
+    assert_eq!(bodies[body].linvel(), expected.linvel());
+    assert_eq!(bodies[body].angvel(), expected.angvel());
+}
+
+#[test]
+fn smoothed_forces_damp_a_step_change() {
+    let (mut coupling, colliders, mut bodies, boundaries, body) = coupled_ball();
+    coupling.set_force_smoothing(0.5);
+    let timestep = TimestepManager::new(0.05).with_dt(0.01);
+    let mass = bodies[body].mass();
+    let boundary = boundaries.iter().next().unwrap().1;
+
+    // The force applied to the body at each substep, once the force exerted by the fluid jumps from
+    // `Vector::x()` to `Vector::y()` after the first substep.
+    let mut transmitted = Vec::new();
+
+    for force in [Vector::x(), Vector::y(), Vector::y(), Vector::y()] {
+        let forces = [force * 0.5, force * 0.5];
+        boundary
+            .forces
+            .as_ref()
+            .unwrap()
+            .write()
+            .unwrap()
+            .copy_from_slice(&forces);
+        bodies[body].set_linvel(Vector::zeros(), true);
+        coupling
+            .as_manager_mut(&colliders, &mut bodies)
+            .transmit_forces(&timestep, &boundaries);
+        transmitted.push(bodies[body].linvel() * (mass / timestep.dt()));
+    }
+
+    let expected = [
+        Vector::x(),
+        (Vector::x() + Vector::y()) / 2.0,
+        (Vector::x() + Vector::y() * 3.0) / 4.0,
+        (Vector::x() + Vector::y() * 7.0) / 8.0,
+    ];
+
+    for (transmitted, expected) in transmitted.iter().zip(expected) {
+        assert!((transmitted - expected).norm() < 1.0e-5);
+    }
+}
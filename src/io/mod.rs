@@ -0,0 +1,4 @@
+//! Import and export of fluid particles in the file formats of other tools.
+
+#[cfg(feature = "partio")]
+pub mod partio;
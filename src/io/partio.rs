@@ -0,0 +1,324 @@
+//! Reading and writing particles in the uncompressed BGEO format of Houdini, as supported by Partio.
+//!
+//! The positions are stored in the implicit `P` attribute, the velocities in the `v` vector
+//! attribute, and the densities in the `density` float attribute, which are the names Houdini uses
+//! for these quantities. The files are always three-dimensional: in 2D, the third coordinate of the
+//! positions and velocities is written as zero and ignored when reading. Compressed files, e.g.,
+//! `.bgeo.gz`, must be decompressed by the caller.
+
+use std::io::{self, Read, Write};
+
+use crate::math::{Point, Real, Vector, DIM};
+use crate::object::Fluid;
+
+const MAGIC: &[u8; 4] = b"Bgeo";
+const VERSION: i32 = 5;
+const FLOAT_ATTRIBUTE: i32 = 0;
+const INT_ATTRIBUTE: i32 = 1;
+const INDEX_ATTRIBUTE: i32 = 4;
+const VECTOR_ATTRIBUTE: i32 = 5;
+
+/// The attributes of a set of particles read from, or written to, a BGEO file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartioParticles {
+    /// The positions of the particles.
+    pub positions: Vec<Point<Real>>,
+    /// The velocities of the particles, if any.
+    pub velocities: Option<Vec<Vector<Real>>>,
+    /// The densities of the particles, if any.
+    pub densities: Option<Vec<Real>>,
+}
+
+impl PartioParticles {
+    /// Collects the positions and velocities of all the particles of `fluid`, and the given densities,
+    /// e.g., `LiquidWorld::fluid_densities`.
+    ///
+    /// The particles waiting to be removed at the next timestep are included, so the particles keep
+    /// their indices.
+    pub fn from_fluid(fluid: &Fluid, densities: Option<&[Real]>) -> Self {
+        if let Some(densities) = densities {
+            assert_eq!(
+                densities.len(),
+                fluid.num_particles(),
+                "There must be one density per particle."
+            );
+        }
+
+        Self {
+            positions: fluid.positions.clone(),
+            velocities: Some(fluid.velocities.clone()),
+            densities: densities.map(|densities| densities.to_vec()),
+        }
+    }
+
+    /// Initializes a fluid with these particles, their velocities, and the given particle radius and rest density.
+    ///
+    /// The densities are not used, as they are recomputed by the pressure solvers.
+    pub fn to_fluid(&self, particle_radius: Real, density: Real) -> Fluid {
+        let mut fluid = Fluid::new(self.positions.clone(), particle_radius, density);
+
+        if let Some(velocities) = &self.velocities {
+            fluid.velocities.clone_from(velocities);
+        }
+
+        fluid
+    }
+}
+
+/// Writes `particles` to `writer` in the BGEO format.
+///
+/// Consider wrapping `writer` into a `std::io::BufWriter` when writing to a file.
+pub fn write_bgeo<W: Write>(mut writer: W, particles: &PartioParticles) -> io::Result<()> {
+    let num_particles = particles.positions.len();
+    let velocities = particles.velocities.as_deref();
+    let densities = particles.densities.as_deref();
+
+    for len in [velocities.map(<[_]>::len), densities.map(<[_]>::len)]
+        .into_iter()
+        .flatten()
+    {
+        if len != num_particles {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "There must be one value of each attribute per particle.",
+            ));
+        }
+    }
+
+    let num_particles = i32::try_from(num_particles)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Too many particles."))?;
+    let num_attributes = velocities.is_some() as i32 + densities.is_some() as i32;
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(b"V")?;
+    // The version, the numbers of points, primitives, point groups, primitive groups, point
+    // attributes, vertex attributes, primitive attributes, and detail attributes.
+    for value in [VERSION, num_particles, 0, 0, 0, num_attributes, 0, 0, 0] {
+        writer.write_all(&value.to_be_bytes())?;
+    }
+
+    if velocities.is_some() {
+        write_attribute_definition(&mut writer, "v", 3, VECTOR_ATTRIBUTE)?;
+    }
+
+    if densities.is_some() {
+        write_attribute_definition(&mut writer, "density", 1, FLOAT_ATTRIBUTE)?;
+    }
+
+    for (i, position) in particles.positions.iter().enumerate() {
+        // The positions are homogeneous coordinates.
+        write_vector(&mut writer, &position.coords)?;
+        writer.write_all(&1.0f32.to_be_bytes())?;
+
+        if let Some(velocities) = velocities {
+            write_vector(&mut writer, &velocities[i])?;
+        }
+
+        if let Some(densities) = densities {
+            write_float(&mut writer, densities[i])?;
+        }
+    }
+
+    // The empty extra section closing the file.
+    writer.write_all(&[0x00, 0xff])
+}
+
+/// Reads particles in the BGEO format from `reader`.
+///
+/// The `v` attribute is read as the velocities if it has three components, and the `density`
+/// attribute as the densities if it has one float component. The other attributes, primitives, and
+/// groups are ignored.
+pub fn read_bgeo<R: Read>(mut reader: R) -> io::Result<PartioParticles> {
+    let mut magic = [0; 5];
+    reader.read_exact(&mut magic)?;
+
+    if &magic[..4] != MAGIC || magic[4] != b'V' {
+        return Err(invalid_data("This is not an uncompressed BGEO file."));
+    }
+
+    let mut header = [0; 9];
+
+    for value in &mut header {
+        *value = read_i32(&mut reader)?;
+    }
+
+    let [version, num_particles, _, _, _, num_attributes, ..] = header;
+
+    if version != VERSION {
+        return Err(invalid_data("Unsupported BGEO version."));
+    }
+
+    let num_particles =
+        usize::try_from(num_particles).map_err(|_| invalid_data("Invalid number of points."))?;
+    let num_attributes = usize::try_from(num_attributes)
+        .map_err(|_| invalid_data("Invalid number of attributes."))?;
+    // The name, number of components, and type of each point attribute.
+    let mut attributes = Vec::new();
+
+    for _ in 0..num_attributes {
+        let name = read_string(&mut reader)?;
+        let size = read_u16(&mut reader)? as usize;
+        let kind = read_i32(&mut reader)?;
+
+        // Skip the default values.
+        match kind {
+            FLOAT_ATTRIBUTE | INT_ATTRIBUTE | VECTOR_ATTRIBUTE => {
+                for _ in 0..size {
+                    let _ = read_i32(&mut reader)?;
+                }
+            }
+            INDEX_ATTRIBUTE => {
+                for _ in 0..read_i32(&mut reader)? {
+                    let _ = read_string(&mut reader)?;
+                }
+            }
+            _ => return Err(invalid_data("Unsupported BGEO attribute type.")),
+        }
+
+        attributes.push((name, size, kind));
+    }
+
+    let is_velocity = |(name, size, kind): &(String, usize, i32)| {
+        name == "v" && *size == 3 && (*kind == VECTOR_ATTRIBUTE || *kind == FLOAT_ATTRIBUTE)
+    };
+    let is_density = |(name, size, kind): &(String, usize, i32)| {
+        name == "density" && *size == 1 && *kind == FLOAT_ATTRIBUTE
+    };
+
+    let mut positions = Vec::new();
+    let mut velocities = attributes.iter().any(is_velocity).then(Vec::new);
+    let mut densities = attributes.iter().any(is_density).then(Vec::new);
+
+    for _ in 0..num_particles {
+        positions.push(Point::from(read_vector(&mut reader)?));
+        // Skip the homogeneous coordinate.
+        let _ = read_float(&mut reader)?;
+
+        for attribute in &attributes {
+            if is_velocity(attribute) {
+                if let Some(velocities) = &mut velocities {
+                    velocities.push(read_vector(&mut reader)?);
+                }
+            } else if is_density(attribute) {
+                if let Some(densities) = &mut densities {
+                    densities.push(read_float(&mut reader)?);
+                }
+            } else {
+                for _ in 0..attribute.1 {
+                    let _ = read_i32(&mut reader)?;
+                }
+            }
+        }
+    }
+
+    Ok(PartioParticles {
+        positions,
+        velocities,
+        densities,
+    })
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_attribute_definition<W: Write>(
+    writer: &mut W,
+    name: &str,
+    size: u16,
+    kind: i32,
+) -> io::Result<()> {
+    writer.write_all(&(name.len() as u16).to_be_bytes())?;
+    writer.write_all(name.as_bytes())?;
+    writer.write_all(&size.to_be_bytes())?;
+    writer.write_all(&kind.to_be_bytes())?;
+
+    // The default values.
+    for _ in 0..size {
+        writer.write_all(&0.0f32.to_be_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn write_float<W: Write>(writer: &mut W, value: Real) -> io::Result<()> {
+    writer.write_all(&value.to_be_bytes())
+}
+
+// Writes the three components of `vector`, padded with zeros in 2D.
+fn write_vector<W: Write>(writer: &mut W, vector: &Vector<Real>) -> io::Result<()> {
+    for i in 0..3 {
+        write_float(writer, if i < DIM { vector[i] } else { 0.0 })?;
+    }
+
+    Ok(())
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> io::Result<u16> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_be_bytes(bytes))
+}
+
+fn read_i32<R: Read>(reader: &mut R) -> io::Result<i32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(i32::from_be_bytes(bytes))
+}
+
+fn read_float<R: Read>(reader: &mut R) -> io::Result<Real> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(Real::from_be_bytes(bytes))
+}
+
+fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+    let mut bytes = vec![0; read_u16(reader)? as usize];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|_| invalid_data("Invalid BGEO attribute name."))
+}
+
+// Reads three components, dropping the third one in 2D.
+fn read_vector<R: Read>(reader: &mut R) -> io::Result<Vector<Real>> {
+    let mut vector = Vector::zeros();
+
+    for i in 0..3 {
+        let value = read_float(reader)?;
+
+        if i < DIM {
+            vector[i] = value;
+        }
+    }
+
+    Ok(vector)
+}
+
+#[test]
+fn bgeo_round_trip() {
+    let positions: Vec<_> = (0..10)
+        .map(|i| Point::from(Vector::repeat(i as Real * 0.1)))
+        .collect();
+    let mut fluid = Fluid::new(positions, 0.05, 1000.0);
+    fluid.set_velocity_field(|p| Vector::x() * p.y - Vector::y());
+    let densities: Vec<_> = (0..10).map(|i| 990.0 + i as Real).collect();
+    let particles = PartioParticles::from_fluid(&fluid, Some(&densities));
+
+    let mut bytes = Vec::new();
+    write_bgeo(&mut bytes, &particles).unwrap();
+    assert_eq!(&bytes[..4], b"Bgeo");
+    assert_eq!(read_bgeo(&bytes[..]).unwrap(), particles);
+
+    // The attributes are optional.
+    let positions_only = PartioParticles {
+        velocities: None,
+        densities: None,
+        ..particles.clone()
+    };
+    bytes.clear();
+    write_bgeo(&mut bytes, &positions_only).unwrap();
+    assert_eq!(read_bgeo(&bytes[..]).unwrap(), positions_only);
+
+    let fluid = particles.to_fluid(0.05, 1000.0);
+    assert_eq!(fluid.num_particles(), 10);
+    assert_eq!(Some(&fluid.velocities), particles.velocities.as_ref());
+}
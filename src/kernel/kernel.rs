@@ -37,4 +37,29 @@ pub trait Kernel: Send + Sync {
     fn points_apply_diff2(p1: &Point<Real>, p2: &Point<Real>, h: Real) -> Vector<Real> {
         -Self::apply_diff(p1 - p2, h)
     }
+
+    /// Evaluate the kernel for the vector equal to `p1 - p2`, with a support stretched along each axis by `anisotropy`.
+    ///
+    /// The support of the kernel is the ellipsoid with the semi-axes `h * anisotropy`, and the kernel
+    /// is scaled so that it still integrates to one. This is equal to `Self::points_apply(p1, p2, h)`
+    /// if all the components of `anisotropy` are equal to one.
+    fn points_apply_anisotropic(
+        p1: &Point<Real>,
+        p2: &Point<Real>,
+        h: Real,
+        anisotropy: &Vector<Real>,
+    ) -> Real {
+        Self::apply((p1 - p2).component_div(anisotropy), h) / anisotropy.product()
+    }
+
+    /// Differential of `Self::points_apply_anisotropic` wrt. the coordinates of `p1`.
+    fn points_apply_anisotropic_diff1(
+        p1: &Point<Real>,
+        p2: &Point<Real>,
+        h: Real,
+        anisotropy: &Vector<Real>,
+    ) -> Vector<Real> {
+        Self::apply_diff((p1 - p2).component_div(anisotropy), h).component_div(anisotropy)
+            / anisotropy.product()
+    }
 }
@@ -0,0 +1,77 @@
+// Free functions evaluating the smoothing kernels.
+//
+// Each function forwards to the implementation of `Kernel` used by the solvers, so the values
+// and gradients are exactly those the solvers compute, normalized for the active dimension.
+
+use crate::kernel::{CubicSplineKernel, Kernel, Poly6Kernel, SpikyKernel, ViscosityKernel};
+use crate::math::{Real, Vector};
+
+/// The Poly6 kernel at the distance `r` with the support length `h`, see `Poly6Kernel`.
+pub fn poly6(r: Real, h: Real) -> Real {
+    Poly6Kernel::scalar_apply(r, h)
+}
+
+/// The gradient of the Poly6 kernel wrt. the offset `r` with the support length `h`.
+pub fn poly6_gradient(r: Vector<Real>, h: Real) -> Vector<Real> {
+    Poly6Kernel::apply_diff(r, h)
+}
+
+/// The Spiky kernel at the distance `r` with the support length `h`, see `SpikyKernel`.
+pub fn spiky(r: Real, h: Real) -> Real {
+    SpikyKernel::scalar_apply(r, h)
+}
+
+/// The gradient of the Spiky kernel wrt. the offset `r` with the support length `h`.
+pub fn spiky_gradient(r: Vector<Real>, h: Real) -> Vector<Real> {
+    SpikyKernel::apply_diff(r, h)
+}
+
+/// The cubic spline kernel at the distance `r` with the support length `h`, see `CubicSplineKernel`.
+pub fn cubic_spline(r: Real, h: Real) -> Real {
+    CubicSplineKernel::scalar_apply(r, h)
+}
+
+/// The gradient of the cubic spline kernel wrt. the offset `r` with the support length `h`.
+pub fn cubic_spline_gradient(r: Vector<Real>, h: Real) -> Vector<Real> {
+    CubicSplineKernel::apply_diff(r, h)
+}
+
+/// The Viscosity kernel at the distance `r` with the support length `h`, see `ViscosityKernel`.
+pub fn viscosity(r: Real, h: Real) -> Real {
+    ViscosityKernel::scalar_apply(r, h)
+}
+
+/// The gradient of the Viscosity kernel wrt. the offset `r` with the support length `h`.
+pub fn viscosity_gradient(r: Vector<Real>, h: Real) -> Vector<Real> {
+    ViscosityKernel::apply_diff(r, h)
+}
+
+#[test]
+fn kernels_are_normalized_for_the_active_dimension() {
+    use crate::math::DIM;
+
+    let h = 1.0;
+    let resolution = 100;
+    let cell = 2.0 * h / resolution as Real;
+    let num_cells = (resolution as usize).pow(DIM as u32);
+
+    for kernel in [poly6, spiky, cubic_spline] {
+        let integral: Real = (0..num_cells)
+            .map(|i| {
+                let r = Vector::from_fn(|axis, _| {
+                    let k = (i / (resolution as usize).pow(axis as u32)) % resolution as usize;
+                    (k as Real + 0.5) * cell - h
+                });
+                kernel(r.norm(), h) * cell.powi(DIM as i32)
+            })
+            .sum();
+        assert!((integral - 1.0).abs() < 1.0e-2, "{}", integral);
+    }
+
+    let r = Vector::x() * 0.3;
+    assert_eq!(
+        cubic_spline_gradient(r, h),
+        CubicSplineKernel::apply_diff(r, h)
+    );
+    assert_eq!(spiky_gradient(-r, h), -spiky_gradient(r, h));
+}
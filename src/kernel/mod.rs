@@ -2,12 +2,17 @@
 
 pub use self::cubic_spline_kernel::CubicSplineKernel;
 pub use self::kernel::Kernel;
+pub use self::kernel_functions::{
+    cubic_spline, cubic_spline_gradient, poly6, poly6_gradient, spiky, spiky_gradient, viscosity,
+    viscosity_gradient,
+};
 pub use self::poly6_kernel::Poly6Kernel;
 pub use self::spiky_kernel::SpikyKernel;
 pub use self::viscosity_kernel::ViscosityKernel;
 
 mod cubic_spline_kernel;
 mod kernel;
+mod kernel_functions;
 mod poly6_kernel;
 mod spiky_kernel;
 mod viscosity_kernel;
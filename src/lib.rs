@@ -86,21 +86,58 @@ macro_rules! par_reduce_sum {
     }};
 }
 
+// Computes both the sum and the maximum of the elements of `$t`.
+macro_rules! par_reduce_sum_max {
+    ($identity: expr, $t: expr) => {{
+        #[cfg(not(feature = "parallel"))]
+        let res = $t.fold(($identity, $identity), |(sum, max), b| {
+            (sum + b, max.max(b))
+        });
+        #[cfg(feature = "parallel")]
+        let res = $t.map(|b| (b, b)).reduce(
+            || ($identity, $identity),
+            |(sum1, max1), (sum2, max2)| (sum1 + sum2, max1.max(max2)),
+        );
+        res
+    }};
+}
+
+mod attachment;
+mod config_error;
 pub mod counters;
 pub mod coupling;
+mod diffuse_particles;
+#[cfg(feature = "spectrum")]
+mod energy_spectrum;
 pub mod geometry;
 pub mod helper;
 pub mod integrations;
+pub mod io;
 pub mod kernel;
 mod liquid_world;
+mod motion_tracking;
 pub mod object;
+mod probe;
+mod resampling;
 #[cfg(feature = "sampling")]
 pub mod sampling;
+mod sleeping;
 pub mod solver;
+mod stability_guard;
+pub mod testbed;
+mod time_varying_gravity;
 mod timestep_manager;
 pub(crate) mod z_order;
 
+pub use crate::attachment::AttachmentHandle;
+pub use crate::config_error::ConfigError;
+pub use crate::diffuse_particles::{DiffuseParticleKind, DiffuseParticles};
 pub use crate::liquid_world::LiquidWorld;
+pub use crate::probe::{Probe, ProbeHandle, ProbeMeasurement, ProbeSet};
+pub use crate::resampling::ResamplingParameters;
+pub use crate::sleeping::SleepParameters;
+pub use crate::stability_guard::{StabilityGuard, StepError};
+pub use crate::time_varying_gravity::TimeVaryingGravity;
 pub use crate::timestep_manager::TimestepManager;
 
 /// Compilation flags dependent aliases for mathematical types.
@@ -108,7 +145,7 @@ pub use crate::timestep_manager::TimestepManager;
 pub mod math {
     use na::{
         Isometry3, Matrix3, Matrix6, Matrix6xX, MatrixView6xX, MatrixViewMut6xX, Point3, Rotation3,
-        Translation3, UnitQuaternion, Vector3, Vector6, U3, U6,
+        Translation3, Unit, UnitQuaternion, Vector3, Vector6, U3, U6,
     };
 
     /// The maximum number of possible rotations and translations of a rigid body.
@@ -182,6 +219,33 @@ pub mod math {
     pub fn gcross_matrix(v: &Vector<Real>) -> Matrix<Real> {
         v.cross_matrix()
     }
+
+    /// The index of the vertical axis, pointing upward.
+    pub const UP_AXIS: usize = 1;
+
+    /// The unit vector pointing upward, i.e., along the `y` axis.
+    pub fn up() -> Unit<Vector<Real>> {
+        Vector::y_axis()
+    }
+
+    /// The gravity vector with the given magnitude, pointing downward, i.e., along the `-y` axis.
+    pub fn down_gravity(magnitude: Real) -> Vector<Real> {
+        Vector::y() * -magnitude
+    }
+
+    /// Creates a vector from its components.
+    ///
+    /// This is the same as `Vector::new(x, y, z)`. In 2D, `z` is ignored.
+    pub fn vector_xyz(x: Real, y: Real, z: Real) -> Vector<Real> {
+        Vector::new(x, y, z)
+    }
+
+    /// Creates a point from its coordinates.
+    ///
+    /// This is the same as `Point::new(x, y, z)`. In 2D, `z` is ignored.
+    pub fn point_xyz(x: Real, y: Real, z: Real) -> Point<Real> {
+        Point::new(x, y, z)
+    }
 }
 
 /// Compilation flags dependent aliases for mathematical types.
@@ -189,7 +253,8 @@ pub mod math {
 pub mod math {
     use na::{
         Isometry2, Matrix1, Matrix2, Matrix3, Matrix6xX, MatrixView3xX, MatrixViewMut3xX, Point2,
-        Rotation2, RowVector2, Translation2, UnitComplex, Vector1, Vector2, Vector3, U1, U2, U3,
+        Rotation2, RowVector2, Translation2, Unit, UnitComplex, Vector1, Vector2, Vector3, U1, U2,
+        U3,
     };
 
     /// The maximum number of possible rotations and translations of a rigid body.
@@ -263,4 +328,31 @@ pub mod math {
     pub fn gcross_matrix(v: &Vector<Real>) -> RowVector2<Real> {
         RowVector2::new(-v.y, v.x)
     }
+
+    /// The index of the vertical axis, pointing upward.
+    pub const UP_AXIS: usize = 1;
+
+    /// The unit vector pointing upward, i.e., along the `y` axis.
+    pub fn up() -> Unit<Vector<Real>> {
+        Vector::y_axis()
+    }
+
+    /// The gravity vector with the given magnitude, pointing downward, i.e., along the `-y` axis.
+    pub fn down_gravity(magnitude: Real) -> Vector<Real> {
+        Vector::y() * -magnitude
+    }
+
+    /// Creates a vector from its components.
+    ///
+    /// This is the same as `Vector::new(x, y)`. In 3D, `z` is used as the third component.
+    pub fn vector_xyz(x: Real, y: Real, _z: Real) -> Vector<Real> {
+        Vector::new(x, y)
+    }
+
+    /// Creates a point from its coordinates.
+    ///
+    /// This is the same as `Point::new(x, y)`. In 3D, `z` is used as the third coordinate.
+    pub fn point_xyz(x: Real, y: Real, _z: Real) -> Point<Real> {
+        Point::new(x, y)
+    }
 }
@@ -1,11 +1,30 @@
+use crate::attachment::{AttachmentTracker, ParticleAttachment, SharedAttachments};
 use crate::counters::Counters;
 use crate::coupling::CouplingManager;
-use crate::geometry::{self, ContactManager, HGrid, HGridEntry};
-use crate::math::{Real, Vector};
-use crate::object::{Boundary, BoundaryHandle, BoundarySet};
+use crate::geometry::{self, ActiveRegion, ContactManager, HGrid, HGridEntry, SurfaceHeightfield};
+use crate::kernel::{CubicSplineKernel, Kernel};
+use crate::math::{AngularVector, Matrix, Point, Real, Vector};
+use crate::motion_tracking::{ReferencePositionsTracker, SharedReferencePositions};
+use crate::object::{AnalyticCollider, Boundary, BoundaryHandle, BoundarySet};
+use crate::object::{ContiguousArenaIndex, GhostBoundary, ReflectivePlane};
 use crate::object::{Fluid, FluidHandle, FluidSet};
-use crate::solver::PressureSolver;
-use crate::TimestepManager;
+use crate::sleeping::{ParticleSleepState, SharedSleepStates, SleepStatesTracker};
+use crate::solver::{
+    BoundaryMethod, IncompressibilityTolerance, IterationCallback, PressureSolver, SolveStats,
+    SolverState, SolverStateError,
+};
+use crate::{
+    AttachmentHandle, ConfigError, ResamplingParameters, SleepParameters, StabilityGuard,
+    StepError, TimeVaryingGravity, TimestepManager,
+};
+use crate::{Probe, ProbeHandle, ProbeSet};
+use fnv::FnvHasher;
+use na::Unit;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::io::{self, Write};
+use std::sync::RwLock;
 #[cfg(feature = "parry")]
 use {
     crate::math::Isometry,
@@ -13,6 +32,35 @@ use {
     parry::{bounding_volume::Aabb, query::PointQuery, shape::Shape},
 };
 
+// The default speed of sound of the equation of state used to estimate the particle pressures, in m/s.
+const DEFAULT_SPEED_OF_SOUND: f64 = 10.0;
+// The exponent of the Tait equation of state, the usual value for water.
+const TAIT_EXPONENT: f64 = 7.0;
+
+// The index, position, and velocity of the frozen particles of each fluid.
+type FrozenParticles = Vec<Vec<(usize, Point<Real>, Vector<Real>)>>;
+
+// The handle, number of particles, and permutation generation of a fluid inserted into the grid.
+type FluidGridLayout = (FluidHandle, usize, u64);
+
+// The positions, velocities, and accelerations of the particles of one fluid, and the states of
+// its non-pressure forces.
+type FluidCheckpoint = (
+    Vec<Point<Real>>,
+    Vec<Vector<Real>>,
+    Vec<Vector<Real>>,
+    Vec<Vec<Real>>,
+);
+
+// The simulation state at the beginning of a substep, restored if the substep has to be retried.
+struct SubstepCheckpoint {
+    timestep_manager: TimestepManager,
+    solver_state: Option<SolverState>,
+    fluid_solver_states: Vec<(FluidHandle, SolverState)>,
+    fluids: Vec<FluidCheckpoint>,
+    boundary_forces: Vec<Option<Vec<Vector<Real>>>>,
+}
+
 /// The physics world for simulating fluids with boundaries.
 pub struct LiquidWorld {
     /// Performance counters of the whole fluid simulation engine.
@@ -23,12 +71,88 @@ pub struct LiquidWorld {
     fluids: FluidSet,
     boundaries: BoundarySet,
     solver: Box<dyn PressureSolver + Send + Sync>,
+    fluid_solvers: HashMap<FluidHandle, Box<dyn PressureSolver + Send + Sync>>,
     contact_manager: ContactManager,
     timestep_manager: TimestepManager,
     hgrid: HGrid<HGridEntry>,
+    hgrid_fluids: Vec<FluidGridLayout>,
+    motion_threshold: Option<Real>,
+    reference_positions: HashMap<FluidHandle, SharedReferencePositions>,
+    previous_positions: HashMap<FluidHandle, Vec<Point<Real>>>,
+    moved_particles: Vec<(FluidHandle, usize)>,
+    stability_guard: Option<StabilityGuard>,
+    sleep_parameters: Option<SleepParameters>,
+    sleep_states: HashMap<FluidHandle, SharedSleepStates>,
+    resampling_parameters: Option<ResamplingParameters>,
+    steps_since_resampling: usize,
+    compact_threshold: Option<Real>,
+    ghost_boundaries: HashMap<BoundaryHandle, GhostBoundary>,
+    analytic_colliders: HashMap<BoundaryHandle, AnalyticCollider>,
+    reflective_planes: Vec<ReflectivePlane>,
+    probes: ProbeSet,
+    attachments: HashMap<FluidHandle, SharedAttachments>,
+    next_attachment_id: u64,
+    global_damping: Real,
+    speed_of_sound: Real,
+    incompressibility_tolerance: Option<IncompressibilityTolerance>,
+    paused: bool,
+    elapsed_time: Real,
+    step_count: u64,
+    substep_count: u64,
 }
 
 impl LiquidWorld {
+    /// The labels of the columns written by `self.export_csv`, which are also the keys of the records written by `self.export_json`.
+    ///
+    /// The fluid handle of each particle is split into its `fluid_index` and `fluid_generation`,
+    /// and `particle` is the index of the particle within its fluid.
+    #[cfg(feature = "dim2")]
+    pub const EXPORT_COLUMNS: &'static [&'static str] = &[
+        "fluid_index",
+        "fluid_generation",
+        "particle",
+        "x",
+        "y",
+        "vx",
+        "vy",
+        "density",
+    ];
+
+    /// The labels of the columns written by `self.export_csv`, which are also the keys of the records written by `self.export_json`.
+    ///
+    /// The fluid handle of each particle is split into its `fluid_index` and `fluid_generation`,
+    /// and `particle` is the index of the particle within its fluid.
+    #[cfg(feature = "dim3")]
+    pub const EXPORT_COLUMNS: &'static [&'static str] = &[
+        "fluid_index",
+        "fluid_generation",
+        "particle",
+        "x",
+        "y",
+        "z",
+        "vx",
+        "vy",
+        "vz",
+        "density",
+    ];
+
+    /// The labels of the columns written by `self.export_neighbor_graph`.
+    ///
+    /// `kind` is either `fluid-fluid` or `fluid-boundary`. The edge goes from the particle `i` of
+    /// the fluid with the handle split into `i_index` and `i_generation`, to the particle `j` of the
+    /// fluid, or boundary, with the handle split into `j_index` and `j_generation`. `weight` is the
+    /// kernel value of the contact.
+    pub const NEIGHBOR_GRAPH_COLUMNS: &'static [&'static str] = &[
+        "kind",
+        "i_index",
+        "i_generation",
+        "i",
+        "j_index",
+        "j_generation",
+        "j",
+        "weight",
+    ];
+
     /// Initialize a new liquid world.
     ///
     /// # Parameters
@@ -36,6 +160,8 @@ impl LiquidWorld {
     /// - `particle_radius`: the radius of every particle on this world.
     /// - `smoothing_factor`: the smoothing factor used to compute the SPH kernel radius.
     ///    The kernel radius will be computed as `particle_radius * smoothing_factor * 2.0.
+    ///
+    /// The parameters are not validated: use `LiquidWorld::try_new` to detect degenerate values.
     pub fn new(
         solver: impl PressureSolver + Send + Sync + 'static,
         particle_radius: Real,
@@ -50,238 +176,4579 @@ impl LiquidWorld {
             fluids: FluidSet::new(),
             boundaries: BoundarySet::new(),
             solver: Box::new(solver),
+            fluid_solvers: HashMap::new(),
             contact_manager: ContactManager::new(),
             timestep_manager: TimestepManager::new(particle_radius),
             hgrid: HGrid::new(h),
+            hgrid_fluids: Vec::new(),
+            motion_threshold: None,
+            reference_positions: HashMap::new(),
+            previous_positions: HashMap::new(),
+            moved_particles: Vec::new(),
+            stability_guard: None,
+            sleep_parameters: None,
+            sleep_states: HashMap::new(),
+            resampling_parameters: None,
+            steps_since_resampling: 0,
+            compact_threshold: None,
+            ghost_boundaries: HashMap::new(),
+            analytic_colliders: HashMap::new(),
+            reflective_planes: Vec::new(),
+            probes: ProbeSet::new(),
+            attachments: HashMap::new(),
+            next_attachment_id: 0,
+            global_damping: na::zero::<Real>(),
+            speed_of_sound: na::convert::<_, Real>(DEFAULT_SPEED_OF_SOUND),
+            incompressibility_tolerance: None,
+            paused: false,
+            elapsed_time: na::zero::<Real>(),
+            step_count: 0,
+            substep_count: 0,
         }
     }
 
+    /// Initialize a new liquid world, after checking that its parameters are consistent.
+    ///
+    /// Returns an error if `particle_radius` is not positive and finite, if `smoothing_factor` is
+    /// smaller than one or not finite, or if the resulting kernel radius is not finite. See
+    /// `LiquidWorld::new` for details about the parameters.
+    pub fn try_new(
+        solver: impl PressureSolver + Send + Sync + 'static,
+        particle_radius: Real,
+        smoothing_factor: Real,
+    ) -> Result<Self, ConfigError> {
+        if !particle_radius.is_finite() || particle_radius <= na::zero::<Real>() {
+            return Err(ConfigError::InvalidParticleRadius(particle_radius));
+        }
+
+        if !smoothing_factor.is_finite() || smoothing_factor < na::one::<Real>() {
+            return Err(ConfigError::InvalidSmoothingFactor(smoothing_factor));
+        }
+
+        let h = particle_radius * smoothing_factor * na::convert::<_, Real>(2.0);
+
+        if !h.is_finite() {
+            return Err(ConfigError::InvalidKernelRadius(h));
+        }
+
+        Ok(Self::new(solver, particle_radius, smoothing_factor))
+    }
+
     /// Advances the simulation by `dt` seconds.
     ///
     /// All the fluid particles will be affected by an acceleration equal to `gravity`.
+    /// If the stability guard gives up, the timestep is interrupted and the error is logged as a
+    /// warning. Use `self.try_step` to handle such failures.
     pub fn step(&mut self, dt: Real, gravity: &Vector<Real>) {
         self.step_with_coupling(dt, gravity, &mut ())
     }
 
     /// Advances the simulation by `dt` seconds, taking into account coupling with an external rigid-body engine.
+    ///
+    /// If the stability guard gives up, the timestep is interrupted and the error is logged as a
+    /// warning. Use `self.try_step_with_coupling` to handle such failures.
     pub fn step_with_coupling(
         &mut self,
         dt: Real,
         gravity: &Vector<Real>,
         coupling: &mut impl CouplingManager,
     ) {
+        if let Err(error) = self.try_step_with_coupling(dt, gravity, coupling) {
+            log::warn!("The timestep was interrupted: {}.", error);
+        }
+    }
+
+    /// Advances the simulation by `dt` seconds, reporting the failures of the stability guard.
+    ///
+    /// If the stability guard gives up, the world is left at the beginning of the diverging
+    /// substep and an error is returned. This never fails if no stability guard is set.
+    pub fn try_step(&mut self, dt: Real, gravity: &Vector<Real>) -> Result<(), StepError> {
+        self.try_step_with_coupling(dt, gravity, &mut ())
+    }
+
+    /// Advances the simulation by `dt` seconds, taking into account coupling with an external
+    /// rigid-body engine and reporting the failures of the stability guard.
+    ///
+    /// See `self.try_step` for details.
+    pub fn try_step_with_coupling(
+        &mut self,
+        dt: Real,
+        gravity: &Vector<Real>,
+        coupling: &mut impl CouplingManager,
+    ) -> Result<(), StepError> {
+        self.try_step_with_gravity_fn(dt, &|_, _| *gravity, coupling)
+    }
+
+    /// Advances the simulation by `dt` seconds, with a gravity varying during the step.
+    ///
+    /// `gravity(s)` is the gravity at the fraction `s` of the step, from 0 at its beginning to 1 at
+    /// its end, e.g., interpolated between the gravities of two consecutive frames while a level
+    /// rotates. It is evaluated once per substep, at the middle of the substep, so the gravity
+    /// impulse of each substep is exact if the gravity varies linearly during the substep, and the
+    /// total impulse over the step matches the one of a finer integration. If the stability guard
+    /// gives up, the timestep is interrupted and the error is logged as a warning. Use
+    /// `self.try_step_with_substep_gravity` to handle such failures.
+    pub fn step_with_substep_gravity(&mut self, dt: Real, gravity: impl Fn(Real) -> Vector<Real>) {
+        if let Err(error) = self.try_step_with_substep_gravity(dt, gravity, &mut ()) {
+            log::warn!("The timestep was interrupted: {}.", error);
+        }
+    }
+
+    /// Advances the simulation by `dt` seconds, with a gravity varying during the step, taking
+    /// into account coupling with an external rigid-body engine and reporting the failures of the
+    /// stability guard.
+    ///
+    /// See `self.step_with_substep_gravity` and `self.try_step` for details.
+    pub fn try_step_with_substep_gravity(
+        &mut self,
+        dt: Real,
+        gravity: impl Fn(Real) -> Vector<Real>,
+        coupling: &mut impl CouplingManager,
+    ) -> Result<(), StepError> {
+        self.try_step_with_gravity_fn(dt, &|_, s| gravity(s), coupling)
+    }
+
+    /// Advances the simulation by `dt` seconds, with a gravity varying with the simulation time.
+    ///
+    /// The gravity is evaluated at the beginning of each substep, at the time given by
+    /// `self.elapsed_time`. If the stability guard gives up, the timestep is interrupted and the
+    /// error is logged as a warning. Use `self.try_step_with_time_varying_gravity` to handle such
+    /// failures.
+    pub fn step_with_time_varying_gravity(&mut self, dt: Real, gravity: &TimeVaryingGravity) {
+        if let Err(error) = self.try_step_with_time_varying_gravity(dt, gravity, &mut ()) {
+            log::warn!("The timestep was interrupted: {}.", error);
+        }
+    }
+
+    /// Advances the simulation by `dt` seconds, with a gravity varying with the simulation time,
+    /// taking into account coupling with an external rigid-body engine and reporting the failures
+    /// of the stability guard.
+    ///
+    /// See `self.step_with_time_varying_gravity` and `self.try_step` for details.
+    pub fn try_step_with_time_varying_gravity(
+        &mut self,
+        dt: Real,
+        gravity: &TimeVaryingGravity,
+        coupling: &mut impl CouplingManager,
+    ) -> Result<(), StepError> {
+        self.try_step_with_gravity_fn(dt, &|t, _| gravity.at(t), coupling)
+    }
+
+    // Performs a step with the gravity `gravity(t, s)`, evaluated once per substep, where `t` is the
+    // elapsed time at the beginning of the substep and `s` is the fraction of the step at its middle.
+    fn try_step_with_gravity_fn(
+        &mut self,
+        dt: Real,
+        gravity: &dyn Fn(Real, Real) -> Vector<Real>,
+        coupling: &mut impl CouplingManager,
+    ) -> Result<(), StepError> {
         self.counters.reset();
         self.counters.step_time.start();
         self.timestep_manager.reset(dt);
+        self.resample_fluids();
 
         self.solver.init_with_fluids(self.fluids.as_slice());
 
+        for solver in self.fluid_solvers.values_mut() {
+            solver.init_with_fluids(self.fluids.as_slice());
+        }
+
         for fluid in self.fluids.as_mut_slice() {
             fluid.apply_particles_removal();
         }
 
+        for (handle, fluid) in self.fluids.iter() {
+            self.previous_positions
+                .entry(handle)
+                .or_default()
+                .clone_from(&fluid.positions);
+        }
+
+        let probe_start_positions = self.collect_probe_start_positions();
+
+        if self.paused {
+            // Keep the contacts and densities up-to-date without moving any particle.
+            self.detect_contacts(coupling);
+            self.counters.stages.solver_time.resume();
+            self.compute_densities();
+            self.counters.stages.solver_time.pause();
+            self.update_probes(dt, &probe_start_positions);
+            self.counters.step_time.pause();
+            return Ok(());
+        }
+
+        self.step_count += 1;
+
         // Perform substeps.
+        let mut num_retries = 0;
+
         while !self.timestep_manager.is_done() {
+            let checkpoint = self
+                .stability_guard
+                .map(|_| self.create_substep_checkpoint());
             self.nsubsteps_since_sort += 1;
             self.counters.nsubsteps += 1;
 
-            self.counters.stages.collision_detection_time.resume();
-            self.counters.cd.grid_insertion_time.resume();
-            self.hgrid.clear();
-            geometry::insert_fluids_to_grid(self.fluids.as_slice(), &mut self.hgrid);
-            self.counters.cd.grid_insertion_time.pause();
-
-            self.counters.cd.boundary_update_time.resume();
-            coupling.update_boundaries(
-                &self.timestep_manager,
-                self.h,
-                self.particle_radius,
-                &self.hgrid,
-                self.fluids.as_mut_slice(),
-                &mut self.boundaries,
-            );
-            self.counters.cd.boundary_update_time.pause();
+            self.detect_contacts(coupling);
+
+            self.counters.stages.solver_time.resume();
+            self.wake_up_particles();
+            self.apply_attachments();
+            let frozen_particles = self.collect_frozen_particles();
 
-            self.counters.cd.grid_insertion_time.resume();
-            geometry::insert_boundaries_to_grid(self.boundaries.as_slice(), &mut self.hgrid);
-            self.counters.cd.grid_insertion_time.pause();
+            let step_fraction = self.timestep_manager.next_substep_midpoint();
+            self.step_solvers(&gravity(self.elapsed_time, step_fraction));
 
-            self.solver.init_with_boundaries(self.boundaries.as_slice());
+            if let Some(stats) = self.last_solve_stats() {
+                if !stats.density_error.is_finite() || !stats.divergence_error.is_finite() {
+                    log::error!(
+                        "The pressure solver produced a non-finite error: the simulation likely contains NaNs."
+                    );
+                }
+            }
 
-            self.contact_manager.update_contacts(
-                &mut self.counters,
-                self.h,
-                self.fluids.as_slice(),
-                self.boundaries.as_slice(),
-                &self.hgrid,
-            );
+            if let (Some(guard), Some(checkpoint)) = (self.stability_guard, checkpoint) {
+                let stats = self.last_solve_stats();
+                let density_error = stats
+                    .map(|stats| stats.density_error)
+                    .unwrap_or(na::zero::<Real>());
+                // NOTE: NaN errors, the usual outcome of a diverging scene, fail every comparison.
+                let is_finite = stats.is_none_or(|stats| {
+                    stats.density_error.is_finite() && stats.divergence_error.is_finite()
+                });
 
-            self.counters.cd.ncontacts = self.contact_manager.ncontacts();
-            self.counters.stages.collision_detection_time.pause();
+                if !is_finite || density_error > guard.max_density_error {
+                    self.restore_substep_checkpoint(checkpoint);
+                    self.counters.stages.solver_time.pause();
 
-            self.counters.stages.solver_time.resume();
-            self.solver.evaluate_kernels(
-                self.h,
-                &mut self.contact_manager,
-                self.fluids.as_slice(),
-                self.boundaries.as_slice(),
-            );
+                    if num_retries >= guard.max_retries {
+                        self.counters.step_time.pause();
+                        return Err(StepError::Diverged {
+                            density_error,
+                            num_retries,
+                        });
+                    }
 
-            self.solver.compute_densities(
-                &self.contact_manager,
-                self.fluids.as_slice(),
-                self.boundaries.as_mut_slice(),
-            );
+                    log::debug!(
+                        "Density error {} above the stability guard threshold, halving the substep.",
+                        density_error
+                    );
+                    num_retries += 1;
+                    self.counters.nsubstep_retries += 1;
+                    self.timestep_manager.halve_substep();
+                    continue;
+                }
+            }
 
-            self.solver.step(
-                &mut self.counters,
-                &mut self.timestep_manager,
-                gravity,
-                &mut self.contact_manager,
-                self.h,
-                self.fluids.as_mut_slice(),
-                self.boundaries.as_slice(),
-            );
+            self.elapsed_time += self.timestep_manager.dt();
+            self.substep_count += 1;
+            self.advance_particle_ages();
+            self.apply_global_damping();
+
+            for ghost in self.ghost_boundaries.values() {
+                ghost.project_fluid_particles(self.fluids.as_mut_slice());
+            }
+
+            for collider in self.analytic_colliders.values() {
+                collider.project_fluid_particles(self.fluids.as_mut_slice());
+            }
 
+            for plane in &self.reflective_planes {
+                let _ = plane.reflect_fluid_particles(self.fluids.as_mut_slice());
+            }
+
+            self.restore_frozen_particles(frozen_particles);
+            self.update_sleeping_particles();
             coupling.transmit_forces(&self.timestep_manager, &self.boundaries);
             self.counters.stages.solver_time.pause();
         }
 
+        self.update_moved_particles();
+        self.update_probes(dt, &probe_start_positions);
+        self.compact_buffers();
+
         //        if self.nsubsteps_since_sort >= 100 {
         //            self.nsubsteps_since_sort = 0;
-        //            println!("Performing z-sort of particles.");
         //            par_iter_mut!(self.fluids.as_mut_slice()).for_each(|fluid| fluid.z_sort())
         //        }
 
         self.counters.step_time.pause();
-        //        println!("Counters: {}", self.counters);
+        log::trace!("Counters: {}", self.counters);
+        Ok(())
     }
 
-    /// Add a fluid to the liquid world.
-    pub fn add_fluid(&mut self, fluid: Fluid) -> FluidHandle {
-        self.fluids.insert(fluid)
+    fn detect_contacts(&mut self, coupling: &mut impl CouplingManager) {
+        self.counters.stages.collision_detection_time.resume();
+        self.counters.cd.grid_insertion_time.resume();
+        let cell_widths = self.contact_manager.cell_widths(self.h);
+        if *self.hgrid.cell_widths() != cell_widths {
+            self.hgrid = HGrid::with_cell_widths(cell_widths);
+        }
+        self.hgrid.clear();
+        geometry::insert_fluids_to_grid(self.fluids.as_slice(), &mut self.hgrid);
+        self.hgrid_fluids.clear();
+        self.hgrid_fluids.extend(fluid_grid_layouts(&self.fluids));
+        self.counters.cd.grid_insertion_time.pause();
+
+        self.counters.cd.boundary_update_time.resume();
+        coupling.update_boundaries(
+            &self.timestep_manager,
+            self.h,
+            self.particle_radius,
+            &self.hgrid,
+            self.fluids.as_mut_slice(),
+            &mut self.boundaries,
+        );
+
+        for (handle, ghost) in &self.ghost_boundaries {
+            if let Some(boundary) = self.boundaries.get_mut(*handle) {
+                ghost.generate_ghost_particles(
+                    self.h,
+                    self.particle_radius,
+                    self.fluids.as_slice(),
+                    boundary,
+                );
+            }
+        }
+
+        for (handle, collider) in &self.analytic_colliders {
+            if let Some(boundary) = self.boundaries.get_mut(*handle) {
+                collider.generate_boundary_particles(
+                    self.h,
+                    self.particle_radius,
+                    self.fluids.as_slice(),
+                    boundary,
+                );
+            }
+        }
+        self.counters.cd.boundary_update_time.pause();
+
+        self.counters.cd.grid_insertion_time.resume();
+        geometry::insert_boundaries_to_grid(self.boundaries.as_slice(), &mut self.hgrid);
+        self.counters.cd.grid_insertion_time.pause();
+
+        self.solver.init_with_boundaries(self.boundaries.as_slice());
+
+        for solver in self.fluid_solvers.values_mut() {
+            solver.init_with_boundaries(self.boundaries.as_slice());
+        }
+
+        self.contact_manager.update_contacts(
+            &mut self.counters,
+            self.h,
+            self.fluids.as_slice(),
+            self.boundaries.as_slice(),
+            &self.hgrid,
+        );
+
+        self.counters.cd.ncontacts = self.contact_manager.ncontacts();
+        self.counters.stages.collision_detection_time.pause();
     }
 
-    /// Add a boundary to the liquid world.
-    pub fn add_boundary(&mut self, boundary: Boundary) -> BoundaryHandle {
-        self.boundaries.insert(boundary)
+    // Computes the densities shared by the non-pressure forces and the pressure solve of a substep.
+    fn compute_densities(&mut self) {
+        self.solver.evaluate_kernels(
+            self.h,
+            &mut self.contact_manager,
+            self.fluids.as_slice(),
+            self.boundaries.as_slice(),
+        );
+
+        self.solver.compute_densities(
+            &self.contact_manager,
+            self.fluids.as_slice(),
+            self.boundaries.as_mut_slice(),
+        );
     }
 
-    /// Add a fluid to the liquid world.
-    pub fn remove_fluid(&mut self, handle: FluidHandle) -> Option<Fluid> {
-        self.fluids.remove(handle)
+    // Steps the solvers assigned to specific fluids, then the default solver on the other fluids.
+    //
+    // The default solver is stepped last so that the kernels and densities kept after the substep
+    // are its own. The inactive fluids are not solved by any solver. If the default solver can't be
+    // restricted to some fluids, it solves all of them and the inactive fluids are restored afterward.
+    fn step_solvers(&mut self, gravity: &Vector<Real>) {
+        let all_active = self.fluids.iter().all(|(_, fluid)| fluid.is_active());
+
+        if self.fluid_solvers.is_empty() && all_active {
+            if let Some(solver) = self.solver.as_partial_mut() {
+                solver.set_solved_fluids(None);
+            }
+        } else if self.solver.as_partial_mut().is_none() {
+            // NOTE: `set_fluid_solver` ensures `self.fluid_solvers` is empty in this case, and the
+            // inactive fluids are restored with the other frozen particles.
+        } else {
+            let mut solved_by_default: Vec<_> = self
+                .fluids
+                .as_slice()
+                .iter()
+                .map(|fluid| fluid.is_active())
+                .collect();
+            let num_fluids = solved_by_default.len();
+            let mut assigned: Vec<_> = self
+                .fluid_solvers
+                .keys()
+                .filter_map(|handle| Some((self.fluids.contiguous_index(*handle)?, *handle)))
+                .filter(|(fluid_id, _)| solved_by_default[*fluid_id])
+                .collect();
+            // Step the solvers in the order of their fluids, for determinism.
+            assigned.sort_unstable_by_key(|(fluid_id, _)| *fluid_id);
+            // Every solver computes the same substep length from the same timestep state.
+            let timestep = self.timestep_manager.clone();
+
+            for (fluid_id, handle) in assigned {
+                let mut solved = vec![false; num_fluids];
+                solved[fluid_id] = true;
+                solved_by_default[fluid_id] = false;
+
+                let solver = self.fluid_solvers.get_mut(&handle).unwrap();
+                if let Some(solver) = solver.as_partial_mut() {
+                    solver.set_solved_fluids(Some(solved));
+                }
+                solver.evaluate_kernels(
+                    self.h,
+                    &mut self.contact_manager,
+                    self.fluids.as_slice(),
+                    self.boundaries.as_slice(),
+                );
+                solver.compute_densities(
+                    &self.contact_manager,
+                    self.fluids.as_slice(),
+                    self.boundaries.as_mut_slice(),
+                );
+                solver.step(
+                    &mut self.counters,
+                    &mut timestep.clone(),
+                    gravity,
+                    &mut self.contact_manager,
+                    self.h,
+                    self.fluids.as_mut_slice(),
+                    self.boundaries.as_slice(),
+                );
+            }
+
+            if let Some(solver) = self.solver.as_partial_mut() {
+                solver.set_solved_fluids(Some(solved_by_default));
+            }
+        }
+
+        self.compute_densities();
+
+        self.solver.step(
+            &mut self.counters,
+            &mut self.timestep_manager,
+            gravity,
+            &mut self.contact_manager,
+            self.h,
+            self.fluids.as_mut_slice(),
+            self.boundaries.as_slice(),
+        );
+
+        // Drop the pressure reactions and the forces accumulated on the inactive fluids.
+        for fluid in self.fluids.as_mut_slice() {
+            if !fluid.is_active() {
+                fluid
+                    .accelerations
+                    .iter_mut()
+                    .for_each(|acceleration| acceleration.fill(na::zero::<Real>()));
+            }
+        }
     }
 
-    /// Add a boundary to the liquid world.
-    pub fn remove_boundary(&mut self, handle: BoundaryHandle) -> Option<Boundary> {
-        self.boundaries.remove(handle)
+    // The convergence of the last resolution of the pressure solver of this world, if it reports it.
+    fn last_solve_stats(&self) -> Option<SolveStats> {
+        self.solver
+            .as_configurable()
+            .map(|solver| solver.last_solve_stats())
     }
 
-    /// The set of fluids on this liquid world.
-    pub fn fluids(&self) -> &FluidSet {
-        &self.fluids
+    fn create_substep_checkpoint(&self) -> SubstepCheckpoint {
+        SubstepCheckpoint {
+            timestep_manager: self.timestep_manager.clone(),
+            solver_state: self
+                .solver
+                .as_stateful()
+                .map(|solver| solver.export_state()),
+            fluid_solver_states: self
+                .fluid_solvers
+                .iter()
+                .filter_map(|(handle, solver)| {
+                    Some((*handle, solver.as_stateful()?.export_state()))
+                })
+                .collect(),
+            fluids: self
+                .fluids
+                .as_slice()
+                .iter()
+                .map(|fluid| {
+                    (
+                        fluid.positions.clone(),
+                        fluid.velocities.clone(),
+                        fluid.accelerations.clone(),
+                        fluid
+                            .nonpressure_forces
+                            .iter()
+                            .map(|force| force.export_state())
+                            .collect(),
+                    )
+                })
+                .collect(),
+            boundary_forces: self
+                .boundaries
+                .as_slice()
+                .iter()
+                .map(|boundary| {
+                    boundary
+                        .forces
+                        .as_ref()
+                        .map(|forces| forces.read().unwrap().clone())
+                })
+                .collect(),
+        }
     }
 
-    /// The mutable set of fluids on this liquid world.
-    pub fn fluids_mut(&mut self) -> &mut FluidSet {
-        &mut self.fluids
+    fn restore_substep_checkpoint(&mut self, checkpoint: SubstepCheckpoint) {
+        self.timestep_manager = checkpoint.timestep_manager;
+
+        for (fluid, (positions, velocities, accelerations, force_states)) in
+            self.fluids.as_mut_slice().iter_mut().zip(checkpoint.fluids)
+        {
+            fluid.positions = positions;
+            fluid.velocities = velocities;
+            fluid.accelerations = accelerations;
+
+            for (force, state) in fluid.nonpressure_forces.iter_mut().zip(force_states) {
+                force.import_state(&state);
+            }
+        }
+
+        for (boundary, forces) in self
+            .boundaries
+            .as_mut_slice()
+            .iter_mut()
+            .zip(checkpoint.boundary_forces)
+        {
+            if let (Some(dst), Some(src)) = (&mut boundary.forces, forces) {
+                *dst.get_mut().unwrap() = src;
+            }
+        }
+
+        // NOTE: this can't fail since the particles are not added or removed during a timestep.
+        if let (Some(solver), Some(state)) =
+            (self.solver.as_stateful_mut(), &checkpoint.solver_state)
+        {
+            solver
+                .import_state(self.fluids.as_slice(), state)
+                .expect("Internal error: failed to restore the pressure solver state.");
+        }
+
+        for (handle, state) in &checkpoint.fluid_solver_states {
+            if let Some(solver) = self
+                .fluid_solvers
+                .get_mut(handle)
+                .and_then(|solver| solver.as_stateful_mut())
+            {
+                solver
+                    .import_state(self.fluids.as_slice(), state)
+                    .expect("Internal error: failed to restore the pressure solver state.");
+            }
+        }
     }
 
-    /// The set of boundaries on this liquid world.
-    pub fn boundaries(&self) -> &BoundarySet {
-        &self.boundaries
+    /// The simulation time elapsed since the creation of this world, or since the last call to `self.reset` or `self.set_elapsed_time`.
+    ///
+    /// This is the sum of the lengths of all the substeps performed so far. It does not advance
+    /// while the world is paused.
+    pub fn elapsed_time(&self) -> Real {
+        self.elapsed_time
     }
 
-    /// The mutable set of boundaries on this liquid world.
-    pub fn boundaries_mut(&mut self) -> &mut BoundarySet {
-        &mut self.boundaries
+    /// The number of timesteps performed since the creation of this world, or since the last call to `self.reset`.
+    ///
+    /// Each call to `self.step`, or to one of its variants, counts as one timestep, except while the
+    /// world is paused.
+    pub fn step_count(&self) -> u64 {
+        self.step_count
     }
 
-    /// The SPH kernel radius.
-    pub fn h(&self) -> Real {
-        self.h
+    /// The number of substeps performed since the creation of this world, or since the last call to `self.reset`.
+    ///
+    /// The substeps retried with a smaller length by the stability guard are only counted once.
+    pub fn substep_count(&self) -> u64 {
+        self.substep_count
     }
 
-    /// The radius of every particle on this liquid world.
-    pub fn particle_radius(&self) -> Real {
-        self.particle_radius
+    /// Sets the simulation time elapsed so far, e.g., to zero for restarting a time-varying gravity.
+    pub fn set_elapsed_time(&mut self, elapsed_time: Real) {
+        self.elapsed_time = elapsed_time;
     }
 
-    /// The set of particles potentially intersecting the given AABB.
-    #[cfg(feature = "parry")]
-    pub fn particles_intersecting_aabb<'a>(
-        &'a self,
-        aabb: Aabb,
-    ) -> impl Iterator<Item = ParticleId> + 'a {
-        self.hgrid
-            .cells_intersecting_aabb(&aabb.mins, &aabb.maxs)
-            .flat_map(|e| e.1)
-            .filter_map(move |entry| match entry {
-                HGridEntry::FluidParticle(fid, pid) => {
-                    let (fluid, handle) = self.fluids.get_from_contiguous_index(*fid)?;
-                    let pt = fluid.positions[*pid];
+    /// The global damping rate of the fluid velocities, in `1/s`.
+    pub fn global_damping(&self) -> Real {
+        self.global_damping
+    }
 
-                    // FIXME: use `distance_to_local_point` once it's supported.
-                    let id = &Isometry::identity();
-                    if aabb.distance_to_point(id, &pt, true) < self.particle_radius {
-                        Some(ParticleId::FluidParticle(handle, *pid))
-                    } else {
-                        None
-                    }
-                }
-                HGridEntry::BoundaryParticle(bid, pid) => {
-                    let (boundary, handle) = self.boundaries.get_from_contiguous_index(*bid)?;
-                    let pt = boundary.positions[*pid]; // FIXME: use `distance_to_local_point` once it's supported.
-                    let id = &Isometry::identity();
-                    if aabb.distance_to_point(id, &pt, true) < self.particle_radius {
-                        Some(ParticleId::BoundaryParticle(handle, *pid))
-                    } else {
-                        None
-                    }
-                }
-            })
+    /// Sets the global damping rate of the fluid velocities, in `1/s`, e.g., to bring a scene to rest quickly.
+    ///
+    /// After the pressure solve of each substep of length `dt`, the velocities of all the fluid
+    /// particles are multiplied by `exp(-damping * dt)`, i.e., `1 - damping * dt` to first order. The
+    /// damping over a step is then independent of the number of its substeps. The default, zero,
+    /// disables the damping.
+    ///
+    /// Panics if `damping` is negative.
+    pub fn set_global_damping(&mut self, damping: Real) {
+        assert!(
+            damping >= na::zero::<Real>(),
+            "The global damping must not be negative."
+        );
+        self.global_damping = damping;
     }
 
-    /// The set of particles potentially intersecting the given shape.
-    #[cfg(feature = "parry")]
-    pub fn particles_intersecting_shape<'a, S: ?Sized>(
-        &'a self,
-        pos: &'a Isometry<Real>,
-        shape: &'a S,
-    ) -> impl Iterator<Item = ParticleId> + 'a
-    where
-        S: Shape,
-    {
-        let aabb = shape.compute_aabb(pos);
-        self.hgrid
-            .cells_intersecting_aabb(&aabb.mins, &aabb.maxs)
-            .flat_map(|e| e.1)
-            .filter_map(move |entry| match entry {
-                HGridEntry::FluidParticle(fid, pid) => {
-                    let (fluid, handle) = self.fluids.get_from_contiguous_index(*fid)?;
-                    let pt = fluid.positions[*pid];
+    /// The speed of sound of the equation of state used to estimate the particle pressures.
+    pub fn speed_of_sound(&self) -> Real {
+        self.speed_of_sound
+    }
 
-                    if shape.distance_to_point(pos, &pt, true) <= self.particle_radius {
-                        Some(ParticleId::FluidParticle(handle, *pid))
-                    } else {
-                        None
-                    }
-                }
-                HGridEntry::BoundaryParticle(bid, pid) => {
-                    let (boundary, handle) = self.boundaries.get_from_contiguous_index(*bid)?;
-                    let pt = boundary.positions[*pid]; // FIXME: use `distance_to_local_point` once it's supported.
-                    if shape.distance_to_point(pos, &pt, true) <= self.particle_radius {
-                        Some(ParticleId::BoundaryParticle(handle, *pid))
-                    } else {
-                        None
-                    }
-                }
-            })
+    /// Sets the speed of sound of the equation of state used to estimate the particle pressures.
+    ///
+    /// This only affects `self.compute_pressure_estimate`, the quantities derived from it, e.g.,
+    /// `self.fluid_stress` and the probe pressures, not the pressure solver. Larger speeds of sound
+    /// give larger pressures for the same compression. The default is 10m/s, which should be scaled
+    /// with the velocities of the scene, e.g., about ten times the largest expected fluid velocity.
+    ///
+    /// Panics if `speed_of_sound` is not positive.
+    pub fn set_speed_of_sound(&mut self, speed_of_sound: Real) {
+        assert!(
+            speed_of_sound > na::zero::<Real>(),
+            "The speed of sound must be positive."
+        );
+        self.speed_of_sound = speed_of_sound;
     }
-}
 
-#[test]
-fn world_is_send_and_sync() {
-    fn check<T: Send + Sync>() {}
-    check::<LiquidWorld>();
+    fn apply_global_damping(&mut self) {
+        if self.global_damping <= na::zero::<Real>() {
+            return;
+        }
+
+        let factor = (-self.global_damping * self.timestep_manager.dt()).exp();
+
+        for fluid in self.fluids.as_mut_slice() {
+            fluid.velocities.iter_mut().for_each(|v| *v *= factor);
+        }
+
+        let solvers = std::iter::once(&mut self.solver).chain(self.fluid_solvers.values_mut());
+
+        for solver in solvers.filter_map(|solver| solver.as_stateful_mut()) {
+            solver.damp_velocity_changes(factor);
+        }
+    }
+
+    fn advance_particle_ages(&mut self) {
+        let dt = self.timestep_manager.dt();
+
+        for fluid in self.fluids.as_mut_slice() {
+            fluid.ages.iter_mut().for_each(|age| *age += dt);
+        }
+    }
+
+    /// Whether the simulation is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pauses or resumes the simulation.
+    ///
+    /// While paused, stepping the liquid world only updates the contacts and the densities of the
+    /// particles, so that they can be inspected, but neither computes forces nor moves the particles.
+    /// The particles marked for deletion are still removed. Resuming the simulation continues it from
+    /// the same state, including particle velocities.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// The stability guard automatically reducing the substep length when the pressure solver diverges.
+    pub fn stability_guard(&self) -> Option<&StabilityGuard> {
+        self.stability_guard.as_ref()
+    }
+
+    /// Sets the stability guard automatically reducing the substep length when the pressure solver diverges.
+    ///
+    /// The number of retries performed during the last timestep is reported by `self.counters.nsubstep_retries`.
+    /// This has no effect if the pressure solver does not report its convergence with `ConfigurablePressureSolver::last_solve_stats`.
+    pub fn set_stability_guard(&mut self, guard: Option<StabilityGuard>) {
+        self.stability_guard = guard;
+    }
+
+    fn update_moved_particles(&mut self) {
+        self.moved_particles.clear();
+
+        let Some(threshold) = self.motion_threshold else {
+            return;
+        };
+
+        let handles: Vec<_> = self.fluids.iter().map(|(handle, _)| handle).collect();
+
+        for handle in handles {
+            let fluid = &mut self.fluids[handle];
+            let reference = self.reference_positions.entry(handle).or_insert_with(|| {
+                let positions = SharedReferencePositions::default();
+                fluid.listeners.push(Box::new(ReferencePositionsTracker {
+                    positions: positions.clone(),
+                }));
+                positions
+            });
+            let mut reference = reference.lock().unwrap();
+            reference.resize(fluid.num_particles(), None);
+
+            for (i, (ref_pos, pos)) in reference.iter_mut().zip(&fluid.positions).enumerate() {
+                // Particles without any reference position are new, so they are reported as moved.
+                if ref_pos.is_none_or(|ref_pos| {
+                    na::distance_squared(&ref_pos, pos) > threshold * threshold
+                }) {
+                    *ref_pos = Some(*pos);
+                    self.moved_particles.push((handle, i));
+                }
+            }
+        }
+    }
+
+    // Saves the positions of every fluid particle at the beginning of a step, if there are probes
+    // to measure the mass flowing through their faces.
+    fn collect_probe_start_positions(&self) -> Vec<Vec<Point<Real>>> {
+        if self.probes.len() == 0 {
+            return Vec::new();
+        }
+
+        self.fluids
+            .as_slice()
+            .iter()
+            .map(|fluid| fluid.positions.clone())
+            .collect()
+    }
+
+    fn update_probes(&mut self, dt: Real, start_positions: &[Vec<Point<Real>>]) {
+        if self.probes.len() == 0 {
+            return;
+        }
+
+        let pressures: Vec<_> = self
+            .fluids
+            .as_slice()
+            .iter()
+            .enumerate()
+            .map(|(fluid_id, fluid)| self.pressure_estimate(fluid_id, fluid))
+            .collect();
+
+        for probe in self.probes.as_mut_slice() {
+            probe.update(dt, self.fluids.as_slice(), start_positions, &pressures);
+        }
+    }
+
+    /// The particles that moved by more than the motion threshold during the last step.
+    ///
+    /// A particle is reported as moved when its distance to the position it had the last time it was
+    /// reported exceeds the threshold set with `self.set_motion_threshold`, so slow motions eventually
+    /// get reported too. Newly added particles are always reported as moved. The reference positions
+    /// follow their particles when the particles of a fluid are reordered or removed, which relies on a
+    /// listener pushed to `Fluid::listeners` that must not be removed.
+    /// This is empty if motion tracking is disabled.
+    pub fn moved_particles_since_last_frame(
+        &self,
+    ) -> impl Iterator<Item = (FluidHandle, usize)> + '_ {
+        self.moved_particles.iter().copied()
+    }
+
+    /// The distance a particle has to move to be reported by `self.moved_particles_since_last_frame`.
+    ///
+    /// Returns `None` if motion tracking is disabled.
+    pub fn motion_threshold(&self) -> Option<Real> {
+        self.motion_threshold
+    }
+
+    /// Sets the distance a particle has to move to be reported by `self.moved_particles_since_last_frame`.
+    ///
+    /// Motion tracking is disabled (the default) if this is set to `None`.
+    pub fn set_motion_threshold(&mut self, threshold: Option<Real>) {
+        if threshold.is_none() {
+            // Keep the entries, their listeners are still registered on the fluids.
+            for positions in self.reference_positions.values() {
+                positions.lock().unwrap().clear();
+            }
+            self.moved_particles.clear();
+        }
+
+        self.motion_threshold = threshold;
+    }
+
+    // Saves the state of every fluid particle sleeping, or part of an inactive fluid.
+    //
+    // NOTE: the particles outside of the active region are skipped by the pressure solvers instead.
+    fn collect_frozen_particles(&self) -> FrozenParticles {
+        if self.sleep_parameters.is_none() && self.fluids.iter().all(|(_, fluid)| fluid.is_active())
+        {
+            return Vec::new();
+        }
+
+        self.fluids
+            .as_slice()
+            .iter()
+            .enumerate()
+            .map(|(fluid_id, fluid)| {
+                let sleep_states = self
+                    .fluids
+                    .get_from_contiguous_index(fluid_id)
+                    .and_then(|(_, handle)| self.sleep_states.get(&handle))
+                    .map(|states| states.lock().unwrap());
+
+                fluid
+                    .positions
+                    .iter()
+                    .zip(fluid.velocities.iter())
+                    .enumerate()
+                    .filter(|(i, _)| {
+                        !fluid.is_active()
+                            || sleep_states
+                                .as_ref()
+                                .and_then(|states| states.get(*i))
+                                .is_some_and(|s| s.is_sleeping())
+                    })
+                    .map(|(i, (pos, vel))| (i, *pos, *vel))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn restore_frozen_particles(&mut self, frozen: FrozenParticles) {
+        for (fluid, frozen) in self.fluids.as_mut_slice().iter_mut().zip(frozen) {
+            for (i, pos, vel) in frozen {
+                fluid.positions[i] = pos;
+                fluid.velocities[i] = vel;
+            }
+        }
+    }
+
+    /// The parameters of the sleeping of the fluid particles at rest.
+    ///
+    /// Returns `None` if sleeping is disabled.
+    pub fn sleep_parameters(&self) -> Option<SleepParameters> {
+        self.sleep_parameters
+    }
+
+    /// Sets the parameters of the sleeping of the fluid particles at rest.
+    ///
+    /// Sleeping is disabled (the default) if this is set to `None`, which wakes up every particle.
+    pub fn set_sleep_parameters(&mut self, parameters: Option<SleepParameters>) {
+        if parameters.is_none() {
+            // Keep the entries, their listeners are still registered on the fluids.
+            for states in self.sleep_states.values() {
+                states.lock().unwrap().clear();
+            }
+        }
+
+        self.sleep_parameters = parameters;
+    }
+
+    /// The parameters of the periodic resampling of the fluid particles.
+    ///
+    /// Returns `None` if resampling is disabled.
+    pub fn resampling_parameters(&self) -> Option<ResamplingParameters> {
+        self.resampling_parameters
+    }
+
+    /// Sets the parameters of the periodic resampling of the fluid particles.
+    ///
+    /// Resampling is disabled (the default) if this is set to `None`. The steps performed while the
+    /// world is paused are not counted in the resampling interval.
+    pub fn set_resampling_parameters(&mut self, parameters: Option<ResamplingParameters>) {
+        self.resampling_parameters = parameters;
+        self.steps_since_resampling = 0;
+    }
+
+    // Resamples the particles of every fluid once every `interval` steps.
+    //
+    // The merged particles are only marked as deleted, so this must be called before the deleted
+    // particles are removed from the fluids and from the per-particle states of the world.
+    fn resample_fluids(&mut self) {
+        let Some(parameters) = self.resampling_parameters else {
+            return;
+        };
+
+        if self.paused {
+            return;
+        }
+
+        if self.steps_since_resampling >= parameters.interval {
+            self.steps_since_resampling = 0;
+
+            for fluid in self.fluids.as_mut_slice() {
+                let (num_merged, num_split) =
+                    crate::resampling::resample_fluid(fluid, self.h, &parameters);
+                log::debug!(
+                    "Resampling merged {} and split {} particles.",
+                    num_merged,
+                    num_split
+                );
+            }
+        }
+
+        self.steps_since_resampling += 1;
+    }
+
+    /// The ratio of live particles to allocated capacity below which the particle buffers are shrunk.
+    ///
+    /// Returns `None` if compaction is disabled.
+    pub fn compact_threshold(&self) -> Option<Real> {
+        self.compact_threshold
+    }
+
+    /// Sets the ratio of live particles to allocated capacity below which the particle buffers are shrunk.
+    ///
+    /// After many cycles of particle insertions and removals, e.g., with emitters and sinks, the
+    /// buffers of the fluids, the contacts, and the pressure solver keep the capacity of the largest
+    /// particle count they ever held. At the end of each step, if the number of particles of any
+    /// fluid is smaller than `ratio` times its capacity, all these buffers are shrunk to fit their
+    /// content. Their capacity then matches the particle counts, so the next compaction only happens
+    /// once enough particles are removed again instead of at every step. The particles keep their
+    /// order, so the compaction does not affect the simulation.
+    ///
+    /// Compaction is disabled (the default) if this is set to `None`.
+    pub fn set_compact_threshold(&mut self, ratio: Option<Real>) {
+        self.compact_threshold = ratio;
+    }
+
+    // Shrinks all the particle buffers if a fluid dropped below the compaction threshold.
+    fn compact_buffers(&mut self) {
+        let Some(ratio) = self.compact_threshold else {
+            return;
+        };
+
+        let below_threshold = self
+            .fluids
+            .as_slice()
+            .iter()
+            .any(|fluid| (fluid.num_particles() as Real) < ratio * fluid.capacity() as Real);
+
+        if !below_threshold {
+            return;
+        }
+
+        log::debug!("Compacting the particle buffers below the ratio {}.", ratio);
+
+        for fluid in self.fluids.as_mut_slice() {
+            fluid.shrink_to_fit();
+        }
+
+        self.contact_manager.shrink_to_fit();
+        let solvers = std::iter::once(&mut self.solver).chain(self.fluid_solvers.values_mut());
+
+        for solver in solvers.filter_map(|solver| solver.as_stateful_mut()) {
+            solver.shrink_to_fit();
+        }
+
+        for states in self.sleep_states.values() {
+            states.lock().unwrap().shrink_to_fit();
+        }
+
+        for positions in self.reference_positions.values() {
+            positions.lock().unwrap().shrink_to_fit();
+        }
+    }
+
+    /// Checks if the `i`-th particle of the given fluid is sleeping.
+    pub fn is_particle_sleeping(&self, handle: FluidHandle, i: usize) -> bool {
+        self.sleep_states
+            .get(&handle)
+            .and_then(|states| states.lock().unwrap().get(i).copied())
+            .is_some_and(|s| s.is_sleeping())
+    }
+
+    // Wakes up the sleeping particles that were disturbed since they fell asleep.
+    fn wake_up_particles(&mut self) {
+        let Some(parameters) = self.sleep_parameters else {
+            return;
+        };
+
+        let threshold2 = parameters.linear_threshold * parameters.linear_threshold;
+        let fluids = self.fluids.as_slice();
+
+        for (fluid_id, fluid) in fluids.iter().enumerate() {
+            let Some((_, handle)) = self.fluids.get_from_contiguous_index(fluid_id) else {
+                continue;
+            };
+            let Some(states) = self.sleep_states.get(&handle) else {
+                continue;
+            };
+            let mut states = states.lock().unwrap();
+            let fluid_fluid_contacts = &self.contact_manager.fluid_fluid_contacts[fluid_id];
+
+            for (i, state) in states.iter_mut().enumerate() {
+                if !state.is_sleeping() {
+                    continue;
+                }
+
+                let disturbed = fluid.velocities[i] != Vector::zeros()
+                    || state.lost_support(num_neighbors(&self.contact_manager, fluid_id, i))
+                    || fluid_fluid_contacts
+                        .iter_particle(i)
+                        .any(|c| fluids[c.j_model].velocities[c.j].norm_squared() > threshold2);
+
+                if disturbed {
+                    *state = ParticleSleepState::default();
+                }
+            }
+        }
+    }
+
+    // Puts to sleep the particles that stayed at rest long enough.
+    fn update_sleeping_particles(&mut self) {
+        let Some(parameters) = self.sleep_parameters else {
+            return;
+        };
+
+        let threshold2 = parameters.linear_threshold * parameters.linear_threshold;
+        let dt = self.timestep_manager.dt();
+
+        for fluid_id in 0..self.fluids.len() {
+            let Some((_, handle)) = self.fluids.get_from_contiguous_index(fluid_id) else {
+                continue;
+            };
+            let fluid = &mut self.fluids.as_mut_slice()[fluid_id];
+            let states = self.sleep_states.entry(handle).or_insert_with(|| {
+                let states = SharedSleepStates::default();
+                fluid.listeners.push(Box::new(SleepStatesTracker {
+                    states: states.clone(),
+                }));
+                states
+            });
+            let mut states = states.lock().unwrap();
+            states.resize(fluid.num_particles(), ParticleSleepState::default());
+
+            for (i, state) in states.iter_mut().enumerate() {
+                if state.is_sleeping() {
+                    continue;
+                }
+
+                if fluid.velocities[i].norm_squared() > threshold2 {
+                    state.calm_time = na::zero::<Real>();
+                    continue;
+                }
+
+                state.calm_time += dt;
+
+                if state.calm_time >= parameters.time_until_sleep {
+                    state.num_neighbors = Some(num_neighbors(&self.contact_manager, fluid_id, i));
+                    fluid.velocities[i] = Vector::zeros();
+                }
+            }
+        }
+    }
+
+    /// Attaches the `index`-th particle of the fluid `fluid` to the point `anchor` with a damped spring.
+    ///
+    /// At each substep, the particle is affected by the force `stiffness * (anchor - x) - damping * v`
+    /// where `x` and `v` are its position and velocity. The forces of several attachments of the
+    /// same particle accumulate. Because the spring is integrated explicitly, `stiffness` should stay
+    /// well below `m / dt²` where `m` is the particle mass and `dt` the substep length.
+    ///
+    /// The attachment follows its particle when the particles of the fluid are reordered or removed,
+    /// and is dropped when its particle is removed. This relies on a listener pushed to
+    /// `Fluid::listeners`, which must not be removed. Attached particles never fall asleep.
+    ///
+    /// Returns `None` if the fluid or the particle does not exist.
+    pub fn attach_particle(
+        &mut self,
+        fluid: FluidHandle,
+        index: usize,
+        anchor: Point<Real>,
+        stiffness: Real,
+        damping: Real,
+    ) -> Option<AttachmentHandle> {
+        let target = self.fluids.get_mut(fluid)?;
+
+        if index >= target.num_particles() {
+            return None;
+        }
+
+        let attachments = self.attachments.entry(fluid).or_insert_with(|| {
+            let attachments = SharedAttachments::default();
+            target.listeners.push(Box::new(AttachmentTracker {
+                attachments: attachments.clone(),
+            }));
+            attachments
+        });
+
+        let handle = AttachmentHandle(self.next_attachment_id);
+        self.next_attachment_id += 1;
+        attachments.lock().unwrap().push(ParticleAttachment {
+            handle,
+            particle: index,
+            anchor,
+            stiffness,
+            damping,
+        });
+        Some(handle)
+    }
+
+    /// Removes an attachment created with `self.attach_particle`.
+    ///
+    /// Returns `false` if the attachment does not exist anymore, e.g., because its particle was removed.
+    pub fn detach_particle(&mut self, handle: AttachmentHandle) -> bool {
+        self.attachments.values().any(|attachments| {
+            let mut attachments = attachments.lock().unwrap();
+            let len = attachments.len();
+            attachments.retain(|a| a.handle != handle);
+            attachments.len() != len
+        })
+    }
+
+    /// Moves the anchor of an attachment, e.g., to drag its particle around.
+    ///
+    /// Returns `false` if the attachment does not exist anymore.
+    pub fn set_attachment_anchor(&mut self, handle: AttachmentHandle, anchor: Point<Real>) -> bool {
+        self.attachments.values().any(|attachments| {
+            let mut attachments = attachments.lock().unwrap();
+            let attachment = attachments.iter_mut().find(|a| a.handle == handle);
+            attachment.map(|a| a.anchor = anchor).is_some()
+        })
+    }
+
+    /// The fluid and the current index of the particle of an attachment.
+    ///
+    /// Returns `None` if the attachment does not exist anymore.
+    pub fn attached_particle(&self, handle: AttachmentHandle) -> Option<(FluidHandle, usize)> {
+        self.attachments.iter().find_map(|(fluid, attachments)| {
+            let attachments = attachments.lock().unwrap();
+            let attachment = attachments.iter().find(|a| a.handle == handle)?;
+            Some((*fluid, attachment.particle))
+        })
+    }
+
+    // Adds the accelerations of the attachment springs to the fluid particles, and wakes up the
+    // attached particles.
+    fn apply_attachments(&mut self) {
+        for (handle, attachments) in &self.attachments {
+            let Some(fluid) = self.fluids.get_mut(*handle) else {
+                continue;
+            };
+            let mut states = self.sleep_states.get(handle).map(|s| s.lock().unwrap());
+
+            for a in attachments.lock().unwrap().iter() {
+                let i = a.particle;
+                let force =
+                    (a.anchor - fluid.positions[i]) * a.stiffness - fluid.velocities[i] * a.damping;
+                let inv_mass = fluid.particle_inv_mass(i);
+                fluid.accelerations[i] += force * inv_mass;
+
+                if let Some(state) = states.as_mut().and_then(|s| s.get_mut(i)) {
+                    *state = ParticleSleepState::default();
+                }
+            }
+        }
+    }
+
+    /// Add a fluid to the liquid world.
+    pub fn add_fluid(&mut self, fluid: Fluid) -> FluidHandle {
+        self.fluids.insert(fluid)
+    }
+
+    /// Add a boundary to the liquid world.
+    pub fn add_boundary(&mut self, boundary: Boundary) -> BoundaryHandle {
+        self.boundaries.insert(boundary)
+    }
+
+    /// Add a planar boundary simulated with mirrored ghost particles to the liquid world.
+    ///
+    /// The returned handle identifies the boundary object holding the ghost particles. Its particles
+    /// are regenerated at each substep, so they should not be modified manually.
+    pub fn add_ghost_boundary(&mut self, ghost: GhostBoundary) -> BoundaryHandle {
+        let mut boundary = Boundary::new(Vec::new());
+        boundary.set_fixed_volumes(true);
+        let handle = self.boundaries.insert(boundary);
+        let _ = self.ghost_boundaries.insert(handle, ghost);
+        handle
+    }
+
+    /// The ghost boundary associated to the given boundary handle, if any.
+    pub fn ghost_boundary(&self, handle: BoundaryHandle) -> Option<&GhostBoundary> {
+        self.ghost_boundaries.get(&handle)
+    }
+
+    /// Add a static collider described by an analytic shape to the liquid world.
+    ///
+    /// The returned handle identifies the boundary object holding the particles generated for this
+    /// collider. Its particles are regenerated at each substep, so they should not be modified
+    /// manually.
+    pub fn add_analytic_collider(&mut self, collider: AnalyticCollider) -> BoundaryHandle {
+        let mut boundary = Boundary::new(Vec::new());
+        boundary.set_fixed_volumes(true);
+        let handle = self.boundaries.insert(boundary);
+        let _ = self.analytic_colliders.insert(handle, collider);
+        handle
+    }
+
+    /// The analytic collider associated to the given boundary handle, if any.
+    pub fn analytic_collider(&self, handle: BoundaryHandle) -> Option<&AnalyticCollider> {
+        self.analytic_colliders.get(&handle)
+    }
+
+    /// Add a plane reflecting the fluid particles crossing it at the end of each substep.
+    ///
+    /// The plane passes through `point`, and the fluid is kept on the side `normal` points to. This
+    /// is a `ReflectivePlane`: it does not involve any boundary particle, so it is a one-line floor
+    /// for tests and demos, which can be combined with the other boundaries. The normal velocity of
+    /// the reflected particles is scaled by `restitution`, typically in `[0, 1]`.
+    pub fn add_reflective_plane(
+        &mut self,
+        point: Point<Real>,
+        normal: Unit<Vector<Real>>,
+        restitution: Real,
+    ) {
+        self.reflective_planes
+            .push(ReflectivePlane::new(point, normal, restitution));
+    }
+
+    /// The reflective planes of this liquid world, in the order they were added.
+    pub fn reflective_planes(&self) -> &[ReflectivePlane] {
+        &self.reflective_planes
+    }
+
+    /// The mutable reflective planes of this liquid world, e.g., to move or remove them.
+    pub fn reflective_planes_mut(&mut self) -> &mut Vec<ReflectivePlane> {
+        &mut self.reflective_planes
+    }
+
+    /// Add a probe measuring the fluids inside of, and flowing through, a region of this liquid world.
+    ///
+    /// The measurements of the probe are updated at the end of each step, and can be read from
+    /// `self.probe(handle)`.
+    pub fn add_probe(&mut self, probe: Probe) -> ProbeHandle {
+        self.probes.insert(probe)
+    }
+
+    /// Removes a probe from this liquid world.
+    pub fn remove_probe(&mut self, handle: ProbeHandle) -> Option<Probe> {
+        self.probes.remove(handle)
+    }
+
+    /// The probe with the given handle, if any.
+    pub fn probe(&self, handle: ProbeHandle) -> Option<&Probe> {
+        self.probes.get(handle)
+    }
+
+    /// The set of probes on this liquid world.
+    pub fn probes(&self) -> &ProbeSet {
+        &self.probes
+    }
+
+    /// Add a fluid to the liquid world.
+    pub fn remove_fluid(&mut self, handle: FluidHandle) -> Option<Fluid> {
+        let _ = self.reference_positions.remove(&handle);
+        let _ = self.previous_positions.remove(&handle);
+        let _ = self.sleep_states.remove(&handle);
+        let _ = self.fluid_solvers.remove(&handle);
+        let _ = self.attachments.remove(&handle);
+        self.moved_particles.retain(|(h, _)| *h != handle);
+        self.fluids.remove(handle)
+    }
+
+    /// Add a boundary to the liquid world.
+    pub fn remove_boundary(&mut self, handle: BoundaryHandle) -> Option<Boundary> {
+        let _ = self.ghost_boundaries.remove(&handle);
+        let _ = self.analytic_colliders.remove(&handle);
+        self.boundaries.remove(handle)
+    }
+
+    /// Removes all the fluids and boundaries of this liquid world, and resets its simulation state.
+    ///
+    /// The contacts, the spacial grid, the state of the pressure solver, the timestep, the elapsed
+    /// time, the step counts, the counters, the tracking of moved particles, the sleeping states of the particles, and
+    /// the measurements of the probes are all reset, so that filling the world again behaves as a brand-new world. The configuration of
+    /// the world is kept: the pressure solver parameters, the particle and kernel radii, the
+    /// probes, and the settings of the contact manager, stability guard, motion
+    /// threshold, sleep and resampling parameters, global damping, and pause state. The handles of the removed fluids and boundaries are invalidated.
+    pub fn reset(&mut self) {
+        let fluids: Vec<_> = self.fluids.iter().map(|(handle, _)| handle).collect();
+        let boundaries: Vec<_> = self.boundaries.iter().map(|(handle, _)| handle).collect();
+
+        for handle in fluids {
+            let _ = self.remove_fluid(handle);
+        }
+
+        for handle in boundaries {
+            let _ = self.remove_boundary(handle);
+        }
+
+        self.contact_manager.fluid_fluid_contacts.clear();
+        self.contact_manager.fluid_boundary_contacts.clear();
+        self.contact_manager.boundary_boundary_contacts.clear();
+        self.hgrid.clear();
+
+        if let Some(solver) = self.solver.as_stateful_mut() {
+            solver.reset();
+        }
+
+        self.timestep_manager = TimestepManager::new(self.particle_radius);
+
+        self.elapsed_time = na::zero::<Real>();
+        self.step_count = 0;
+        self.substep_count = 0;
+        self.nsubsteps_since_sort = 0;
+        self.counters.reset();
+        self.hgrid_fluids.clear();
+        for positions in self.reference_positions.values() {
+            positions.lock().unwrap().clear();
+        }
+        self.previous_positions.clear();
+        self.moved_particles.clear();
+        for states in self.sleep_states.values() {
+            states.lock().unwrap().clear();
+        }
+        self.steps_since_resampling = 0;
+
+        for probe in self.probes.as_mut_slice() {
+            probe.reset_measurement();
+        }
+    }
+
+    /// The set of fluids on this liquid world.
+    pub fn fluids(&self) -> &FluidSet {
+        &self.fluids
+    }
+
+    /// The mutable set of fluids on this liquid world.
+    pub fn fluids_mut(&mut self) -> &mut FluidSet {
+        &mut self.fluids
+    }
+
+    /// The set of boundaries on this liquid world.
+    pub fn boundaries(&self) -> &BoundarySet {
+        &self.boundaries
+    }
+
+    /// The mutable set of boundaries on this liquid world.
+    pub fn boundaries_mut(&mut self) -> &mut BoundarySet {
+        &mut self.boundaries
+    }
+
+    /// The total force and torque applied by the fluids to the given boundary during the last substep.
+    ///
+    /// These are the sums of the forces accumulated on the particles of the boundary, i.e., the
+    /// forces given to the coupling manager at the end of the last substep of `self.step`, before
+    /// any smoothing by the coupling manager. The torque is computed about `torque_center`, e.g.,
+    /// the center of mass of the coupled body. Returns `None` if the boundary does not exist or
+    /// does not accumulate forces, i.e., if its `forces` are `None`, as for the boundaries not
+    /// coupled with any body.
+    pub fn boundary_force(
+        &self,
+        handle: BoundaryHandle,
+        torque_center: &Point<Real>,
+    ) -> Option<(Vector<Real>, AngularVector<Real>)> {
+        let boundary = self.boundaries.get(handle)?;
+        let forces = boundary.forces.as_ref()?.read().unwrap();
+        let mut force = Vector::zeros();
+        let mut torque = AngularVector::zeros();
+
+        for (pt, f) in boundary.positions.iter().zip(forces.iter()) {
+            force += f;
+            #[cfg(feature = "dim2")]
+            {
+                torque.x += (pt - torque_center).perp(f);
+            }
+            #[cfg(feature = "dim3")]
+            {
+                torque += (pt - torque_center).cross(f);
+            }
+        }
+
+        Some((force, torque))
+    }
+
+    /// Sets the forces accumulated on the particles of the given boundary to zero.
+    ///
+    /// Does nothing if the boundary does not exist or does not accumulate forces.
+    pub fn clear_boundary_forces(&mut self, handle: BoundaryHandle) {
+        if let Some(boundary) = self.boundaries.get_mut(handle) {
+            boundary.clear_forces(false);
+        }
+    }
+
+    /// Overrides the forces accumulated on the particles of the given boundary, one per particle.
+    ///
+    /// This lets the forces be inspected, or transmitted with `CouplingManager::transmit_forces`,
+    /// with known values. Accumulating forces is enabled for this boundary if it was not. The
+    /// forces are cleared by the coupling manager at the beginning of each substep. Does nothing
+    /// if the boundary does not exist.
+    ///
+    /// Panics if there is not exactly one force per particle of the boundary.
+    pub fn set_boundary_forces(&mut self, handle: BoundaryHandle, forces: &[Vector<Real>]) {
+        if let Some(boundary) = self.boundaries.get_mut(handle) {
+            assert_eq!(
+                forces.len(),
+                boundary.num_particles(),
+                "A boundary must have exactly one force per particle."
+            );
+            boundary.forces = Some(RwLock::new(forces.to_vec()));
+        }
+    }
+
+    /// The contact manager responsible for the neighborhood search of this liquid world.
+    pub fn contact_manager(&self) -> &ContactManager {
+        &self.contact_manager
+    }
+
+    /// The mutable contact manager responsible for the neighborhood search of this liquid world.
+    pub fn contact_manager_mut(&mut self) -> &mut ContactManager {
+        &mut self.contact_manager
+    }
+
+    /// Emits new particles into the given fluid, with velocities blended toward the local fluid velocity.
+    ///
+    /// Each new particle at `positions[i]` is given the velocity `velocities[i] * (1 - settling) + v * settling`
+    /// where `v` is the fluid velocity sampled at its position with `self.sample_velocity`. This
+    /// reduces the pressure shocks caused by particles injected into a fluid moving differently.
+    /// A `settling` of zero keeps the given velocities, which are also kept as-is for the particles
+    /// without any fluid particle nearby.
+    ///
+    /// Returns `false`, without adding any particle, if the fluid does not exist.
+    pub fn emit_particles(
+        &mut self,
+        handle: FluidHandle,
+        positions: &[Point<Real>],
+        velocities: &[Vector<Real>],
+        settling: Real,
+    ) -> bool {
+        assert!(
+            settling >= na::zero::<Real>() && settling <= na::one::<Real>(),
+            "The settling factor must be between 0 and 1."
+        );
+        assert_eq!(
+            positions.len(),
+            velocities.len(),
+            "The provided positions and velocities arrays must have the same length."
+        );
+
+        if self.fluids.get(handle).is_none() {
+            return false;
+        }
+
+        let velocities: Vec<_> = positions
+            .iter()
+            .zip(velocities.iter())
+            .map(|(pt, vel)| {
+                if settling == na::zero::<Real>() {
+                    return *vel;
+                }
+
+                match self.sample_velocity(pt) {
+                    Some(fluid_vel) => vel.lerp(&fluid_vel, settling),
+                    None => *vel,
+                }
+            })
+            .collect();
+
+        self.fluids[handle].add_particles(positions, Some(&velocities));
+        true
+    }
+
+    // Calls `f` with each fluid particle closer than `radius` to `center`, and its offset from `center`.
+    //
+    // The particles are found with the spacial grid of the last step, unless fluids or particles
+    // were added, removed, or reordered since, in which case all the particles are tested.
+    fn for_each_fluid_particle_in_ball(
+        &mut self,
+        center: &Point<Real>,
+        radius: Real,
+        mut f: impl FnMut(&mut Fluid, usize, Vector<Real>),
+    ) {
+        let grid_is_current = self
+            .hgrid_fluids
+            .iter()
+            .copied()
+            .eq(fluid_grid_layouts(&self.fluids));
+        let fluids = self.fluids.as_mut_slice();
+        let mut visit = |fluids: &mut [Fluid], fluid_id: usize, particle_id: usize| {
+            let Some(fluid) = fluids.get_mut(fluid_id) else {
+                return;
+            };
+            let Some(offset) = fluid.positions.get(particle_id).map(|pt| pt - center) else {
+                return;
+            };
+
+            if offset.norm() < radius {
+                f(fluid, particle_id, offset);
+            }
+        };
+
+        if !grid_is_current {
+            for fluid_id in 0..fluids.len() {
+                for particle_id in 0..fluids[fluid_id].num_particles() {
+                    visit(fluids, fluid_id, particle_id);
+                }
+            }
+
+            return;
+        }
+
+        // Enlarge the region to account for the particles displacement since the grid was built.
+        let margin = Vector::repeat(radius + self.h);
+
+        for entry in self
+            .hgrid
+            .cells_intersecting_aabb(&(center - margin), &(center + margin))
+            .flat_map(|e| e.1)
+        {
+            if let HGridEntry::FluidParticle(fluid_id, particle_id) = *entry {
+                visit(fluids, fluid_id, particle_id);
+            }
+        }
+    }
+
+    /// Applies an impulse to all the fluid particles closer than `radius` to `center`.
+    ///
+    /// A particle at a distance `d` from `center` receives the impulse `impulse * (1 - d / radius)^falloff`,
+    /// resulting in a velocity change inversely proportional to its mass. A `falloff` of zero applies
+    /// the same impulse to all the particles in the region.
+    /// The particles are found using the spacial grid computed during the last step. If fluids or
+    /// particles were added, removed, or reordered since, this grid is outdated and all the particles
+    /// are tested instead, which is slower. Particles moved by more than the kernel radius since the
+    /// last step, e.g., by setting their positions directly, may be missed.
+    ///
+    /// Returns the number of particles affected by the impulse.
+    pub fn apply_impulse(
+        &mut self,
+        center: &Point<Real>,
+        radius: Real,
+        impulse: &Vector<Real>,
+        falloff: Real,
+    ) -> usize {
+        let mut num_affected = 0;
+
+        self.for_each_fluid_particle_in_ball(center, radius, |fluid, i, offset| {
+            let weight = (na::one::<Real>() - offset.norm() / radius).powf(falloff);
+            let inv_mass = fluid.particle_inv_mass(i);
+            fluid.velocities[i] += impulse * (weight * inv_mass);
+            num_affected += 1;
+        });
+
+        num_affected
+    }
+
+    /// Adds a swirling velocity around `axis` to all the fluid particles closer than `radius` to `center`.
+    ///
+    /// A particle at a distance `d` from `center` receives the velocity change `strength * (1 - d / radius)`,
+    /// along the direction of a counterclockwise rotation about the axis passing through `center` with the
+    /// direction `axis`, e.g., to create a whirlpool. In 2D, `axis` only gives the direction of the rotation,
+    /// which is counterclockwise if it is positive. Unlike `self.apply_impulse`, the velocity change does not
+    /// depend on the particle masses. The particles on the axis are not affected. The particles are found as
+    /// in `self.apply_impulse`.
+    ///
+    /// Returns the number of particles affected by the swirl.
+    pub fn apply_swirl(
+        &mut self,
+        center: &Point<Real>,
+        axis: &AngularVector<Real>,
+        radius: Real,
+        strength: Real,
+    ) -> usize {
+        let Some(axis) = axis.try_normalize(na::zero::<Real>()) else {
+            return 0;
+        };
+
+        let mut num_affected = 0;
+
+        self.for_each_fluid_particle_in_ball(center, radius, |fluid, i, offset| {
+            #[cfg(feature = "dim2")]
+            let tangent = Vector::new(-offset.y, offset.x) * axis.x;
+            #[cfg(feature = "dim3")]
+            let tangent = axis.cross(&offset);
+
+            if let Some(tangent) = tangent.try_normalize(na::zero::<Real>()) {
+                let weight = na::one::<Real>() - offset.norm() / radius;
+                fluid.velocities[i] += tangent * (strength * weight);
+                num_affected += 1;
+            }
+        });
+
+        num_affected
+    }
+
+    /// Casts a ray against the fluid particles, each seen as a ball of the given `radius`.
+    ///
+    /// Returns the fluid and index of the first particle hit by the ray `origin + dir * t`, `t >= 0`,
+    /// together with the time of impact `t`, e.g., to select the particle under the cursor. The time of
+    /// impact is zero if `origin` is inside of the ball of this particle. Only the cells of the spacial
+    /// grid along the ray are visited, so this does not account for the particles added since the last
+    /// step.
+    pub fn raycast_particles(
+        &self,
+        origin: &Point<Real>,
+        dir: &Vector<Real>,
+        radius: Real,
+    ) -> Option<(FluidHandle, usize, Real)> {
+        use crate::math::DIM;
+
+        let dir_norm2 = dir.norm_squared();
+
+        if dir_norm2 == na::zero::<Real>() {
+            return None;
+        }
+
+        // Enlarge the visited cells to account for the particles displacement since the grid was built.
+        let margin = Vector::repeat(radius + self.h);
+        let cell_widths = *self.hgrid.cell_widths();
+        let mut keys = self.hgrid.cells().map(|(key, _)| key);
+        let first_key = *keys.next()?;
+        let (mins, maxs) = keys.fold((first_key, first_key), |(mins, maxs), key| {
+            (mins.inf(key), maxs.sup(key))
+        });
+
+        // Clip the ray to the cells of the grid, enlarged by the margin.
+        let mut toi_range = (na::zero::<Real>(), Real::MAX);
+
+        for i in 0..DIM {
+            let min = mins[i] as Real * cell_widths[i] - margin[i];
+            let max = (maxs[i] + 1) as Real * cell_widths[i] + margin[i];
+
+            if dir[i] == na::zero::<Real>() {
+                if origin[i] < min || origin[i] > max {
+                    return None;
+                }
+            } else {
+                let toi1 = (min - origin[i]) / dir[i];
+                let toi2 = (max - origin[i]) / dir[i];
+                toi_range.0 = toi_range.0.max(toi1.min(toi2));
+                toi_range.1 = toi_range.1.min(toi1.max(toi2));
+            }
+        }
+
+        if toi_range.0 > toi_range.1 {
+            return None;
+        }
+
+        let start = origin + dir * toi_range.0;
+        let fluids = self.fluids.as_slice();
+        let mut visited = std::collections::HashSet::new();
+        let mut closest: Option<(usize, usize, Real)> = None;
+
+        for (key, toi) in self
+            .hgrid
+            .cells_along_ray(&start, dir, toi_range.1 - toi_range.0)
+        {
+            // A ball hit at the time of impact `t` has its center closer than `radius` to the point of
+            // the ray at `t`, so it is found around the cells traversed before `t`.
+            if closest.is_some_and(|(_, _, best_toi)| toi_range.0 + toi > best_toi) {
+                break;
+            }
+
+            let cell_mins = Point::from(key.coords.zip_map(&cell_widths, |k, w| k as Real * w));
+            let cell_maxs = cell_mins + cell_widths;
+
+            for (neighbor_key, entries) in self
+                .hgrid
+                .cells_intersecting_aabb(&(cell_mins - margin), &(cell_maxs + margin))
+            {
+                if !visited.insert(neighbor_key) {
+                    continue;
+                }
+
+                for entry in entries {
+                    if let HGridEntry::FluidParticle(fluid_id, particle_id) = *entry {
+                        let Some(center) = fluids
+                            .get(fluid_id)
+                            .and_then(|fluid| fluid.positions.get(particle_id))
+                        else {
+                            continue;
+                        };
+
+                        // Solve `|origin + dir * t - center|² = radius²` for the smallest `t >= 0`.
+                        let local_origin = origin - center;
+                        let b = local_origin.dot(dir);
+                        let c = local_origin.norm_squared() - radius * radius;
+
+                        let hit_toi = if c <= na::zero::<Real>() {
+                            na::zero::<Real>()
+                        } else {
+                            let discriminant = b * b - dir_norm2 * c;
+
+                            if b >= na::zero::<Real>() || discriminant < na::zero::<Real>() {
+                                continue;
+                            }
+
+                            (-b - discriminant.sqrt()) / dir_norm2
+                        };
+
+                        if closest.is_none_or(|(_, _, best_toi)| hit_toi < best_toi) {
+                            closest = Some((fluid_id, particle_id, hit_toi));
+                        }
+                    }
+                }
+            }
+        }
+
+        let (fluid_id, particle_id, toi) = closest?;
+        let (_, handle) = self.fluids.get_from_contiguous_index(fluid_id)?;
+        Some((handle, particle_id, toi))
+    }
+
+    /// Computes the height of the free surface of all the fluids over a horizontal grid.
+    ///
+    /// Each column of the heightfield has a width of `cell_width` and its height is the height
+    /// of its highest fluid particle along the axis `up_axis`, or `None` if it is empty. This relies
+    /// on the spacial grid built during the last step, so this does not account for the particles
+    /// added since.
+    pub fn surface_heightfield(&self, cell_width: Real, up_axis: usize) -> SurfaceHeightfield {
+        geometry::compute_surface_heightfield(
+            &self.hgrid,
+            self.fluids.as_slice(),
+            cell_width,
+            up_axis,
+        )
+    }
+
+    /// The positions of the particles of the given fluid, linearly interpolated between the beginning
+    /// and the end of the last step, e.g., to render smooth motions at a framerate different from the
+    /// simulation rate.
+    ///
+    /// An `alpha` of 0 gives the positions at the beginning of the last step, and an `alpha` of 1
+    /// gives exactly the current positions. The particles added since the beginning of the last step
+    /// are given their current positions. Returns `None` if the fluid does not exist. The positions
+    /// are not interpolated properly if the particles were reordered or removed since the last step.
+    pub fn interpolated_positions(
+        &self,
+        handle: FluidHandle,
+        alpha: Real,
+    ) -> Option<Vec<Point<Real>>> {
+        let fluid = self.fluids.get(handle)?;
+        let previous = self
+            .previous_positions
+            .get(&handle)
+            .map(|positions| &positions[..])
+            .unwrap_or(&[]);
+        let weight = na::one::<Real>() - alpha;
+
+        Some(
+            fluid
+                .positions
+                .iter()
+                .enumerate()
+                .map(|(i, pos)| match previous.get(i) {
+                    Some(previous) => pos + (previous - pos) * weight,
+                    None => *pos,
+                })
+                .collect(),
+        )
+    }
+
+    /// Interpolates the velocity of the fluids at the given point, e.g., to advect passive particles.
+    ///
+    /// This is the normalized SPH interpolation, with a cubic spline kernel, of the velocities of
+    /// the fluid particles inside of the kernel support centered at `point`. Returns `None` if there is no
+    /// such particle. This relies on the spacial grid built during the last step, so this does not
+    /// account for the particles added since.
+    pub fn sample_velocity(&self, point: &Point<Real>) -> Option<Vector<Real>> {
+        let mut velocity = Vector::zeros();
+        let mut total_weight = na::zero::<Real>();
+
+        self.for_each_fluid_neighbor(point, |fluid, i, weight| {
+            velocity += fluid.velocities[i] * weight;
+            total_weight += weight;
+        });
+
+        if total_weight > na::zero::<Real>() {
+            Some(velocity / total_weight)
+        } else {
+            None
+        }
+    }
+
+    /// Computes the radially-averaged kinetic energy spectrum of the fluids, e.g., to study turbulence.
+    ///
+    /// The velocity of the fluids is sampled with `self.sample_velocity` at the centers of the cells
+    /// of a regular grid with `resolution` cells along each axis, covering the square (or cube) of
+    /// side `size` with the lower corner `mins`. The velocity is zero where there is no fluid, and the
+    /// grid is assumed to be periodic.
+    ///
+    /// The `k`-th element of the result is the energy per unit mass of the Fourier modes with a
+    /// wavenumber norm closest to `2π k / size`, and the elements sum to the mean of `|v|² / 2` over
+    /// the grid cells. The elements beyond `resolution / 2` only account for the corners of the
+    /// Fourier domain, and are usually ignored.
+    #[cfg(feature = "spectrum")]
+    pub fn kinetic_energy_spectrum(
+        &self,
+        mins: &Point<Real>,
+        size: Real,
+        resolution: usize,
+    ) -> Vec<Real> {
+        use crate::math::DIM;
+
+        let cell_width = size / resolution as Real;
+        let velocities: Vec<_> = (0..resolution.pow(DIM as u32))
+            .map(|i| {
+                let mut point = *mins;
+
+                for axis in 0..DIM {
+                    let k = (i / resolution.pow(axis as u32)) % resolution;
+                    point[axis] += (k as Real + na::convert::<_, Real>(0.5)) * cell_width;
+                }
+
+                self.sample_velocity(&point).unwrap_or_else(Vector::zeros)
+            })
+            .collect();
+
+        crate::energy_spectrum::radial_energy_spectrum(&velocities, resolution)
+    }
+
+    // Calls `f` with each fluid particle inside of the kernel support centered at `point`, and the
+    // product of its volume with the kernel evaluated at its distance to `point`.
+    pub(crate) fn for_each_fluid_neighbor(
+        &self,
+        point: &Point<Real>,
+        mut f: impl FnMut(&Fluid, usize, Real),
+    ) {
+        let fluids = self.fluids.as_slice();
+        let anisotropy = self.contact_manager.kernel_anisotropy();
+        let radii = anisotropy * self.h;
+
+        for (_, entries) in self
+            .hgrid
+            .neighbor_cells_with_radii(&self.hgrid.key(point), &radii)
+        {
+            for entry in entries {
+                if let HGridEntry::FluidParticle(fluid_id, particle_id) = entry {
+                    let Some(fluid) = fluids.get(*fluid_id) else {
+                        continue;
+                    };
+                    let Some(pt) = fluid.positions.get(*particle_id) else {
+                        continue;
+                    };
+
+                    if (pt - point).component_div(anisotropy).norm_squared() < self.h * self.h {
+                        let weight = CubicSplineKernel::points_apply_anisotropic(
+                            point, pt, self.h, anisotropy,
+                        );
+                        f(fluid, *particle_id, fluid.volumes[*particle_id] * weight);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The center of mass of the given fluid.
+    ///
+    /// Returns `None` if the fluid does not exist or has no particle.
+    pub fn fluid_center_of_mass(&self, handle: FluidHandle) -> Option<Point<Real>> {
+        self.fluids.get(handle)?.center_of_mass()
+    }
+
+    /// A sphere enclosing all the particles of the given fluid, given as its center and radius.
+    ///
+    /// Returns `None` if the fluid does not exist or has no particle.
+    pub fn fluid_bounding_sphere(&self, handle: FluidHandle) -> Option<(Point<Real>, Real)> {
+        self.fluids.get(handle)?.bounding_sphere()
+    }
+
+    /// The total volume of the fluid particles inside of the given half-space.
+    ///
+    /// The half-space is bounded by the plane passing through `point` with the outward `normal`,
+    /// i.e., it contains the points `p` such that `(p - point).dot(normal) <= 0`. The volumes of the
+    /// particles of all the fluids are summed, so this remains accurate with particles of different
+    /// sizes. Returns zero if no particle is inside of the half-space.
+    pub fn fluid_volume_in_halfspace(&self, point: &Point<Real>, normal: &Vector<Real>) -> Real {
+        self.fluids
+            .as_slice()
+            .iter()
+            .flat_map(|fluid| fluid.positions.iter().zip(fluid.volumes.iter()))
+            .filter(|(pt, _)| (*pt - point).dot(normal) <= na::zero::<Real>())
+            .map(|(_, volume)| *volume)
+            .sum()
+    }
+
+    /// The average relative density deviation `(density - density0) / density0` of all the fluid particles.
+    ///
+    /// Positive values indicate that the fluids are compressed, and negative values that they
+    /// are expanded. The densities computed by the solver during the last substep are reused when
+    /// available. Returns zero if there is no fluid particle.
+    pub fn volume_compression(&self) -> Real {
+        let mut total = na::zero::<Real>();
+        let mut num_particles = 0;
+
+        for (fluid_id, fluid) in self.fluids.as_slice().iter().enumerate() {
+            total += self.total_density_deviation(fluid_id, fluid);
+            num_particles += fluid.num_particles();
+        }
+
+        if num_particles == 0 {
+            na::zero::<Real>()
+        } else {
+            total / (num_particles as Real)
+        }
+    }
+
+    /// The average relative density deviation `(density - density0) / density0` of the particles of one fluid.
+    ///
+    /// See `self.volume_compression` for details. Returns `None` if the fluid does not exist.
+    pub fn fluid_volume_compression(&self, handle: FluidHandle) -> Option<Real> {
+        let fluid = self.fluids.get(handle)?;
+        let fluid_id = self.fluids.contiguous_index(handle)?;
+
+        if fluid.num_particles() == 0 {
+            return Some(na::zero::<Real>());
+        }
+
+        Some(self.total_density_deviation(fluid_id, fluid) / (fluid.num_particles() as Real))
+    }
+
+    fn total_density_deviation(&self, fluid_id: usize, fluid: &Fluid) -> Real {
+        self.estimate_densities(fluid_id, fluid)
+            .iter()
+            .enumerate()
+            .map(|(i, density)| (*density - fluid.rest_density(i)) / fluid.rest_density(i))
+            .sum()
+    }
+
+    /// The densities of the particles of the given fluid, as computed during the last substep.
+    ///
+    /// These are the densities computed once per substep before the non-pressure forces and the
+    /// pressure solve, and given to each `NonPressureForce`. Returns `None` if the fluid does not
+    /// exist, if the pressure solver does not keep track of densities, or if particles were added
+    /// or removed since the last substep.
+    pub fn fluid_densities(&self, handle: FluidHandle) -> Option<&[Real]> {
+        let fluid = self.fluids.get(handle)?;
+        let fluid_id = self.fluids.contiguous_index(handle)?;
+        let densities = self.solver.densities()?.get(fluid_id)?;
+
+        if densities.len() == fluid.num_particles() {
+            Some(densities)
+        } else {
+            None
+        }
+    }
+
+    /// The distinct boundaries touched by the `particle`-th particle of the given fluid.
+    ///
+    /// A boundary is touched if at least one of its particles is inside of the kernel support of
+    /// the fluid particle, as detected at the beginning of the last substep, so this can be called
+    /// right after `self.step` to react to the fluids touching a specific boundary. Each boundary is
+    /// yielded once, in the order of `self.boundaries()`. This yields nothing if the fluid or the
+    /// particle does not exist, and may be inaccurate if fluids, particles, or boundaries were added
+    /// or removed since the last step.
+    pub fn fluid_boundary_contacts_for(
+        &self,
+        fluid: FluidHandle,
+        particle: usize,
+    ) -> impl Iterator<Item = BoundaryHandle> + '_ {
+        let mut boundary_ids: Vec<_> = self
+            .fluids
+            .contiguous_index(fluid)
+            .and_then(|fluid_id| self.contact_manager.fluid_boundary_contacts.get(fluid_id))
+            .and_then(|contacts| contacts.contacts().get(particle))
+            .map(|contacts| contacts.read().unwrap().iter().map(|c| c.j_model).collect())
+            .unwrap_or_default();
+        boundary_ids.sort_unstable();
+        boundary_ids.dedup();
+
+        boundary_ids.into_iter().filter_map(|boundary_id| {
+            self.boundaries
+                .get_from_contiguous_index(boundary_id)
+                .map(|(_, handle)| handle)
+        })
+    }
+
+    /// The fluid particles that went through a boundary surface during the last step, e.g., to check
+    /// that a tank is watertight.
+    ///
+    /// The boundary particles within the kernel radius of the segment swept by a fluid particle
+    /// during the step are its candidates, so that the particles going through a wall in a single
+    /// step are detected even if they end far behind it. A particle leaked if, relative to the
+    /// candidate closest to its current position, it started the step on the side pointed by the
+    /// boundary normal, and ended it on the other side, deeper than the particle radius, after
+    /// crossing the boundary surface within the kernel radius of this boundary particle. The
+    /// particles sliding along a wall, or slightly penetrating it, are not reported. Only the
+    /// boundaries with normals, e.g., built with `Boundary::with_normals` or the primitive shapes
+    /// like `Boundary::cuboid`, are checked, and only for the fluids they interact with. The particles are given as their fluid handle and
+    /// their index on this fluid, and the particles added since the beginning of the last step are
+    /// ignored. The result is not accurate if the particles were reordered or removed, or the
+    /// boundaries modified, since the last step.
+    pub fn leaked_particles(&self) -> Vec<(FluidHandle, usize)> {
+        let mut grid = HGrid::new(self.h);
+
+        for (boundary_id, boundary) in self.boundaries.as_slice().iter().enumerate() {
+            if boundary.normals.is_some() {
+                for (j, pt) in boundary.positions.iter().enumerate() {
+                    grid.insert(pt, (boundary_id, j));
+                }
+            }
+        }
+
+        let boundaries = self.boundaries.as_slice();
+        let mut result = Vec::new();
+
+        for (fluid_id, fluid) in self.fluids.as_slice().iter().enumerate() {
+            let Some((_, handle)) = self.fluids.get_from_contiguous_index(fluid_id) else {
+                continue;
+            };
+            let Some(previous) = self.previous_positions.get(&handle) else {
+                continue;
+            };
+
+            for (i, (start, end)) in previous.iter().zip(fluid.positions.iter()).enumerate() {
+                let sweep = end - start;
+                let sweep_length2 = sweep.norm_squared();
+                // The cells around evenly spaced points of the swept segment, at most `h` apart.
+                let num_samples = (sweep_length2.sqrt() / self.h).ceil() as usize;
+                let closest = (0..=num_samples)
+                    .flat_map(|k| {
+                        let sample = start + sweep * (k as Real / num_samples.max(1) as Real);
+                        grid.neighbor_cells(&grid.key(&sample), self.h)
+                    })
+                    .flat_map(|(_, particles)| particles.iter().copied())
+                    .filter(|(boundary_id, _)| {
+                        boundaries[*boundary_id].interacts_with_fluid(fluid_id)
+                    })
+                    .filter(|(boundary_id, j)| {
+                        let pt = boundaries[*boundary_id].positions[*j];
+                        let t = if sweep_length2 > na::zero::<Real>() {
+                            (sweep.dot(&(pt - start)) / sweep_length2)
+                                .clamp(na::zero::<Real>(), na::one::<Real>())
+                        } else {
+                            na::zero::<Real>()
+                        };
+                        na::distance(&(start + sweep * t), &pt) <= self.h
+                    })
+                    .map(|(boundary_id, j)| {
+                        let pt = boundaries[boundary_id].positions[j];
+                        (boundary_id, j, na::distance(end, &pt))
+                    })
+                    .min_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal));
+
+                let Some((boundary_id, j, _)) = closest else {
+                    continue;
+                };
+
+                let boundary = &boundaries[boundary_id];
+                let (pt, normal) = match &boundary.normals {
+                    Some(normals) => (boundary.positions[j], normals[j]),
+                    None => continue,
+                };
+                let start_dist = normal.dot(&(start - pt));
+                let end_dist = normal.dot(&(end - pt));
+
+                if start_dist < na::zero::<Real>() || end_dist >= -self.particle_radius {
+                    continue;
+                }
+
+                let crossing = start + (end - start) * (start_dist / (start_dist - end_dist));
+
+                if na::distance(&crossing, &pt) <= self.h {
+                    result.push((handle, i));
+                }
+            }
+        }
+
+        result
+    }
+
+    /// The area of the given boundary currently wetted by the fluids.
+    ///
+    /// This sums the areas of the boundary particles having at least one fluid neighbor during the
+    /// last step. The area of each boundary particle is estimated from its volume `V` computed as
+    /// in Akinci et al. 2012 as `V^((DIM - 1) / DIM)`, i.e., it is a length in 2D. Dividing this by
+    /// `self.boundary_area(handle)` gives the submerged fraction of the boundary. Returns zero if
+    /// no fluid particle touches the boundary, and `None` if the boundary does not exist.
+    pub fn boundary_wetted_area(&self, handle: BoundaryHandle) -> Option<Real> {
+        let boundary = self.boundaries.get(handle)?;
+        let boundary_id = self.boundaries.contiguous_index(handle)?;
+        let mut wetted = vec![false; boundary.num_particles()];
+
+        for contacts in &self.contact_manager.fluid_boundary_contacts {
+            for c in contacts.iter_pairs() {
+                if c.j_model == boundary_id {
+                    if let Some(wetted) = wetted.get_mut(c.j) {
+                        *wetted = true;
+                    }
+                }
+            }
+        }
+
+        Some(
+            boundary
+                .volumes
+                .iter()
+                .zip(wetted.iter())
+                .filter(|(_, wetted)| **wetted)
+                .map(|(volume, _)| particle_area(*volume))
+                .sum(),
+        )
+    }
+
+    /// The total area of the given boundary, estimated from the volumes of its particles.
+    ///
+    /// See `self.boundary_wetted_area` for details. Returns `None` if the boundary does not exist.
+    pub fn boundary_area(&self, handle: BoundaryHandle) -> Option<Real> {
+        let boundary = self.boundaries.get(handle)?;
+        Some(boundary.volumes.iter().map(|v| particle_area(*v)).sum())
+    }
+
+    /// Estimates the pressure at each particle of the given fluid, independently from the solver used.
+    ///
+    /// The pressure is computed from the particle densities with the Tait equation of state
+    /// `p = rho0 * c² / 7 * ((rho / rho0)^7 - 1)` where `c` is `self.speed_of_sound()`, 10m/s by
+    /// default, and `rho0` is the rest density of the particle.
+    /// Negative pressures are clamped to zero. The densities computed by the solver during the
+    /// last substep are reused when available. Otherwise, they are computed from the last contacts.
+    /// This does not modify the simulation state.
+    ///
+    /// Returns `None` if the fluid does not exist.
+    pub fn compute_pressure_estimate(&self, handle: FluidHandle) -> Option<Vec<Real>> {
+        let fluid = self.fluids.get(handle)?;
+        let fluid_id = self.fluids.contiguous_index(handle)?;
+        Some(self.pressure_estimate(fluid_id, fluid))
+    }
+
+    /// Labels the connected components of the given fluid, e.g., to detect when a stream splits into droplets.
+    ///
+    /// Two particles of the fluid are connected if they are neighbors during the last step, i.e.,
+    /// if they have a contact in the fluid-fluid contacts. The `i`-th element of the result is the
+    /// label of the component of the `i`-th particle. The labels are consecutive integers starting at
+    /// zero, numbered in the order of the first particle of each component, so a fluid made of a
+    /// single body of liquid has all its particles labeled zero. Particles added since the last step
+    /// have no contacts yet, so each of them is its own component.
+    ///
+    /// Returns `None` if the fluid does not exist.
+    pub fn connected_components(&self, handle: FluidHandle) -> Option<Vec<usize>> {
+        let fluid = self.fluids.get(handle)?;
+        let fluid_id = self.fluids.contiguous_index(handle)?;
+        let num_particles = fluid.num_particles();
+        let mut parents: Vec<_> = (0..num_particles).collect();
+
+        if let Some(contacts) = self.contact_manager.fluid_fluid_contacts.get(fluid_id) {
+            for c in contacts.iter_pairs() {
+                if c.j_model == fluid_id && c.i < num_particles && c.j < num_particles {
+                    let root_i = find_root(&mut parents, c.i);
+                    let root_j = find_root(&mut parents, c.j);
+                    parents[root_i.max(root_j)] = root_i.min(root_j);
+                }
+            }
+        }
+
+        // Each root is the smallest particle index of its component, so it is labeled before
+        // the other particles of its component.
+        let mut labels = vec![0; num_particles];
+        let mut num_components = 0;
+
+        for i in 0..num_particles {
+            let root = find_root(&mut parents, i);
+
+            if root == i {
+                labels[i] = num_components;
+                num_components += 1;
+            } else {
+                labels[i] = labels[root];
+            }
+        }
+
+        Some(labels)
+    }
+
+    /// The indices of the particles of the largest connected component of the given fluid.
+    ///
+    /// See `self.connected_components` for details. If several components have the same number of
+    /// particles, the one with the smallest label is returned. Returns `None` if the fluid does not
+    /// exist, and an empty vector if it has no particle.
+    pub fn largest_connected_component(&self, handle: FluidHandle) -> Option<Vec<usize>> {
+        let labels = self.connected_components(handle)?;
+        let num_components = labels.iter().max().map(|l| l + 1).unwrap_or(0);
+        let mut sizes = vec![0usize; num_components];
+        labels.iter().for_each(|l| sizes[*l] += 1);
+
+        let Some(largest) = (0..num_components).max_by_key(|l| (sizes[*l], std::cmp::Reverse(*l)))
+        else {
+            return Some(Vec::new());
+        };
+
+        Some(
+            (0..labels.len())
+                .filter(|i| labels[*i] == largest)
+                .collect(),
+        )
+    }
+
+    /// Computes the stress tensor at each particle of the given fluid, e.g., to visualize principal stress directions.
+    ///
+    /// The stress of a Newtonian fluid is `σ = -p I + 2 μ D`, where `p` is the pressure estimated by
+    /// `self.compute_pressure_estimate`, `μ` is the given `dynamic_viscosity`, and `D` is the strain
+    /// rate tensor, i.e., the symmetric part of the velocity gradient. The velocity gradient of a
+    /// particle `i` is estimated from its fluid neighbors during the last step as
+    /// `Σ_j V_j (v_j - v_i) ⊗ ∇W_ij`, where `V_j` is the volume of the neighbor `j`, and corrected
+    /// with the inverse of `Σ_j V_j (x_j - x_i) ⊗ ∇W_ij` so that linear velocity fields are
+    /// differentiated exactly, even near the free surface.
+    ///
+    /// The returned tensors are symmetric, and their eigenvectors are the principal stress directions.
+    /// This is expensive so it is only computed when this method is called, and it does not modify
+    /// the simulation state. Returns `None` if the fluid does not exist.
+    pub fn fluid_stress(
+        &self,
+        handle: FluidHandle,
+        dynamic_viscosity: Real,
+    ) -> Option<Vec<Matrix<Real>>> {
+        let fluid = self.fluids.get(handle)?;
+        let fluid_id = self.fluids.contiguous_index(handle)?;
+        let pressures = self.pressure_estimate(fluid_id, fluid);
+        let fluid_fluid_contacts = self.contact_manager.fluid_fluid_contacts.get(fluid_id);
+        let fluids = self.fluids.as_slice();
+        let two = na::convert::<_, Real>(2.0);
+
+        let stress = (0..fluid.num_particles())
+            .map(|i| {
+                let mut velocity_gradient = Matrix::zeros();
+                let mut correction = Matrix::zeros();
+
+                if let Some(ff) = fluid_fluid_contacts.filter(|ff| i < ff.contacts().len()) {
+                    for c in ff.iter_particle(i) {
+                        let fluid_j = &fluids[c.j_model];
+                        let dvel = fluid_j.velocities[c.j] - fluid.velocities[i];
+                        let dpos = fluid_j.positions[c.j] - fluid.positions[i];
+                        let weighted_gradient = c.gradient.transpose() * fluid_j.volumes[c.j];
+                        velocity_gradient += dvel * weighted_gradient;
+                        correction += dpos * weighted_gradient;
+                    }
+                }
+
+                // Isolated particles, with a singular correction, are left uncorrected.
+                if let Some(inv_correction) = correction.try_inverse() {
+                    velocity_gradient *= inv_correction;
+                }
+
+                let strain_rate = (velocity_gradient + velocity_gradient.transpose()) / two;
+                strain_rate * (two * dynamic_viscosity) - Matrix::identity() * pressures[i]
+            })
+            .collect();
+
+        Some(stress)
+    }
+
+    /// Computes the curvature of the free surface at each particle of the given fluid, e.g., to spawn foam at wave crests.
+    ///
+    /// The curvature is the divergence of the outward unit normals of the surface, i.e., the sum of
+    /// the principal curvatures, which is `1 / R` on a disk of radius `R` in 2D and `2 / R` on a
+    /// sphere in 3D. It is positive where the surface is convex, e.g., at crests, and negative where
+    /// it is concave, e.g., in troughs. The normals are the gradients of the color field of the fluid
+    /// computed during the last step by its non-pressure forces, e.g., `Akinci2013SurfaceTension`,
+    /// see `NonPressureForce::surface_normals`, or from the contacts of the last step if none of them
+    /// computes them. A particle is at the surface if its color gradient is large enough, and the
+    /// divergence at a surface particle is estimated in the tangent plane of the surface from its
+    /// surface neighbors only, with a correction for the missing neighbors.
+    ///
+    /// The curvature is only meaningful at the surface: the interior particles, and the isolated
+    /// ones, have a zero curvature. This is only computed when this method is called, and it does
+    /// not modify the simulation state. Returns `None` if the fluid does not exist.
+    pub fn fluid_surface_curvature(&self, handle: FluidHandle) -> Option<Vec<Real>> {
+        use crate::math::DIM;
+
+        let fluid = self.fluids.get(handle)?;
+        let fluid_id = self.fluids.contiguous_index(handle)?;
+        let num_particles = fluid.num_particles();
+        let Some(ff) = self
+            .contact_manager
+            .fluid_fluid_contacts
+            .get(fluid_id)
+            .filter(|ff| num_particles <= ff.contacts().len())
+        else {
+            return Some(vec![na::zero::<Real>(); num_particles]);
+        };
+
+        let densities = self.estimate_densities(fluid_id, fluid);
+        let volume = |j: usize| fluid.particle_mass(j) / densities[j];
+        let normals = match fluid.nonpressure_forces.iter().find_map(|force| {
+            force
+                .surface_normals()
+                .filter(|normals| normals.len() == num_particles)
+        }) {
+            Some(normals) => Cow::Borrowed(normals),
+            None => Cow::Owned(
+                (0..num_particles)
+                    .map(|i| {
+                        ff.iter_particle(i)
+                            .filter(|c| c.i_model == c.j_model)
+                            .map(|c| c.gradient * (volume(c.j) * self.h))
+                            .sum::<Vector<Real>>()
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+        };
+
+        // The outward unit normals of the surface particles. The color gradient vanishes inside of
+        // the fluid, where its direction is meaningless.
+        let surface_threshold = na::convert::<_, Real>(0.1);
+        let unit_normals: Vec<_> = normals
+            .iter()
+            .map(|normal| {
+                if normal.norm() > surface_threshold {
+                    Some(-normal.normalize())
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        let curvatures = (0..num_particles)
+            .map(|i| {
+                let Some(normal_i) = unit_normals[i] else {
+                    return na::zero::<Real>();
+                };
+                // The divergence is restricted to the tangent plane of the surface, because the
+                // normals of the neighbors deeper inside of the fluid are less accurate.
+                let tangent_projection = Matrix::identity() - normal_i * normal_i.transpose();
+                let mut divergence = na::zero::<Real>();
+                let mut correction = na::zero::<Real>();
+
+                for c in ff.iter_particle(i).filter(|c| c.i_model == c.j_model) {
+                    if let Some(normal_j) = unit_normals[c.j] {
+                        let tangent_gradient = tangent_projection * c.gradient * volume(c.j);
+                        divergence += (normal_j - normal_i).dot(&tangent_gradient);
+                        correction +=
+                            (fluid.positions[c.j] - fluid.positions[i]).dot(&tangent_gradient);
+                    }
+                }
+
+                // Account for the missing neighbors, outside of the fluid or below its surface.
+                correction /= (DIM - 1) as Real;
+
+                if correction > Real::EPSILON {
+                    divergence / correction
+                } else {
+                    na::zero::<Real>()
+                }
+            })
+            .collect();
+
+        Some(curvatures)
+    }
+
+    fn pressure_estimate(&self, fluid_id: usize, fluid: &Fluid) -> Vec<Real> {
+        let densities = self.estimate_densities(fluid_id, fluid);
+
+        let speed_of_sound = self.speed_of_sound;
+        let gamma = na::convert::<_, Real>(TAIT_EXPONENT);
+
+        densities
+            .iter()
+            .enumerate()
+            .map(|(i, density)| {
+                let density0 = fluid.rest_density(i);
+                let stiffness = density0 * speed_of_sound * speed_of_sound / gamma;
+                let ratio = *density / density0;
+                (stiffness * (ratio.powf(gamma) - na::one::<Real>())).max(na::zero::<Real>())
+            })
+            .collect()
+    }
+
+    fn estimate_densities(&self, fluid_id: usize, fluid: &Fluid) -> Cow<'_, [Real]> {
+        if let Some(densities) = self.solver.densities().and_then(|d| d.get(fluid_id)) {
+            if densities.len() == fluid.num_particles() {
+                return Cow::Borrowed(densities);
+            }
+        }
+
+        let fluid_fluid_contacts = self.contact_manager.fluid_fluid_contacts.get(fluid_id);
+        let fluid_boundary_contacts = self.contact_manager.fluid_boundary_contacts.get(fluid_id);
+        let fluids = self.fluids.as_slice();
+        let boundaries = self.boundaries.as_slice();
+        let boundary_method = self.boundary_method();
+
+        (0..fluid.num_particles())
+            .map(|i| {
+                let (Some(ff), Some(fb)) = (fluid_fluid_contacts, fluid_boundary_contacts) else {
+                    return fluid.rest_density(i);
+                };
+
+                if i >= ff.contacts().len() || i >= fb.contacts().len() {
+                    return fluid.rest_density(i);
+                }
+
+                let mut density = na::zero::<Real>();
+
+                for c in ff.iter_particle(i) {
+                    density += fluids[c.j_model].particle_mass(c.j) * c.weight;
+                }
+
+                for c in fb.iter_particle(i) {
+                    let volume = boundary_method
+                        .boundary_volume(boundaries[c.j_model].volumes[c.j], fluid.volumes[i]);
+                    density += volume * fluid.rest_density(i) * c.weight;
+                }
+
+                density
+            })
+            .collect()
+    }
+
+    /// Writes the state of every fluid particle to `writer` as CSV, e.g., for post-processing.
+    ///
+    /// The first line is the header `Self::EXPORT_COLUMNS`, followed by one line per fluid particle.
+    /// The densities are the same as the ones used by `self.volume_compression`. Consider wrapping
+    /// `writer` into a `std::io::BufWriter` when writing to a file.
+    pub fn export_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{}", Self::EXPORT_COLUMNS.join(","))?;
+
+        self.export_particles(|(fluid_index, fluid_generation), particle, values| {
+            write!(writer, "{},{},{}", fluid_index, fluid_generation, particle)?;
+
+            for value in values {
+                write!(writer, ",{}", value)?;
+            }
+
+            writeln!(writer)
+        })
+    }
+
+    /// Writes the state of every fluid particle to `writer` as a JSON array of records.
+    ///
+    /// Each record has one field per label of `Self::EXPORT_COLUMNS`. Non-finite values are written
+    /// as `null`. See `self.export_csv` for details.
+    pub fn export_json<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let columns = Self::EXPORT_COLUMNS;
+        let mut first = true;
+        write!(writer, "[")?;
+
+        self.export_particles(|(fluid_index, fluid_generation), particle, values| {
+            if !first {
+                write!(writer, ",")?;
+            }
+
+            first = false;
+            write!(
+                writer,
+                "\n{{\"{}\":{},\"{}\":{},\"{}\":{}",
+                columns[0], fluid_index, columns[1], fluid_generation, columns[2], particle
+            )?;
+
+            for (column, value) in columns[3..].iter().zip(values) {
+                if value.is_finite() {
+                    write!(writer, ",\"{}\":{}", column, value)?;
+                } else {
+                    write!(writer, ",\"{}\":null", column)?;
+                }
+            }
+
+            write!(writer, "}}")
+        })?;
+
+        writeln!(writer, "\n]")
+    }
+
+    /// Writes the contacts of the fluid particles detected during the last step to `writer` as a
+    /// CSV edge list, e.g., to analyze the connectivity of the fluids with external tools.
+    ///
+    /// The first line is the header `Self::NEIGHBOR_GRAPH_COLUMNS`, followed by all the fluid-fluid
+    /// edges, then all the fluid-boundary edges. Each pair of fluid particles in contact results
+    /// in two edges, one in each direction, and the contacts of the particles with themselves are
+    /// skipped. The contacts are read as stored by the contact manager, without detecting them
+    /// again, so they may be outdated if particles, fluids, or boundaries were added or removed since
+    /// the last step. Consider wrapping `writer` into a `std::io::BufWriter` when writing to a file.
+    pub fn export_neighbor_graph<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{}", Self::NEIGHBOR_GRAPH_COLUMNS.join(","))?;
+
+        // The raw parts of the handles, indexed like the contacts.
+        let mut fluid_handles = vec![(0, 0); self.fluids.len()];
+        let mut boundary_handles = vec![(0, 0); self.boundaries.len()];
+
+        for (handle, _) in self.fluids.iter() {
+            let id = self.fluids.contiguous_index(handle).unwrap();
+            fluid_handles[id] = Into::<ContiguousArenaIndex>::into(handle).into_raw_parts();
+        }
+
+        for (handle, _) in self.boundaries.iter() {
+            let id = self.boundaries.contiguous_index(handle).unwrap();
+            boundary_handles[id] = Into::<ContiguousArenaIndex>::into(handle).into_raw_parts();
+        }
+
+        let sections = [
+            (
+                "fluid-fluid",
+                &self.contact_manager.fluid_fluid_contacts,
+                &fluid_handles,
+            ),
+            (
+                "fluid-boundary",
+                &self.contact_manager.fluid_boundary_contacts,
+                &boundary_handles,
+            ),
+        ];
+
+        for (kind, contacts, j_handles) in sections {
+            for (fluid_id, contacts) in contacts.iter().enumerate() {
+                let Some((i_index, i_generation)) = fluid_handles.get(fluid_id) else {
+                    continue;
+                };
+
+                for c in contacts.iter_pairs() {
+                    if kind == "fluid-fluid" && c.is_same_particle_contact() {
+                        continue;
+                    }
+
+                    if let Some((j_index, j_generation)) = j_handles.get(c.j_model) {
+                        writeln!(
+                            writer,
+                            "{},{},{},{},{},{},{},{}",
+                            kind, i_index, i_generation, c.i, j_index, j_generation, c.j, c.weight
+                        )?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Calls `f` with the raw parts of the fluid handle, the index, and the position, velocity,
+    // and density of each fluid particle.
+    fn export_particles(
+        &self,
+        mut f: impl FnMut((usize, u64), usize, &[Real]) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let mut values = Vec::with_capacity(Self::EXPORT_COLUMNS.len());
+
+        for (handle, fluid) in self.fluids.iter() {
+            let fluid_id = self.fluids.contiguous_index(handle).unwrap();
+            let densities = self.estimate_densities(fluid_id, fluid);
+            let raw_handle = Into::<ContiguousArenaIndex>::into(handle).into_raw_parts();
+
+            for i in 0..fluid.num_particles() {
+                values.clear();
+                values.extend(fluid.positions[i].coords.iter().copied());
+                values.extend(fluid.velocities[i].iter().copied());
+                values.push(densities[i]);
+                f(raw_handle, i, &values)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// A checksum of the positions and velocities of all the fluid and boundary particles.
+    ///
+    /// This is meant to find where two runs that should be identical diverge, by comparing their
+    /// checksums at matching steps. Identical states have the same checksum across builds and
+    /// platforms. The checksum depends on the handles of the fluids and boundaries, but not on the
+    /// order of the particles within each of them, e.g., after a reordering of the particles of a
+    /// fluid. It is linear in the number of particles and does not allocate, so it is cheap enough
+    /// to be logged periodically.
+    pub fn checksum(&self) -> u64 {
+        let mut checksum = 0u64;
+
+        for (handle, fluid) in self.fluids.iter() {
+            let raw_handle = Into::<ContiguousArenaIndex>::into(handle).into_raw_parts();
+            checksum = checksum.wrapping_add(particles_checksum(
+                0,
+                raw_handle,
+                &fluid.positions,
+                &fluid.velocities,
+            ));
+        }
+
+        for (handle, boundary) in self.boundaries.iter() {
+            let raw_handle = Into::<ContiguousArenaIndex>::into(handle).into_raw_parts();
+            checksum = checksum.wrapping_add(particles_checksum(
+                1,
+                raw_handle,
+                &boundary.positions,
+                &boundary.velocities,
+            ));
+        }
+
+        checksum
+    }
+
+    /// Exports the warm-start state of the pressure solver, e.g., for checkpointing.
+    ///
+    /// The state is empty if the pressure solver does not implement `StatefulPressureSolver`.
+    pub fn export_solver_state(&self) -> SolverState {
+        self.solver
+            .as_stateful()
+            .map(|solver| solver.export_state())
+            .unwrap_or_default()
+    }
+
+    /// Imports a pressure solver state previously exported with `self.export_solver_state()`.
+    ///
+    /// Fails, leaving the solver unchanged, if the state does not match the current fluids.
+    pub fn import_solver_state(&mut self, state: &SolverState) -> Result<(), SolverStateError> {
+        match self.solver.as_stateful_mut() {
+            Some(solver) => solver.import_state(self.fluids.as_slice(), state),
+            None if *state == SolverState::default() => Ok(()),
+            None => Err(SolverStateError::LayoutMismatch),
+        }
+    }
+
+    /// Sets the function called after each internal iteration of the pressure solver, e.g., to
+    /// debug its convergence, or disables it if `None`.
+    ///
+    /// See `ConfigurablePressureSolver::set_iteration_callback` for details. This has no effect
+    /// if the pressure solver does not implement `ConfigurablePressureSolver`.
+    pub fn set_pressure_iteration_callback(&mut self, callback: Option<Box<IterationCallback>>) {
+        if let Some(solver) = self.solver.as_configurable_mut() {
+            solver.set_iteration_callback(callback)
+        }
+    }
+
+    /// Assigns a pressure solver to the given fluid, or reverts it to the pressure solver of this
+    /// world if `solver` is `None`.
+    ///
+    /// By default, all the fluids are handled by the single pressure solver given to
+    /// `LiquidWorld::new`. At each substep, the solvers assigned to specific fluids are run first,
+    /// each one correcting and integrating its own fluid only, and the pressure solver of this world
+    /// then handles all the other fluids. The densities still account for the particles of all the
+    /// fluids: each solver sees the particles of the fluids it does not solve as neighbors without
+    /// pressure, moving at their current velocities, and pushes them back with the reaction of its
+    /// pressure forces. This reaction is integrated by their own solver, during the same substep if
+    /// it runs later, e.g., for the fluids of the pressure solver of this world, and during the
+    /// next substep otherwise.
+    ///
+    /// Both `solver` and the pressure solver of this world must implement `PartialPressureSolver`,
+    /// as `DFSPHSolver` and `IISPHSolver` do. Otherwise, this returns
+    /// `ConfigError::UnsupportedFluidSolver` and leaves the solvers of this world unchanged.
+    ///
+    /// Only the pressure solver of this world is monitored by the stability guard, exported by
+    /// `self.export_solver_state`, and used by the density queries. The solver assigned to a fluid
+    /// is dropped when this fluid is removed. The incompressibility tolerance of this world, if
+    /// any, is given to the solver. Does nothing if the fluid does not exist.
+    pub fn set_fluid_solver(
+        &mut self,
+        handle: FluidHandle,
+        solver: Option<Box<dyn PressureSolver + Send + Sync>>,
+    ) -> Result<(), ConfigError> {
+        match solver {
+            Some(mut solver) if self.fluids.get(handle).is_some() => {
+                if solver.as_partial_mut().is_none() || self.solver.as_partial_mut().is_none() {
+                    return Err(ConfigError::UnsupportedFluidSolver);
+                }
+
+                if let (Some(tolerance), Some(solver)) = (
+                    self.incompressibility_tolerance,
+                    solver.as_configurable_mut(),
+                ) {
+                    solver.set_incompressibility_tolerance(tolerance);
+                }
+
+                let _ = self.fluid_solvers.insert(handle, solver);
+            }
+            _ => {
+                let _ = self.fluid_solvers.remove(&handle);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks if a pressure solver was assigned to the given fluid with `self.set_fluid_solver`.
+    pub fn has_fluid_solver(&self, handle: FluidHandle) -> bool {
+        self.fluid_solvers.contains_key(&handle)
+    }
+
+    /// The method used by the pressure solver to handle the boundaries.
+    ///
+    /// This is `BoundaryMethod::Akinci` for custom pressure solvers that do not implement
+    /// `ConfigurablePressureSolver`.
+    pub fn boundary_method(&self) -> BoundaryMethod {
+        self.solver
+            .as_configurable()
+            .map(|solver| solver.boundary_method())
+            .unwrap_or(BoundaryMethod::Akinci)
+    }
+
+    /// Sets the method used by the pressure solver to handle the boundaries.
+    ///
+    /// This has no effect on custom pressure solvers that do not implement
+    /// `ConfigurablePressureSolver`.
+    pub fn set_boundary_method(&mut self, method: BoundaryMethod) {
+        if let Some(solver) = self.solver.as_configurable_mut() {
+            solver.set_boundary_method(method)
+        }
+    }
+
+    /// The compression of the fluids tolerated by all the pressure solvers of this world.
+    ///
+    /// Returns `None` if it was not set, in which case each solver uses its own parameters.
+    pub fn incompressibility_tolerance(&self) -> Option<IncompressibilityTolerance> {
+        self.incompressibility_tolerance
+    }
+
+    /// Sets the compression of the fluids tolerated by all the pressure solvers of this world.
+    ///
+    /// The tolerance is translated by each solver into its own stopping criteria with
+    /// `ConfigurablePressureSolver::set_incompressibility_tolerance`, for the pressure solver of this world,
+    /// the solvers assigned to specific fluids, and the solvers assigned later with
+    /// `self.set_fluid_solver`. Setting `None` leaves the parameters of the solvers unchanged.
+    pub fn set_incompressibility_tolerance(
+        &mut self,
+        tolerance: Option<IncompressibilityTolerance>,
+    ) {
+        if let Some(tolerance) = tolerance {
+            let solvers = std::iter::once(&mut self.solver).chain(self.fluid_solvers.values_mut());
+
+            for solver in solvers.filter_map(|solver| solver.as_configurable_mut()) {
+                solver.set_incompressibility_tolerance(tolerance);
+            }
+        }
+
+        self.incompressibility_tolerance = tolerance;
+    }
+
+    /// Multiplies all the lengths of the simulation by `factor`, e.g., to convert a scene to another unit system.
+    ///
+    /// The positions and velocities of the fluid and boundary particles, the particle radius, the
+    /// kernel radius, the ghost boundaries, the analytic colliders, the active region, the speed of
+    /// sound, and the motion threshold are all scaled by `factor`, about the origin. The particle masses are preserved: their volumes are
+    /// multiplied by `factor^DIM` and the rest densities of the fluids are divided by it. The
+    /// spacial grid and the contacts are then rebuilt with the new kernel radius.
+    ///
+    /// The simulation then behaves identically up to units, provided the gravity given to
+    /// `self.step` is scaled by `factor` too. The coefficients of the non-pressure forces (e.g.,
+    /// viscosity or surface tension) are not converted, and the boundaries managed by a coupling
+    /// manager are regenerated by it at the next step.
+    ///
+    /// Panics if `factor` is not positive.
+    pub fn rescale(&mut self, factor: Real) {
+        assert!(
+            factor > na::zero::<Real>(),
+            "The rescaling factor must be positive."
+        );
+
+        self.particle_radius *= factor;
+        self.h *= factor;
+        self.speed_of_sound *= factor;
+        self.timestep_manager
+            .set_particle_radius(self.particle_radius);
+
+        for fluid in self.fluids.as_mut_slice() {
+            fluid.rescale(factor);
+        }
+
+        for boundary in self.boundaries.as_mut_slice() {
+            boundary.rescale(factor);
+        }
+
+        for ghost in self.ghost_boundaries.values_mut() {
+            ghost.point.coords *= factor;
+        }
+
+        for collider in self.analytic_colliders.values_mut() {
+            collider.rescale(factor);
+        }
+
+        for plane in &mut self.reflective_planes {
+            plane.point.coords *= factor;
+        }
+
+        for probe in self.probes.as_mut_slice() {
+            probe.mins.coords *= factor;
+            probe.maxs.coords *= factor;
+            probe.reset_measurement();
+        }
+
+        if let Some(region) = self.contact_manager.active_region().copied() {
+            self.contact_manager
+                .set_active_region(Some(ActiveRegion::new(
+                    region.mins * factor,
+                    region.maxs * factor,
+                )));
+        }
+
+        if let Some(threshold) = &mut self.motion_threshold {
+            *threshold *= factor;
+        }
+
+        if let Some(parameters) = &mut self.sleep_parameters {
+            parameters.linear_threshold *= factor;
+        }
+
+        for positions in self.reference_positions.values() {
+            positions
+                .lock()
+                .unwrap()
+                .iter_mut()
+                .flatten()
+                .for_each(|p| p.coords *= factor);
+        }
+
+        let solvers = std::iter::once(&mut self.solver).chain(self.fluid_solvers.values_mut());
+
+        for solver in solvers.filter_map(|solver| solver.as_stateful_mut()) {
+            solver.rescale(factor);
+        }
+        self.detect_contacts(&mut ());
+    }
+
+    /// The SPH kernel radius.
+    pub fn h(&self) -> Real {
+        self.h
+    }
+
+    /// The radius of every particle on this liquid world.
+    pub fn particle_radius(&self) -> Real {
+        self.particle_radius
+    }
+
+    /// The set of particles potentially intersecting the given AABB.
+    #[cfg(feature = "parry")]
+    pub fn particles_intersecting_aabb<'a>(
+        &'a self,
+        aabb: Aabb,
+    ) -> impl Iterator<Item = ParticleId> + 'a {
+        self.hgrid
+            .cells_intersecting_aabb(&aabb.mins, &aabb.maxs)
+            .flat_map(|e| e.1)
+            .filter_map(move |entry| match entry {
+                HGridEntry::FluidParticle(fid, pid) => {
+                    let (fluid, handle) = self.fluids.get_from_contiguous_index(*fid)?;
+                    let pt = fluid.positions[*pid];
+
+                    // FIXME: use `distance_to_local_point` once it's supported.
+                    let id = &Isometry::identity();
+                    if aabb.distance_to_point(id, &pt, true) < self.particle_radius {
+                        Some(ParticleId::FluidParticle(handle, *pid))
+                    } else {
+                        None
+                    }
+                }
+                HGridEntry::BoundaryParticle(bid, pid) => {
+                    let (boundary, handle) = self.boundaries.get_from_contiguous_index(*bid)?;
+                    let pt = boundary.positions[*pid]; // FIXME: use `distance_to_local_point` once it's supported.
+                    let id = &Isometry::identity();
+                    if aabb.distance_to_point(id, &pt, true) < self.particle_radius {
+                        Some(ParticleId::BoundaryParticle(handle, *pid))
+                    } else {
+                        None
+                    }
+                }
+            })
+    }
+
+    /// The set of particles potentially intersecting the given shape.
+    #[cfg(feature = "parry")]
+    pub fn particles_intersecting_shape<'a, S: ?Sized>(
+        &'a self,
+        pos: &'a Isometry<Real>,
+        shape: &'a S,
+    ) -> impl Iterator<Item = ParticleId> + 'a
+    where
+        S: Shape,
+    {
+        let aabb = shape.compute_aabb(pos);
+        self.hgrid
+            .cells_intersecting_aabb(&aabb.mins, &aabb.maxs)
+            .flat_map(|e| e.1)
+            .filter_map(move |entry| match entry {
+                HGridEntry::FluidParticle(fid, pid) => {
+                    let (fluid, handle) = self.fluids.get_from_contiguous_index(*fid)?;
+                    let pt = fluid.positions[*pid];
+
+                    if shape.distance_to_point(pos, &pt, true) <= self.particle_radius {
+                        Some(ParticleId::FluidParticle(handle, *pid))
+                    } else {
+                        None
+                    }
+                }
+                HGridEntry::BoundaryParticle(bid, pid) => {
+                    let (boundary, handle) = self.boundaries.get_from_contiguous_index(*bid)?;
+                    let pt = boundary.positions[*pid]; // FIXME: use `distance_to_local_point` once it's supported.
+                    if shape.distance_to_point(pos, &pt, true) <= self.particle_radius {
+                        Some(ParticleId::BoundaryParticle(handle, *pid))
+                    } else {
+                        None
+                    }
+                }
+            })
+    }
+}
+
+// The sum of the hashes of each particle, so that it does not depend on the order of the particles.
+// The bytes are hashed in little-endian order so that the result does not depend on the platform.
+fn particles_checksum(
+    kind: u8,
+    (index, generation): (usize, u64),
+    positions: &[Point<Real>],
+    velocities: &[Vector<Real>],
+) -> u64 {
+    let mut checksum = 0u64;
+
+    for (pos, vel) in positions.iter().zip(velocities.iter()) {
+        let mut hasher = FnvHasher::default();
+        hasher.write_u8(kind);
+        hasher.write(&(index as u64).to_le_bytes());
+        hasher.write(&generation.to_le_bytes());
+
+        for value in pos.coords.iter().chain(vel.iter()) {
+            hasher.write(&value.to_bits().to_le_bytes());
+        }
+
+        checksum = checksum.wrapping_add(hasher.finish());
+    }
+
+    checksum
+}
+
+// The area of a boundary particle with the given volume.
+fn particle_area(volume: Real) -> Real {
+    let dim = crate::math::DIM as Real;
+    volume.powf((dim - na::one::<Real>()) / dim)
+}
+
+// The layout of each fluid, compared to the layouts recorded when the spacial grid was built to
+// detect that it is outdated.
+fn fluid_grid_layouts(fluids: &FluidSet) -> impl Iterator<Item = FluidGridLayout> + '_ {
+    fluids.iter().map(|(handle, fluid)| {
+        (
+            handle,
+            fluid.num_particles(),
+            fluid.permutation_generation(),
+        )
+    })
+}
+
+// The number of fluid and boundary particles in the neighborhood of the `i`-th particle of a fluid.
+fn num_neighbors(contact_manager: &ContactManager, fluid_id: usize, i: usize) -> usize {
+    let fluid_contacts = &contact_manager.fluid_fluid_contacts[fluid_id];
+    let boundary_contacts = &contact_manager.fluid_boundary_contacts[fluid_id];
+    fluid_contacts.particle_contacts(i).read().unwrap().len()
+        + boundary_contacts.particle_contacts(i).read().unwrap().len()
+}
+
+// The root of the set of `i` in the union-find forest `parents`, with path halving.
+fn find_root(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+
+    i
+}
+
+#[test]
+fn world_is_send_and_sync() {
+    fn check<T: Send + Sync>() {}
+    check::<LiquidWorld>();
+}
+
+#[test]
+fn world_with_empty_fluids_steps_cleanly() {
+    use crate::solver::{DFSPHSolver, WCSPHSurfaceTension};
+
+    let particle_radius = 0.1;
+    let gravity = crate::math::down_gravity(9.81);
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+
+    // An all-empty world.
+    let mut empty = Fluid::new(Vec::new(), particle_radius, 1000.0);
+    empty
+        .nonpressure_forces
+        .push(Box::new(WCSPHSurfaceTension::new(1.0, 1.0)));
+    let empty = world.add_fluid(empty);
+    let _ = world.add_boundary(Boundary::new(Vec::new()));
+    world.step(0.016, &gravity);
+
+    // Mix empty and non-empty fluids.
+    let points: Vec<_> = (0..10)
+        .map(|i| Point::from(Vector::x() * (i as Real * particle_radius * 2.0)))
+        .collect();
+    let mut fluid = Fluid::new(points.clone(), particle_radius, 1000.0);
+    fluid
+        .nonpressure_forces
+        .push(Box::new(WCSPHSurfaceTension::new(1.0, 1.0)));
+    let _ = world.add_fluid(fluid);
+    world.step(0.016, &gravity);
+
+    // Fill the empty fluid, then empty it again.
+    world.fluids_mut()[empty].add_particles(&points, None);
+    world.step(0.016, &gravity);
+
+    for i in 0..points.len() {
+        world.fluids_mut()[empty].delete_particle_at_next_timestep(i);
+    }
+    world.step(0.016, &gravity);
+
+    assert_eq!(world.fluids()[empty].num_particles(), 0);
+    assert!(world
+        .fluids()
+        .iter()
+        .flat_map(|(_, fluid)| fluid.positions.iter())
+        .all(|pt| pt.coords.iter().all(|x| x.is_finite())));
+}
+
+#[test]
+fn export_has_one_labeled_row_per_particle() {
+    use crate::solver::DFSPHSolver;
+
+    let particle_radius = 0.1;
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    let points: Vec<_> = (0..3)
+        .map(|i| Point::from(Vector::x() * (i as Real * particle_radius * 2.0)))
+        .collect();
+    let _ = world.add_fluid(Fluid::new(points.clone(), particle_radius, 1000.0));
+    let _ = world.add_fluid(Fluid::new(points, particle_radius, 500.0));
+    world.step(0.016, &crate::math::down_gravity(9.81));
+
+    let mut csv = Vec::new();
+    world.export_csv(&mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let lines: Vec<_> = csv.lines().collect();
+    assert_eq!(lines.len(), 7);
+    assert_eq!(lines[0], LiquidWorld::EXPORT_COLUMNS.join(","));
+    assert!(lines[1..]
+        .iter()
+        .all(|line| line.split(',').count() == LiquidWorld::EXPORT_COLUMNS.len()));
+
+    let mut json = Vec::new();
+    world.export_json(&mut json).unwrap();
+    let json = String::from_utf8(json).unwrap();
+    assert!(json.starts_with('[') && json.trim_end().ends_with(']'));
+    assert_eq!(json.matches("\"density\":").count(), 6);
+}
+
+#[test]
+fn rescaled_world_behaves_identically_up_to_units() {
+    use crate::math::{down_gravity, point_xyz};
+    use crate::solver::DFSPHSolver;
+
+    let particle_radius = 0.05;
+    let factor = 100.0;
+    let make_world = || {
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        let points: Vec<_> = (0..4usize.pow(crate::math::DIM as u32))
+            .map(|i| {
+                let (x, y, z) = ((i % 4) as Real, ((i / 4) % 4) as Real, (i / 16) as Real);
+                point_xyz(x, y + 1.0, z) * (particle_radius * 2.0)
+            })
+            .collect();
+        let _ = world.add_fluid(Fluid::new(points, particle_radius, 1000.0));
+        let _ = world.add_boundary(Boundary::cuboid(
+            &point_xyz(0.15, 0.5, 0.15),
+            &crate::math::vector_xyz(0.5, 0.5, 0.5),
+            particle_radius,
+            2,
+        ));
+        world
+    };
+
+    let mut world = make_world();
+    let mut rescaled = make_world();
+    world.step(0.016, &down_gravity(9.81));
+    rescaled.step(0.016, &down_gravity(9.81));
+    rescaled.rescale(factor);
+    assert_eq!(rescaled.h(), world.h() * factor);
+
+    for _ in 0..5 {
+        world.step(0.016, &down_gravity(9.81));
+        rescaled.step(0.016, &down_gravity(9.81 * factor));
+    }
+
+    let fluid = world.fluids().values().next().unwrap();
+    let rescaled_fluid = rescaled.fluids().values().next().unwrap();
+
+    for (pt, rescaled_pt) in fluid.positions.iter().zip(rescaled_fluid.positions.iter()) {
+        assert!((pt - rescaled_pt / factor).norm() < particle_radius * 1.0e-3);
+    }
+}
+
+#[test]
+fn wetted_area_only_counts_boundaries_touched_by_fluids() {
+    use crate::solver::DFSPHSolver;
+
+    let particle_radius = 0.05;
+    let spacing = particle_radius * 2.0;
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+
+    // A floor twice as wide as the fluid column resting on it, and a dry wall far away.
+    let floor = (-10..10)
+        .map(|i| Point::from(Vector::x() * (i as Real * spacing)))
+        .collect();
+    let wall = (0..10)
+        .map(|i| Point::from(Vector::x() * 100.0 + Vector::y() * (i as Real * spacing)))
+        .collect();
+    let fluid = (0..5)
+        .map(|i| Point::from(Vector::x() * (i as Real * spacing) + Vector::y() * spacing))
+        .collect();
+
+    let floor = world.add_boundary(Boundary::new(floor));
+    let wall = world.add_boundary(Boundary::new(wall));
+    let _ = world.add_fluid(Fluid::new(fluid, particle_radius, 1000.0));
+    world.step(0.001, &Vector::zeros());
+
+    let wetted = world.boundary_wetted_area(floor).unwrap();
+    assert!(wetted > 0.0);
+    assert!(wetted < world.boundary_area(floor).unwrap());
+    assert_eq!(world.boundary_wetted_area(wall), Some(0.0));
+    assert!(world.boundary_area(wall).unwrap() > 0.0);
+}
+
+#[test]
+fn reset_world_behaves_as_a_new_world() {
+    let gravity = crate::math::down_gravity(9.81);
+    let mut reset = crate::testbed::generate_dam_break(0.05, 5);
+    let mut new = crate::testbed::generate_dam_break(0.05, 5);
+
+    for _ in 0..10 {
+        reset.step(0.01, &gravity);
+    }
+
+    reset.reset();
+    assert_eq!(reset.fluids().len(), 0);
+    assert_eq!(reset.boundaries().len(), 0);
+    assert_eq!(reset.elapsed_time(), 0.0);
+
+    // Fill the world again with the same scene.
+    for fluid in new.fluids().as_slice() {
+        let _ = reset.add_fluid(Fluid::new(
+            fluid.positions.clone(),
+            fluid.particle_radius(),
+            fluid.density0,
+        ));
+    }
+    for boundary in new.boundaries().as_slice() {
+        let _ = reset.add_boundary(Boundary::new(boundary.positions.clone()));
+    }
+
+    for _ in 0..10 {
+        reset.step(0.01, &gravity);
+        new.step(0.01, &gravity);
+    }
+
+    assert_eq!(
+        reset.fluids().as_slice()[0].positions,
+        new.fluids().as_slice()[0].positions
+    );
+}
+
+#[test]
+fn checksum_detects_diverging_runs() {
+    use crate::testbed::generate_dam_break;
+
+    let gravity = Vector::y() * -9.81;
+    let mut world = generate_dam_break(0.05, 4);
+    let mut again = generate_dam_break(0.05, 4);
+
+    for _ in 0..5 {
+        world.step(0.01, &gravity);
+        again.step(0.01, &gravity);
+        assert_eq!(world.checksum(), again.checksum());
+    }
+
+    // The checksum does not depend on the order of the particles.
+    let checksum = world.checksum();
+    let fluid = &mut world.fluids_mut().as_mut_slice()[0];
+    fluid.positions.reverse();
+    fluid.velocities.reverse();
+    assert_eq!(world.checksum(), checksum);
+
+    world.fluids_mut().as_mut_slice()[0].velocities[0].x += 1.0e-6;
+    assert_ne!(world.checksum(), checksum);
+}
+
+#[test]
+fn compaction_shrinks_the_buffers_once_below_the_threshold() {
+    use crate::solver::DFSPHSolver;
+
+    let particle_radius = 0.05;
+    let positions: Vec<_> = (0..100)
+        .map(|i| Point::from(Vector::x() * (i as Real) * particle_radius * 2.0))
+        .collect();
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    let handle = world.add_fluid(Fluid::new(positions.clone(), particle_radius, 1000.0));
+    world.set_compact_threshold(Some(0.5));
+    world.step(0.01, &Vector::zeros());
+
+    // Removing a few particles does not cross the threshold.
+    let fluid = world.fluids_mut().get_mut(handle).unwrap();
+    let capacity = fluid.capacity();
+    (0..40).for_each(|i| fluid.delete_particle_at_next_timestep(i));
+    world.step(0.01, &Vector::zeros());
+    assert_eq!(world.fluids().get(handle).unwrap().capacity(), capacity);
+
+    // Crossing the threshold shrinks the buffers without altering the particles.
+    let fluid = world.fluids_mut().get_mut(handle).unwrap();
+    (0..20).for_each(|i| fluid.delete_particle_at_next_timestep(i));
+    world.step(0.01, &Vector::zeros());
+    let fluid = world.fluids().get(handle).unwrap();
+    assert_eq!(fluid.capacity(), 40);
+    assert_eq!(fluid.positions, positions[60..]);
+    assert_eq!(world.fluid_densities(handle).unwrap().len(), 40);
+
+    // The shrunk buffers keep working when particles are added back.
+    let fluid = world.fluids_mut().get_mut(handle).unwrap();
+    fluid.add_particles(&positions[..60], None);
+    world.step(0.01, &Vector::zeros());
+    assert_eq!(world.fluids().get(handle).unwrap().num_particles(), 100);
+}
+
+#[test]
+fn fluid_stress_measures_the_shear_rate() {
+    use crate::math::{Matrix, DIM};
+    use crate::solver::DFSPHSolver;
+
+    // A block of fluid sheared with the velocity field `v = (shear_rate * y, 0, 0)`.
+    let particle_radius = 0.05;
+    let size: usize = 9;
+    let positions: Vec<_> = (0..size.pow(DIM as u32))
+        .map(|i| {
+            let coords = Vector::from_fn(|k, _| (i / size.pow(k as u32) % size) as Real);
+            Point::from(coords * particle_radius * 2.0)
+        })
+        .collect();
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    let handle = world.add_fluid(Fluid::new(positions, particle_radius, 1000.0));
+
+    // Detect the contacts without moving the particles.
+    world.set_paused(true);
+    world.step(0.01, &Vector::zeros());
+
+    let shear_rate = 2.0;
+    let viscosity = 0.5;
+    let fluid = world.fluids_mut().get_mut(handle).unwrap();
+    fluid.set_velocity_field(|p| Vector::x() * (shear_rate * p.y));
+
+    let stress = world.fluid_stress(handle, viscosity).unwrap();
+    let pressures = world.compute_pressure_estimate(handle).unwrap();
+    let center = size.pow(DIM as u32) / 2;
+
+    // The strain rate of a simple shear only has off-diagonal terms, equal to half the shear rate.
+    let mut expected = -Matrix::identity() * pressures[center];
+    expected[(0, 1)] = viscosity * shear_rate;
+    expected[(1, 0)] = viscosity * shear_rate;
+
+    assert_eq!(stress[center], stress[center].transpose());
+    assert!((stress[center] - expected).norm() < 1.0e-3 * viscosity * shear_rate);
+}
+
+#[test]
+fn pressure_estimate_follows_the_speed_of_sound() {
+    use crate::math::DIM;
+    use crate::solver::DFSPHSolver;
+
+    // A compressed block of fluid, so its inner particles are denser than the rest density.
+    let particle_radius = 0.05;
+    let size: usize = 7;
+    let positions: Vec<_> = (0..size.pow(DIM as u32))
+        .map(|i| {
+            let coords = Vector::from_fn(|k, _| (i / size.pow(k as u32) % size) as Real);
+            Point::from(coords * particle_radius * 1.6)
+        })
+        .collect();
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    let handle = world.add_fluid(Fluid::new(positions, particle_radius, 1000.0));
+    world.set_paused(true);
+    world.step(0.01, &Vector::zeros());
+
+    let center = size.pow(DIM as u32) / 2;
+    let density = world.fluid_densities(handle).unwrap()[center];
+    let pressures = world.compute_pressure_estimate(handle).unwrap();
+    let expected = 1000.0 * 10.0 * 10.0 / 7.0 * ((density / 1000.0).powi(7) - 1.0);
+    assert_eq!(world.speed_of_sound(), 10.0);
+    assert!(density > 1000.0);
+    assert!(approx::relative_eq!(
+        pressures[center],
+        expected,
+        max_relative = 1.0e-4
+    ));
+
+    // The pressures grow with the square of the speed of sound.
+    world.set_speed_of_sound(20.0);
+    let faster = world.compute_pressure_estimate(handle).unwrap();
+    assert!(approx::relative_eq!(
+        faster[center],
+        pressures[center] * 4.0,
+        max_relative = 1.0e-4
+    ));
+}
+
+#[test]
+fn connected_components_separate_distant_blocks() {
+    use crate::math::DIM;
+    use crate::solver::DFSPHSolver;
+
+    let particle_radius = 0.05;
+    let block = |x: Real, size: usize| -> Vec<Point<Real>> {
+        (0..size.pow(DIM as u32))
+            .map(|i| {
+                let coords = Vector::from_fn(|k, _| (i / size.pow(k as u32) % size) as Real);
+                Point::from(coords * particle_radius * 2.0 + Vector::x() * x)
+            })
+            .collect()
+    };
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    let single = world.add_fluid(Fluid::new(block(0.0, 3), particle_radius, 1000.0));
+    let mut positions = block(-2.0, 2);
+    positions.extend(block(2.0, 3));
+    let split = world.add_fluid(Fluid::new(positions, particle_radius, 1000.0));
+    world.set_paused(true);
+    world.step(0.01, &Vector::zeros());
+
+    let labels = world.connected_components(single).unwrap();
+    assert!(labels.iter().all(|l| *l == 0));
+
+    let small = 2usize.pow(DIM as u32);
+    let large = 3usize.pow(DIM as u32);
+    let labels = world.connected_components(split).unwrap();
+    assert!(labels[..small].iter().all(|l| *l == 0));
+    assert!(labels[small..].iter().all(|l| *l == 1));
+    assert_eq!(
+        world.largest_connected_component(split).unwrap(),
+        (small..small + large).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn fluid_solvers_share_the_densities_of_all_fluids() {
+    use crate::math::DIM;
+    use crate::solver::{DFSPHSolver, IISPHSolver};
+    use crate::testbed::particle_block;
+
+    // Two layers of fluid stacked in a tank, each one with its own solver.
+    let particle_radius = 0.05;
+    let spacing = particle_radius * 2.0;
+    let width = 4;
+    let layer = |y0: usize| {
+        let origin = Vector::repeat(spacing) + Vector::y() * (y0 as Real * spacing);
+        particle_block(width, 3, spacing, origin)
+    };
+    let half_extents =
+        Vector::from_fn(|k, _| spacing * if k == 1 { 7.0 } else { width as Real + 1.0 } / 2.0);
+    let mean_height = |fluid: &Fluid| {
+        fluid.positions.iter().map(|p| p.y).sum::<Real>() / fluid.num_particles() as Real
+    };
+
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    let bottom = world.add_fluid(Fluid::new(layer(0), particle_radius, 1000.0));
+    let top = world.add_fluid(Fluid::new(layer(3), particle_radius, 1000.0));
+    let _ = world.add_boundary(Boundary::cuboid(
+        &Point::from(half_extents),
+        &half_extents,
+        particle_radius,
+        2,
+    ));
+    world
+        .set_fluid_solver(top, Some(Box::new(IISPHSolver::<CubicSplineKernel>::new())))
+        .unwrap();
+    assert!(world.has_fluid_solver(top) && !world.has_fluid_solver(bottom));
+
+    let initial_heights = [bottom, top].map(|h| mean_height(world.fluids().get(h).unwrap()));
+
+    for _ in 0..50 {
+        world.step(0.01, &(Vector::y() * -9.81));
+    }
+
+    // The top layer rests on the bottom one instead of sinking through it.
+    let heights = [bottom, top].map(|h| mean_height(world.fluids().get(h).unwrap()));
+    assert!(heights[1] > heights[0] + spacing * 2.0);
+
+    for (id, handle) in [bottom, top].into_iter().enumerate() {
+        // Both layers are compressed a bit, but neither collapses nor explodes.
+        assert!(heights[id] > initial_heights[id] * 0.7 && heights[id] < initial_heights[id]);
+
+        for p in &world.fluids().get(handle).unwrap().positions {
+            for k in 0..DIM {
+                assert!(p[k] > 0.0 && p[k] < half_extents[k] * 2.0);
+            }
+        }
+    }
+
+    world.set_fluid_solver(top, None).unwrap();
+    assert!(!world.has_fluid_solver(top));
+    world.step(0.01, &(Vector::y() * -9.81));
+}
+
+#[test]
+fn constant_rest_density_profiles_match_the_fluid_rest_density() {
+    use crate::solver::{DFSPHSolver, IISPHSolver};
+    use crate::testbed::generate_dam_break_with_solver;
+
+    let gravity = Vector::y() * -9.81;
+    let worlds = || {
+        [
+            generate_dam_break_with_solver(DFSPHSolver::<CubicSplineKernel>::new(), 0.05, 4),
+            generate_dam_break_with_solver(IISPHSolver::<CubicSplineKernel>::new(), 0.05, 4),
+        ]
+    };
+
+    for (mut world, mut profiled) in worlds().into_iter().zip(worlds()) {
+        let fluid = &mut profiled.fluids_mut().as_mut_slice()[0];
+        let density0 = fluid.density0;
+        fluid.set_rest_density_profile(|_| density0);
+
+        for _ in 0..20 {
+            world.step(0.01, &gravity);
+            profiled.step(0.01, &gravity);
+        }
+
+        let fluid = &world.fluids().as_slice()[0];
+        let profiled_fluid = &profiled.fluids().as_slice()[0];
+        assert_eq!(fluid.positions, profiled_fluid.positions);
+        assert_eq!(fluid.velocities, profiled_fluid.velocities);
+    }
+}
+
+#[test]
+fn stratified_columns_reach_their_prescribed_equilibrium() {
+    use crate::solver::{DFSPHSolver, IISPHSolver};
+    use crate::testbed::particle_block;
+
+    // A column of fluid in a tank, with a rest density growing linearly with the depth, from 1000
+    // at its top to 1500 at its bottom.
+    let particle_radius = 0.05;
+    let spacing = particle_radius * 2.0;
+    let (width, height) = (4, 8);
+    let top = spacing * height as Real;
+    let profile = |pt: &Point<Real>| 1000.0 * (1.5 - pt.y / top * 0.5);
+    let half_extents = Vector::from_fn(|k, _| spacing * if k == 1 { 9.0 } else { 5.0 } / 2.0);
+    let gravity = Vector::y() * -9.81;
+
+    let mut worlds = [
+        LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        ),
+        LiquidWorld::new(
+            IISPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        ),
+    ];
+
+    for world in &mut worlds {
+        let positions = particle_block(width, height, spacing, Vector::repeat(spacing));
+        let mut fluid = Fluid::new(positions, particle_radius, 1000.0);
+        fluid.set_rest_density_profile(profile);
+        let handle = world.add_fluid(fluid);
+        let _ = world.add_boundary(Boundary::cuboid(
+            &Point::from(half_extents),
+            &half_extents,
+            particle_radius,
+            2,
+        ));
+
+        for _ in 0..100 {
+            world.step(0.01, &gravity);
+        }
+
+        let fluid = &world.fluids()[handle];
+        let densities = world.fluid_densities(handle).unwrap();
+        let rest_densities = fluid.rest_densities().unwrap();
+
+        // The relative density error of the particles in the lower half of the column, away from
+        // the free surface, is the same as for a fluid with a uniform rest density.
+        let errors: Vec<_> = (0..fluid.num_particles())
+            .filter(|i| fluid.positions[*i].y < top / 2.0)
+            .map(|i| (densities[i] - rest_densities[i]).abs() / rest_densities[i])
+            .collect();
+        let mean_error = errors.iter().sum::<Real>() / errors.len() as Real;
+        assert!(mean_error < 0.03);
+        assert!(errors.iter().all(|e| *e < 0.07));
+
+        // The layers of particles sharing the same rest density stay stacked by decreasing density.
+        let mut order: Vec<_> = (0..fluid.num_particles()).collect();
+        order.sort_by(|a, b| rest_densities[*b].total_cmp(&rest_densities[*a]));
+        let layer_heights: Vec<_> = order
+            .chunks(fluid.num_particles() / height)
+            .map(|layer| {
+                layer.iter().map(|i| fluid.positions[*i].y).sum::<Real>() / layer.len() as Real
+            })
+            .collect();
+        assert!(layer_heights.windows(2).all(|w| w[0] < w[1]));
+    }
+}
+
+#[test]
+fn stability_guard_retries_diverging_substeps_from_their_initial_state() {
+    use crate::solver::{
+        ConfigurablePressureSolver, DFSPHSolver, OverlapRepulsion, StatefulPressureSolver,
+    };
+    use crate::testbed::particle_block;
+    use std::sync::{Arc, Mutex};
+
+    // The length, the initial solver state, and the initial positions of each solved substep.
+    type SubstepLog = Arc<Mutex<Vec<(Real, SolverState, Vec<Point<Real>>)>>>;
+
+    // A DFSPH solver reporting a density error of 1 for the substeps longer than `max_dt`.
+    struct DtLimitedSolver {
+        inner: DFSPHSolver<CubicSplineKernel>,
+        max_dt: Real,
+        dt: Real,
+        log: SubstepLog,
+    }
+
+    impl PressureSolver for DtLimitedSolver {
+        fn init_with_fluids(&mut self, fluids: &[Fluid]) {
+            self.inner.init_with_fluids(fluids)
+        }
+
+        fn init_with_boundaries(&mut self, boundaries: &[Boundary]) {
+            self.inner.init_with_boundaries(boundaries)
+        }
+
+        fn predict_advection(
+            &mut self,
+            timestep: &TimestepManager,
+            kernel_radius: Real,
+            contact_manager: &ContactManager,
+            gravity: &Vector<Real>,
+            fluids: &mut [Fluid],
+            boundaries: &[Boundary],
+        ) {
+            self.inner.predict_advection(
+                timestep,
+                kernel_radius,
+                contact_manager,
+                gravity,
+                fluids,
+                boundaries,
+            )
+        }
+
+        fn evaluate_kernels(
+            &mut self,
+            kernel_radius: Real,
+            contact_manager: &mut ContactManager,
+            fluids: &[Fluid],
+            boundaries: &[Boundary],
+        ) {
+            self.inner
+                .evaluate_kernels(kernel_radius, contact_manager, fluids, boundaries)
+        }
+
+        fn compute_densities(
+            &mut self,
+            contact_manager: &ContactManager,
+            fluids: &[Fluid],
+            boundaries: &mut [Boundary],
+        ) {
+            self.inner
+                .compute_densities(contact_manager, fluids, boundaries)
+        }
+
+        fn step(
+            &mut self,
+            counters: &mut Counters,
+            timestep: &mut TimestepManager,
+            gravity: &Vector<Real>,
+            contact_manager: &mut ContactManager,
+            kernel_radius: Real,
+            fluids: &mut [Fluid],
+            boundaries: &[Boundary],
+        ) {
+            let state = self.inner.export_state();
+            let positions = fluids[0].positions.clone();
+            self.inner.step(
+                counters,
+                timestep,
+                gravity,
+                contact_manager,
+                kernel_radius,
+                fluids,
+                boundaries,
+            );
+            self.dt = timestep.dt();
+            self.log.lock().unwrap().push((self.dt, state, positions));
+        }
+
+        fn as_stateful(&self) -> Option<&dyn StatefulPressureSolver> {
+            Some(&self.inner)
+        }
+
+        fn as_stateful_mut(&mut self) -> Option<&mut dyn StatefulPressureSolver> {
+            Some(&mut self.inner)
+        }
+
+        fn as_configurable(&self) -> Option<&dyn ConfigurablePressureSolver> {
+            Some(self)
+        }
+    }
+
+    impl ConfigurablePressureSolver for DtLimitedSolver {
+        fn last_solve_stats(&self) -> SolveStats {
+            SolveStats {
+                density_error: if self.dt > self.max_dt { 1.0 } else { 0.0 },
+                ..self.inner.last_solve_stats()
+            }
+        }
+
+        fn boundary_method(&self) -> BoundaryMethod {
+            self.inner.boundary_method()
+        }
+
+        fn set_boundary_method(&mut self, method: BoundaryMethod) {
+            self.inner.set_boundary_method(method)
+        }
+
+        fn set_incompressibility_tolerance(&mut self, tolerance: IncompressibilityTolerance) {
+            self.inner.set_incompressibility_tolerance(tolerance)
+        }
+
+        fn set_iteration_callback(&mut self, callback: Option<Box<IterationCallback>>) {
+            self.inner.set_iteration_callback(callback)
+        }
+    }
+
+    let particle_radius = 0.05;
+    let log = SubstepLog::default();
+    let mut inner = DFSPHSolver::new();
+    // The densities of the diverging substeps are advanced with the continuity equation.
+    inner.density_resync_interval = 10;
+    let mut world = LiquidWorld::new(
+        DtLimitedSolver {
+            inner,
+            max_dt: 0.0025,
+            dt: 0.0,
+            log: log.clone(),
+        },
+        particle_radius,
+        2.0,
+    );
+    let positions = particle_block(3, 3, particle_radius * 2.0, Vector::zeros());
+    let mut fluid = Fluid::new(positions, particle_radius, 1000.0);
+    fluid.add_nonpressure_force(Box::new(OverlapRepulsion::new(1.0)));
+    let handle = world.add_fluid(fluid);
+    world.set_stability_guard(Some(StabilityGuard::new(0.5, 2)));
+    let gravity = Vector::y() * -9.81;
+    let elapsed_time =
+        |world: &LiquidWorld| world.fluids()[handle].nonpressure_forces[0].export_state()[0];
+
+    assert_eq!(world.try_step(0.0025, &gravity), Ok(()));
+    assert_eq!(world.counters.nsubstep_retries, 0);
+    log.lock().unwrap().clear();
+
+    // The substep is halved twice, and each attempt starts from the same state.
+    assert_eq!(world.try_step(0.01, &gravity), Ok(()));
+    assert_eq!(world.counters.nsubstep_retries, 2);
+    assert_eq!(world.substep_count(), 5);
+    assert!((world.elapsed_time() - 0.0125).abs() < 1.0e-6);
+    // The non-pressure forces are solved before the substep length is updated, so the repulsion
+    // only counted the four substeps following a substep of 0.0025, not the diverging attempts.
+    assert!((elapsed_time(&world) - 0.01).abs() < 1.0e-6);
+
+    let substeps = std::mem::take(&mut *log.lock().unwrap());
+    let dts: Vec<_> = substeps.iter().map(|(dt, _, _)| *dt).collect();
+    assert_eq!(dts, [0.01, 0.005, 0.0025, 0.0025, 0.0025, 0.0025]);
+    assert_eq!(substeps[0].1, substeps[1].1);
+    assert_eq!(substeps[0].1, substeps[2].1);
+    assert_eq!(substeps[0].2, substeps[1].2);
+    assert_eq!(substeps[0].2, substeps[2].2);
+    assert_ne!(substeps[2].2, substeps[3].2);
+
+    // The stability guard gives up after its last retry, leaving the world at the beginning of the
+    // diverging substep.
+    let positions = world.fluids()[handle].positions.clone();
+    let velocities = world.fluids()[handle].velocities.clone();
+    let solver_state = world.export_solver_state();
+    world.set_stability_guard(Some(StabilityGuard::new(0.5, 1)));
+
+    assert_eq!(
+        world.try_step(0.01, &gravity),
+        Err(StepError::Diverged {
+            density_error: 1.0,
+            num_retries: 1
+        })
+    );
+    assert_eq!(world.counters.nsubstep_retries, 1);
+    assert_eq!(log.lock().unwrap().len(), 2);
+    assert_eq!(world.substep_count(), 5);
+    assert!((elapsed_time(&world) - 0.01).abs() < 1.0e-6);
+    assert_eq!(world.fluids()[handle].positions, positions);
+    assert_eq!(world.fluids()[handle].velocities, velocities);
+    assert_eq!(world.export_solver_state(), solver_state);
+}
+
+#[test]
+fn stability_guard_treats_non_finite_errors_as_divergence() {
+    use crate::solver::{DFSPHSolver, IISPHSolver};
+    use crate::testbed::particle_block;
+
+    let particle_radius = 0.05;
+    let worlds = [
+        LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        ),
+        LiquidWorld::new(
+            IISPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        ),
+    ];
+
+    for mut world in worlds {
+        let mut fluid = Fluid::new(
+            particle_block(4, 4, particle_radius * 2.0, Vector::zeros()),
+            particle_radius,
+            1000.0,
+        );
+        fluid.velocities[5].x = Real::NAN;
+        let handle = world.add_fluid(fluid);
+        let positions = world.fluids()[handle].positions.clone();
+        world.set_stability_guard(Some(StabilityGuard::new(1.0e3, 2)));
+
+        let result = world.try_step(0.01, &Vector::zeros());
+        assert!(
+            matches!(result, Err(StepError::Diverged { num_retries: 2, .. })),
+            "{:?}",
+            result
+        );
+        assert_eq!(world.counters.nsubstep_retries, 2);
+        assert_eq!(world.substep_count(), 0);
+        assert_eq!(world.fluids()[handle].positions, positions);
+    }
+}
+
+#[test]
+fn solvers_that_cant_be_restricted_still_handle_inactive_fluids() {
+    use crate::solver::DFSPHSolver;
+
+    // A pressure solver only implementing the required methods of `PressureSolver`.
+    struct MinimalSolver(DFSPHSolver<CubicSplineKernel>);
+
+    impl PressureSolver for MinimalSolver {
+        fn init_with_fluids(&mut self, fluids: &[Fluid]) {
+            self.0.init_with_fluids(fluids)
+        }
+
+        fn init_with_boundaries(&mut self, boundaries: &[Boundary]) {
+            self.0.init_with_boundaries(boundaries)
+        }
+
+        fn predict_advection(
+            &mut self,
+            timestep: &TimestepManager,
+            kernel_radius: Real,
+            contact_manager: &ContactManager,
+            gravity: &Vector<Real>,
+            fluids: &mut [Fluid],
+            boundaries: &[Boundary],
+        ) {
+            self.0.predict_advection(
+                timestep,
+                kernel_radius,
+                contact_manager,
+                gravity,
+                fluids,
+                boundaries,
+            )
+        }
+
+        fn evaluate_kernels(
+            &mut self,
+            kernel_radius: Real,
+            contact_manager: &mut ContactManager,
+            fluids: &[Fluid],
+            boundaries: &[Boundary],
+        ) {
+            self.0
+                .evaluate_kernels(kernel_radius, contact_manager, fluids, boundaries)
+        }
+
+        fn compute_densities(
+            &mut self,
+            contact_manager: &ContactManager,
+            fluids: &[Fluid],
+            boundaries: &mut [Boundary],
+        ) {
+            self.0
+                .compute_densities(contact_manager, fluids, boundaries)
+        }
+
+        fn step(
+            &mut self,
+            counters: &mut Counters,
+            timestep: &mut TimestepManager,
+            gravity: &Vector<Real>,
+            contact_manager: &mut ContactManager,
+            kernel_radius: Real,
+            fluids: &mut [Fluid],
+            boundaries: &[Boundary],
+        ) {
+            self.0.step(
+                counters,
+                timestep,
+                gravity,
+                contact_manager,
+                kernel_radius,
+                fluids,
+                boundaries,
+            )
+        }
+    }
+
+    let particle_radius = 0.05;
+    let mut world = LiquidWorld::new(MinimalSolver(DFSPHSolver::new()), particle_radius, 2.0);
+    let frozen = world.add_fluid(Fluid::new(vec![Point::origin()], particle_radius, 1000.0));
+    let falling = world.add_fluid(Fluid::new(
+        vec![Point::from(Vector::x())],
+        particle_radius,
+        1000.0,
+    ));
+    world.fluids_mut()[frozen].set_active(false);
+
+    // Neither the default solver nor the assigned solver can be restricted to a single fluid.
+    assert_eq!(
+        world.set_fluid_solver(
+            falling,
+            Some(Box::new(DFSPHSolver::<CubicSplineKernel>::new()))
+        ),
+        Err(ConfigError::UnsupportedFluidSolver)
+    );
+    assert!(!world.has_fluid_solver(falling));
+
+    for _ in 0..5 {
+        world.step(0.01, &(Vector::y() * -9.81));
+    }
+
+    // The inactive fluid is solved with the other, then restored.
+    assert_eq!(world.fluids()[frozen].positions[0], Point::origin());
+    assert_eq!(world.fluids()[frozen].velocities[0], Vector::zeros());
+    assert!(world.fluids()[falling].positions[0].y < 0.0);
+
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    let fluid = world.add_fluid(Fluid::new(vec![Point::origin()], particle_radius, 1000.0));
+    assert_eq!(
+        world.set_fluid_solver(fluid, Some(Box::new(MinimalSolver(DFSPHSolver::new())))),
+        Err(ConfigError::UnsupportedFluidSolver)
+    );
+    assert!(!world.has_fluid_solver(fluid));
+}
+
+#[cfg(feature = "spectrum")]
+#[test]
+fn kinetic_energy_spectrum_of_a_uniform_flow() {
+    use crate::math::DIM;
+    use crate::solver::DFSPHSolver;
+
+    let particle_radius = 0.05;
+    let n: usize = 12;
+    let positions: Vec<_> = (0..n.pow(DIM as u32))
+        .map(|i| {
+            let mut pt = Point::origin();
+
+            for axis in 0..DIM {
+                pt[axis] = ((i / n.pow(axis as u32)) % n) as Real * particle_radius * 2.0;
+            }
+
+            pt
+        })
+        .collect();
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    let mut fluid = Fluid::new(positions, particle_radius, 1000.0);
+    fluid.velocities.fill(Vector::x() * 2.0);
+    let _ = world.add_fluid(fluid);
+    world.set_paused(true);
+    world.step(0.01, &Vector::zeros());
+
+    // The whole energy of a uniform flow is in the mean flow.
+    let spectrum = world.kinetic_energy_spectrum(&Point::from(Vector::repeat(0.2)), 0.6, 8);
+    assert_eq!(
+        spectrum.len(),
+        (DIM as Real * 16.0).sqrt().round() as usize + 1
+    );
+    assert!((spectrum[0] - 2.0).abs() < 1.0e-4);
+    assert!(spectrum[1..].iter().all(|e| e.abs() < 1.0e-4));
+}
+
+#[test]
+fn fluid_boundary_contacts_yield_distinct_boundaries() {
+    use crate::solver::DFSPHSolver;
+
+    let particle_radius = 0.05;
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    let fluid = world.add_fluid(Fluid::new(
+        vec![Point::origin(), Point::from(Vector::repeat(2.0))],
+        particle_radius,
+        1000.0,
+    ));
+    let _far = world.add_boundary(Boundary::new(vec![Point::from(Vector::repeat(-2.0))]));
+    let drain = world.add_boundary(Boundary::new(vec![
+        Point::from(Vector::x() * 0.05),
+        Point::from(Vector::x() * -0.05),
+        Point::from(Vector::y() * 0.1),
+    ]));
+    let wall = world.add_boundary(Boundary::new(vec![Point::from(Vector::y() * -0.1)]));
+    world.step(1.0e-3, &Vector::zeros());
+
+    let touched: Vec<_> = world.fluid_boundary_contacts_for(fluid, 0).collect();
+    assert_eq!(touched, vec![drain, wall]);
+    assert_eq!(world.fluid_boundary_contacts_for(fluid, 1).count(), 0);
+    assert_eq!(world.fluid_boundary_contacts_for(fluid, 2).count(), 0);
+}
+
+#[test]
+fn interpolated_positions_blend_the_last_step() {
+    use crate::solver::DFSPHSolver;
+
+    let particle_radius = 0.05;
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    let mut fluid = Fluid::new(vec![Point::origin()], particle_radius, 1000.0);
+    fluid.set_uniform_velocity(&Vector::x());
+    let handle = world.add_fluid(fluid);
+    assert_eq!(
+        world.interpolated_positions(handle, 0.5),
+        Some(vec![Point::origin()])
+    );
+
+    world.step(0.01, &Vector::zeros());
+    let current = world.fluids()[handle].positions.clone();
+    assert_eq!(world.interpolated_positions(handle, 1.0).unwrap(), current);
+    let halfway = world.interpolated_positions(handle, 0.5).unwrap();
+    assert!((halfway[0] - current[0] * 0.5).norm() < 1.0e-6);
+
+    // Particles added since the last step stay at their current positions.
+    let added = Point::from(Vector::repeat(1.0));
+    world.fluids_mut()[handle].add_particles(&[added], None);
+    let positions = world.interpolated_positions(handle, 0.0).unwrap();
+    assert!((positions[0] - Point::origin()).norm() < 1.0e-6);
+    assert_eq!(positions[1], added);
+}
+
+#[test]
+fn substep_gravity_is_sampled_at_the_middle_of_the_substeps() {
+    use crate::solver::DFSPHSolver;
+
+    let particle_radius = 0.1;
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    let fluid = world.add_fluid(Fluid::new(vec![Point::origin()], particle_radius, 1000.0));
+    let (from, to) = (-Vector::y() * 10.0, Vector::x() * 10.0);
+    let dt = 0.01;
+    world.step_with_substep_gravity(dt, |s| from.lerp(&to, s));
+
+    // The impulse of a gravity varying linearly is the one of its average.
+    let displacement = world.fluids()[fluid].positions[0].coords;
+    let expected = (from + to) * (0.5 * dt * dt);
+    assert!((displacement - expected).norm() < expected.norm() * 1.0e-4);
+    assert!((world.elapsed_time() - dt).abs() < 1.0e-6);
+}
+
+#[test]
+fn leaked_particles_went_through_the_boundary_gaps() {
+    use crate::solver::DFSPHSolver;
+    use na::Unit;
+
+    let particle_radius = 0.05;
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    // A floor with a gap at the origin.
+    let floor = Boundary::plane(
+        &Point::origin(),
+        &Unit::new_normalize(Vector::y()),
+        2.0,
+        particle_radius,
+        1,
+    );
+    let positions: Vec<_> = floor
+        .positions
+        .into_iter()
+        .filter(|pt| pt.coords.norm() > particle_radius * 1.5)
+        .collect();
+    let normals = vec![Vector::y(); positions.len()];
+    let _ = world.add_boundary(Boundary::with_normals(positions, normals));
+
+    let height = Vector::y() * (particle_radius * 2.0);
+    let fluid = world.add_fluid(Fluid::new(
+        vec![Point::from(height), Point::from(Vector::x() + height)],
+        particle_radius,
+        1000.0,
+    ));
+    world.fluids_mut()[fluid].velocities = vec![-Vector::y() * 20.0, Vector::x() * 20.0];
+    world.step(0.01, &Vector::zeros());
+
+    // The particle sliding along the floor is not reported.
+    assert!(world.fluids()[fluid].positions[0].y < -particle_radius);
+    assert_eq!(world.leaked_particles(), vec![(fluid, 0)]);
+
+    // The leaked particle is reported once.
+    world.step(0.01, &Vector::zeros());
+    assert!(world.leaked_particles().is_empty());
+}
+
+#[test]
+fn leaked_particles_went_through_primitive_boundaries() {
+    use crate::solver::DFSPHSolver;
+
+    let particle_radius = 0.05;
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    let half_extents = Vector::repeat(0.5);
+    let _ = world.add_boundary(Boundary::cuboid(
+        &Point::from(half_extents),
+        &half_extents,
+        particle_radius,
+        2,
+    ));
+
+    let height = Vector::y() * (particle_radius * 2.0);
+    let start = [
+        Point::from(half_extents.component_mul(&Vector::x()) + height),
+        Point::from(half_extents * 0.2 + height),
+    ];
+    let fluid = world.add_fluid(Fluid::new(start.to_vec(), particle_radius, 1000.0));
+    world.step(0.01, &Vector::zeros());
+
+    // Move the particles as if the first one went through the floor of the tank in a single step,
+    // and ended far below it, while the second one slid along the floor.
+    let positions = &mut world.fluids_mut()[fluid].positions;
+    positions[0] = start[0] - Vector::y();
+    positions[1] = start[1] + Vector::x() * 0.5;
+    assert_eq!(world.leaked_particles(), vec![(fluid, 0)]);
+}
+
+#[test]
+fn global_damping_is_independent_of_the_substeps() {
+    use crate::solver::DFSPHSolver;
+
+    let particle_radius = 0.05;
+    let velocity = Vector::x() * 2.0;
+    let final_velocity = |damping: Real, num_steps: usize| {
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        world.set_global_damping(damping);
+        let mut fluid = Fluid::new(vec![Point::origin()], particle_radius, 1000.0);
+        fluid.velocities[0] = velocity;
+        let handle = world.add_fluid(fluid);
+
+        for _ in 0..num_steps {
+            world.step(0.1 / num_steps as Real, &Vector::zeros());
+        }
+
+        world.fluids()[handle].velocities[0]
+    };
+
+    assert_eq!(final_velocity(0.0, 1), velocity);
+
+    let expected = velocity * (-3.0 * 0.1 as Real).exp();
+    assert!((final_velocity(3.0, 1) - expected).norm() < 1.0e-5);
+    assert!((final_velocity(3.0, 10) - expected).norm() < 1.0e-5);
+}
+
+#[test]
+fn neighbor_graph_lists_the_stored_contacts() {
+    use crate::solver::DFSPHSolver;
+
+    let particle_radius = 0.1;
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    let points: Vec<_> = (0..4)
+        .map(|i| Point::from(Vector::x() * (i as Real * particle_radius * 2.0)))
+        .collect();
+    let _ = world.add_fluid(Fluid::new(points, particle_radius, 1000.0));
+    let _ = world.add_boundary(Boundary::new(vec![Point::from(
+        -Vector::y() * particle_radius * 2.0,
+    )]));
+    world.set_paused(true);
+    world.step(0.01, &Vector::zeros());
+
+    let mut csv = Vec::new();
+    world.export_neighbor_graph(&mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let lines: Vec<_> = csv.lines().collect();
+    assert_eq!(lines[0], LiquidWorld::NEIGHBOR_GRAPH_COLUMNS.join(","));
+
+    let contacts = world.contact_manager();
+    let num_fluid_edges = contacts.fluid_fluid_contacts[0].len() - 4;
+    let num_boundary_edges = contacts.fluid_boundary_contacts[0].len();
+    assert!(num_fluid_edges > 0 && num_boundary_edges > 0);
+    assert_eq!(lines.len(), 1 + num_fluid_edges + num_boundary_edges);
+
+    // The fluid-fluid edges come first, and each pair is listed in both directions.
+    let kinds: Vec<_> = lines[1..]
+        .iter()
+        .map(|line| line.split(',').next().unwrap())
+        .collect();
+    assert!(kinds[..num_fluid_edges].iter().all(|k| *k == "fluid-fluid"));
+    assert!(kinds[num_fluid_edges..]
+        .iter()
+        .all(|k| *k == "fluid-boundary"));
+
+    let edges: Vec<_> = lines[1..=num_fluid_edges]
+        .iter()
+        .map(|line| {
+            let fields: Vec<_> = line.split(',').collect();
+            (fields[3].to_owned(), fields[6].to_owned())
+        })
+        .collect();
+    assert!(edges.iter().all(|(i, j)| i != j));
+    assert!(edges
+        .iter()
+        .all(|(i, j)| edges.contains(&(j.clone(), i.clone()))));
+}
+
+#[test]
+fn fluid_volume_in_halfspace_sums_the_particle_volumes() {
+    use crate::solver::DFSPHSolver;
+
+    let particle_radius = 0.05;
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    let positions: Vec<_> = (0..4)
+        .map(|i| Point::from(Vector::y() * (i as Real * 0.1)))
+        .collect();
+    let mut fluid = Fluid::new(positions, particle_radius, 1000.0);
+    fluid.volumes[0] *= 2.0;
+    let volume = fluid.default_particle_volume();
+    let _ = world.add_fluid(fluid);
+
+    let level = Point::from(Vector::y() * 0.15);
+    let below = world.fluid_volume_in_halfspace(&level, &Vector::y());
+    let above = world.fluid_volume_in_halfspace(&level, &-Vector::y());
+    assert!((below - volume * 3.0).abs() < volume * 1.0e-5);
+    assert!((above - volume * 2.0).abs() < volume * 1.0e-5);
+
+    let far = Point::from(Vector::y() * -1.0);
+    assert_eq!(world.fluid_volume_in_halfspace(&far, &Vector::y()), 0.0);
+}
+
+#[test]
+fn boundary_forces_sum_the_particle_forces() {
+    use crate::solver::DFSPHSolver;
+
+    let particle_radius = 0.05;
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    let positions = vec![Point::origin(), Point::from(Vector::x())];
+    let handle = world.add_boundary(Boundary::new(positions));
+    assert!(world.boundary_force(handle, &Point::origin()).is_none());
+
+    world.set_boundary_forces(handle, &[Vector::y(), Vector::y() * 2.0]);
+    let (force, torque) = world.boundary_force(handle, &Point::origin()).unwrap();
+    assert_eq!(force, Vector::y() * 3.0);
+    // Only the force applied at one unit along the x axis has a lever arm.
+    #[cfg(feature = "dim2")]
+    assert_eq!(torque.x, 2.0);
+    #[cfg(feature = "dim3")]
+    assert_eq!(torque, Vector::z() * 2.0);
+
+    world.clear_boundary_forces(handle);
+    let (force, torque) = world.boundary_force(handle, &Point::origin()).unwrap();
+    assert_eq!(force, Vector::zeros());
+    assert_eq!(torque, AngularVector::zeros());
+
+    // The fluid resting on a floor pushes it down.
+    let floor = Boundary::cuboid(&Point::origin(), &Vector::repeat(0.3), particle_radius, 2);
+    let num_particles = floor.num_particles();
+    let floor = world.add_boundary(floor);
+    world.set_boundary_forces(floor, &vec![Vector::zeros(); num_particles]);
+    let positions: Vec<_> = (0..5)
+        .map(|i| Point::from(Vector::x() * (i as Real * 0.1 - 0.2) + Vector::y() * 0.35))
+        .collect();
+    let _ = world.add_fluid(Fluid::new(positions, particle_radius, 1000.0));
+
+    for _ in 0..5 {
+        world.step(0.01, &(Vector::y() * -9.81));
+    }
+
+    let (force, _) = world.boundary_force(floor, &Point::origin()).unwrap();
+    assert!(force.y < 0.0);
+}
+
+#[test]
+fn incompressibility_tolerance_is_shared_by_the_solvers() {
+    use crate::solver::{DFSPHSolver, IISPHSolver};
+    use crate::testbed::particle_block;
+
+    let particle_radius = 0.05;
+    let spacing = particle_radius * 2.0;
+    let tolerance = IncompressibilityTolerance::new(0.002);
+    // The density error of the last density solve of a column of fluid falling in a box, which
+    // converged before the default maximum number of iterations.
+    let density_error = |solver: Box<dyn PressureSolver + Send + Sync>| {
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        world.solver = solver;
+        world.set_incompressibility_tolerance(Some(tolerance));
+        let half_extents = Vector::repeat(0.3);
+        let _ = world.add_boundary(Boundary::cuboid(
+            &Point::from(half_extents),
+            &half_extents,
+            particle_radius,
+            2,
+        ));
+        let positions = particle_block(4, 5, spacing, Vector::repeat(spacing));
+        let _ = world.add_fluid(Fluid::new(positions, particle_radius, 1000.0));
+
+        for _ in 0..20 {
+            world.step(0.01, &(Vector::y() * -9.81));
+        }
+
+        let stats = world.last_solve_stats().unwrap();
+        assert!(stats.num_pressure_iterations < 50);
+        stats.density_error
+    };
+
+    let dfsph = density_error(Box::new(DFSPHSolver::<CubicSplineKernel>::new()));
+    let iisph = density_error(Box::new(IISPHSolver::<CubicSplineKernel>::new()));
+    assert!(dfsph <= tolerance.max_relative_density_error);
+    assert!(iisph <= tolerance.max_relative_density_error);
+}
+
+#[test]
+fn raycast_particles_returns_the_closest_hit() {
+    use crate::solver::DFSPHSolver;
+
+    let particle_radius = 0.05;
+    let spacing = particle_radius * 2.0;
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    let positions: Vec<_> = (0..125)
+        .map(|i| {
+            let coords = Vector::from_fn(|k, _| ((i / 5usize.pow(k as u32)) % 5) as Real);
+            Point::from(coords * spacing)
+        })
+        .collect();
+    let fluid = world.add_fluid(Fluid::new(positions, particle_radius, 1000.0));
+    world.step(0.01, &Vector::zeros());
+
+    // The brute-force search of the first particle hit by the ray.
+    let expected = |origin: &Point<Real>, dir: &Vector<Real>| {
+        let positions = &world.fluids()[fluid].positions;
+        (0..positions.len())
+            .filter_map(|i| {
+                let local_origin = origin - positions[i];
+                let b = local_origin.dot(dir);
+                let c = local_origin.norm_squared() - particle_radius * particle_radius;
+                let discriminant = b * b - dir.norm_squared() * c;
+                (b < 0.0 && discriminant >= 0.0)
+                    .then(|| (i, (-b - discriminant.sqrt()) / dir.norm_squared()))
+            })
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+    };
+
+    let origin = Point::from(Vector::repeat(0.23) - Vector::x() * 0.5);
+    for dir in [Vector::x(), Vector::x() * 0.5 + Vector::y() * 0.05] {
+        let (i, toi) = expected(&origin, &dir).unwrap();
+        let (handle, particle, hit_toi) = world
+            .raycast_particles(&origin, &dir, particle_radius)
+            .unwrap();
+        assert_eq!((handle, particle), (fluid, i));
+        assert!((hit_toi - toi).abs() < 1.0e-5);
+    }
+
+    // Rays starting past the fluid, or pointing away from it, miss.
+    assert!(world
+        .raycast_particles(&origin, &-Vector::x(), particle_radius)
+        .is_none());
+    assert!(world
+        .raycast_particles(&(origin + Vector::x() * 1.5), &Vector::x(), particle_radius)
+        .is_none());
+}
+
+#[test]
+fn apply_swirl_rotates_the_particles_around_the_axis() {
+    use crate::solver::DFSPHSolver;
+
+    let particle_radius = 0.05;
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    let positions = vec![
+        Point::from(Vector::x() * 0.2),
+        Point::from(Vector::y() * 0.4),
+        Point::from(Vector::x() * 2.0),
+    ];
+    let fluid = world.add_fluid(Fluid::new(positions, particle_radius, 1000.0));
+    world.step(0.01, &Vector::zeros());
+    let velocities = world.fluids()[fluid].velocities.clone();
+
+    #[cfg(feature = "dim2")]
+    let axis = AngularVector::new(1.0);
+    #[cfg(feature = "dim3")]
+    let axis = Vector::z() * 3.0;
+    assert_eq!(world.apply_swirl(&Point::origin(), &axis, 0.5, 2.0), 2);
+
+    // Counterclockwise, with a speed decreasing away from the center.
+    let dvels: Vec<_> = (0..3)
+        .map(|i| world.fluids()[fluid].velocities[i] - velocities[i])
+        .collect();
+    assert!((dvels[0] - Vector::y() * 1.2).norm() < 1.0e-5);
+    assert!((dvels[1] + Vector::x() * 0.4).norm() < 1.0e-5);
+    assert_eq!(dvels[2], Vector::zeros());
+}
+
+#[test]
+fn apply_impulse_finds_the_particles_added_since_the_last_step() {
+    use crate::solver::DFSPHSolver;
+
+    let particle_radius = 0.05;
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    let first = world.add_fluid(Fluid::new(
+        vec![Point::origin(), Point::from(Vector::x() * 0.2)],
+        particle_radius,
+        1000.0,
+    ));
+    world.set_paused(true);
+    world.step(0.01, &Vector::zeros());
+    assert_eq!(
+        world.apply_impulse(&Point::origin(), 0.5, &Vector::y(), 0.0),
+        2
+    );
+
+    // The fluid stored first in the grid is replaced by a distant fluid.
+    let _ = world.remove_fluid(first);
+    let center = Point::from(Vector::x() * 5.0);
+    let second = world.add_fluid(Fluid::new(
+        vec![center, center + Vector::x() * 0.2],
+        particle_radius,
+        1000.0,
+    ));
+    world.fluids_mut()[second].add_particles(&[center - Vector::x() * 0.2], None);
+
+    assert_eq!(
+        world.apply_impulse(&Point::origin(), 0.5, &Vector::y(), 0.0),
+        0
+    );
+    assert_eq!(world.apply_impulse(&center, 0.5, &Vector::y(), 0.0), 3);
+
+    let fluid = &world.fluids()[second];
+    for i in 0..3 {
+        let expected = Vector::y() * fluid.particle_inv_mass(i);
+        assert!((fluid.velocities[i] - expected).norm() < 1.0e-5);
+    }
+}
+
+#[test]
+fn try_new_rejects_degenerate_parameters() {
+    use crate::solver::DFSPHSolver;
+
+    let try_new = |particle_radius, smoothing_factor| {
+        LiquidWorld::try_new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            smoothing_factor,
+        )
+        .map(|world| world.h())
+    };
+
+    assert_eq!(try_new(0.05, 2.0), Ok(0.2));
+    assert_eq!(
+        try_new(0.0, 2.0),
+        Err(ConfigError::InvalidParticleRadius(0.0))
+    );
+    assert!(matches!(
+        try_new(Real::NAN, 2.0),
+        Err(ConfigError::InvalidParticleRadius(_))
+    ));
+    assert_eq!(
+        try_new(0.05, 0.5),
+        Err(ConfigError::InvalidSmoothingFactor(0.5))
+    );
+    assert_eq!(
+        try_new(Real::MAX, 2.0),
+        Err(ConfigError::InvalidKernelRadius(Real::INFINITY))
+    );
+}
+
+#[test]
+fn fluid_surface_curvature_is_positive_on_a_ball() {
+    use crate::math::DIM;
+    use crate::solver::{Akinci2013SurfaceTension, DFSPHSolver};
+
+    let particle_radius = 0.025;
+    let ball_radius = 0.25;
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    // A ball sampled on a regular lattice.
+    let n = (ball_radius / (particle_radius * 2.0)) as i32;
+    let side = 2 * n + 1;
+    let positions: Vec<_> = (0..side.pow(DIM as u32))
+        .map(|id| {
+            let coords = Vector::from_fn(|k, _| (id / side.pow(k as u32) % side - n) as Real);
+            Point::from(coords * particle_radius * 2.0)
+        })
+        .filter(|pt| pt.coords.norm() <= ball_radius)
+        .collect();
+
+    let fluid = Fluid::new(positions.clone(), particle_radius, 1000.0);
+    let handle = world.add_fluid(fluid);
+    world.step(0.001, &Vector::zeros());
+
+    let curvatures = world.fluid_surface_curvature(handle).unwrap();
+    let positions = &world.fluids()[handle].positions;
+    let expected = (DIM - 1) as Real / ball_radius;
+    let (mut sum, mut count) = (0.0, 0);
+
+    for (position, curvature) in positions.iter().zip(&curvatures) {
+        assert!(curvature.is_finite());
+
+        if position.coords.norm() < ball_radius - 2.0 * world.h() {
+            assert_eq!(*curvature, 0.0);
+        } else if position.coords.norm() > ball_radius - particle_radius * 2.0 {
+            sum += *curvature;
+            count += 1;
+        }
+    }
+
+    assert!((sum / count as Real - expected).abs() < expected * 0.5);
+
+    // The normals of the surface tension are reused.
+    let mut fluid = Fluid::new(positions.clone(), particle_radius, 1000.0);
+    fluid.add_nonpressure_force(Box::new(Akinci2013SurfaceTension::new(0.0, 0.0)));
+    let _ = world.remove_fluid(handle);
+    let handle = world.add_fluid(fluid);
+    world.step(0.001, &Vector::zeros());
+    let with_tension = world.fluid_surface_curvature(handle).unwrap();
+
+    for (a, b) in with_tension.iter().zip(&curvatures) {
+        assert!((a - b).abs() < expected * 1.0e-3);
+    }
+}
+
+#[test]
+fn step_counts_track_the_integrated_steps() {
+    use crate::solver::DFSPHSolver;
+
+    let particle_radius = 0.1;
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    let _ = world.add_fluid(Fluid::new(vec![Point::origin()], particle_radius, 1000.0));
+
+    for _ in 0..3 {
+        world.step(0.02, &Vector::zeros());
+    }
+
+    world.set_paused(true);
+    world.step(0.02, &Vector::zeros());
+    assert_eq!(world.step_count(), 3);
+    assert_eq!(world.substep_count(), 3);
+    assert!((world.elapsed_time() - 0.06).abs() < 1.0e-5);
+
+    world.reset();
+    assert_eq!(world.step_count(), 0);
+    assert_eq!(world.substep_count(), 0);
+    assert_eq!(world.elapsed_time(), 0.0);
+}
+
+#[test]
+fn emitted_particles_settle_toward_the_local_fluid_velocity() {
+    use crate::solver::DFSPHSolver;
+    use crate::testbed::particle_block;
+
+    let particle_radius = 0.05;
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    // A submerged inflow into a fluid flowing along `x`.
+    let mut fluid = Fluid::new(
+        particle_block(5, 5, particle_radius * 2.0, Vector::zeros()),
+        particle_radius,
+        1000.0,
+    );
+    fluid.set_uniform_velocity(&Vector::x());
+    let handle = world.add_fluid(fluid);
+    world.step(0.01, &Vector::zeros());
+
+    let inside = Point::from(Vector::repeat(0.2));
+    let outside = Point::from(Vector::repeat(10.0));
+    let nozzle_velocity = Vector::y() * -2.0;
+    let fluid_velocity = world.sample_velocity(&inside).unwrap();
+    assert!(world.sample_velocity(&outside).is_none());
+    let num_particles = world.fluids()[handle].num_particles();
+
+    assert!(world.emit_particles(handle, &[inside, outside], &[nozzle_velocity; 2], 0.0));
+    assert!(world.emit_particles(handle, &[inside, outside], &[nozzle_velocity; 2], 0.5));
+    let velocities = &world.fluids()[handle].velocities[num_particles..];
+    let expected = nozzle_velocity.lerp(&fluid_velocity, 0.5);
+    assert_eq!(velocities[..2], [nozzle_velocity; 2]);
+    assert!((velocities[2] - expected).norm() < 1.0e-6);
+    // The particle without any fluid neighbor keeps the nozzle velocity.
+    assert_eq!(velocities[3], nozzle_velocity);
+
+    let _ = world.remove_fluid(handle);
+    assert!(!world.emit_particles(handle, &[inside], &[nozzle_velocity], 0.5));
 }
@@ -0,0 +1,77 @@
+use crate::math::{Point, Real};
+use crate::object::FluidListener;
+use std::sync::{Arc, Mutex};
+
+// The position of each particle of a fluid the last time it was reported as moved, or `None` if it
+// was never reported, shared with the `ReferencePositionsTracker` registered on this fluid.
+pub(crate) type SharedReferencePositions = Arc<Mutex<Vec<Option<Point<Real>>>>>;
+
+// Keeps the reference positions of a fluid aligned with its particles when they are removed or
+// reordered. The particles added since the last step have no reference position yet.
+pub(crate) struct ReferencePositionsTracker {
+    pub positions: SharedReferencePositions,
+}
+
+impl FluidListener for ReferencePositionsTracker {
+    fn on_particles_removed(&mut self, indices: &[usize]) {
+        let mut positions = self.positions.lock().unwrap();
+        let mut i = 0;
+        positions.retain(|_| {
+            i += 1;
+            indices.binary_search(&(i - 1)).is_err()
+        });
+    }
+
+    fn on_particles_permuted(&mut self, permutation: &[usize]) {
+        let mut positions = self.positions.lock().unwrap();
+        let permuted = permutation
+            .iter()
+            .map(|old| positions.get(*old).copied().flatten())
+            .collect();
+        *positions = permuted;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kernel::CubicSplineKernel;
+    use crate::math::{Point, Real, Vector};
+    use crate::object::Fluid;
+    use crate::LiquidWorld;
+
+    #[test]
+    fn moved_particles_follow_the_reordered_and_removed_particles() {
+        use crate::solver::DFSPHSolver;
+
+        let particle_radius = 0.05;
+        // Isolated particles, in reverse Morton order so that `z_sort` reverses them.
+        let positions: Vec<_> = (0..3)
+            .rev()
+            .map(|i| Point::from(Vector::repeat(i as Real)))
+            .collect();
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        let fluid = world.add_fluid(Fluid::new(positions.clone(), particle_radius, 1000.0));
+        world.set_motion_threshold(Some(0.1));
+        let dt = 1.0e-3;
+        let moved = |world: &LiquidWorld| world.moved_particles_since_last_frame().count();
+
+        world.step(dt, &Vector::zeros());
+        assert_eq!(moved(&world), 3);
+
+        // The particles are still, so none of them moved despite their new indices.
+        world.fluids_mut()[fluid].z_sort();
+        world.fluids_mut()[fluid].delete_particle_at_next_timestep(0);
+        world.step(dt, &Vector::zeros());
+        assert_eq!(moved(&world), 0);
+
+        // The new particles are reported even where the removed particles were.
+        world.fluids_mut()[fluid].clear();
+        world.fluids_mut()[fluid].add_particles(&positions[1..], None);
+        world.step(dt, &Vector::zeros());
+        assert_eq!(moved(&world), 2);
+    }
+}
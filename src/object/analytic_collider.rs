@@ -0,0 +1,158 @@
+use crate::math::{Point, Real, Vector};
+use crate::object::ghost_boundary::{generate_mirrored_particles, project_fluid_particles_onto};
+use crate::object::{Boundary, Fluid};
+use approx::AbsDiffEq;
+use na::Unit;
+
+/// A static collider described by an analytic shape instead of sampled boundary particles.
+///
+/// As for `GhostBoundary`, the collider is represented, at each substep, by the reflection of every
+/// fluid particle closer than the kernel radius to its surface, across the plane tangent to the
+/// surface at the point closest to this fluid particle. The closest point and the normal are
+/// computed on the fly, so an analytic collider has no cost where no fluid is close to it, and an
+/// infinite ground plane does not need any sampling. The reflected particles are regular boundary
+/// particles, so analytic colliders and sampled boundaries can be mixed freely.
+///
+/// Fluid particles are expected to stay at least one particle radius away from the surface. Those
+/// that still go inside of the collider are projected back onto its surface.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AnalyticCollider {
+    /// The half-space behind the plane passing through `point` with the given `normal`.
+    HalfSpace {
+        /// A point on the plane.
+        point: Point<Real>,
+        /// The normal of the plane, pointing out of the half-space, toward the fluid.
+        normal: Unit<Vector<Real>>,
+    },
+    /// A solid ball, i.e., a disk in 2D.
+    Ball {
+        /// The center of the ball.
+        center: Point<Real>,
+        /// The radius of the ball.
+        radius: Real,
+    },
+}
+
+impl AnalyticCollider {
+    /// The signed distance from `point` to the surface of this collider, negative inside of it, and
+    /// the outward unit normal of the surface at the point closest to `point`.
+    pub fn signed_distance_and_normal(&self, point: &Point<Real>) -> (Real, Unit<Vector<Real>>) {
+        match self {
+            AnalyticCollider::HalfSpace {
+                point: origin,
+                normal,
+            } => ((point - origin).dot(normal), *normal),
+            AnalyticCollider::Ball { center, radius } => {
+                let (normal, dist) = Unit::try_new_and_get(point - center, Real::default_epsilon())
+                    .unwrap_or((Vector::y_axis(), na::zero::<Real>()));
+                (dist - *radius, normal)
+            }
+        }
+    }
+
+    /// The point of the surface of this collider closest to `point`.
+    pub fn project_point(&self, point: &Point<Real>) -> Point<Real> {
+        let (dist, normal) = self.signed_distance_and_normal(point);
+        point - normal.into_inner() * dist
+    }
+
+    /// Replaces the particles of `boundary` with the reflections of the fluid particles closer than
+    /// `h` to the surface of this collider.
+    ///
+    /// This behaves as `GhostBoundary::generate_ghost_particles` with the plane tangent to the
+    /// surface at the point closest to each fluid particle.
+    pub fn generate_boundary_particles(
+        &self,
+        h: Real,
+        particle_radius: Real,
+        fluids: &[Fluid],
+        boundary: &mut Boundary,
+    ) {
+        generate_mirrored_particles(
+            |pt| self.signed_distance_and_normal(pt),
+            h,
+            particle_radius,
+            fluids,
+            boundary,
+        )
+    }
+
+    /// Projects back onto the surface of this collider the fluid particles inside of it, and cancels
+    /// their velocity toward it.
+    pub fn project_fluid_particles(&self, fluids: &mut [Fluid]) {
+        project_fluid_particles_onto(|pt| self.signed_distance_and_normal(pt), fluids)
+    }
+
+    pub(crate) fn rescale(&mut self, factor: Real) {
+        match self {
+            AnalyticCollider::HalfSpace { point, .. } => point.coords *= factor,
+            AnalyticCollider::Ball { center, radius } => {
+                center.coords *= factor;
+                *radius *= factor;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AnalyticCollider;
+    use crate::kernel::CubicSplineKernel;
+    use crate::math::{Point, Vector};
+    use crate::object::{Boundary, Fluid};
+    use crate::testbed::particle_block;
+    use crate::LiquidWorld;
+
+    #[test]
+    fn analytic_colliders_hold_the_fluid_with_sampled_walls() {
+        use crate::solver::DFSPHSolver;
+        use na::Unit;
+
+        let particle_radius = 0.05;
+        let spacing = particle_radius * 2.0;
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        let floor = world.add_analytic_collider(AnalyticCollider::HalfSpace {
+            point: Point::origin(),
+            normal: Unit::new_normalize(Vector::y()),
+        });
+        let ball = AnalyticCollider::Ball {
+            center: Point::from(Vector::repeat(0.4) - Vector::y() * 0.2),
+            radius: 0.15,
+        };
+        let ball_handle = world.add_analytic_collider(ball);
+        // Sampled walls around the analytic floor.
+        let half_extents = Vector::repeat(0.4);
+        let _ = world.add_boundary(Boundary::cuboid(
+            &Point::from(half_extents),
+            &(half_extents + Vector::repeat(particle_radius)),
+            particle_radius,
+            2,
+        ));
+
+        let positions = particle_block(
+            8,
+            3,
+            spacing,
+            Vector::repeat(spacing * 0.5) + Vector::y() * 0.3,
+        );
+        let fluid = world.add_fluid(Fluid::new(positions, particle_radius, 1000.0));
+
+        for _ in 0..40 {
+            world.step(0.01, &(Vector::y() * -9.81));
+        }
+
+        assert_eq!(world.analytic_collider(ball_handle), Some(&ball));
+        assert!(!world.boundaries()[floor].positions.is_empty());
+        assert!(!world.boundaries()[ball_handle].positions.is_empty());
+
+        for pt in &world.fluids()[fluid].positions {
+            assert!(pt.y >= 0.0);
+            assert!(ball.signed_distance_and_normal(pt).0 >= 0.0);
+            assert!(pt.coords.iter().all(|x| *x > 0.0 && *x < 0.8));
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::math::{Isometry, Point, Real, Vector};
+use crate::math::{Isometry, Point, Real, Vector, DIM};
 use crate::object::{ContiguousArena, ContiguousArenaIndex};
 
 use std::sync::RwLock;
@@ -13,10 +13,24 @@ pub struct Boundary {
     pub velocities: Vec<Vector<Real>>,
     /// The volume computed for each boundary particle.
     pub volumes: Vec<Real>,
+    /// The unit normal of the boundary surface at each particle, if known.
+    ///
+    /// This is set by `Boundary::with_normals`, e.g., for particles sampled from a mesh, and by the
+    /// primitive shapes like `Boundary::cuboid`, where the normals point toward the side of the
+    /// fluid. This is `None` for boundaries created with `Boundary::new`.
+    pub normals: Option<Vec<Vector<Real>>>,
     /// The forces applied to each particle of this boundary object.
     /// If this is set to `None` (which is the default), the boundary won't receive any
     /// force for fluids.
     pub forces: Option<RwLock<Vec<Vector<Real>>>>,
+    /// The indices of the fluids this boundary interacts with.
+    ///
+    /// The indices are those of the fluids in `LiquidWorld::fluids().as_slice()`, as for the
+    /// `i_model` of the contacts. The fluids missing from this list are not affected by this
+    /// boundary and flow through it. If this is empty (which is the default), this boundary
+    /// interacts with all the fluids.
+    pub fluid_mask: Vec<usize>,
+    fixed_volumes: bool,
 }
 
 impl Boundary {
@@ -34,18 +48,73 @@ impl Boundary {
             positions: particle_positions,
             velocities,
             volumes,
+            normals: None,
             forces: None,
+            fluid_mask: Vec::new(),
+            fixed_volumes: false,
         }
     }
 
+    /// Initialize a boundary object with the given particles and the unit normal of the boundary
+    /// surface at each particle.
+    pub fn with_normals(particle_positions: Vec<Point<Real>>, normals: Vec<Vector<Real>>) -> Self {
+        assert_eq!(
+            particle_positions.len(),
+            normals.len(),
+            "A boundary must have exactly one normal per particle."
+        );
+        let mut result = Self::new(particle_positions);
+        result.normals = Some(normals);
+        result
+    }
+
     /// The number of particles of this boundary object.
     pub fn num_particles(&self) -> usize {
         self.positions.len()
     }
 
-    /// Transforms all the particle positions of this boundary by the given isometry.
+    /// Checks if this boundary interacts with the fluid with the given index, according to `self.fluid_mask`.
+    pub fn interacts_with_fluid(&self, fluid_id: usize) -> bool {
+        self.fluid_mask.is_empty() || self.fluid_mask.contains(&fluid_id)
+    }
+
+    /// Whether the volumes of this boundary's particles are left untouched by the pressure solvers.
+    pub fn fixed_volumes(&self) -> bool {
+        self.fixed_volumes
+    }
+
+    /// Sets whether the volumes of this boundary's particles are left untouched by the pressure solvers.
+    ///
+    /// By default, the pressure solvers recompute the volume of each boundary particle from its
+    /// neighboring boundary particles at each substep. If this is set to `true`, the content of
+    /// `self.volumes` is used as-is instead.
+    pub fn set_fixed_volumes(&mut self, fixed_volumes: bool) {
+        self.fixed_volumes = fixed_volumes;
+    }
+
+    // Multiplies all the lengths of this boundary by `factor`.
+    pub(crate) fn rescale(&mut self, factor: Real) {
+        self.positions.iter_mut().for_each(|p| p.coords *= factor);
+        self.velocities.iter_mut().for_each(|v| *v *= factor);
+        let volume_factor = factor.powi(DIM as i32);
+        self.volumes.iter_mut().for_each(|v| *v *= volume_factor);
+
+        if let Some(forces) = &mut self.forces {
+            forces
+                .get_mut()
+                .unwrap()
+                .iter_mut()
+                .for_each(|f| *f *= factor);
+        }
+    }
+
+    /// Transforms all the particle positions, and normals if any, of this boundary by the given isometry.
     pub fn transform_by(&mut self, pose: &Isometry<Real>) {
         self.positions.iter_mut().for_each(|p| *p = pose * *p);
+
+        if let Some(normals) = &mut self.normals {
+            normals.iter_mut().for_each(|n| *n = pose * *n);
+        }
     }
 
     /// Apply a force `f` to the `i`-th particle of this boundary object.
@@ -93,3 +162,56 @@ impl Into<ContiguousArenaIndex> for BoundaryHandle {
         self.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::kernel::CubicSplineKernel;
+    use crate::math::{Point, Real, Vector};
+    use crate::object::{Boundary, Fluid};
+    use crate::LiquidWorld;
+
+    #[test]
+    fn boundary_fluid_mask_lets_other_fluids_through() {
+        use crate::math::DIM;
+        use crate::solver::DFSPHSolver;
+
+        let particle_radius = 0.05;
+        let block = |x: Real| -> Vec<Point<Real>> {
+            (0..3usize.pow(DIM as u32))
+                .map(|i| {
+                    let mut pt = Point::from(Vector::from_fn(|k, _| {
+                        (i / 3usize.pow(k as u32) % 3) as Real * particle_radius * 2.0
+                    }));
+                    pt.x += x;
+                    pt.y += 0.3;
+                    pt
+                })
+                .collect()
+        };
+
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        let passing = world.add_fluid(Fluid::new(block(-0.4), particle_radius, 1000.0));
+        let blocked = world.add_fluid(Fluid::new(block(0.2), particle_radius, 1000.0));
+
+        // A membrane only blocking the second fluid.
+        let mut half_extents = Vector::repeat(0.8);
+        half_extents.y = 0.05;
+        let mut membrane = Boundary::cuboid(&Point::origin(), &half_extents, particle_radius, 2);
+        membrane.fluid_mask = vec![1];
+        let _ = world.add_boundary(membrane);
+
+        let gravity = Vector::y() * -9.81;
+        for _ in 0..40 {
+            world.step(0.01, &gravity);
+        }
+
+        let passing = world.fluids().get(passing).unwrap();
+        let blocked = world.fluids().get(blocked).unwrap();
+        assert!(passing.positions.iter().all(|p| p.y < -0.1));
+        assert!(blocked.positions.iter().all(|p| p.y > 0.05));
+    }
+}
@@ -0,0 +1,467 @@
+use crate::math::{Point, Real, Vector};
+use crate::object::Boundary;
+use na::{RealField, Unit};
+
+// The number of intervals of length at most `spacing` needed to cover `length`.
+fn num_intervals(length: Real, spacing: Real) -> usize {
+    (length / spacing).ceil() as usize
+}
+
+// The spacing between the particles of a layer, and the one between two successive layers.
+fn spacings(particle_radius: Real, layers: usize) -> (Real, Real) {
+    assert!(layers > 0, "A boundary must have at least one layer.");
+    (
+        particle_radius * na::convert::<_, Real>(2.0),
+        particle_radius,
+    )
+}
+
+// `n + 1` evenly spaced values covering `[-half_extent, half_extent]`.
+fn evenly_spaced(half_extent: Real, n: usize) -> impl Iterator<Item = Real> {
+    let step = if n == 0 {
+        na::zero::<Real>()
+    } else {
+        half_extent * na::convert::<_, Real>(2.0) / (n as Real)
+    };
+    (0..=n).map(move |i| -half_extent + step * (i as Real))
+}
+
+// The unit normal, pointing toward the inside of a box, at a point of its surface given by its
+// index `i` along each axis among the `n + 1` points along this axis. Along edges and at corners,
+// this is the average of the normals of the adjacent faces.
+fn cuboid_normal(i: &[usize], n: &Vector<usize>) -> Vector<Real> {
+    Vector::from_fn(|k, _| {
+        if i[k] == 0 {
+            na::one::<Real>()
+        } else if i[k] == n[k] {
+            -na::one::<Real>()
+        } else {
+            na::zero::<Real>()
+        }
+    })
+    .normalize()
+}
+
+fn cuboid_surface(
+    center: &Point<Real>,
+    half_extents: &Vector<Real>,
+    spacing: Real,
+    out: &mut Vec<(Point<Real>, Vector<Real>)>,
+) {
+    let n = half_extents.map(|e| num_intervals(e * na::convert::<_, Real>(2.0), spacing));
+
+    #[cfg(feature = "dim2")]
+    for (i, x) in evenly_spaced(half_extents.x, n.x).enumerate() {
+        for (j, y) in evenly_spaced(half_extents.y, n.y).enumerate() {
+            if i == 0 || i == n.x || j == 0 || j == n.y {
+                out.push((center + Vector::new(x, y), cuboid_normal(&[i, j], &n)));
+            }
+        }
+    }
+
+    #[cfg(feature = "dim3")]
+    for (i, x) in evenly_spaced(half_extents.x, n.x).enumerate() {
+        for (j, y) in evenly_spaced(half_extents.y, n.y).enumerate() {
+            for (k, z) in evenly_spaced(half_extents.z, n.z).enumerate() {
+                if i == 0 || i == n.x || j == 0 || j == n.y || k == 0 || k == n.z {
+                    out.push((center + Vector::new(x, y, z), cuboid_normal(&[i, j, k], &n)));
+                }
+            }
+        }
+    }
+}
+
+fn sphere_surface(
+    center: &Point<Real>,
+    radius: Real,
+    spacing: Real,
+    out: &mut Vec<(Point<Real>, Vector<Real>)>,
+) {
+    #[cfg(feature = "dim2")]
+    circle_points(center, &Vector::x(), &Vector::y(), radius, spacing, out);
+
+    #[cfg(feature = "dim3")]
+    {
+        // Distribute the points on a Fibonacci lattice, with an area per particle
+        // matching an hexagonal packing with the given spacing.
+        let area = na::convert::<_, Real>(4.0) * Real::pi() * radius * radius;
+        let particle_area = spacing * spacing * na::convert::<_, Real>(0.75f64.sqrt());
+        let n = ((area / particle_area).ceil() as usize).max(1);
+        let golden_angle =
+            Real::pi() * (na::convert::<_, Real>(3.0) - na::convert::<_, Real>(5.0f64.sqrt()));
+
+        for i in 0..n {
+            let y = na::one::<Real>()
+                - na::convert::<_, Real>(2.0) * ((i as Real) + na::convert::<_, Real>(0.5))
+                    / (n as Real);
+            let ring_radius = (na::one::<Real>() - y * y).max(na::zero::<Real>()).sqrt();
+            let angle = golden_angle * (i as Real);
+            let dir = Vector::new(angle.cos() * ring_radius, y, angle.sin() * ring_radius);
+            out.push((center + dir * radius, dir));
+        }
+    }
+}
+
+#[cfg(feature = "dim3")]
+fn cylinder_surface(
+    center: &Point<Real>,
+    half_height: Real,
+    radius: Real,
+    spacing: Real,
+    out: &mut Vec<(Point<Real>, Vector<Real>)>,
+) {
+    let u = Vector::x();
+    let v = Vector::z();
+
+    // Side, with normals pointing toward the axis, averaged with the ones of the caps at their rims.
+    let nh = num_intervals(half_height * na::convert::<_, Real>(2.0), spacing);
+    for (i, y) in evenly_spaced(half_height, nh).enumerate() {
+        let first = out.len();
+        circle_points(&(center + Vector::y() * y), &u, &v, radius, spacing, out);
+        let cap_normal = if i == 0 {
+            Vector::y()
+        } else if i == nh {
+            -Vector::y()
+        } else {
+            Vector::zeros()
+        };
+
+        for (_, normal) in &mut out[first..] {
+            *normal = (cap_normal - *normal).normalize();
+        }
+    }
+
+    // Caps, without their outer ring which is already part of the side.
+    let nr = num_intervals(radius, spacing);
+    for (cap_center, cap_normal) in [
+        (center + Vector::y() * half_height, -Vector::y()),
+        (center - Vector::y() * half_height, Vector::y()),
+    ] {
+        for i in 0..nr {
+            let first = out.len();
+            let ring_radius = radius * (i as Real) / (nr as Real);
+            circle_points(&cap_center, &u, &v, ring_radius, spacing, out);
+            out[first..]
+                .iter_mut()
+                .for_each(|(_, normal)| *normal = cap_normal);
+        }
+    }
+}
+
+// Evenly spaced points along a circle centered at `center`, in the plane spanned by `u` and `v`,
+// with the normals of the circle pointing away from its center.
+fn circle_points(
+    center: &Point<Real>,
+    u: &Vector<Real>,
+    v: &Vector<Real>,
+    radius: Real,
+    spacing: Real,
+    out: &mut Vec<(Point<Real>, Vector<Real>)>,
+) {
+    let n = num_intervals(Real::two_pi() * radius, spacing);
+
+    if n == 0 {
+        out.push((*center, *u));
+        return;
+    }
+
+    for i in 0..n {
+        let angle = Real::two_pi() * (i as Real) / (n as Real);
+        let dir = u * angle.cos() + v * angle.sin();
+        out.push((center + dir * radius, dir));
+    }
+}
+
+// Builds a boundary from points paired with their normals.
+fn with_sampled_normals(samples: Vec<(Point<Real>, Vector<Real>)>) -> Boundary {
+    let (points, normals) = samples.into_iter().unzip();
+    Boundary::with_normals(points, normals)
+}
+
+impl Boundary {
+    /// Initializes a boundary object with particles covering the walls of a box, e.g., for a tank.
+    ///
+    /// In 2D, this covers the outline of a rectangle. The particles of each layer are spaced by at
+    /// most `2 * particle_radius`. The walls are made of `layers` layers spaced by `particle_radius`:
+    /// the inner layer covers the surface of the box with the given `half_extents`, and the other
+    /// layers are added outside of it. A single layer may let fast particles through, so at least
+    /// two layers are recommended. The boundary volumes computed by the pressure solvers account
+    /// for all the layers. The normals of the boundary point toward the inside of the box.
+    ///
+    /// Panics if `layers` is zero.
+    pub fn cuboid(
+        center: &Point<Real>,
+        half_extents: &Vector<Real>,
+        particle_radius: Real,
+        layers: usize,
+    ) -> Self {
+        let (spacing, layer_spacing) = spacings(particle_radius, layers);
+        let mut points = Vec::new();
+
+        for layer in 0..layers {
+            let offset = layer_spacing * (layer as Real);
+            let layer_half_extents = half_extents.add_scalar(offset);
+            cuboid_surface(center, &layer_half_extents, spacing, &mut points);
+        }
+
+        with_sampled_normals(points)
+    }
+
+    /// Initializes a boundary object with particles covering a sphere, e.g., for an obstacle.
+    ///
+    /// In 2D, this covers a circle. The particles of each layer are spaced by roughly
+    /// `2 * particle_radius`. The sphere is made of up to `layers` layers spaced by `particle_radius`:
+    /// the outer layer covers the surface of the sphere with the given `radius`, and the other
+    /// layers are added inside of it, as long as they fit. The normals of the boundary point away
+    /// from the center of the sphere.
+    ///
+    /// Panics if `layers` is zero.
+    pub fn sphere(
+        center: &Point<Real>,
+        radius: Real,
+        particle_radius: Real,
+        layers: usize,
+    ) -> Self {
+        let (spacing, layer_spacing) = spacings(particle_radius, layers);
+        let mut points = Vec::new();
+
+        for layer in 0..layers {
+            let layer_radius = radius - layer_spacing * (layer as Real);
+
+            if layer_radius >= na::zero::<Real>() {
+                sphere_surface(center, layer_radius, spacing, &mut points);
+            }
+        }
+
+        with_sampled_normals(points)
+    }
+
+    /// Initializes a boundary object with particles covering a square patch of a plane.
+    ///
+    /// The patch is centered at `point`, orthogonal to `normal`, and its sides have a length of
+    /// `2 * half_extent`. In 2D, this covers a line segment. The particles of each layer are spaced
+    /// by at most `2 * particle_radius`. The patch is made of `layers` layers spaced by
+    /// `particle_radius`: the first layer covers the plane, and the other layers are added behind
+    /// it, i.e., in the direction opposite to `normal`. The normals of the boundary are all `normal`.
+    ///
+    /// Panics if `layers` is zero.
+    pub fn plane(
+        point: &Point<Real>,
+        normal: &Unit<Vector<Real>>,
+        half_extent: Real,
+        particle_radius: Real,
+        layers: usize,
+    ) -> Self {
+        let (spacing, layer_spacing) = spacings(particle_radius, layers);
+        let n = num_intervals(half_extent * na::convert::<_, Real>(2.0), spacing);
+        let mut points = Vec::new();
+
+        for layer in 0..layers {
+            let origin = point - normal.into_inner() * (layer_spacing * (layer as Real));
+
+            #[cfg(feature = "dim2")]
+            {
+                let tangent = Vector::new(-normal.y, normal.x);
+
+                for s in evenly_spaced(half_extent, n) {
+                    points.push((origin + tangent * s, normal.into_inner()));
+                }
+            }
+
+            #[cfg(feature = "dim3")]
+            {
+                let axis = if normal.x.abs() < na::convert::<_, Real>(0.9) {
+                    Vector::x()
+                } else {
+                    Vector::y()
+                };
+                let u = normal.cross(&axis).normalize();
+                let v = normal.cross(&u);
+
+                for s in evenly_spaced(half_extent, n) {
+                    for t in evenly_spaced(half_extent, n) {
+                        points.push((origin + u * s + v * t, normal.into_inner()));
+                    }
+                }
+            }
+        }
+
+        with_sampled_normals(points)
+    }
+
+    /// Initializes a boundary object with particles covering the walls of a closed cylinder, e.g., for a tank.
+    ///
+    /// The cylinder is centered at `center` and its axis is aligned with the `y` axis. In 2D, the
+    /// cylinder is seen from the side, i.e., this covers the outline of a rectangle with half-extents
+    /// `radius` and `half_height`. The particles of each layer are spaced by roughly
+    /// `2 * particle_radius`. The walls are made of `layers` layers spaced by `particle_radius`: the
+    /// inner layer covers the surface of the cylinder with the given dimensions, and the other
+    /// layers are added outside of it. The normals of the boundary point toward the inside of the
+    /// cylinder.
+    ///
+    /// Panics if `layers` is zero.
+    pub fn cylinder(
+        center: &Point<Real>,
+        half_height: Real,
+        radius: Real,
+        particle_radius: Real,
+        layers: usize,
+    ) -> Self {
+        #[cfg(feature = "dim2")]
+        {
+            Self::cuboid(
+                center,
+                &Vector::new(radius, half_height),
+                particle_radius,
+                layers,
+            )
+        }
+
+        #[cfg(feature = "dim3")]
+        {
+            let (spacing, layer_spacing) = spacings(particle_radius, layers);
+            let mut points = Vec::new();
+
+            for layer in 0..layers {
+                let offset = layer_spacing * (layer as Real);
+                cylinder_surface(
+                    center,
+                    half_height + offset,
+                    radius + offset,
+                    spacing,
+                    &mut points,
+                );
+            }
+
+            with_sampled_normals(points)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::math::{Point, Real, Vector};
+    use crate::object::Boundary;
+    use na::Unit;
+
+    // The largest distance between a point of `samples` and its closest particle of `boundary`.
+    fn max_gap(boundary: &Boundary, samples: &Boundary) -> Real {
+        samples
+            .positions
+            .iter()
+            .map(|sample| {
+                boundary
+                    .positions
+                    .iter()
+                    .map(|pt| na::distance(pt, sample))
+                    .fold(Real::MAX, Real::min)
+            })
+            .fold(0.0, Real::max)
+    }
+
+    #[test]
+    fn primitive_boundaries_cover_their_surfaces_without_gaps() {
+        let particle_radius = 0.05;
+        let fine_radius = particle_radius / 4.0;
+        let center = Point::from(Vector::repeat(0.5));
+        let half_extents = Vector::repeat(0.3);
+        let normal = Unit::new_normalize(Vector::repeat(1.0));
+
+        // The first layer of each shape, which the fluid sees, and a finer sampling of its surface.
+        let shapes = [
+            (
+                Boundary::cuboid(&center, &half_extents, particle_radius, 1),
+                Boundary::cuboid(&center, &half_extents, fine_radius, 1),
+            ),
+            (
+                Boundary::sphere(&center, 0.4, particle_radius, 1),
+                Boundary::sphere(&center, 0.4, fine_radius, 1),
+            ),
+            (
+                Boundary::plane(&center, &normal, 0.4, particle_radius, 1),
+                Boundary::plane(&center, &normal, 0.4, fine_radius, 1),
+            ),
+            (
+                Boundary::cylinder(&center, 0.3, 0.2, particle_radius, 1),
+                Boundary::cylinder(&center, 0.3, 0.2, fine_radius, 1),
+            ),
+        ];
+
+        for (boundary, samples) in &shapes {
+            assert!(max_gap(boundary, samples) < particle_radius * 1.5);
+        }
+
+        // The additional layers are stacked at `particle_radius` from each other.
+        let sphere = Boundary::sphere(&center, 0.4, particle_radius, 3);
+        assert!(sphere.positions.iter().all(|pt| {
+            let depth = (0.4 - na::distance(pt, &center)) / particle_radius;
+            (depth - depth.round()).abs() < 1.0e-3 && depth.round() <= 2.0
+        }));
+
+        let plane = Boundary::plane(&center, &normal, 0.4, particle_radius, 2);
+        assert!(plane.positions.iter().all(|pt| {
+            let depth = -(pt - center).dot(&normal) / particle_radius;
+            depth.abs() < 1.0e-3 || (depth - 1.0).abs() < 1.0e-3
+        }));
+        assert_eq!(
+            plane.positions.len(),
+            Boundary::plane(&center, &normal, 0.4, particle_radius, 1)
+                .positions
+                .len()
+                * 2
+        );
+
+        let tank = Boundary::cuboid(&center, &half_extents, particle_radius, 2);
+        assert!(tank.positions.iter().all(|pt| {
+            let depth = ((pt - center).abs() - half_extents).max() / particle_radius;
+            depth.abs() < 1.0e-3 || (depth - 1.0).abs() < 1.0e-3
+        }));
+    }
+
+    #[test]
+    fn primitive_boundaries_have_normals_toward_the_fluid() {
+        let particle_radius = 0.05;
+        let center = Point::from(Vector::repeat(0.5));
+        let half_extents = Vector::repeat(0.3);
+        let normal = Unit::new_normalize(Vector::repeat(1.0));
+
+        // Checks that moving along the normals gets closer to the inside of the shape if the fluid
+        // is inside of it, and away from it otherwise, given its signed distance `dist`.
+        fn check(boundary: Boundary, dist: impl Fn(&Point<Real>) -> Real, fluid_inside: bool) {
+            let normals = boundary.normals.as_ref().unwrap();
+            assert_eq!(normals.len(), boundary.num_particles());
+
+            for (pt, n) in boundary.positions.iter().zip(normals) {
+                assert!((n.norm() - 1.0).abs() < 1.0e-5);
+                let moved = pt + n * 0.05;
+                assert_eq!(dist(&moved) < dist(pt), fluid_inside);
+            }
+        }
+
+        check(
+            Boundary::cuboid(&center, &half_extents, particle_radius, 2),
+            |pt| ((pt - center).abs() - half_extents).max(),
+            true,
+        );
+        check(
+            Boundary::sphere(&center, 0.4, particle_radius, 2),
+            |pt| na::distance(pt, &center) - 0.4,
+            false,
+        );
+        check(
+            Boundary::cylinder(&center, 0.3, 0.2, particle_radius, 2),
+            |pt| {
+                let d = pt - center;
+                (d.y.abs() - 0.3).max((d - Vector::y() * d.y).norm() - 0.2)
+            },
+            true,
+        );
+
+        let plane = Boundary::plane(&center, &normal, 0.4, particle_radius, 2);
+        assert!(plane
+            .normals
+            .unwrap()
+            .iter()
+            .all(|n| (n - normal.into_inner()).norm() < 1.0e-6));
+    }
+}
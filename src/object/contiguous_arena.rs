@@ -40,6 +40,15 @@ impl<Idx, T> ContiguousArena<Idx, T> {
         }
     }
 
+    #[inline]
+    /// Get the position on the contiguous array of the element identified by `handle`.
+    pub fn contiguous_index(&self, handle: Idx) -> Option<usize>
+    where
+        Idx: Into<ContiguousArenaIndex>,
+    {
+        self.indices.get(handle.into()).copied()
+    }
+
     #[inline]
     /// The number of objects on this arena.
     pub fn len(&self) -> usize {
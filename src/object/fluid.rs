@@ -1,5 +1,5 @@
-use crate::math::{Isometry, Point, Real, Vector};
-use crate::object::{ContiguousArena, ContiguousArenaIndex};
+use crate::math::{Isometry, Point, Real, Vector, DIM};
+use crate::object::{ContiguousArena, ContiguousArenaIndex, FluidListener};
 use crate::solver::NonPressureForce;
 
 use num::Zero;
@@ -10,6 +10,8 @@ use num::Zero;
 pub struct Fluid {
     /// Nonpressure forces this fluid is subject to.
     pub nonpressure_forces: Vec<Box<dyn NonPressureForce>>,
+    /// Listeners notified when particles are added to, removed from, or reordered within this fluid.
+    pub listeners: Vec<Box<dyn FluidListener>>,
     /// The world-space position of the fluid particles.
     pub positions: Vec<Point<Real>>,
     /// The velocities of the fluid particles.
@@ -18,14 +20,40 @@ pub struct Fluid {
     pub accelerations: Vec<Vector<Real>>,
     /// The volume of the fluid particles.
     pub volumes: Vec<Real>,
+    /// The time elapsed since each particle was added to this fluid.
+    ///
+    /// The ages are zero for the particles given at the creation of this fluid or added with
+    /// `Fluid::add_particles`, and are increased by the length of each substep of the liquid
+    /// world, including when this fluid is inactive.
+    pub ages: Vec<Real>,
     /// The rest density of this fluid.
+    ///
+    /// This is the rest density of all the particles, unless per-particle rest densities
+    /// have been set with `Fluid::set_rest_densities`.
     pub density0: Real,
+    /// The factor applied to the gravity before it is applied to this fluid's particles.
+    ///
+    /// Defaults to `1.0`. Values smaller than `1.0` make this fluid lighter, e.g., for a gas-like phase,
+    /// and negative values make its particles rise.
+    pub gravity_scale: Real,
+    /// The optional rest density of each particle.
+    pub(crate) rest_densities: Option<Vec<Real>>,
     /// Mask indicating what particles have been deleted.
     deleted_particles: Vec<bool>,
     /// Indicates if a bit of the `deleted_particles` mask has been set.
     num_deleted_particles: usize,
     /// The particles radius.
     particle_radius: Real,
+    /// The number of non-pressure forces and of particles when the forces were last initialized.
+    nonpressure_forces_init: (usize, usize),
+    /// Indicates if the particles of this fluid are integrated by the liquid world.
+    active: bool,
+    /// The number of sub-substeps over which the non-pressure forces are integrated.
+    nonpressure_substeps: usize,
+    /// The accelerations of the independent non-pressure forces solved in parallel.
+    pub(crate) independent_force_accelerations: Vec<Vec<Vector<Real>>>,
+    /// The number of times the particles of this fluid were reordered.
+    permutation_generation: u64,
 }
 
 impl Fluid {
@@ -42,25 +70,197 @@ impl Fluid {
             .take(num_particles)
             .collect();
         let accelerations: Vec<_> = velocities.clone();
+        let ages = vec![na::zero::<Real>(); num_particles];
 
         let particle_volume = Self::particle_volume(particle_radius);
 
         Self {
             nonpressure_forces: Vec::new(),
+            listeners: Vec::new(),
             positions: particle_positions,
             velocities,
             accelerations,
             volumes: std::iter::repeat(particle_volume)
                 .take(num_particles)
                 .collect(),
+            ages,
             deleted_particles: std::iter::repeat(false).take(num_particles).collect(),
             num_deleted_particles: 0,
             density0,
+            gravity_scale: na::one::<Real>(),
+            rest_densities: None,
             particle_radius,
+            nonpressure_forces_init: (0, num_particles),
+            active: true,
+            nonpressure_substeps: 1,
+            independent_force_accelerations: Vec::new(),
+            permutation_generation: 0,
+        }
+    }
+
+    /// Initializes a new fluid object at rest from the given particle positions.
+    ///
+    /// This is the same as `Fluid::new`, but accepts any iterator of positions. The volume of each
+    /// particle is set to `self.default_particle_volume()`, which the solvers use to derive the
+    /// particle masses from `density0`.
+    pub fn from_positions(
+        particle_positions: impl IntoIterator<Item = Point<Real>>,
+        particle_radius: Real,
+        density0: Real,
+    ) -> Self {
+        Self::new(
+            particle_positions.into_iter().collect(),
+            particle_radius,
+            density0,
+        )
+    }
+
+    /// Initializes a new fluid object at rest filling the interior of the implicit surface `sdf`.
+    ///
+    /// The particles are placed on a regular lattice with a spacing of `2 * particle_radius`
+    /// covering the box `[mins, maxs]`, with one particle at the center of each lattice cell fully
+    /// inside of this box, wherever the signed distance `sdf` is negative. The lattice is the same as
+    /// the one of `sampling::particle_count_for_volume`.
+    ///
+    /// `sdf` must not overestimate the distance to the surface: the lattice points closer than
+    /// `|sdf(p)|` to a point `p` are known to be on the same side of the surface as `p`, so they are
+    /// not tested, which skips most of the empty regions of a large box. If `jitter` is positive,
+    /// each particle is then moved away from its lattice point by a pseudo-random offset of at most
+    /// `jitter * particle_radius` along each axis, e.g., to break the symmetry of the lattice. The
+    /// offsets are deterministic.
+    pub fn from_sdf(
+        sdf: impl Fn(&Point<Real>) -> Real,
+        mins: &Point<Real>,
+        maxs: &Point<Real>,
+        particle_radius: Real,
+        density0: Real,
+        jitter: Real,
+    ) -> Self {
+        let spacing = particle_radius * na::convert::<_, Real>(2.0);
+        let half = na::convert::<_, Real>(0.5);
+        // Absorb the rounding errors of extents that are exact multiples of the spacing.
+        let counts = (maxs - mins).map(|extent| {
+            let cells = extent / spacing + na::convert::<_, Real>(1.0e-4);
+            cells.floor().max(na::zero::<Real>()) as usize
+        });
+        let num_rows: usize = counts.iter().skip(1).product();
+        let mut positions = Vec::new();
+
+        // Scan the lattice row by row along the first axis.
+        for row in 0..num_rows {
+            let mut cell = Vector::zeros();
+            let mut rest = row;
+
+            for k in 1..DIM {
+                cell[k] = (rest % counts[k]) as Real + half;
+                rest /= counts[k];
+            }
+
+            let lattice_point = |i: usize| {
+                let mut cell = cell;
+                cell[0] = i as Real + half;
+                mins + cell * spacing
+            };
+
+            let mut i = 0;
+
+            while i < counts[0] {
+                let distance = sdf(&lattice_point(i));
+                let num_same_side = ((distance.abs() / spacing).floor() as usize)
+                    .max(1)
+                    .min(counts[0] - i);
+
+                if distance < na::zero::<Real>() {
+                    positions.extend((i..i + num_same_side).map(lattice_point));
+                }
+
+                i += num_same_side;
+            }
+        }
+
+        if jitter > na::zero::<Real>() {
+            // A xorshift64* generator, giving pseudo-random numbers in `[-1, 1)`.
+            let mut rng_state = 0x853c_49e6_748f_ea9b_u64;
+            let mut random = || {
+                rng_state ^= rng_state >> 12;
+                rng_state ^= rng_state << 25;
+                rng_state ^= rng_state >> 27;
+                let bits = rng_state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 40;
+                (bits as Real) / ((1u64 << 23) as Real) - na::one::<Real>()
+            };
+
+            for position in &mut positions {
+                let offset = Vector::from_fn(|_, _| random());
+                *position += offset * (jitter * particle_radius);
+            }
         }
+
+        Self::new(positions, particle_radius, density0)
+    }
+
+    /// Initializes a new fluid object from the given particle positions, with initial velocities given by `velocity`.
+    ///
+    /// The function `velocity` is called exactly once per particle, with its position, e.g.,
+    /// to initialize a rotating disk of fluid. See `Fluid::from_positions` for details.
+    pub fn from_positions_with_velocity(
+        particle_positions: impl IntoIterator<Item = Point<Real>>,
+        particle_radius: Real,
+        density0: Real,
+        velocity: impl Fn(&Point<Real>) -> Vector<Real>,
+    ) -> Self {
+        let mut result = Self::from_positions(particle_positions, particle_radius, density0);
+        result.set_velocity_field(velocity);
+        result
+    }
+
+    /// Overwrites the velocity of every particle with the value of the field `velocity` at its position.
+    ///
+    /// The function `velocity` is called exactly once per particle, in order, e.g., to initialize
+    /// a vortex or a shear flow from an analytic velocity field.
+    pub fn set_velocity_field(&mut self, mut velocity: impl FnMut(&Point<Real>) -> Vector<Real>) {
+        self.velocities.clear();
+        self.velocities
+            .extend(self.positions.iter().map(&mut velocity));
+    }
+
+    /// Overwrites the velocity of every particle with `velocity`, e.g., to throw a blob of fluid.
+    ///
+    /// The whole fluid then moves coherently from the next step on, without needing a force to
+    /// accelerate it.
+    pub fn set_uniform_velocity(&mut self, velocity: &Vector<Real>) {
+        self.velocities.clear();
+        self.velocities.resize(self.positions.len(), *velocity);
+    }
+
+    /// The analytic hydrostatic pressure `density0 * |gravity| * depth` at each particle, e.g., to
+    /// validate the pressures computed by a solver in a tank at rest.
+    ///
+    /// The height of a particle is its coordinate along the direction opposite to `gravity`, and its
+    /// depth is `surface_height` minus its height. The particles above the surface have a zero
+    /// pressure, and so do all the particles if `gravity` is zero. This only depends on the particle
+    /// positions and on `self.density0`, so it ignores the per-particle rest densities and does not
+    /// depend on the solver that produced the positions.
+    pub fn hydrostatic_pressures(&self, gravity: &Vector<Real>, surface_height: Real) -> Vec<Real> {
+        let gravity_norm = gravity.norm();
+
+        if gravity_norm == na::zero::<Real>() {
+            return vec![na::zero::<Real>(); self.num_particles()];
+        }
+
+        let up = -*gravity / gravity_norm;
+
+        self.positions
+            .iter()
+            .map(|p| {
+                let depth = surface_height - p.coords.dot(&up);
+                self.density0 * gravity_norm * depth.max(na::zero::<Real>())
+            })
+            .collect()
     }
 
     /// Mark the given particle to be deleted at the next timestep.
+    ///
+    /// The listeners of this fluid are notified once the particle is actually removed.
     pub fn delete_particle_at_next_timestep(&mut self, particle: usize) {
         if !self.deleted_particles[particle] {
             self.deleted_particles[particle] = true;
@@ -68,6 +268,74 @@ impl Fluid {
         }
     }
 
+    /// Adds a non-pressure force to this fluid, and initializes it immediately with `NonPressureForce::init`.
+    ///
+    /// Forces pushed directly to `self.nonpressure_forces` are initialized before their first solve instead.
+    pub fn add_nonpressure_force(&mut self, mut force: Box<dyn NonPressureForce>) {
+        force.init(self);
+
+        // Don't initialize this force again before its first solve if the others are up-to-date.
+        if self.nonpressure_forces_init == (self.nonpressure_forces.len(), self.num_particles()) {
+            self.nonpressure_forces_init.0 += 1;
+        }
+
+        self.nonpressure_forces.push(force);
+    }
+
+    // Initializes the non-pressure forces if forces were added or the number of particles
+    // changed since their last initialization.
+    pub(crate) fn init_nonpressure_forces(&mut self) {
+        let (num_initialized, num_particles) = self.nonpressure_forces_init;
+        let first_uninitialized = if num_particles != self.num_particles() {
+            0
+        } else {
+            num_initialized.min(self.nonpressure_forces.len())
+        };
+
+        if first_uninitialized < self.nonpressure_forces.len() {
+            let mut forces = std::mem::take(&mut self.nonpressure_forces);
+
+            for force in &mut forces[first_uninitialized..] {
+                force.init(self);
+            }
+
+            self.nonpressure_forces = forces;
+        }
+
+        self.nonpressure_forces_init = (self.nonpressure_forces.len(), self.num_particles());
+    }
+
+    /// Removes all the particles of this fluid, but keeps the capacity of its buffers.
+    ///
+    /// The non-pressure forces, listeners, and parameters of this fluid are kept, and the
+    /// listeners are notified of the removal of all the particles.
+    pub fn clear(&mut self) {
+        let removed: Vec<_> = if self.listeners.is_empty() && self.nonpressure_forces.is_empty() {
+            Vec::new()
+        } else {
+            (0..self.positions.len()).collect()
+        };
+
+        self.positions.clear();
+        self.velocities.clear();
+        self.accelerations.clear();
+        self.volumes.clear();
+        self.ages.clear();
+        if let Some(rest_densities) = &mut self.rest_densities {
+            rest_densities.clear();
+        }
+        self.deleted_particles.clear();
+        self.num_deleted_particles = 0;
+
+        for force in &mut self.nonpressure_forces {
+            force.apply_removal(&removed);
+        }
+
+        for listener in &mut self.listeners {
+            listener.on_particles_removed(&removed);
+        }
+    }
+
     /// The number of particles that will be deleted at the next timestep.
     pub fn num_deleted_particles(&self) -> usize {
         self.num_deleted_particles
@@ -80,14 +348,126 @@ impl Fluid {
 
     pub(crate) fn apply_particles_removal(&mut self) {
         if self.num_deleted_particles != 0 {
+            let removed: Vec<_> = if self.listeners.is_empty() && self.nonpressure_forces.is_empty()
+            {
+                Vec::new()
+            } else {
+                (0..self.deleted_particles.len())
+                    .filter(|i| self.deleted_particles[*i])
+                    .collect()
+            };
+
             crate::helper::filter_from_mask(&self.deleted_particles, &mut self.positions);
             crate::helper::filter_from_mask(&self.deleted_particles, &mut self.velocities);
             crate::helper::filter_from_mask(&self.deleted_particles, &mut self.accelerations);
             crate::helper::filter_from_mask(&self.deleted_particles, &mut self.volumes);
+            crate::helper::filter_from_mask(&self.deleted_particles, &mut self.ages);
+            if let Some(rest_densities) = &mut self.rest_densities {
+                crate::helper::filter_from_mask(&self.deleted_particles, rest_densities);
+            }
             self.deleted_particles.truncate(self.positions.len());
             self.deleted_particles.iter_mut().for_each(|i| *i = false);
             self.num_deleted_particles = 0;
+
+            for force in &mut self.nonpressure_forces {
+                force.apply_removal(&removed);
+            }
+
+            for listener in &mut self.listeners {
+                listener.on_particles_removed(&removed);
+            }
+        }
+    }
+
+    /// The number of particles the buffers of this fluid can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.positions.capacity()
+    }
+
+    // Releases the capacity of the particle buffers that exceeds the number of particles.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.positions.shrink_to_fit();
+        self.velocities.shrink_to_fit();
+        self.accelerations.shrink_to_fit();
+        self.volumes.shrink_to_fit();
+        self.ages.shrink_to_fit();
+        if let Some(rest_densities) = &mut self.rest_densities {
+            rest_densities.shrink_to_fit();
+        }
+        self.deleted_particles.shrink_to_fit();
+        self.independent_force_accelerations
+            .iter_mut()
+            .for_each(Vec::shrink_to_fit);
+    }
+
+    // Multiplies all the lengths of this fluid by `factor` while preserving the particle masses.
+    pub(crate) fn rescale(&mut self, factor: Real) {
+        let volume_factor = factor.powi(DIM as i32);
+
+        self.positions.iter_mut().for_each(|p| p.coords *= factor);
+        self.velocities.iter_mut().for_each(|v| *v *= factor);
+        self.accelerations.iter_mut().for_each(|a| *a *= factor);
+        self.volumes.iter_mut().for_each(|v| *v *= volume_factor);
+        self.density0 /= volume_factor;
+
+        if let Some(rest_densities) = &mut self.rest_densities {
+            rest_densities.iter_mut().for_each(|d| *d /= volume_factor);
+        }
+
+        self.particle_radius *= factor;
+
+        for force in &mut self.nonpressure_forces {
+            force.rescale(factor);
+        }
+    }
+
+    /// Is this fluid active?
+    ///
+    /// Fluids are active by default.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Activates or deactivates this fluid.
+    ///
+    /// The particles of an inactive fluid are not integrated by the liquid world: they keep their
+    /// positions and velocities, and deactivating a fluid sets all its velocities to zero. They
+    /// still contribute to the densities of their neighbors, so the active fluids rest on them
+    /// like on a boundary. A pressure solver that does not implement `PartialPressureSolver`
+    /// solves the inactive fluids with the others, and their positions and velocities are then
+    /// restored.
+    pub fn set_active(&mut self, active: bool) {
+        if self.active && !active {
+            self.velocities
+                .iter_mut()
+                .for_each(|v| v.fill(na::zero::<Real>()));
         }
+
+        self.active = active;
+    }
+
+    /// The number of sub-substeps over which the non-pressure forces of this fluid are integrated.
+    ///
+    /// Defaults to `1`.
+    pub fn nonpressure_substeps(&self) -> usize {
+        self.nonpressure_substeps
+    }
+
+    /// Sets the number of sub-substeps over which the non-pressure forces of this fluid are integrated.
+    ///
+    /// The non-pressure forces are solved `num_substeps` times per substep of the liquid world, each
+    /// time with the timestep they are given divided by `num_substeps`, and the velocities are
+    /// integrated in-between while the positions and contacts are kept fixed. The pressure solve still runs once per
+    /// substep with the mean of these accelerations, which gives the same velocities as the nested
+    /// integration, and the forces applied to the boundaries are averaged the same way. This makes
+    /// stiff forces, e.g., strong surface tension, stable without reducing the timestep of the whole
+    /// simulation. A single sub-substep, the default, solves the forces once per substep.
+    pub fn set_nonpressure_substeps(&mut self, num_substeps: usize) {
+        assert!(
+            num_substeps > 0,
+            "The number of non-pressure sub-substeps must be positive."
+        );
+        self.nonpressure_substeps = num_substeps;
     }
 
     /// The radius of this fluid's particles.
@@ -121,6 +501,7 @@ impl Fluid {
         positions: &[Point<Real>],
         velocities: Option<&[Vector<Real>]>,
     ) {
+        let first_new_particle = self.positions.len();
         let nparticles = self.positions.len() + positions.len();
         let particle_volume = self.default_particle_volume();
 
@@ -139,7 +520,63 @@ impl Fluid {
 
         self.accelerations.resize(nparticles, Vector::zeros());
         self.volumes.resize(nparticles, particle_volume);
+        self.ages.resize(nparticles, na::zero::<Real>());
         self.deleted_particles.resize(nparticles, false);
+
+        if let Some(rest_densities) = &mut self.rest_densities {
+            rest_densities.resize(nparticles, self.density0);
+        }
+
+        for listener in &mut self.listeners {
+            listener.on_particles_added(first_new_particle..nparticles);
+        }
+    }
+
+    /// The rest density of the `i`-th particle of this fluid.
+    #[inline]
+    pub fn rest_density(&self, i: usize) -> Real {
+        match &self.rest_densities {
+            Some(rest_densities) => rest_densities[i],
+            None => self.density0,
+        }
+    }
+
+    /// The per-particle rest densities of this fluid, if they have been set.
+    pub fn rest_densities(&self) -> Option<&[Real]> {
+        self.rest_densities.as_deref()
+    }
+
+    /// Sets the rest density of each particle of this fluid.
+    ///
+    /// The rest densities follow the particles: particles added afterwards are given the
+    /// rest density `self.density0`. Setting `None` makes all the particles use `self.density0` again.
+    /// Note that only the pressure solvers take the per-particle rest densities into account,
+    /// the non-pressure forces still use `self.density0`.
+    pub fn set_rest_densities(&mut self, rest_densities: Option<Vec<Real>>) {
+        if let Some(rest_densities) = &rest_densities {
+            assert_eq!(
+                rest_densities.len(),
+                self.num_particles(),
+                "The rest densities array must have one element per particle."
+            );
+        }
+
+        self.rest_densities = rest_densities;
+    }
+
+    /// Sets the rest density of each particle of this fluid by evaluating `profile` at its current position.
+    ///
+    /// This can be used to set up, e.g., a stratified fluid where deeper particles are denser.
+    pub fn set_rest_density_profile(&mut self, profile: impl Fn(&Point<Real>) -> Real) {
+        self.rest_densities = Some(self.positions.iter().map(profile).collect());
+    }
+
+    /// The number of times the particles of this fluid were reordered, e.g., by `self.z_sort`.
+    ///
+    /// This can be compared to a previous value to detect that the particle indices changed, e.g.,
+    /// to invalidate per-particle data that cannot be permuted.
+    pub fn permutation_generation(&self) -> u64 {
+        self.permutation_generation
     }
 
     /// Sorts all the particles of this fluids according to morton order.
@@ -149,10 +586,21 @@ impl Fluid {
         self.velocities = crate::z_order::apply_permutation(&order, &self.velocities);
         self.accelerations = crate::z_order::apply_permutation(&order, &self.accelerations);
         self.volumes = crate::z_order::apply_permutation(&order, self.volumes.as_slice());
+        self.ages = crate::z_order::apply_permutation(&order, self.ages.as_slice());
+
+        if let Some(rest_densities) = &mut self.rest_densities {
+            *rest_densities = crate::z_order::apply_permutation(&order, rest_densities.as_slice());
+        }
 
         for forces in &mut self.nonpressure_forces {
             forces.apply_permutation(&order);
         }
+
+        for listener in &mut self.listeners {
+            listener.on_particles_permuted(&order);
+        }
+
+        self.permutation_generation += 1;
     }
 
     /// Apply the given transformation to each particle of this fluid.
@@ -165,6 +613,27 @@ impl Fluid {
         self.positions.len()
     }
 
+    /// The coordinates of this fluid's particle positions, as one contiguous array per axis.
+    ///
+    /// The positions are stored as an array of points, so the coordinates along one axis are not
+    /// contiguous in memory and are copied here. Use `self.copy_positions_soa` to reuse the
+    /// allocations of existing arrays instead.
+    pub fn positions_soa(&self) -> [Vec<Real>; DIM] {
+        let mut out = std::array::from_fn(|_| Vec::with_capacity(self.num_particles()));
+        self.copy_positions_soa(&mut out);
+        out
+    }
+
+    /// Copies the coordinates of this fluid's particle positions into `out`, one array per axis.
+    ///
+    /// Each array of `out` is cleared before being filled, keeping its allocation.
+    pub fn copy_positions_soa(&self, out: &mut [Vec<Real>; DIM]) {
+        for (k, coords) in out.iter_mut().enumerate() {
+            coords.clear();
+            coords.extend(self.positions.iter().map(|pt| pt[k]));
+        }
+    }
+
     /// Computes the AABB of this fluid.
     #[cfg(feature = "nphysics")]
     pub fn compute_aabb(&self, particle_radius: Real) -> ncollide::bounding_volume::AABB<Real> {
@@ -172,9 +641,51 @@ impl Fluid {
         bounding_volume::local_point_cloud_aabb(&self.positions).loosened(particle_radius)
     }
 
+    /// The center of mass of this fluid's particles.
+    ///
+    /// Returns `None` if this fluid has no particle, or if all its particles have a zero mass.
+    pub fn center_of_mass(&self) -> Option<Point<Real>> {
+        let mut total_mass = na::zero::<Real>();
+        let mut weighted_sum = Vector::zeros();
+
+        for (i, pt) in self.positions.iter().enumerate() {
+            let mass = self.particle_mass(i);
+            total_mass += mass;
+            weighted_sum += pt.coords * mass;
+        }
+
+        if total_mass.is_zero() {
+            None
+        } else {
+            Some(Point::from(weighted_sum / total_mass))
+        }
+    }
+
+    /// A sphere enclosing all the particles of this fluid, given as its center and radius.
+    ///
+    /// This sphere is computed incrementally in a single pass over the particles, so it may be
+    /// somewhat larger than the smallest enclosing sphere. Returns `None` if this fluid has no particle.
+    pub fn bounding_sphere(&self) -> Option<(Point<Real>, Real)> {
+        let mut center = *self.positions.first()?;
+        let mut radius = na::zero::<Real>();
+
+        for pt in &self.positions[1..] {
+            let dist = na::distance(&center, pt);
+
+            if dist > radius {
+                // Grow the sphere just enough to enclose both the previous sphere and `pt`.
+                let new_radius = (radius + dist) * na::convert::<_, Real>(0.5);
+                center += (pt - center) * ((new_radius - radius) / dist);
+                radius = new_radius;
+            }
+        }
+
+        Some((center, radius + self.particle_radius))
+    }
+
     /// The mass of the `i`-th particle of this fluid.
     pub fn particle_mass(&self, i: usize) -> Real {
-        self.volumes[i] * self.density0
+        self.volumes[i] * self.rest_density(i)
     }
 
     /// The inverse mass of the `i`-th particle of this fluid.
@@ -184,7 +695,7 @@ impl Fluid {
         if self.volumes[i].is_zero() {
             na::zero::<Real>()
         } else {
-            na::one::<Real>() / (self.volumes[i] * self.density0)
+            na::one::<Real>() / (self.volumes[i] * self.rest_density(i))
         }
     }
 }
@@ -208,3 +719,438 @@ impl Into<ContiguousArenaIndex> for FluidHandle {
         self.0
     }
 }
+
+#[test]
+fn velocity_field_overwrites_every_velocity() {
+    use std::f32::consts::PI;
+
+    // A Taylor-Green vortex in the `xy` plane.
+    let taylor_green = |p: &Point<Real>| {
+        let mut v = Vector::zeros();
+        v.x = (PI * p.x).sin() * (PI * p.y).cos();
+        v.y = -(PI * p.x).cos() * (PI * p.y).sin();
+        v
+    };
+
+    let positions = (0..100).map(|i| {
+        let mut p = Point::origin();
+        p.x = (i % 10) as Real * 0.1;
+        p.y = (i / 10) as Real * 0.1;
+        p
+    });
+    let mut fluid =
+        Fluid::from_positions_with_velocity(positions, 0.05, 1000.0, |_| Vector::repeat(1.0));
+    fluid.set_velocity_field(taylor_green);
+
+    assert_eq!(fluid.velocities.len(), fluid.num_particles());
+    for (p, v) in fluid.positions.iter().zip(fluid.velocities.iter()) {
+        assert_eq!(*v, taylor_green(p));
+    }
+}
+
+#[test]
+fn hydrostatic_pressure_grows_with_depth() {
+    let positions = (0..10).map(|i| Point::from(Vector::x() * (i as Real * 0.1)));
+    let fluid = Fluid::from_positions(positions, 0.05, 1000.0);
+
+    // The gravity points toward `-x`, so the height of a particle is its `x` coordinate.
+    let pressures = fluid.hydrostatic_pressures(&(Vector::x() * -9.81), 0.5);
+
+    for (p, pressure) in fluid.positions.iter().zip(pressures.iter()) {
+        let expected = 1000.0 * 9.81 * (0.5 - p.x).max(0.0);
+        assert!((pressure - expected).abs() < 1.0e-2);
+    }
+
+    let no_gravity = fluid.hydrostatic_pressures(&Vector::zeros(), 0.5);
+    assert!(no_gravity.iter().all(|p| *p == 0.0));
+}
+
+#[test]
+fn sdf_filling_matches_the_lattice_points_inside() {
+    let particle_radius = 0.05;
+    let spacing = particle_radius * 2.0;
+    let center = Point::from(Vector::repeat(0.5));
+    let ball = |p: &Point<Real>| na::distance(p, &center) - 0.3;
+    let mins = Point::origin();
+    let maxs = Point::from(Vector::repeat(10.0));
+
+    // The empty regions of the large box are skipped instead of testing every lattice point.
+    let num_evaluations = std::cell::Cell::new(0);
+    let counted_ball = |p: &Point<Real>| {
+        num_evaluations.set(num_evaluations.get() + 1);
+        ball(p)
+    };
+    let fluid = Fluid::from_sdf(counted_ball, &mins, &maxs, particle_radius, 1000.0, 0.0);
+    assert!(num_evaluations.get() < 100usize.pow(DIM as u32) / 10);
+
+    // Test every lattice point close to the ball, where all the particles should be.
+    let n = (1.0 / spacing).round() as usize;
+    let expected: Vec<_> = (0..n.pow(DIM as u32))
+        .map(|i| {
+            Point::from(
+                Vector::from_fn(|k, _| i / n.pow(k as u32) % n)
+                    .map(|c| (c as Real + 0.5) * spacing),
+            )
+        })
+        .filter(|p| ball(p) < 0.0)
+        .collect();
+    let mut positions = fluid.positions.clone();
+    let key = |p: &Point<Real>| {
+        (0..DIM)
+            .map(|k| (p[k] / spacing) as i64)
+            .collect::<Vec<_>>()
+    };
+    positions.sort_by_key(key);
+    let mut expected = expected;
+    expected.sort_by_key(key);
+    assert!(!expected.is_empty());
+    assert_eq!(positions.len(), expected.len());
+    assert!(positions
+        .iter()
+        .zip(expected.iter())
+        .all(|(p, q)| na::distance(p, q) < 1.0e-4));
+
+    // The jitter moves the particles, within bounds.
+    let jittered = Fluid::from_sdf(ball, &mins, &maxs, particle_radius, 1000.0, 0.2);
+    assert_eq!(jittered.num_particles(), fluid.num_particles());
+    assert!(jittered
+        .positions
+        .iter()
+        .zip(fluid.positions.iter())
+        .all(|(p, q)| (p - q).amax() <= 0.2 * particle_radius && p != q));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kernel::CubicSplineKernel;
+    use crate::math::{Point, Real, Vector};
+    use crate::object::{Boundary, Fluid};
+    use crate::testbed::particle_block;
+    use crate::{LiquidWorld, TimestepManager};
+
+    #[test]
+    fn nonpressure_forces_are_initialized_when_the_particle_count_changes() {
+        use crate::geometry::ParticlesContacts;
+        use crate::solver::{DFSPHSolver, NonPressureForce};
+        use std::sync::{Arc, Mutex};
+
+        // Records the particle count at each initialization, and checks its buffer at each solve.
+        struct BufferedForce {
+            inits: Arc<Mutex<Vec<usize>>>,
+            buffer: Vec<Real>,
+        }
+
+        impl NonPressureForce for BufferedForce {
+            fn init(&mut self, fluid: &Fluid) {
+                self.inits.lock().unwrap().push(fluid.num_particles());
+                self.buffer.resize(fluid.num_particles(), 0.0);
+            }
+
+            fn solve(
+                &mut self,
+                _: &TimestepManager,
+                _: Real,
+                _: &ParticlesContacts,
+                _: &ParticlesContacts,
+                fluid: &mut Fluid,
+                _: &[Boundary],
+                _: &[Real],
+            ) {
+                assert_eq!(self.buffer.len(), fluid.num_particles());
+            }
+        }
+
+        let particle_radius = 0.05;
+        let positions: Vec<_> = (0..10)
+            .map(|i| Point::from(Vector::x() * (i as Real) * particle_radius * 2.0))
+            .collect();
+        let added_inits = Arc::new(Mutex::new(Vec::new()));
+        let pushed_inits = Arc::new(Mutex::new(Vec::new()));
+        let mut fluid = Fluid::new(positions.clone(), particle_radius, 1000.0);
+        fluid.add_nonpressure_force(Box::new(BufferedForce {
+            inits: added_inits.clone(),
+            buffer: Vec::new(),
+        }));
+        fluid.nonpressure_forces.push(Box::new(BufferedForce {
+            inits: pushed_inits.clone(),
+            buffer: Vec::new(),
+        }));
+        assert_eq!(*added_inits.lock().unwrap(), [10]);
+        assert!(pushed_inits.lock().unwrap().is_empty());
+
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        let handle = world.add_fluid(fluid);
+        world.step(0.01, &Vector::zeros());
+        world.step(0.01, &Vector::zeros());
+
+        let fluid = world.fluids_mut().get_mut(handle).unwrap();
+        fluid.add_particles(&positions[..5], None);
+        world.step(0.01, &Vector::zeros());
+
+        assert_eq!(*added_inits.lock().unwrap(), [10, 15]);
+        assert_eq!(*pushed_inits.lock().unwrap(), [10, 15]);
+    }
+
+    #[test]
+    fn uniform_velocity_moves_the_fluid_coherently() {
+        use crate::solver::DFSPHSolver;
+
+        let particle_radius = 0.05;
+        let positions: Vec<_> = (0..27)
+            .map(|i| {
+                Point::from(
+                    Vector::repeat((i % 3) as Real * 0.1) + Vector::y() * (i / 3) as Real * 0.1,
+                )
+            })
+            .collect();
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        let mut fluid = Fluid::new(positions.clone(), particle_radius, 1000.0);
+        fluid.velocities[0] = Vector::y();
+        let velocity = Vector::x() * 3.0;
+        fluid.set_uniform_velocity(&velocity);
+        assert!(fluid.velocities.iter().all(|v| *v == velocity));
+
+        let handle = world.add_fluid(fluid);
+        let dt = 1.0e-3;
+        world.step(dt, &Vector::zeros());
+
+        for (p, p0) in world.fluids()[handle]
+            .positions
+            .iter()
+            .zip(positions.iter())
+        {
+            assert!((p - p0 - velocity * dt).norm() < 1.0e-4);
+        }
+    }
+
+    #[test]
+    fn particle_ages_follow_the_particles() {
+        use crate::solver::DFSPHSolver;
+
+        let particle_radius = 0.05;
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        let positions: Vec<_> = (0..4)
+            .map(|i| Point::from(Vector::x() * (i as Real)))
+            .collect();
+        let handle = world.add_fluid(Fluid::new(positions, particle_radius, 1000.0));
+        world.step(0.1, &Vector::zeros());
+
+        let fluid = &mut world.fluids_mut()[handle];
+        assert!(fluid.ages.iter().all(|age| (age - 0.1).abs() < 1.0e-5));
+        fluid.add_particles(&[Point::from(Vector::x() * 4.0)], None);
+        assert_eq!(fluid.ages[4], 0.0);
+        fluid.delete_particle_at_next_timestep(1);
+        world.step(0.1, &Vector::zeros());
+
+        // The ages are kept by the remaining particles, which are then sorted.
+        let fluid = &mut world.fluids_mut()[handle];
+        assert_eq!(fluid.ages.len(), 4);
+        fluid.ages[3] += 1.0;
+        fluid.z_sort();
+        let oldest = fluid
+            .ages
+            .iter()
+            .position(|age| (age - 1.1).abs() < 1.0e-5)
+            .unwrap();
+        assert_eq!(fluid.positions[oldest], Point::from(Vector::x() * 4.0));
+        assert!(fluid
+            .ages
+            .iter()
+            .all(|age| (age - 0.2).abs() < 1.0e-5 || (age - 1.1).abs() < 1.0e-5));
+    }
+
+    #[test]
+    fn listeners_keep_parallel_arrays_in_sync() {
+        use crate::object::FluidListener;
+        use crate::solver::DFSPHSolver;
+        use std::ops::Range;
+        use std::sync::{Arc, Mutex};
+
+        // A per-particle color, here the `x` coordinate of each particle when it was created.
+        struct Colors(Arc<Mutex<Vec<Real>>>);
+
+        impl FluidListener for Colors {
+            fn on_particles_added(&mut self, range: Range<usize>) {
+                let mut colors = self.0.lock().unwrap();
+                assert_eq!(colors.len(), range.start);
+                colors.extend(range.map(|i| 10.0 + i as Real));
+            }
+
+            fn on_particles_removed(&mut self, indices: &[usize]) {
+                let mut colors = self.0.lock().unwrap();
+                for i in indices.iter().rev() {
+                    let _ = colors.remove(*i);
+                }
+            }
+
+            fn on_particles_permuted(&mut self, permutation: &[usize]) {
+                let mut colors = self.0.lock().unwrap();
+                *colors = permutation.iter().map(|i| colors[*i]).collect();
+            }
+        }
+
+        let particle_radius = 0.05;
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        // Isolated particles, in reverse Morton order so that `z_sort` reverses them.
+        let positions: Vec<_> = (0..4)
+            .rev()
+            .map(|i| Point::from(Vector::repeat(i as Real)))
+            .collect();
+        let colors = Arc::new(Mutex::new(positions.iter().map(|pt| pt.x).collect()));
+        let mut fluid = Fluid::new(positions, particle_radius, 1000.0);
+        fluid.listeners.push(Box::new(Colors(colors.clone())));
+        let handle = world.add_fluid(fluid);
+        let check_colors = |world: &LiquidWorld| {
+            let fluid = &world.fluids()[handle];
+            let expected: Vec<_> = fluid.positions.iter().map(|pt| pt.x).collect();
+            assert_eq!(*colors.lock().unwrap(), expected);
+        };
+
+        // The emitted particles are colored from their index, which matches their position.
+        let emitted = [
+            Point::from(Vector::repeat(14.0)),
+            Point::from(Vector::repeat(15.0)),
+        ];
+        assert!(world.emit_particles(handle, &emitted, &[Vector::zeros(); 2], 0.0));
+        check_colors(&world);
+
+        // The removed indices refer to the particles before the removal.
+        world.fluids_mut()[handle].delete_particle_at_next_timestep(4);
+        world.fluids_mut()[handle].delete_particle_at_next_timestep(1);
+        world.step(0.01, &Vector::zeros());
+        assert_eq!(world.fluids()[handle].num_particles(), 4);
+        check_colors(&world);
+
+        world.fluids_mut()[handle].z_sort();
+        check_colors(&world);
+
+        world.fluids_mut()[handle].clear();
+        assert!(colors.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn active_fluids_rest_on_inactive_fluids() {
+        use crate::math::DIM;
+        use crate::solver::DFSPHSolver;
+
+        let particle_radius = 0.05;
+        let spacing = particle_radius * 2.0;
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        let block = |width: usize, height: usize, offset: Vector<Real>| {
+            particle_block(
+                width,
+                height,
+                spacing,
+                Vector::repeat(spacing * 0.5) + offset,
+            )
+        };
+
+        // A narrow column of oil above a wide frozen pool of water.
+        let mut water = Fluid::new(block(10, 3, Vector::zeros()), particle_radius, 1000.0);
+        water.set_uniform_velocity(&Vector::y());
+        water.set_active(false);
+        let water_positions = water.positions.clone();
+        let water = world.add_fluid(water);
+        let oil = world.add_fluid(Fluid::new(
+            block(4, 4, Vector::repeat(0.3)),
+            particle_radius,
+            800.0,
+        ));
+
+        for _ in 0..50 {
+            world.step(0.01, &(Vector::y() * -9.81));
+        }
+
+        assert!(!world.fluids()[water].is_active());
+        assert_eq!(world.fluids()[water].positions, water_positions);
+        assert!(world.fluids()[water]
+            .velocities
+            .iter()
+            .all(|v| *v == Vector::zeros()));
+
+        // The oil spreading beyond the edges of the pool falls, but the oil above the pool rests on it.
+        let above_pool =
+            |pt: &&Point<Real>| (0..DIM).all(|k| k == 1 || (0.2..0.8).contains(&pt[k]));
+
+        for pt in world.fluids()[oil].positions.iter().filter(above_pool) {
+            assert!(pt.y > 0.3 - particle_radius);
+        }
+    }
+
+    #[test]
+    fn nonpressure_substeps_integrate_stiff_forces() {
+        use crate::geometry::ParticlesContacts;
+        use crate::solver::{DFSPHSolver, NonPressureForce};
+
+        // A linear drag, stiff enough to overshoot when integrated explicitly over a whole substep.
+        struct Drag(Real);
+
+        impl NonPressureForce for Drag {
+            fn solve(
+                &mut self,
+                _timestep: &TimestepManager,
+                _kernel_radius: Real,
+                _fluid_fluid_contacts: &ParticlesContacts,
+                _fluid_boundaries_contacts: &ParticlesContacts,
+                fluid: &mut Fluid,
+                _boundaries: &[Boundary],
+                _densities: &[Real],
+            ) {
+                for (acceleration, velocity) in
+                    fluid.accelerations.iter_mut().zip(&fluid.velocities)
+                {
+                    *acceleration -= velocity * self.0;
+                }
+            }
+        }
+
+        let dt = 0.01;
+        let drag = 250.0;
+        // The velocities at the end of the second and third steps.
+        let velocities = |num_substeps| {
+            let mut world = LiquidWorld::new(DFSPHSolver::<CubicSplineKernel>::new(), 0.05, 2.0);
+            let mut fluid = Fluid::new(vec![Point::origin()], 0.05, 1000.0);
+            fluid.set_uniform_velocity(&Vector::x());
+            fluid.add_nonpressure_force(Box::new(Drag(drag)));
+            fluid.set_nonpressure_substeps(num_substeps);
+            let handle = world.add_fluid(fluid);
+
+            // The forces of the first substep see a zero timestep, and the velocity changes of a
+            // substep are applied at the beginning of the next one.
+            world.step(dt, &Vector::zeros());
+            world.step(dt, &Vector::zeros());
+            let velocity = world.fluids()[handle].velocities[0];
+            world.step(dt, &Vector::zeros());
+            (velocity, world.fluids()[handle].velocities[0])
+        };
+
+        let (v2, v3) = velocities(1);
+        assert!((v3 - v2 * (1.0 - drag * dt)).norm() < 1.0e-4);
+        assert!(v3.x * v2.x < 0.0);
+
+        let (v2, v3) = velocities(4);
+        let expected = v2 * (1.0 - drag * dt / 4.0).powi(4);
+        assert!((v3 - expected).norm() < 1.0e-4);
+        assert!(v3.x * v2.x > 0.0);
+    }
+}
@@ -0,0 +1,23 @@
+use std::ops::Range;
+
+/// Trait implemented by objects notified when particles are added to, removed from, or reordered within a fluid.
+///
+/// This can be used to keep per-particle user data in sync with the particles of a fluid.
+/// All the methods do nothing by default.
+pub trait FluidListener: Send + Sync {
+    /// Called after particles have been appended to the fluid.
+    ///
+    /// The new particles are at the indices within `range`.
+    fn on_particles_added(&mut self, _range: Range<usize>) {}
+
+    /// Called after particles have been removed from the fluid.
+    ///
+    /// The `indices` of the removed particles are sorted in increasing order, and refer to the
+    /// particle indices before the removal. The remaining particles keep their relative order.
+    fn on_particles_removed(&mut self, _indices: &[usize]) {}
+
+    /// Called after the particles of the fluid have been reordered.
+    ///
+    /// The particle now at the index `i` was at the index `permutation[i]` before the reordering.
+    fn on_particles_permuted(&mut self, _permutation: &[usize]) {}
+}
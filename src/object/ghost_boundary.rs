@@ -0,0 +1,231 @@
+use crate::math::{Point, Real, Vector};
+use crate::object::{Boundary, Fluid};
+use na::Unit;
+
+/// A planar boundary simulated with ghost particles mirroring the fluid particles close to it.
+///
+/// Instead of being sampled with boundary particles, the plane is represented, at each substep, by
+/// the reflection of every fluid particle closer than the kernel radius to it. These ghost particles
+/// carry the opposite velocity of the fluid particle they mirror, which enforces a no-slip
+/// condition. They only exist for the duration of a substep.
+///
+/// Fluid particles are expected to stay at least one particle radius away from the plane. Those
+/// that still go behind it are projected back onto it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GhostBoundary {
+    /// A point on the plane.
+    pub point: Point<Real>,
+    /// The normal of the plane, pointing toward the fluid.
+    pub normal: Unit<Vector<Real>>,
+}
+
+impl GhostBoundary {
+    /// Initializes a ghost boundary covering the plane passing through `point` with the given `normal`.
+    ///
+    /// The fluids are expected to lie on the side of the plane `normal` points to.
+    pub fn new(point: Point<Real>, normal: Unit<Vector<Real>>) -> Self {
+        Self { point, normal }
+    }
+
+    /// Replaces the particles of `boundary` with the ghost particles of the fluid particles closer than `h` to this plane.
+    ///
+    /// The fluid particles lying behind the plane are ignored, and the ghosts of the fluid particles
+    /// closer than `particle_radius` to the plane are placed as if they were at this distance. The buffers of `boundary` are reused,
+    /// and its volumes are marked as fixed so that the pressure solvers don't overwrite them.
+    pub fn generate_ghost_particles(
+        &self,
+        h: Real,
+        particle_radius: Real,
+        fluids: &[Fluid],
+        boundary: &mut Boundary,
+    ) {
+        generate_mirrored_particles(
+            |pt| ((pt - self.point).dot(&self.normal), self.normal),
+            h,
+            particle_radius,
+            fluids,
+            boundary,
+        )
+    }
+
+    /// Projects back onto this plane the fluid particles lying behind it, and cancels their velocity toward it.
+    pub fn project_fluid_particles(&self, fluids: &mut [Fluid]) {
+        project_fluid_particles_onto(
+            |pt| ((pt - self.point).dot(&self.normal), self.normal),
+            fluids,
+        )
+    }
+}
+
+// Replaces the particles of `boundary` with the reflections of the fluid particles closer than `h`
+// to a surface, across the plane tangent to this surface at the point closest to each fluid
+// particle. `surface(pt)` is the signed distance from `pt` to the surface, positive on the side of
+// the fluids, and the unit normal of the surface at the point closest to `pt`.
+pub(crate) fn generate_mirrored_particles(
+    surface: impl Fn(&Point<Real>) -> (Real, Unit<Vector<Real>>),
+    h: Real,
+    particle_radius: Real,
+    fluids: &[Fluid],
+    boundary: &mut Boundary,
+) {
+    boundary.positions.clear();
+    boundary.velocities.clear();
+    boundary.volumes.clear();
+    boundary.normals = None;
+    boundary.set_fixed_volumes(true);
+
+    for fluid in fluids {
+        for (i, pt) in fluid.positions.iter().enumerate() {
+            let (dist, normal) = surface(pt);
+
+            if dist >= na::zero::<Real>() && dist < h {
+                // NOTE: the kernel gradient vanishes when a particle and its ghost
+                // get too close, so the ghost is kept at least `particle_radius`
+                // behind the plane to keep repelling the particle.
+                let depth = dist + dist.max(particle_radius);
+                boundary.positions.push(pt - normal.into_inner() * depth);
+                boundary.velocities.push(-fluid.velocities[i]);
+                // NOTE: the pressure solvers only account for the pressure of the fluid
+                // particle when computing its interaction with a boundary particle. Doubling
+                // the ghost volume accounts for the (mirrored) pressure of the ghost itself.
+                boundary
+                    .volumes
+                    .push(fluid.volumes[i] * na::convert::<_, Real>(2.0));
+            }
+        }
+    }
+
+    boundary.clear_forces(true);
+}
+
+// Projects back onto a surface the fluid particles lying behind it, and cancels their velocity
+// toward it. `surface` is as for `generate_mirrored_particles`.
+pub(crate) fn project_fluid_particles_onto(
+    surface: impl Fn(&Point<Real>) -> (Real, Unit<Vector<Real>>),
+    fluids: &mut [Fluid],
+) {
+    let mut num_projected = 0;
+
+    for fluid in fluids {
+        for (pt, vel) in fluid.positions.iter_mut().zip(fluid.velocities.iter_mut()) {
+            let (dist, normal) = surface(pt);
+
+            if dist < na::zero::<Real>() {
+                *pt -= normal.into_inner() * dist;
+                let normal_vel = vel.dot(&normal);
+
+                if normal_vel < na::zero::<Real>() {
+                    *vel -= normal.into_inner() * normal_vel;
+                }
+
+                num_projected += 1;
+            }
+        }
+    }
+
+    if num_projected != 0 {
+        log::debug!(
+            "Projected {} fluid particles back onto a ghost boundary.",
+            num_projected
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GhostBoundary;
+    use crate::kernel::CubicSplineKernel;
+    use crate::math::{Point, Vector};
+    use crate::object::{Boundary, Fluid};
+    use crate::LiquidWorld;
+
+    #[test]
+    fn ghost_particles_mirror_the_fluid_particles_near_the_plane() {
+        let particle_radius = 0.05;
+        let h = particle_radius * 4.0;
+        let floor = GhostBoundary::new(Point::origin(), Vector::y_axis());
+        // Particles closer than the particle radius, closer than the kernel radius, farther than
+        // the kernel radius, and behind the floor.
+        let positions = vec![
+            Point::from(Vector::y() * 0.02),
+            Point::from(Vector::x() + Vector::y() * 0.1),
+            Point::from(Vector::y() * 0.5),
+            Point::from(Vector::y() * -0.1),
+        ];
+        let mut fluid = Fluid::new(positions, particle_radius, 1000.0);
+        fluid.velocities = vec![Vector::x() + Vector::y(); 4];
+        let mut boundary = Boundary::new(Vec::new());
+
+        floor.generate_ghost_particles(
+            h,
+            particle_radius,
+            std::slice::from_ref(&fluid),
+            &mut boundary,
+        );
+        assert_eq!(boundary.positions.len(), 2);
+        assert!(
+            (boundary.positions[0] - Point::from(Vector::y() * -particle_radius)).norm() < 1.0e-6
+        );
+        assert!(
+            (boundary.positions[1] - Point::from(Vector::x() - Vector::y() * 0.1)).norm() < 1.0e-6
+        );
+        assert_eq!(boundary.velocities, vec![-Vector::x() - Vector::y(); 2]);
+        assert_eq!(boundary.volumes, vec![fluid.volumes[0] * 2.0; 2]);
+        assert!(boundary.fixed_volumes());
+
+        // The ghosts are regenerated from scratch.
+        fluid.positions[1].y = 0.5;
+        floor.generate_ghost_particles(
+            h,
+            particle_radius,
+            std::slice::from_ref(&fluid),
+            &mut boundary,
+        );
+        assert_eq!(boundary.positions.len(), 1);
+    }
+
+    #[test]
+    fn ghost_boundaries_hold_the_fluid() {
+        use crate::math::DIM;
+        use crate::solver::DFSPHSolver;
+        use crate::testbed::particle_block;
+
+        let particle_radius = 0.05;
+        let gravity = Vector::y() * -9.81;
+        let width = 0.4;
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        // A tank made only of ghost boundaries, filled with a block of fluid.
+        let floor = world.add_ghost_boundary(GhostBoundary::new(Point::origin(), Vector::y_axis()));
+        for k in (0..DIM).filter(|k| *k != 1) {
+            let _ =
+                world.add_ghost_boundary(GhostBoundary::new(Point::origin(), Vector::ith_axis(k)));
+            let _ = world.add_ghost_boundary(GhostBoundary::new(
+                Point::from(Vector::ith(k, width)),
+                -Vector::ith_axis(k),
+            ));
+        }
+        let positions =
+            particle_block(4, 4, particle_radius * 2.0, Vector::repeat(particle_radius));
+        let num_particles = positions.len();
+        let fluid = world.add_fluid(Fluid::new(positions, particle_radius, 1000.0));
+
+        for _ in 0..100 {
+            world.step(0.005, &gravity);
+            let ghosts = &world.boundaries()[floor];
+            assert!(!ghosts.positions.is_empty() && ghosts.positions.len() <= num_particles);
+        }
+
+        // The fluid rests in the tank, about one particle radius away from its walls, instead of
+        // reaching a free-fall velocity of about 5.
+        let fluid = &world.fluids()[fluid];
+        assert!(fluid.positions.iter().all(|pt| {
+            pt.y > particle_radius * 0.5
+                && (0..DIM).all(|k| k == 1 || (pt[k] > 0.0 && pt[k] < width))
+        }));
+        assert!(fluid.velocities.iter().all(|vel| vel.norm() < 0.2));
+    }
+}
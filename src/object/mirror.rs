@@ -0,0 +1,150 @@
+use crate::math::{Point, Real, Vector};
+use crate::object::{Boundary, Fluid};
+use na::Unit;
+
+fn mirror_point(
+    pt: &Point<Real>,
+    plane_point: &Point<Real>,
+    plane_normal: &Vector<Real>,
+) -> Point<Real> {
+    pt - *plane_normal * ((pt - plane_point).dot(plane_normal) * na::convert::<_, Real>(2.0))
+}
+
+fn mirror_vector(v: &Vector<Real>, plane_normal: &Vector<Real>) -> Vector<Real> {
+    v - *plane_normal * (v.dot(plane_normal) * na::convert::<_, Real>(2.0))
+}
+
+/// Creates a new fluid by reflecting all the particles of `fluid` across a plane.
+///
+/// The velocity component normal to the plane is flipped, and the particle volumes, ages, rest densities,
+/// and gravity scale are copied.
+/// Particles closer than one particle radius to the plane are skipped so that they don't
+/// overlap with their own reflection at the seam. Note that the non-pressure forces of
+/// `fluid` are not copied.
+pub fn mirror_fluid(
+    fluid: &Fluid,
+    plane_point: &Point<Real>,
+    plane_normal: &Unit<Vector<Real>>,
+) -> Fluid {
+    let mut positions = Vec::new();
+    let mut velocities = Vec::new();
+    let mut volumes = Vec::new();
+    let mut ages = Vec::new();
+    let mut rest_densities = Vec::new();
+
+    for i in 0..fluid.num_particles() {
+        let dist = (fluid.positions[i] - plane_point).dot(plane_normal);
+
+        if dist.abs() >= fluid.particle_radius() {
+            positions.push(mirror_point(&fluid.positions[i], plane_point, plane_normal));
+            velocities.push(mirror_vector(&fluid.velocities[i], plane_normal));
+            volumes.push(fluid.volumes[i]);
+            ages.push(fluid.ages[i]);
+            rest_densities.push(fluid.rest_density(i));
+        }
+    }
+
+    let mut result = Fluid::new(positions, fluid.particle_radius(), fluid.density0);
+    result.velocities = velocities;
+    result.volumes = volumes;
+    result.ages = ages;
+    result.gravity_scale = fluid.gravity_scale;
+
+    if fluid.rest_densities().is_some() {
+        result.set_rest_densities(Some(rest_densities));
+    }
+
+    result
+}
+
+/// Creates a new boundary by reflecting all the particles of `boundary` across a plane.
+///
+/// Particles lying on the plane (up to `particle_radius`) are skipped so that they don't
+/// overlap with their own reflection at the seam. The particle volumes are copied only if
+/// `boundary` has fixed volumes. The normals of the particles, if any, are reflected too, and
+/// the fluid mask is copied.
+pub fn mirror_boundary(
+    boundary: &Boundary,
+    plane_point: &Point<Real>,
+    plane_normal: &Unit<Vector<Real>>,
+    particle_radius: Real,
+) -> Boundary {
+    let mut positions = Vec::new();
+    let mut velocities = Vec::new();
+    let mut volumes = Vec::new();
+    let mut normals = Vec::new();
+
+    for (i, (pt, vel)) in boundary
+        .positions
+        .iter()
+        .zip(boundary.velocities.iter())
+        .enumerate()
+    {
+        if (pt - plane_point).dot(plane_normal).abs() >= particle_radius {
+            positions.push(mirror_point(pt, plane_point, plane_normal));
+            velocities.push(mirror_vector(vel, plane_normal));
+            volumes.push(boundary.volumes[i]);
+
+            if let Some(boundary_normals) = &boundary.normals {
+                normals.push(mirror_vector(&boundary_normals[i], plane_normal));
+            }
+        }
+    }
+
+    let mut result = Boundary::new(positions);
+    result.velocities = velocities;
+    result.fluid_mask = boundary.fluid_mask.clone();
+
+    if boundary.normals.is_some() {
+        result.normals = Some(normals);
+    }
+
+    if boundary.fixed_volumes() {
+        result.volumes = volumes;
+        result.set_fixed_volumes(true);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mirror_boundary, mirror_fluid};
+    use crate::math::{Point, Vector};
+    use crate::object::{Boundary, Fluid};
+
+    #[test]
+    fn mirrored_particles_are_reflected_without_duplicates_at_the_seam() {
+        let particle_radius = 0.05;
+        let plane_point = Point::from(Vector::x());
+        // A particle on the plane, and two particles on its negative side.
+        let positions = vec![
+            Point::from(Vector::x() + Vector::y() * 0.3),
+            Point::from(Vector::x() * 0.9),
+            Point::from(Vector::x() * 0.5 + Vector::y()),
+        ];
+        let mut fluid = Fluid::new(positions, particle_radius, 1000.0);
+        fluid.velocities = vec![Vector::x(), Vector::x() * 2.0 + Vector::y(), Vector::y()];
+        fluid.volumes = vec![1.0e-3, 2.0e-3, 3.0e-3];
+        fluid.gravity_scale = 0.5;
+
+        let mirrored = mirror_fluid(&fluid, &plane_point, &Vector::x_axis());
+        assert_eq!(mirrored.num_particles(), 2);
+        assert!((mirrored.positions[0] - Point::from(Vector::x() * 1.1)).norm() < 1.0e-6);
+        assert!(
+            (mirrored.positions[1] - Point::from(Vector::x() * 1.5 + Vector::y())).norm() < 1.0e-6
+        );
+        assert_eq!(
+            mirrored.velocities,
+            vec![Vector::x() * -2.0 + Vector::y(), Vector::y()]
+        );
+        assert_eq!(mirrored.volumes, vec![2.0e-3, 3.0e-3]);
+        assert_eq!(mirrored.gravity_scale, 0.5);
+
+        let mut boundary = Boundary::new(fluid.positions.clone());
+        boundary.normals = Some(vec![Vector::x(), Vector::x(), -Vector::x()]);
+        let mirrored = mirror_boundary(&boundary, &plane_point, &Vector::x_axis(), particle_radius);
+        assert_eq!(mirrored.positions.len(), 2);
+        assert_eq!(mirrored.normals, Some(vec![-Vector::x(), Vector::x()]));
+    }
+}
@@ -1,12 +1,23 @@
 //! Fluid and boundary objects that can be simulated.
 
+pub use self::analytic_collider::AnalyticCollider;
 pub use self::boundary::{Boundary, BoundaryHandle, BoundarySet};
 pub use self::contiguous_arena::{ContiguousArena, ContiguousArenaIndex};
 pub use self::fluid::{Fluid, FluidHandle, FluidSet};
+pub use self::fluid_listener::FluidListener;
+pub use self::ghost_boundary::GhostBoundary;
+pub use self::mirror::{mirror_boundary, mirror_fluid};
+pub use self::reflective_plane::ReflectivePlane;
 
+mod analytic_collider;
 mod boundary;
+mod boundary_shapes;
 mod contiguous_arena;
 mod fluid;
+mod fluid_listener;
+mod ghost_boundary;
+mod mirror;
+mod reflective_plane;
 
 /// The identifier of a single particle.
 pub enum ParticleId {
@@ -0,0 +1,110 @@
+use crate::math::{Point, Real, Vector};
+use crate::object::Fluid;
+use na::Unit;
+
+/// A plane reflecting the fluid particles crossing it, without any boundary particle.
+///
+/// Unlike `AnalyticCollider::HalfSpace`, the plane is not seen by the pressure solver: the fluid
+/// particles behind the plane at the end of a substep are simply moved back in front of it, and
+/// their velocity toward the plane is reflected and scaled by the restitution coefficient. This is
+/// cheaper and less accurate than a boundary, e.g., the fluid resting on such a plane is slightly
+/// compressed, but it is enough for quick tests and demos, and it can be combined with any boundary.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ReflectivePlane {
+    /// A point on the plane.
+    pub point: Point<Real>,
+    /// The normal of the plane, pointing toward the side where the fluid is kept.
+    pub normal: Unit<Vector<Real>>,
+    /// The ratio between the normal velocities of a particle after and before its reflection.
+    ///
+    /// This is typically in `[0, 1]`: `0` stops the particles on the plane, and `1` reflects them
+    /// without any loss of energy.
+    pub restitution: Real,
+}
+
+impl ReflectivePlane {
+    /// Initializes a reflective plane passing through `point` with the given `normal` and restitution coefficient.
+    pub fn new(point: Point<Real>, normal: Unit<Vector<Real>>, restitution: Real) -> Self {
+        Self {
+            point,
+            normal,
+            restitution,
+        }
+    }
+
+    /// Reflects the fluid particles behind this plane, and returns the number of reflected particles.
+    ///
+    /// The penetration depth of each of these particles is reflected, and scaled by the restitution
+    /// coefficient as its normal velocity, so particles with a zero restitution end up on the plane.
+    pub fn reflect_fluid_particles(&self, fluids: &mut [Fluid]) -> usize {
+        let normal = self.normal.into_inner();
+        let restitution_factor = na::one::<Real>() + self.restitution;
+        let mut num_reflected = 0;
+
+        for fluid in fluids {
+            for (pt, vel) in fluid.positions.iter_mut().zip(fluid.velocities.iter_mut()) {
+                let dist = (*pt - self.point).dot(&normal);
+
+                if dist < na::zero::<Real>() {
+                    *pt -= normal * (dist * restitution_factor);
+                    let normal_vel = vel.dot(&normal);
+
+                    if normal_vel < na::zero::<Real>() {
+                        *vel -= normal * (normal_vel * restitution_factor);
+                    }
+
+                    num_reflected += 1;
+                }
+            }
+        }
+
+        num_reflected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kernel::CubicSplineKernel;
+    use crate::math::{Point, Vector};
+    use crate::object::{Boundary, Fluid};
+    use crate::LiquidWorld;
+
+    #[test]
+    fn reflective_planes_bounce_the_fluid_particles() {
+        use crate::solver::DFSPHSolver;
+
+        let particle_radius = 0.05;
+        let gravity = Vector::y() * -9.81;
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        world.add_reflective_plane(Point::origin(), Vector::y_axis(), 0.5);
+        // A sampled boundary standing on the plane, with a particle falling on it.
+        let _ = world.add_boundary(Boundary::cuboid(
+            &Point::from(Vector::x() * 2.0 + Vector::y() * 0.25),
+            &Vector::repeat(0.25),
+            particle_radius,
+            2,
+        ));
+        let positions = vec![
+            Point::from(Vector::y() * 0.2),
+            Point::from(Vector::x() * 2.0 + Vector::y() * 0.7),
+        ];
+        let fluid = world.add_fluid(Fluid::new(positions, particle_radius, 1000.0));
+        let mut bounced = false;
+
+        for _ in 0..40 {
+            world.step(0.01, &gravity);
+            let fluid = &world.fluids()[fluid];
+            assert!(fluid.positions[0].y >= 0.0);
+            assert!(fluid.positions[1].y > 0.5);
+            bounced |= fluid.velocities[0].y > 0.0;
+        }
+
+        assert!(bounced);
+        assert_eq!(world.reflective_planes().len(), 1);
+        assert_eq!(world.reflective_planes()[0].restitution, 0.5);
+    }
+}
@@ -0,0 +1,259 @@
+use crate::math::{Point, Real, Vector, DIM};
+use crate::object::{ContiguousArena, ContiguousArenaIndex, Fluid};
+
+/// A virtual probe measuring the fluids inside of, and flowing through, an axis-aligned box.
+///
+/// Probes are added to a liquid world with `LiquidWorld::add_probe` and updated at the end of each
+/// step. They only read the state of the simulation, so they do not affect it.
+#[derive(Clone, Debug)]
+pub struct Probe {
+    /// The lower corner of the measured region.
+    pub mins: Point<Real>,
+    /// The upper corner of the measured region.
+    pub maxs: Point<Real>,
+    measurement: ProbeMeasurement,
+}
+
+/// The quantities measured by a probe during the last step.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ProbeMeasurement {
+    /// The number of fluid particles inside of the region at the end of the step.
+    pub num_particles: usize,
+    /// The average pressure of the fluid particles inside of the region, as estimated by
+    /// `LiquidWorld::compute_pressure_estimate`.
+    pub average_pressure: Real,
+    /// The mass-weighted average velocity of the fluid particles inside of the region.
+    pub average_velocity: Vector<Real>,
+    /// The mass flowing out of the region through each of its faces per unit of time.
+    ///
+    /// The faces `2 * i` and `2 * i + 1` are the faces orthogonal to the axis `i`, at the coordinates
+    /// `mins[i]` and `maxs[i]` respectively. Negative values indicate a mass flowing into the region.
+    pub face_mass_fluxes: [Real; 2 * DIM],
+}
+
+impl Default for ProbeMeasurement {
+    fn default() -> Self {
+        Self {
+            num_particles: 0,
+            average_pressure: na::zero::<Real>(),
+            average_velocity: Vector::zeros(),
+            face_mass_fluxes: [na::zero::<Real>(); 2 * DIM],
+        }
+    }
+}
+
+impl ProbeMeasurement {
+    /// The net mass flowing out of the region per unit of time, through all its faces.
+    pub fn net_mass_flux(&self) -> Real {
+        self.face_mass_fluxes.iter().copied().sum()
+    }
+}
+
+impl Probe {
+    /// Creates a new probe measuring the region with the given lower and upper corners.
+    pub fn new(mins: Point<Real>, maxs: Point<Real>) -> Self {
+        assert!(
+            (0..DIM).all(|i| mins[i] <= maxs[i]),
+            "The lower corner of a probe must not be greater than its upper corner."
+        );
+        Self {
+            mins,
+            maxs,
+            measurement: ProbeMeasurement::default(),
+        }
+    }
+
+    /// The quantities measured during the last step.
+    pub fn measurement(&self) -> &ProbeMeasurement {
+        &self.measurement
+    }
+
+    /// Checks if `point` is inside of the measured region.
+    pub fn contains_point(&self, point: &Point<Real>) -> bool {
+        (0..DIM).all(|i| point[i] >= self.mins[i] && point[i] <= self.maxs[i])
+    }
+
+    pub(crate) fn reset_measurement(&mut self) {
+        self.measurement = ProbeMeasurement::default();
+    }
+
+    // Measures the fluids after a step of length `dt` during which the particles moved from
+    // `start_positions` to their current positions.
+    pub(crate) fn update(
+        &mut self,
+        dt: Real,
+        fluids: &[Fluid],
+        start_positions: &[Vec<Point<Real>>],
+        pressures: &[Vec<Real>],
+    ) {
+        let mut measurement = ProbeMeasurement::default();
+        let mut total_mass = na::zero::<Real>();
+        let mut total_pressure = na::zero::<Real>();
+        let mut momentum = Vector::zeros();
+
+        for ((fluid, start_positions), pressures) in
+            fluids.iter().zip(start_positions).zip(pressures)
+        {
+            for (i, pos) in fluid.positions.iter().enumerate() {
+                let mass = fluid.particle_mass(i);
+
+                if self.contains_point(pos) {
+                    measurement.num_particles += 1;
+                    total_mass += mass;
+                    total_pressure += pressures[i];
+                    momentum += fluid.velocities[i] * mass;
+                }
+
+                if dt > na::zero::<Real>() {
+                    if let Some(start) = start_positions.get(i) {
+                        self.accumulate_face_crossings(
+                            start,
+                            pos,
+                            mass / dt,
+                            &mut measurement.face_mass_fluxes,
+                        );
+                    }
+                }
+            }
+        }
+
+        if measurement.num_particles != 0 {
+            measurement.average_pressure =
+                total_pressure / na::convert::<_, Real>(measurement.num_particles as f64);
+        }
+
+        if total_mass > na::zero::<Real>() {
+            measurement.average_velocity = momentum / total_mass;
+        }
+
+        self.measurement = measurement;
+    }
+
+    // Adds `flux` to the flux of each face crossed by the segment from `start` to `end`, with a
+    // positive sign if the segment leaves the region through this face.
+    fn accumulate_face_crossings(
+        &self,
+        start: &Point<Real>,
+        end: &Point<Real>,
+        flux: Real,
+        face_fluxes: &mut [Real; 2 * DIM],
+    ) {
+        for axis in 0..DIM {
+            for (face, plane) in [(2 * axis, self.mins[axis]), (2 * axis + 1, self.maxs[axis])] {
+                let start_dist = start[axis] - plane;
+                let end_dist = end[axis] - plane;
+
+                if (start_dist < na::zero::<Real>()) == (end_dist < na::zero::<Real>()) {
+                    continue;
+                }
+
+                let t = start_dist / (start_dist - end_dist);
+                let crossing = start + (end - start) * t;
+                let on_face = (0..DIM).all(|i| {
+                    i == axis || (crossing[i] >= self.mins[i] && crossing[i] <= self.maxs[i])
+                });
+
+                if on_face {
+                    // The segment leaves the region if it ends below the lower face or above the upper face.
+                    let leaves = (face % 2 == 1) == (end_dist >= na::zero::<Real>());
+
+                    if leaves {
+                        face_fluxes[face] += flux;
+                    } else {
+                        face_fluxes[face] -= flux;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+/// The unique identifier of a probe.
+pub struct ProbeHandle(ContiguousArenaIndex);
+/// The set of all probes.
+pub type ProbeSet = ContiguousArena<ProbeHandle, Probe>;
+
+impl From<ContiguousArenaIndex> for ProbeHandle {
+    #[inline]
+    fn from(i: ContiguousArenaIndex) -> Self {
+        ProbeHandle(i)
+    }
+}
+
+impl From<ProbeHandle> for ContiguousArenaIndex {
+    #[inline]
+    fn from(handle: ProbeHandle) -> Self {
+        handle.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Probe;
+    use crate::kernel::CubicSplineKernel;
+    use crate::math::{Point, Real, Vector};
+    use crate::object::Fluid;
+    use crate::LiquidWorld;
+
+    #[test]
+    fn probe_measures_the_flux_through_its_faces() {
+        use crate::math::DIM;
+        use crate::solver::DFSPHSolver;
+
+        let particle_radius = 0.05;
+        let spacing = particle_radius * 2.0;
+        let n: usize = 4;
+        let positions: Vec<_> = (0..n.pow(DIM as u32))
+            .map(|k| {
+                Point::from(Vector::from_fn(|a, _| {
+                    ((k / n.pow(a as u32)) % n) as Real * spacing
+                }))
+            })
+            .collect();
+        let velocity = Vector::x();
+
+        let make_world = || {
+            let mut world = LiquidWorld::new(
+                DFSPHSolver::<CubicSplineKernel>::new(),
+                particle_radius,
+                2.0,
+            );
+            let mut fluid = Fluid::new(positions.clone(), particle_radius, 1000.0);
+            fluid.velocities.fill(velocity);
+            let _ = world.add_fluid(fluid);
+            world
+        };
+
+        // The lower and upper faces along `x` lie between the first two and the last two columns of
+        // particles, which all move by half a spacing.
+        let mut mins = Point::from(Vector::repeat(-1.0));
+        let mut maxs = Point::from(Vector::repeat(1.0));
+        mins.x = spacing * 0.2;
+        maxs.x = spacing * 3.2;
+
+        let mut world = make_world();
+        let mut reference = make_world();
+        let probe = world.add_probe(Probe::new(mins, maxs));
+        let dt = spacing * 0.5 / velocity.norm();
+        world.step(dt, &Vector::zeros());
+        reference.step(dt, &Vector::zeros());
+
+        // Probes do not affect the simulation.
+        assert_eq!(
+            world.fluids().as_slice()[0].positions,
+            reference.fluids().as_slice()[0].positions
+        );
+
+        let measurement = world.probe(probe).unwrap().measurement();
+        let column_size = n.pow(DIM as u32 - 1);
+        let column_flux = world.fluids().as_slice()[0].particle_mass(0) * column_size as Real / dt;
+
+        assert_eq!(measurement.num_particles, column_size * 3);
+        assert!((measurement.average_velocity - velocity).norm() < 1.0e-3);
+        assert!((measurement.face_mass_fluxes[0] + column_flux).abs() < column_flux * 1.0e-3);
+        assert!((measurement.face_mass_fluxes[1] - column_flux).abs() < column_flux * 1.0e-3);
+        assert!(measurement.net_mass_flux().abs() < column_flux * 1.0e-3);
+        assert!(measurement.face_mass_fluxes[2..].iter().all(|f| *f == 0.0));
+    }
+}
@@ -0,0 +1,238 @@
+use crate::geometry::HGrid;
+use crate::math::{Point, Real, Vector, DIM};
+use crate::object::Fluid;
+
+/// Parameters of the periodic resampling of the fluid particles.
+///
+/// Every `interval` steps, at the beginning of the step, the particles of each fluid are resampled
+/// to keep their distribution close to uniform:
+/// - two particles closer than `merge_distance * 2 * particle_radius` are merged into a single
+///   particle at their center of mass, with their total mass and momentum.
+/// - a particle whose closest neighbor within the kernel radius is farther than
+///   `split_distance * 2 * particle_radius` is split into two particles with half its mass and
+///   its velocity, on each side of its position along the direction of this neighbor.
+///
+/// The total mass, momentum, and center of mass of each fluid are preserved. A merged particle keeps
+/// the age of one of its two particles, and both halves of a split particle keep its age. To keep the particles
+/// of similar sizes, a particle is only split if its volume is at least the default particle volume
+/// of its fluid, and two particles are only merged if their total volume is at most twice this
+/// default volume. Isolated particles, e.g., spray, are left untouched. The merged particles are
+/// removed, and the split particles added, like particles removed and added by the user, so the
+/// listeners of the fluids and the solvers are notified accordingly.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ResamplingParameters {
+    /// The number of steps between two resampling passes.
+    pub interval: usize,
+    /// The distance, as a multiple of the particle spacing, below which two particles are merged.
+    pub merge_distance: Real,
+    /// The distance, as a multiple of the particle spacing, beyond which a particle is split.
+    pub split_distance: Real,
+}
+
+impl ResamplingParameters {
+    /// Initializes new resampling parameters.
+    ///
+    /// The particles are merged when closer than half the particle spacing, and split when their
+    /// closest neighbor is farther than 1.5 times the particle spacing.
+    pub fn new(interval: usize) -> Self {
+        assert!(interval > 0, "The resampling interval must be positive.");
+        Self {
+            interval,
+            merge_distance: na::convert::<_, Real>(0.5),
+            split_distance: na::convert::<_, Real>(1.5),
+        }
+    }
+}
+
+// Resamples the particles of `fluid`, looking for neighbors within the kernel radius `h`.
+//
+// Returns the number of merged and split particles.
+pub(crate) fn resample_fluid(
+    fluid: &mut Fluid,
+    h: Real,
+    parameters: &ResamplingParameters,
+) -> (usize, usize) {
+    let spacing = fluid.particle_radius() * na::convert::<_, Real>(2.0);
+    let merge_distance = parameters.merge_distance * spacing;
+    let split_distance = parameters.split_distance * spacing;
+    let default_volume = fluid.default_particle_volume();
+    // Absorbs the rounding errors of the volumes obtained by merging and splitting particles.
+    let tolerance = na::convert::<_, Real>(1.0e-4);
+    let max_merged_volume = default_volume * (na::convert::<_, Real>(2.0) + tolerance);
+    let min_split_volume = default_volume * (na::one::<Real>() - tolerance);
+    // The spacing of particles with half the default volume.
+    let split_spacing = spacing * na::convert::<_, Real>(0.5).powf(na::one::<Real>() / DIM as Real);
+
+    let mut grid = HGrid::new(h);
+    let mut resampled = fluid.deleted_particles_mask().to_vec();
+
+    for (i, pt) in fluid.positions.iter().enumerate() {
+        if !resampled[i] {
+            grid.insert(pt, i);
+        }
+    }
+
+    let mut new_positions = Vec::new();
+    let mut new_velocities = Vec::new();
+    let mut new_particles = Vec::new();
+    let mut num_merged = 0;
+
+    for i in 0..fluid.num_particles() {
+        if resampled[i] {
+            continue;
+        }
+
+        let pi = fluid.positions[i];
+        let closest = grid
+            .neighbor_cells(&grid.key(&pi), h)
+            .flat_map(|(_, particles)| particles.iter().copied())
+            .filter(|j| *j != i)
+            .map(|j| (j, na::distance(&pi, &fluid.positions[j])))
+            .filter(|(_, dist)| *dist <= h)
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+
+        let Some((j, dist)) = closest else {
+            continue;
+        };
+
+        if dist < merge_distance
+            && !resampled[j]
+            && fluid.volumes[i] + fluid.volumes[j] <= max_merged_volume
+        {
+            let (mi, mj) = (fluid.particle_mass(i), fluid.particle_mass(j));
+            let mass = mi + mj;
+
+            if mass > na::zero::<Real>() {
+                fluid.positions[i] = Point::from(
+                    (fluid.positions[i].coords * mi + fluid.positions[j].coords * mj) / mass,
+                );
+                fluid.velocities[i] = (fluid.velocities[i] * mi + fluid.velocities[j] * mj) / mass;
+                fluid.volumes[i] = mass / fluid.rest_density(i);
+            }
+
+            fluid.delete_particle_at_next_timestep(j);
+            resampled[i] = true;
+            resampled[j] = true;
+            num_merged += 1;
+        } else if dist > split_distance && fluid.volumes[i] >= min_split_volume {
+            let dir: Vector<Real> = (pi - fluid.positions[j]) / dist;
+            let offset = dir * (split_spacing * na::convert::<_, Real>(0.5));
+
+            fluid.positions[i] = pi - offset;
+            fluid.volumes[i] *= na::convert::<_, Real>(0.5);
+            new_positions.push(pi + offset);
+            new_velocities.push(fluid.velocities[i]);
+            new_particles.push(i);
+            resampled[i] = true;
+        }
+    }
+
+    if new_particles.is_empty() {
+        return (num_merged, 0);
+    }
+
+    let first_new_particle = fluid.num_particles();
+    fluid.add_particles(&new_positions, Some(&new_velocities));
+
+    for (k, i) in new_particles.iter().enumerate() {
+        let new_particle = first_new_particle + k;
+        fluid.volumes[new_particle] = fluid.volumes[*i];
+        fluid.ages[new_particle] = fluid.ages[*i];
+
+        if let Some(rest_densities) = &mut fluid.rest_densities {
+            rest_densities[new_particle] = rest_densities[*i];
+        }
+    }
+
+    (num_merged, new_particles.len())
+}
+
+#[test]
+fn resampling_conserves_mass_and_momentum() {
+    use crate::object::FluidListener;
+    use std::ops::Range;
+    use std::sync::{Arc, Mutex};
+
+    struct Counter(Arc<Mutex<(usize, usize)>>);
+
+    impl FluidListener for Counter {
+        fn on_particles_added(&mut self, range: Range<usize>) {
+            self.0.lock().unwrap().0 += range.len();
+        }
+
+        fn on_particles_removed(&mut self, indices: &[usize]) {
+            self.0.lock().unwrap().1 += indices.len();
+        }
+    }
+
+    let particle_radius = 0.05;
+    let spacing = particle_radius * 2.0;
+    let h = spacing * 2.0;
+    // A regular row, with a clump at its start, and a gap before its last particle.
+    let mut positions: Vec<_> = (0..8)
+        .map(|i| Point::from(Vector::x() * (i as Real * spacing)))
+        .collect();
+    positions.push(Point::from(Vector::x() * (0.3 * spacing)));
+    positions.push(Point::from(Vector::x() * (8.7 * spacing)));
+    let mut fluid = Fluid::new(positions, particle_radius, 1000.0);
+    fluid.set_velocity_field(|p| Vector::x() * p.x + Vector::y());
+    let counts = Arc::new(Mutex::new((0, 0)));
+    fluid.listeners.push(Box::new(Counter(counts.clone())));
+
+    let momentum = |fluid: &Fluid| {
+        (0..fluid.num_particles())
+            .filter(|i| !fluid.deleted_particles_mask()[*i])
+            .map(|i| {
+                (
+                    fluid.velocities[i] * fluid.particle_mass(i),
+                    fluid.particle_mass(i),
+                )
+            })
+            .fold((Vector::zeros(), 0.0), |acc, (p, m)| (acc.0 + p, acc.1 + m))
+    };
+    let (momentum0, mass0) = momentum(&fluid);
+    let center0 = fluid.center_of_mass().unwrap();
+
+    let parameters = ResamplingParameters::new(1);
+    assert_eq!(resample_fluid(&mut fluid, h, &parameters), (1, 1));
+    let (momentum1, mass1) = momentum(&fluid);
+    assert!((mass1 - mass0).abs() < mass0 * 1.0e-5);
+    assert!((momentum1 - momentum0).norm() < momentum0.norm() * 1.0e-5);
+
+    fluid.apply_particles_removal();
+    assert_eq!(*counts.lock().unwrap(), (1, 1));
+    assert_eq!(fluid.num_particles(), 10);
+    assert!((fluid.center_of_mass().unwrap() - center0).norm() < 1.0e-5);
+
+    // The resampled particles are not resampled back.
+    assert_eq!(resample_fluid(&mut fluid, h, &parameters), (0, 0));
+}
+
+#[test]
+fn resampling_runs_every_interval() {
+    use crate::kernel::CubicSplineKernel;
+    use crate::solver::DFSPHSolver;
+    use crate::LiquidWorld;
+
+    let particle_radius = 0.05;
+    let positions: Vec<_> = (0..10)
+        .map(|i| Point::from(Vector::x() * (i as Real * 0.03)))
+        .collect();
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    let fluid = world.add_fluid(Fluid::new(positions, particle_radius, 1000.0));
+    world.set_resampling_parameters(Some(ResamplingParameters::new(2)));
+
+    for _ in 0..2 {
+        world.step(1.0e-4, &Vector::zeros());
+        assert_eq!(world.fluids()[fluid].num_particles(), 10);
+    }
+
+    // The clumped particles are merged at the beginning of the third step.
+    world.step(1.0e-4, &Vector::zeros());
+    assert!(world.fluids()[fluid].num_particles() < 10);
+    assert!(world.fluid_densities(fluid).is_some());
+}
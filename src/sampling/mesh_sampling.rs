@@ -0,0 +1,233 @@
+use crate::math::{Point, Real, Vector};
+
+use approx::AbsDiffEq;
+#[cfg(feature = "dim2")]
+use parry::shape::Polyline;
+#[cfg(feature = "dim3")]
+use parry::shape::TriMesh;
+#[cfg(feature = "dim3")]
+use std::collections::HashMap;
+
+/// Samples the surface of a triangle mesh, with the normal of the mesh at each sample point.
+///
+/// Each triangle is covered by points separated by at most `2 * particle_rad`. The normal of a point
+/// inside of a triangle is the normal of this triangle, oriented by the winding of its vertices.
+/// The points on an edge or a vertex are generated only once even if they are shared by several
+/// triangles, and their normal is the normalized sum of the normals of these triangles.
+///
+/// Returns the sample points and their unit normals, e.g., for `Boundary::with_normals`.
+#[cfg(feature = "dim3")]
+pub fn trimesh_surface_sample(
+    mesh: &TriMesh,
+    particle_rad: Real,
+) -> (Vec<Point<Real>>, Vec<Vector<Real>>) {
+    let spacing = particle_rad * na::convert::<_, Real>(2.0);
+    let vertices = mesh.vertices();
+    let mut vertex_normals = vec![NormalSum::default(); vertices.len()];
+    let mut edges = Vec::new();
+    let mut edge_ids = HashMap::new();
+    let mut faces = Vec::new();
+
+    for idx in mesh.indices() {
+        let [a, b, c] = idx.map(|i| vertices[i as usize]);
+
+        // Skip degenerate triangles, which have no normal.
+        let Some(normal) = (b - a)
+            .cross(&(c - a))
+            .try_normalize(Real::default_epsilon())
+        else {
+            continue;
+        };
+
+        for k in 0..3 {
+            let (i1, i2) = (idx[k], idx[(k + 1) % 3]);
+            vertex_normals[i1 as usize].add(&normal);
+
+            let key = (i1.min(i2), i1.max(i2));
+            let edge_id = *edge_ids.entry(key).or_insert_with(|| {
+                edges.push((key, NormalSum::default()));
+                edges.len() - 1
+            });
+            edges[edge_id].1.add(&normal);
+        }
+
+        faces.push(([a, b, c], normal));
+    }
+
+    let mut points = Vec::new();
+    let mut normals = Vec::new();
+
+    for (vertex, normal) in vertices.iter().zip(vertex_normals.iter()) {
+        if let Some(normal) = normal.average() {
+            points.push(*vertex);
+            normals.push(normal);
+        }
+    }
+
+    for ((i1, i2), normal) in &edges {
+        let normal = normal.average().unwrap();
+        sample_segment_interior(
+            &vertices[*i1 as usize],
+            &vertices[*i2 as usize],
+            &normal,
+            spacing,
+            &mut points,
+            &mut normals,
+        );
+    }
+
+    for ([a, b, c], normal) in faces {
+        let n = [(a, b), (b, c), (c, a)]
+            .iter()
+            .map(|(p1, p2)| num_intervals(na::distance(p1, p2), spacing))
+            .max()
+            .unwrap();
+
+        for i in 1..n {
+            for j in 1..n - i {
+                let u = (i as Real) / (n as Real);
+                let v = (j as Real) / (n as Real);
+                points.push(a + (b - a) * u + (c - a) * v);
+                normals.push(normal);
+            }
+        }
+    }
+
+    (points, normals)
+}
+
+/// Samples a polyline, with the normal of the polyline at each sample point.
+///
+/// Each segment is covered by points separated by at most `2 * particle_rad`. The normal of a point
+/// inside of a segment is the counterclockwise normal of this segment, as given by
+/// `parry::shape::Segment::normal`. The vertices are generated only once even if they are shared
+/// by several segments, and their normal is the normalized sum of the normals of these segments.
+///
+/// Returns the sample points and their unit normals, e.g., for `Boundary::with_normals`.
+#[cfg(feature = "dim2")]
+pub fn polyline_surface_sample(
+    polyline: &Polyline,
+    particle_rad: Real,
+) -> (Vec<Point<Real>>, Vec<Vector<Real>>) {
+    let spacing = particle_rad * na::convert::<_, Real>(2.0);
+    let vertices = polyline.vertices();
+    let mut vertex_normals = vec![NormalSum::default(); vertices.len()];
+    let mut segments = Vec::new();
+
+    for idx in polyline.indices() {
+        let [a, b] = idx.map(|i| vertices[i as usize]);
+        let dir = b - a;
+
+        // Skip degenerate segments, which have no normal.
+        let Some(normal) = Vector::new(dir.y, -dir.x).try_normalize(Real::default_epsilon()) else {
+            continue;
+        };
+
+        vertex_normals[idx[0] as usize].add(&normal);
+        vertex_normals[idx[1] as usize].add(&normal);
+        segments.push((a, b, normal));
+    }
+
+    let mut points = Vec::new();
+    let mut normals = Vec::new();
+
+    for (vertex, normal) in vertices.iter().zip(vertex_normals.iter()) {
+        if let Some(normal) = normal.average() {
+            points.push(*vertex);
+            normals.push(normal);
+        }
+    }
+
+    for (a, b, normal) in segments {
+        sample_segment_interior(&a, &b, &normal, spacing, &mut points, &mut normals);
+    }
+
+    (points, normals)
+}
+
+// The sum of the normals of the faces sharing a sample point.
+#[derive(Copy, Clone, Default)]
+struct NormalSum {
+    sum: Vector<Real>,
+    first: Option<Vector<Real>>,
+}
+
+impl NormalSum {
+    fn add(&mut self, normal: &Vector<Real>) {
+        self.sum += normal;
+        let _ = self.first.get_or_insert(*normal);
+    }
+
+    // The normalized sum, or the first normal if the normals cancel out, e.g., for the two sides
+    // of a thin wall. Returns `None` if no normal was added.
+    fn average(&self) -> Option<Vector<Real>> {
+        let first = self.first?;
+        Some(
+            self.sum
+                .try_normalize(Real::default_epsilon())
+                .unwrap_or(first),
+        )
+    }
+}
+
+// The number of intervals of length at most `spacing` needed to cover `length`.
+fn num_intervals(length: Real, spacing: Real) -> usize {
+    ((length / spacing).ceil() as usize).max(1)
+}
+
+// Samples the segment `[a, b]`, excluding its endpoints.
+fn sample_segment_interior(
+    a: &Point<Real>,
+    b: &Point<Real>,
+    normal: &Vector<Real>,
+    spacing: Real,
+    points: &mut Vec<Point<Real>>,
+    normals: &mut Vec<Vector<Real>>,
+) {
+    let n = num_intervals(na::distance(a, b), spacing);
+
+    for k in 1..n {
+        points.push(a + (b - a) * ((k as Real) / (n as Real)));
+        normals.push(*normal);
+    }
+}
+
+#[cfg(feature = "dim3")]
+#[test]
+fn trimesh_sample_normals_follow_the_faces() {
+    use parry::shape::Cuboid;
+
+    let (vertices, indices) = Cuboid::new(Vector::repeat(1.0)).to_trimesh();
+    let mesh = TriMesh::new(vertices, indices);
+    let (points, normals) = trimesh_surface_sample(&mesh, 0.1);
+    assert_eq!(points.len(), normals.len());
+
+    // The points shared by several triangles are generated only once.
+    for (i, p1) in points.iter().enumerate() {
+        assert!(points[i + 1..]
+            .iter()
+            .all(|p2| na::distance(p1, p2) > 1.0e-3));
+    }
+
+    for (pt, normal) in points.iter().zip(normals.iter()) {
+        assert!((normal.norm() - 1.0).abs() < 1.0e-5);
+        let on_faces = pt.coords.iter().filter(|e| e.abs() > 1.0 - 1.0e-5).count();
+
+        if on_faces == 1 {
+            // Points inside of a face, including its diagonal, have the face normal.
+            let axis = pt.coords.iamax();
+            let mut expected = Vector::zeros();
+            expected[axis] = pt[axis].signum();
+            assert!((normal - expected).norm() < 1.0e-5);
+        } else {
+            // Points on shared edges and corners point outward, between the normals of their faces.
+            for axis in 0..3 {
+                if pt[axis].abs() > 1.0 - 1.0e-5 {
+                    assert!(normal[axis] * pt[axis] > 0.1);
+                } else {
+                    assert!(normal[axis].abs() < 1.0e-5);
+                }
+            }
+        }
+    }
+}
@@ -1,7 +1,18 @@
 //! Methods for converting shapes from ncollide to sets of points.
 
+#[cfg(feature = "dim2")]
+pub use self::mesh_sampling::polyline_surface_sample;
+#[cfg(feature = "dim3")]
+pub use self::mesh_sampling::trimesh_surface_sample;
+pub use self::particle_count::{
+    particle_count_for_volume, particle_radius_for_count, volume_for_particle_count,
+};
 pub use self::ray_sampling::{
     shape_surface_ray_sample, shape_volume_ray_sample, surface_ray_sample, volume_ray_sample,
 };
+pub use self::voxel_downsample::voxel_downsample;
 
+mod mesh_sampling;
+mod particle_count;
 mod ray_sampling;
+mod voxel_downsample;
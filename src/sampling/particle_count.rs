@@ -0,0 +1,79 @@
+use crate::math::{Real, DIM};
+
+use parry::bounding_volume::Aabb;
+
+/// The number of particles of radius `particle_radius` filling the box `aabb`.
+///
+/// The particles are assumed to be packed on a regular grid with a spacing of `2 * particle_radius`,
+/// as done by the volume samplers of this module. Along each axis, only the cells fully inside of
+/// `aabb` are counted.
+pub fn particle_count_for_volume(aabb: &Aabb, particle_radius: Real) -> usize {
+    let spacing = particle_radius * na::convert::<_, Real>(2.0);
+    let extents = aabb.extents();
+
+    (0..DIM)
+        .map(|i| {
+            // Absorb the rounding errors of extents that are exact multiples of the spacing.
+            let cells = extents[i] / spacing + na::convert::<_, Real>(1.0e-4);
+            cells.floor().max(na::zero::<Real>()) as usize
+        })
+        .product()
+}
+
+/// The volume filled by `count` particles of radius `particle_radius`.
+///
+/// This is the inverse of `particle_count_for_volume`: each particle occupies a grid cell of
+/// side `2 * particle_radius`. Note that `Fluid::default_particle_volume`, used by the solvers to
+/// compute the particle masses, is 80% of this cell volume.
+pub fn volume_for_particle_count(count: usize, particle_radius: Real) -> Real {
+    let spacing = particle_radius * na::convert::<_, Real>(2.0);
+    na::convert::<_, Real>(count as f64) * spacing.powi(DIM as i32)
+}
+
+/// The radius of the particles such that `count` particles fill the given `volume`, e.g., to size
+/// a simulation for a particle budget.
+///
+/// This is the inverse of `volume_for_particle_count`: each particle occupies a grid cell of side
+/// `2 * particle_radius`, as done by the volume samplers of this module, so the volume is an area
+/// in 2D. The samplers only keep the cells fully inside of the sampled shape, so they generate
+/// slightly fewer particles than `count`, especially for thin shapes.
+///
+/// Panics if `count` is zero.
+pub fn particle_radius_for_count(volume: Real, count: usize) -> Real {
+    assert!(count > 0, "The particle count must be positive.");
+    let cell_volume = volume / na::convert::<_, Real>(count as f64);
+    cell_volume.powf(na::one::<Real>() / DIM as Real) * na::convert::<_, Real>(0.5)
+}
+
+#[test]
+fn particle_count_matches_the_fluid_volumes() {
+    use crate::math::{Point, Vector};
+    use crate::object::Fluid;
+
+    let particle_radius = 0.05;
+    let aabb = Aabb::new(Point::origin(), Point::from(Vector::repeat(1.0)));
+    let count = particle_count_for_volume(&aabb, particle_radius);
+    assert_eq!(count, 10usize.pow(DIM as u32));
+    assert!((volume_for_particle_count(count, particle_radius) - 1.0).abs() < 1.0e-5);
+
+    // Partial cells are not counted.
+    let larger = Aabb::new(Point::origin(), Point::from(Vector::repeat(1.09)));
+    assert_eq!(particle_count_for_volume(&larger, particle_radius), count);
+
+    let fluid = Fluid::new(vec![Point::origin()], particle_radius, 1000.0);
+    let fluid_volume = fluid.default_particle_volume() * count as Real;
+    assert!((fluid_volume - 0.8).abs() < 1.0e-5);
+}
+
+#[test]
+fn particle_radius_fills_the_volume_with_the_target_count() {
+    use crate::math::{Point, Vector};
+
+    let aabb = Aabb::new(Point::origin(), Point::from(Vector::repeat(2.0)));
+    let volume = 2.0f32.powi(DIM as i32);
+    let count = 20usize.pow(DIM as u32);
+    let particle_radius = particle_radius_for_count(volume, count);
+    assert!((particle_radius - 0.05).abs() < 1.0e-6);
+    assert_eq!(particle_count_for_volume(&aabb, particle_radius), count);
+    assert!((volume_for_particle_count(count, particle_radius) - volume).abs() < 1.0e-4);
+}
@@ -0,0 +1,66 @@
+use crate::math::{Point, Real, Vector};
+
+use std::collections::HashMap;
+
+/// Downsamples a point cloud to a single point per voxel of side `particle_radius`.
+///
+/// The space is divided into a regular grid of cubic (or square in 2D) voxels of side
+/// `particle_radius`, aligned with the origin. Each voxel containing at least one of the `points`
+/// yields exactly one point: the centroid of the points it contains. The resulting points are
+/// returned in the order in which their voxels are first encountered in `points`.
+pub fn voxel_downsample(points: &[Point<Real>], particle_radius: Real) -> Vec<Point<Real>> {
+    assert!(
+        particle_radius > na::zero::<Real>(),
+        "The voxel size must be positive."
+    );
+
+    let mut voxel_ids = HashMap::new();
+    let mut voxels: Vec<(Vector<Real>, usize)> = Vec::new();
+
+    for point in points {
+        let key = (point.coords / particle_radius).map(|e| e.floor() as i64);
+        let id = *voxel_ids.entry(key).or_insert_with(|| {
+            voxels.push((Vector::zeros(), 0));
+            voxels.len() - 1
+        });
+        voxels[id].0 += point.coords;
+        voxels[id].1 += 1;
+    }
+
+    voxels
+        .into_iter()
+        .map(|(sum, count)| Point::from(sum / count as Real))
+        .collect()
+}
+
+#[test]
+fn voxel_downsample_keeps_one_centroid_per_voxel() {
+    use crate::math::DIM;
+
+    let particle_radius = 0.1;
+    // Points on both sides of the center of each of three voxels.
+    let centers = [
+        Vector::repeat(0.05),
+        Vector::repeat(0.25),
+        Vector::repeat(-0.35),
+    ];
+    let points: Vec<_> = centers
+        .iter()
+        .flat_map(|center| {
+            (0..DIM * 2).map(move |k| {
+                let mut offset = Vector::zeros();
+                offset[k / 2] = if k % 2 == 0 { 0.02 } else { -0.02 };
+                Point::from(center + offset)
+            })
+        })
+        .collect();
+
+    let samples = voxel_downsample(&points, particle_radius);
+    assert_eq!(samples.len(), centers.len());
+
+    for (sample, center) in samples.iter().zip(centers.iter()) {
+        assert!((sample.coords - center).norm() < 1.0e-5);
+    }
+
+    assert!(voxel_downsample(&[], particle_radius).is_empty());
+}
@@ -0,0 +1,219 @@
+use crate::math::Real;
+use crate::object::FluidListener;
+use std::sync::{Arc, Mutex};
+
+/// Parameters of the sleeping of the fluid particles at rest.
+///
+/// A fluid particle falls asleep once its speed stayed below `linear_threshold` during
+/// `time_until_sleep` seconds. Sleeping particles are neither affected by gravity nor integrated,
+/// so they stay perfectly still instead of slowly drifting, but they still exert pressure on their
+/// neighbors. A sleeping particle wakes up, and is affected by gravity again, as soon as:
+/// - one of its fluid neighbors moves faster than `linear_threshold`,
+/// - it lost more than 10% of its neighbors, e.g., because the particles or boundaries supporting it
+///   were removed,
+/// - or its velocity was modified, e.g., with `Fluid::set_velocity_field`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SleepParameters {
+    /// The speed below which a fluid particle is considered at rest.
+    pub linear_threshold: Real,
+    /// The time a fluid particle has to stay at rest before falling asleep.
+    pub time_until_sleep: Real,
+}
+
+impl SleepParameters {
+    /// Initializes new sleep parameters.
+    pub fn new(linear_threshold: Real, time_until_sleep: Real) -> Self {
+        Self {
+            linear_threshold,
+            time_until_sleep,
+        }
+    }
+}
+
+// The sleeping state of a fluid particle.
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct ParticleSleepState {
+    // The time during which the particle stayed at rest.
+    pub calm_time: Real,
+    // The number of neighbors of the particle when it fell asleep, if it is sleeping.
+    pub num_neighbors: Option<usize>,
+}
+
+impl ParticleSleepState {
+    pub fn is_sleeping(&self) -> bool {
+        self.num_neighbors.is_some()
+    }
+
+    // Checks if a sleeping particle lost enough neighbors to be woken up.
+    pub fn lost_support(&self, num_neighbors: usize) -> bool {
+        self.num_neighbors
+            .is_some_and(|n| num_neighbors * 10 < n * 9)
+    }
+}
+
+// The sleeping state of each particle of a fluid, shared with the `SleepStatesTracker` registered
+// on this fluid.
+pub(crate) type SharedSleepStates = Arc<Mutex<Vec<ParticleSleepState>>>;
+
+// Keeps the sleeping states of a fluid aligned with its particles when they are removed or
+// reordered. The particles added since the last step are awake.
+pub(crate) struct SleepStatesTracker {
+    pub states: SharedSleepStates,
+}
+
+impl FluidListener for SleepStatesTracker {
+    fn on_particles_removed(&mut self, indices: &[usize]) {
+        let mut states = self.states.lock().unwrap();
+        let mut i = 0;
+        states.retain(|_| {
+            i += 1;
+            indices.binary_search(&(i - 1)).is_err()
+        });
+    }
+
+    fn on_particles_permuted(&mut self, permutation: &[usize]) {
+        let mut states = self.states.lock().unwrap();
+        let permuted = permutation
+            .iter()
+            .map(|old| states.get(*old).copied().unwrap_or_default())
+            .collect();
+        *states = permuted;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SleepParameters;
+    use crate::kernel::CubicSplineKernel;
+    use crate::math::{Point, Real, Vector};
+    use crate::object::{Boundary, Fluid};
+    use crate::LiquidWorld;
+
+    #[test]
+    fn sleeping_particles_stay_still_until_disturbed() {
+        use crate::math::DIM;
+        use crate::solver::DFSPHSolver;
+
+        // A block of fluid filling a tank, so that it is at rest.
+        let particle_radius = 0.05;
+        let spacing = particle_radius * 2.0;
+        let size: usize = 4;
+        let positions: Vec<_> = (0..size.pow(DIM as u32))
+            .map(|i| {
+                let coords = Vector::from_fn(|k, _| (i / size.pow(k as u32) % size) as Real + 1.0);
+                Point::from(coords * spacing)
+            })
+            .collect();
+        let half_extents = Vector::repeat(spacing * (size + 1) as Real / 2.0);
+        let tank = Boundary::cuboid(
+            &Point::from(half_extents),
+            &half_extents,
+            particle_radius,
+            2,
+        );
+
+        let gravity = Vector::y() * -9.81;
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        let handle = world.add_fluid(Fluid::new(positions, particle_radius, 1000.0));
+        let tank = world.add_boundary(tank);
+        world.set_sleep_parameters(Some(SleepParameters::new(0.5, 0.1)));
+
+        // The fluid is not perfectly still, but slow enough to fall asleep.
+        for _ in 0..15 {
+            world.step(0.01, &gravity);
+        }
+
+        let num_particles = world.fluids().as_slice()[0].num_particles();
+        assert!((0..num_particles).all(|i| world.is_particle_sleeping(handle, i)));
+        let positions = world.fluids().as_slice()[0].positions.clone();
+
+        for _ in 0..10 {
+            world.step(0.01, &gravity);
+        }
+
+        assert_eq!(world.fluids().as_slice()[0].positions, positions);
+
+        // Removing the tank wakes up the particles it supported, and then the rest of the fluid.
+        let _ = world.remove_boundary(tank);
+
+        for _ in 0..30 {
+            world.step(0.01, &gravity);
+        }
+
+        let fluid = &world.fluids().as_slice()[0];
+        assert!((0..num_particles).all(|i| !world.is_particle_sleeping(handle, i)));
+        assert!(fluid
+            .positions
+            .iter()
+            .zip(positions.iter())
+            .all(|(p, p0)| p.y < p0.y - 0.1));
+    }
+
+    #[test]
+    fn sleeping_states_follow_the_reordered_particles() {
+        use crate::solver::DFSPHSolver;
+        use crate::testbed::particle_block;
+
+        // A block of fluid at rest in a tank, and a particle falling far above it.
+        let particle_radius = 0.05;
+        let spacing = particle_radius * 2.0;
+        let size = 4;
+        let falling = Point::from(Vector::y() * 10.0);
+        let mut positions = vec![falling];
+        positions.extend(particle_block(size, size, spacing, Vector::repeat(spacing)));
+        let half_extents = Vector::repeat(spacing * (size + 1) as Real / 2.0);
+        let tank = Boundary::cuboid(
+            &Point::from(half_extents),
+            &half_extents,
+            particle_radius,
+            2,
+        );
+
+        let gravity = Vector::y() * -9.81;
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        let handle = world.add_fluid(Fluid::new(positions, particle_radius, 1000.0));
+        let _ = world.add_boundary(tank);
+        world.set_sleep_parameters(Some(SleepParameters::new(0.5, 0.1)));
+
+        for _ in 0..15 {
+            world.step(0.01, &gravity);
+        }
+
+        let is_falling =
+            |world: &LiquidWorld, i: usize| world.fluids()[handle].positions[i].y > 5.0;
+        let num_particles = world.fluids()[handle].num_particles();
+        assert!((0..num_particles)
+            .all(|i| world.is_particle_sleeping(handle, i) != is_falling(&world, i)));
+
+        let unsorted = world.fluids()[handle].positions.clone();
+        world.fluids_mut()[handle].z_sort();
+        assert_ne!(world.fluids()[handle].positions, unsorted);
+        assert!((0..num_particles)
+            .all(|i| world.is_particle_sleeping(handle, i) != is_falling(&world, i)));
+
+        // The sleeping particles stay still while the falling one keeps falling.
+        let positions = world.fluids()[handle].positions.clone();
+        world.step(0.01, &gravity);
+
+        for (i, (p, p0)) in world.fluids()[handle]
+            .positions
+            .iter()
+            .zip(&positions)
+            .enumerate()
+        {
+            if is_falling(&world, i) {
+                assert!(p.y < p0.y);
+            } else {
+                assert_eq!(p, p0);
+            }
+        }
+    }
+}
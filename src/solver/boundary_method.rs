@@ -0,0 +1,363 @@
+use crate::geometry::ParticlesContacts;
+use crate::math::Real;
+use crate::object::{Boundary, Fluid};
+
+#[cfg(all(feature = "rapier", feature = "dim2"))]
+type MlsMatrix = na::Matrix3<Real>;
+#[cfg(all(feature = "rapier", feature = "dim3"))]
+type MlsMatrix = na::Matrix4<Real>;
+#[cfg(all(feature = "rapier", feature = "dim2"))]
+type MlsVector = na::Vector3<Real>;
+#[cfg(all(feature = "rapier", feature = "dim3"))]
+type MlsVector = na::Vector4<Real>;
+
+/// The method used by the pressure solvers to account for the boundary particles in the densities
+/// and the pressure forces of the fluid particles.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum BoundaryMethod {
+    /// The volume of each boundary particle is computed from its boundary neighbors, and its
+    /// pressure is mirrored from the fluid particle it interacts with.
+    ///
+    /// This is the method of Akinci et al. 2012 "Versatile Rigid-Fluid Coupling for Incompressible SPH",
+    /// which is robust to irregularly sampled boundaries.
+    #[default]
+    Akinci,
+    /// Both the volume and the pressure of each boundary particle are mirrored from the fluid
+    /// particle it interacts with.
+    ///
+    /// The boundary particles then behave as copies of the fluid particles, which is only accurate
+    /// if the boundaries are sampled with the same spacing as the fluids: denser boundaries push
+    /// harder, and sparser boundaries let the fluids stick to them.
+    PressureMirroring,
+    /// Each boundary particle behaves as a fluid particle frozen in place: it has the volume of the
+    /// fluid particle it interacts with, and a pressure extrapolated from the pressures of all its
+    /// fluid neighbors, weighted by the kernel, as in Adami et al. 2012 "A generalized wall boundary
+    /// condition for smoothed particle hydrodynamics".
+    ///
+    /// The pressure is then continuous along the walls, instead of being mirrored from each fluid
+    /// particle independently, which reduces the artifacts where the fluids leave the walls, and
+    /// keeps the fluid particles from clumping against the walls.
+    FrozenFluid,
+    /// Each boundary particle has its own pressure, computed from the pressures of its fluid
+    /// neighbors by a weighted linear least-squares fit, as in Band et al. 2018 "Pressure
+    /// Boundaries for Implicit Incompressible SPH", and its volume is computed as with `Akinci`.
+    ///
+    /// Unlike `FrozenFluid`, the fit captures the pressure gradient, e.g., the hydrostatic
+    /// pressure, so the pressure at the boundary particles is extrapolated beyond their fluid
+    /// neighbors instead of averaged. This gives more accurate forces on the coupled bodies,
+    /// e.g., for the buoyancy of floating bodies, at the cost of a small linear system per
+    /// boundary particle touched by the fluids.
+    #[cfg(feature = "rapier")]
+    PressureBoundaries,
+}
+
+impl BoundaryMethod {
+    /// The volume of a boundary particle with the volume `boundary_volume`, when it interacts with a
+    /// fluid particle with the volume `fluid_volume`.
+    #[inline]
+    pub fn boundary_volume(self, boundary_volume: Real, fluid_volume: Real) -> Real {
+        match self {
+            BoundaryMethod::Akinci => boundary_volume,
+            #[cfg(feature = "rapier")]
+            BoundaryMethod::PressureBoundaries => boundary_volume,
+            BoundaryMethod::PressureMirroring | BoundaryMethod::FrozenFluid => fluid_volume,
+        }
+    }
+
+    /// Whether the volumes of the boundary particles are computed from their boundary neighbors,
+    /// as in Akinci et al. 2012, instead of being mirrored from the fluid particles.
+    #[inline]
+    pub fn uses_boundary_volumes(self) -> bool {
+        match self {
+            BoundaryMethod::Akinci => true,
+            #[cfg(feature = "rapier")]
+            BoundaryMethod::PressureBoundaries => true,
+            BoundaryMethod::PressureMirroring | BoundaryMethod::FrozenFluid => false,
+        }
+    }
+
+    /// Whether the pressure of the boundary particles is extrapolated from their fluid neighbors
+    /// instead of being mirrored from the fluid particle they interact with.
+    #[inline]
+    pub fn extrapolates_pressure(self) -> bool {
+        match self {
+            BoundaryMethod::Akinci | BoundaryMethod::PressureMirroring => false,
+            BoundaryMethod::FrozenFluid => true,
+            #[cfg(feature = "rapier")]
+            BoundaryMethod::PressureBoundaries => true,
+        }
+    }
+}
+
+// Sets `result[b][j]` to the value of `fluid_values(fluid_id, i)` extrapolated to the `j`-th
+// particle of the boundary `b` from its fluid neighbors, as required by `method`.
+#[cfg_attr(not(feature = "rapier"), allow(unused_variables))]
+pub(crate) fn extrapolate_to_boundaries(
+    method: BoundaryMethod,
+    fluid_boundary_contacts: &[ParticlesContacts],
+    boundaries: &[Boundary],
+    fluids: &[Fluid],
+    fluid_values: impl Fn(usize, usize) -> Real,
+    result: &mut Vec<Vec<Real>>,
+) {
+    #[cfg(feature = "rapier")]
+    if method == BoundaryMethod::PressureBoundaries {
+        fit_to_boundaries(
+            fluid_boundary_contacts,
+            boundaries,
+            fluids,
+            fluid_values,
+            result,
+        );
+        return;
+    }
+
+    average_to_boundaries(fluid_boundary_contacts, boundaries, fluid_values, result);
+}
+
+// Sets `result[b][j]` to the average of `fluid_values(fluid_id, i)` over the fluid neighbors of
+// the `j`-th particle of the boundary `b`, weighted by the kernel.
+fn average_to_boundaries(
+    fluid_boundary_contacts: &[ParticlesContacts],
+    boundaries: &[Boundary],
+    fluid_values: impl Fn(usize, usize) -> Real,
+    result: &mut Vec<Vec<Real>>,
+) {
+    let mut weights: Vec<Vec<Real>> = boundaries
+        .iter()
+        .map(|b| vec![na::zero::<Real>(); b.num_particles()])
+        .collect();
+    result.resize(boundaries.len(), Vec::new());
+
+    for (values, boundary) in result.iter_mut().zip(boundaries.iter()) {
+        values.clear();
+        values.resize(boundary.num_particles(), na::zero::<Real>());
+    }
+
+    for (fluid_id, contacts) in fluid_boundary_contacts.iter().enumerate() {
+        for (i, particle_contacts) in contacts.contacts().iter().enumerate() {
+            let value = fluid_values(fluid_id, i);
+
+            for c in particle_contacts.read().unwrap().iter() {
+                result[c.j_model][c.j] += value * c.weight;
+                weights[c.j_model][c.j] += c.weight;
+            }
+        }
+    }
+
+    for (values, weights) in result.iter_mut().zip(weights.iter()) {
+        for (value, weight) in values.iter_mut().zip(weights.iter()) {
+            if *weight > na::zero::<Real>() {
+                *value /= *weight;
+            }
+        }
+    }
+}
+
+// Sets `result[b][j]` to the value at the `j`-th particle of the boundary `b` of the linear function
+// fitting `fluid_values(fluid_id, i)` at the positions of its fluid neighbors, by least squares
+// weighted by the kernel. The values are pressures, so the negative results are clamped to zero.
+#[cfg(feature = "rapier")]
+fn fit_to_boundaries(
+    fluid_boundary_contacts: &[ParticlesContacts],
+    boundaries: &[Boundary],
+    fluids: &[Fluid],
+    fluid_values: impl Fn(usize, usize) -> Real,
+    result: &mut Vec<Vec<Real>>,
+) {
+    let mut systems: Vec<Vec<(MlsMatrix, MlsVector)>> = boundaries
+        .iter()
+        .map(|b| vec![(MlsMatrix::zeros(), MlsVector::zeros()); b.num_particles()])
+        .collect();
+    result.resize(boundaries.len(), Vec::new());
+
+    for (fluid_id, contacts) in fluid_boundary_contacts.iter().enumerate() {
+        for (i, particle_contacts) in contacts.contacts().iter().enumerate() {
+            let value = fluid_values(fluid_id, i);
+            let pi = fluids[fluid_id].positions[i];
+
+            for c in particle_contacts.read().unwrap().iter() {
+                let offset = pi - boundaries[c.j_model].positions[c.j];
+                let basis = MlsVector::from_fn(|k, _| {
+                    if k == 0 {
+                        na::one::<Real>()
+                    } else {
+                        offset[k - 1]
+                    }
+                });
+                let system = &mut systems[c.j_model][c.j];
+                system.0 += basis * basis.transpose() * c.weight;
+                system.1 += basis * (value * c.weight);
+            }
+        }
+    }
+
+    for (values, systems) in result.iter_mut().zip(systems.iter()) {
+        values.clear();
+        values.extend(systems.iter().map(|(matrix, rhs)| {
+            if matrix[(0, 0)] <= na::zero::<Real>() {
+                return na::zero::<Real>();
+            }
+
+            // Regularize the gradient so that the fit falls back to the weighted average of the
+            // values when the neighbors do not span all the dimensions.
+            let mut matrix = *matrix;
+            let spread = (1..matrix.nrows()).map(|k| matrix[(k, k)]).sum::<Real>();
+            let regularization = spread * na::convert::<_, Real>(1.0e-3);
+
+            for k in 1..matrix.nrows() {
+                matrix[(k, k)] += regularization;
+            }
+
+            let value = match matrix.cholesky() {
+                Some(cholesky) => cholesky.solve(rhs)[0],
+                None => rhs[0] / matrix[(0, 0)],
+            };
+            value.max(na::zero::<Real>())
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kernel::CubicSplineKernel;
+    use crate::math::{Point, Real, Vector};
+    use crate::object::{Boundary, Fluid};
+    use crate::testbed::particle_block;
+    use crate::LiquidWorld;
+
+    #[test]
+    fn boundary_methods_hold_a_fluid_column_at_rest() {
+        use crate::math::DIM;
+        use crate::solver::{BoundaryMethod, DFSPHSolver, IISPHSolver};
+
+        // A column of fluid filling a tank.
+        let particle_radius = 0.05;
+        let spacing = particle_radius * 2.0;
+        let (width, height): (usize, usize) = (4, 6);
+        let positions = particle_block(width, height, spacing, Vector::repeat(spacing));
+        let half_extents = Vector::from_fn(|k, _| if k == 1 { height } else { width })
+            .map(|n| spacing * (n + 1) as Real / 2.0);
+        let mean_height = |fluid: &Fluid| {
+            fluid.positions.iter().map(|p| p.y).sum::<Real>() / fluid.num_particles() as Real
+        };
+
+        let methods = [
+            BoundaryMethod::Akinci,
+            BoundaryMethod::PressureMirroring,
+            BoundaryMethod::FrozenFluid,
+            #[cfg(feature = "rapier")]
+            BoundaryMethod::PressureBoundaries,
+        ];
+
+        for iisph in [false, true] {
+            for method in methods {
+                let mut world = if iisph {
+                    LiquidWorld::new(
+                        IISPHSolver::<CubicSplineKernel>::new(),
+                        particle_radius,
+                        2.0,
+                    )
+                } else {
+                    LiquidWorld::new(
+                        DFSPHSolver::<CubicSplineKernel>::new(),
+                        particle_radius,
+                        2.0,
+                    )
+                };
+                world.set_boundary_method(method);
+                assert_eq!(world.boundary_method(), method);
+
+                let fluid = Fluid::new(positions.clone(), particle_radius, 1000.0);
+                let initial_height = mean_height(&fluid);
+                let _ = world.add_fluid(fluid);
+                let _ = world.add_boundary(Boundary::cuboid(
+                    &Point::from(half_extents),
+                    &half_extents,
+                    particle_radius,
+                    2,
+                ));
+
+                for _ in 0..50 {
+                    world.step(0.01, &(Vector::y() * -9.81));
+                }
+
+                let fluid = &world.fluids().as_slice()[0];
+                // The column is compressed a bit, but neither collapses nor explodes.
+                let height = mean_height(fluid);
+                assert!(height > initial_height * 0.7 && height < initial_height);
+
+                for p in &fluid.positions {
+                    for k in 0..DIM {
+                        assert!(p[k] > 0.0 && p[k] < half_extents[k] * 2.0);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn extrapolated_boundary_pressures_keep_the_fluid_off_the_floor() {
+        use crate::solver::{BoundaryMethod, ConfigurablePressureSolver, DFSPHSolver, IISPHSolver};
+
+        let particle_radius = 0.05;
+        let spacing = particle_radius * 2.0;
+        let size = 5;
+        let positions = particle_block(size, size, spacing, Vector::repeat(spacing));
+        let half_extents = Vector::repeat(spacing * (size + 1) as Real / 2.0);
+        // The mean height of the lowest layer of a block of fluid settled in a tank.
+        let bottom_layer_height = |iisph: bool, method: BoundaryMethod| {
+            let mut world = if iisph {
+                LiquidWorld::new(
+                    IISPHSolver::<CubicSplineKernel>::new(),
+                    particle_radius,
+                    2.0,
+                )
+            } else {
+                LiquidWorld::new(
+                    DFSPHSolver::<CubicSplineKernel>::new(),
+                    particle_radius,
+                    2.0,
+                )
+            };
+            world.set_boundary_method(method);
+            let fluid = world.add_fluid(Fluid::new(positions.clone(), particle_radius, 1000.0));
+            let _ = world.add_boundary(Boundary::cuboid(
+                &Point::from(half_extents),
+                &half_extents,
+                particle_radius,
+                2,
+            ));
+
+            for _ in 0..50 {
+                world.step(0.01, &(Vector::y() * -9.81));
+            }
+
+            let mut heights: Vec<_> = world.fluids()[fluid]
+                .positions
+                .iter()
+                .map(|p| p.y)
+                .collect();
+            heights.sort_by(|a, b| a.total_cmp(b));
+            let layer_size = heights.len() / size;
+            heights[..layer_size].iter().sum::<Real>() / layer_size as Real
+        };
+
+        assert_eq!(
+            DFSPHSolver::<CubicSplineKernel>::new().boundary_method(),
+            BoundaryMethod::Akinci
+        );
+        assert_eq!(
+            IISPHSolver::<CubicSplineKernel>::new().boundary_method(),
+            BoundaryMethod::Akinci
+        );
+
+        // The pressures mirrored from each fluid particle let the lowest layer sink toward the
+        // floor, while the pressures extrapolated along the floor hold it further up.
+        for iisph in [false, true] {
+            let mirrored = bottom_layer_height(iisph, BoundaryMethod::Akinci);
+            let extrapolated = bottom_layer_height(iisph, BoundaryMethod::FrozenFluid);
+            assert!(extrapolated > mirrored + spacing * 0.05);
+        }
+    }
+}
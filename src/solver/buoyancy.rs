@@ -0,0 +1,138 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::geometry::ParticlesContacts;
+
+use crate::math::{Real, Vector};
+use crate::object::{Boundary, Fluid};
+use crate::solver::{IndependentForce, IndependentForceInputs, NonPressureForce};
+use crate::TimestepManager;
+
+/// The buoyancy due to the deviation of a per-particle scalar, e.g., a temperature, from a reference value.
+///
+/// Following the Boussinesq approximation, each particle with the scalar value `φ` receives the
+/// acceleration `-gravity * expansion_coefficient * (φ - reference)`, which accounts for the
+/// density change due to `φ` without changing the particle masses, e.g., to make hot smoke rise.
+/// The particles with the reference value, and all the particles of a fluid with a uniform field
+/// equal to the reference, are not affected.
+///
+/// The scalar value of each particle is given by a function of the fluid and the particle index,
+/// which can derive it from the state of the fluid, e.g., `Fluid::ages` for a plume cooling down
+/// over time, or read it from a buffer maintained by the user.
+pub struct Buoyancy {
+    /// The value of the scalar field for which the particles are not subject to buoyancy.
+    pub reference: Real,
+    /// The relative density decrease per unit of deviation of the scalar from its reference.
+    pub expansion_coefficient: Real,
+    /// The gravity opposed by the buoyancy, usually the same as the one given to `LiquidWorld::step`.
+    pub gravity: Vector<Real>,
+    field: Box<ScalarField>,
+}
+
+// The value of the scalar field at a particle of a fluid.
+type ScalarField = dyn Fn(&Fluid, usize) -> Real + Send + Sync;
+
+impl Buoyancy {
+    /// Initializes the buoyancy due to the scalar `field(fluid, i)` of each particle `i`.
+    pub fn new(
+        reference: Real,
+        expansion_coefficient: Real,
+        gravity: Vector<Real>,
+        field: impl Fn(&Fluid, usize) -> Real + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            reference,
+            expansion_coefficient,
+            gravity,
+            field: Box::new(field),
+        }
+    }
+
+    /// The buoyancy acceleration of a particle with the scalar value `value`.
+    pub fn acceleration(&self, value: Real) -> Vector<Real> {
+        self.gravity * (-self.expansion_coefficient * (value - self.reference))
+    }
+}
+
+impl NonPressureForce for Buoyancy {
+    fn solve(
+        &mut self,
+        timestep: &TimestepManager,
+        kernel_radius: Real,
+        fluid_fluid_contacts: &ParticlesContacts,
+        fluid_boundaries_contacts: &ParticlesContacts,
+        fluid: &mut Fluid,
+        boundaries: &[Boundary],
+        densities: &[Real],
+    ) {
+        let mut accelerations = std::mem::take(&mut fluid.accelerations);
+        let inputs = IndependentForceInputs {
+            timestep,
+            kernel_radius,
+            fluid_fluid_contacts,
+            fluid_boundaries_contacts,
+            fluid,
+            boundaries,
+            densities,
+        };
+        self.solve_independently(&inputs, &mut accelerations);
+        fluid.accelerations = accelerations;
+    }
+
+    fn as_independent_mut(&mut self) -> Option<&mut dyn IndependentForce> {
+        Some(self)
+    }
+}
+
+impl IndependentForce for Buoyancy {
+    fn solve_independently(
+        &mut self,
+        inputs: &IndependentForceInputs,
+        accelerations: &mut [Vector<Real>],
+    ) {
+        let IndependentForceInputs { fluid, .. } = *inputs;
+        let this = &*self;
+
+        par_iter_mut!(accelerations)
+            .enumerate()
+            .for_each(|(i, acceleration)| {
+                *acceleration += this.acceleration((this.field)(fluid, i));
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kernel::CubicSplineKernel;
+    use crate::math::{Point, Vector};
+    use crate::object::Fluid;
+    use crate::LiquidWorld;
+
+    #[test]
+    fn buoyancy_lifts_the_particles_above_the_reference() {
+        use crate::solver::{Buoyancy, DFSPHSolver};
+
+        let particle_radius = 0.05;
+        let gravity = Vector::y() * -9.81;
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        // Two isolated particles, the second one twice as far from the reference as needed to float.
+        let positions = vec![Point::origin(), Point::from(Vector::x())];
+        let mut fluid = Fluid::new(positions, particle_radius, 1000.0);
+        fluid.add_nonpressure_force(Box::new(Buoyancy::new(300.0, 0.02, gravity, |fluid, i| {
+            300.0 + 100.0 * fluid.positions[i].x
+        })));
+        let handle = world.add_fluid(fluid);
+
+        for _ in 0..5 {
+            world.step(0.01, &gravity);
+        }
+
+        let positions = &world.fluids()[handle].positions;
+        assert!(positions[0].y < 0.0);
+        assert!((positions[1].y + positions[0].y).abs() < 1.0e-5);
+    }
+}
@@ -7,9 +7,9 @@ use approx::AbsDiffEq;
 
 use crate::geometry::{self, ParticlesContacts};
 use crate::kernel::{CubicSplineKernel, Kernel};
-use crate::math::{Matrix, Point, Real, RotationMatrix, SpatialVector, Vector};
+use crate::math::{Matrix, Point, Real, RotationMatrix, SpatialVector, Vector, DIM};
 use crate::object::{Boundary, Fluid};
-use crate::solver::NonPressureForce;
+use crate::solver::{IndependentForce, IndependentForceInputs, NonPressureForce};
 use crate::TimestepManager;
 
 fn elasticity_coefficients(young_modulus: Real, poisson_ratio: Real) -> (Real, Real, Real) {
@@ -100,6 +100,7 @@ impl<KernelDensity: Kernel, KernelGradient: Kernel>
                     let p2 = &self.positions0[c.j];
                     c.weight = KernelDensity::points_apply(p1, p2, kernel_radius);
                     c.gradient = KernelGradient::points_apply_diff1(p1, p2, kernel_radius);
+                    c.distance = na::distance(p1, p2);
 
                     self.volumes0[c.i] += fluid.particle_mass(c.j) * c.weight;
                     self.volumes0[c.j] += fluid.particle_mass(c.i) * c.weight;
@@ -267,14 +268,67 @@ impl<KernelDensity: Kernel, KernelGradient: Kernel> NonPressureForce
 {
     fn solve(
         &mut self,
-        _timestep: &TimestepManager,
+        timestep: &TimestepManager,
         kernel_radius: Real,
-        _fluid_fluid_contacts: &ParticlesContacts,
-        _fluid_boundaries_contacts: &ParticlesContacts,
+        fluid_fluid_contacts: &ParticlesContacts,
+        fluid_boundaries_contacts: &ParticlesContacts,
         fluid: &mut Fluid,
-        _boundaries: &[Boundary],
-        _densities: &[Real],
+        boundaries: &[Boundary],
+        densities: &[Real],
     ) {
+        let mut accelerations = std::mem::take(&mut fluid.accelerations);
+        let inputs = IndependentForceInputs {
+            timestep,
+            kernel_radius,
+            fluid_fluid_contacts,
+            fluid_boundaries_contacts,
+            fluid,
+            boundaries,
+            densities,
+        };
+        self.solve_independently(&inputs, &mut accelerations);
+        fluid.accelerations = accelerations;
+    }
+
+    fn as_independent_mut(&mut self) -> Option<&mut dyn IndependentForce> {
+        Some(self)
+    }
+
+    fn apply_permutation(&mut self, permutation: &[usize]) {
+        self.volumes0 = crate::z_order::apply_permutation(permutation, &self.volumes0);
+        self.positions0 = crate::z_order::apply_permutation(permutation, &self.positions0);
+        self.rotations = crate::z_order::apply_permutation(permutation, &self.rotations);
+        self.contacts0.apply_permutation(permutation);
+    }
+
+    fn rescale(&mut self, factor: Real) {
+        let volume_factor = factor.powi(DIM as i32);
+        self.positions0.iter_mut().for_each(|p| p.coords *= factor);
+        self.volumes0.iter_mut().for_each(|v| *v *= volume_factor);
+
+        // The kernels are scaled by `1 / h^DIM` and their gradients by `1 / h^(DIM + 1)`.
+        for contacts in self.contacts0.contacts_mut() {
+            for c in contacts.get_mut().unwrap() {
+                c.weight /= volume_factor;
+                c.gradient /= volume_factor * factor;
+            }
+        }
+    }
+}
+
+impl<KernelDensity: Kernel, KernelGradient: Kernel> IndependentForce
+    for Becker2009Elasticity<KernelDensity, KernelGradient>
+{
+    fn solve_independently(
+        &mut self,
+        inputs: &IndependentForceInputs,
+        accelerations: &mut [Vector<Real>],
+    ) {
+        let IndependentForceInputs {
+            kernel_radius,
+            fluid,
+            ..
+        } = *inputs;
         self.init(kernel_radius, fluid);
 
         let _0_5: Real = na::convert::<_, Real>(0.5f64);
@@ -291,7 +345,7 @@ impl<KernelDensity: Kernel, KernelGradient: Kernel> NonPressureForce
         let density0 = fluid.density0;
 
         if self.nonlinear_strain {
-            par_iter_mut!(fluid.accelerations)
+            par_iter_mut!(accelerations)
                 .enumerate()
                 .for_each(|(i, acceleration)| {
                     for c in contacts0.particle_contacts(i).read().unwrap().iter() {
@@ -313,7 +367,7 @@ impl<KernelDensity: Kernel, KernelGradient: Kernel> NonPressureForce
                     }
                 })
         } else {
-            par_iter_mut!(fluid.accelerations)
+            par_iter_mut!(accelerations)
                 .enumerate()
                 .for_each(|(i, acceleration)| {
                     for c in contacts0.particle_contacts(i).read().unwrap().iter() {
@@ -332,11 +386,4 @@ impl<KernelDensity: Kernel, KernelGradient: Kernel> NonPressureForce
                 })
         }
     }
-
-    fn apply_permutation(&mut self, permutation: &[usize]) {
-        self.volumes0 = crate::z_order::apply_permutation(permutation, &self.volumes0);
-        self.positions0 = crate::z_order::apply_permutation(permutation, &self.positions0);
-        self.rotations = crate::z_order::apply_permutation(permutation, &self.rotations);
-        self.contacts0.apply_permutation(permutation);
-    }
 }
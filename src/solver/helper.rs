@@ -1,13 +1,17 @@
 use crate::geometry::ParticlesContacts;
 use crate::kernel::Kernel;
-use crate::math::Real;
+use crate::math::{Real, Vector};
 use crate::object::{Boundary, Fluid};
+#[cfg(feature = "parallel")]
+use crate::solver::IndependentForceInputs;
+use crate::TimestepManager;
 
 #[cfg(feature = "parallel")]
 use rayon::prelude::*;
 
 pub fn update_fluid_contacts<KernelDensity: Kernel, KernelGradient: Kernel>(
     kernel_radius: Real,
+    anisotropy: &Vector<Real>,
     fluid_fluid_contacts: &mut [ParticlesContacts],
     fluid_boundary_contacts: &mut [ParticlesContacts],
     fluids: &[Fluid],
@@ -21,8 +25,15 @@ pub fn update_fluid_contacts<KernelDensity: Kernel, KernelGradient: Kernel>(
                 let pi = fluid1.positions[c.i];
                 let pj = fluid2.positions[c.j];
 
-                c.weight = KernelDensity::points_apply(&pi, &pj, kernel_radius);
-                c.gradient = KernelGradient::points_apply_diff1(&pi, &pj, kernel_radius);
+                c.weight =
+                    KernelDensity::points_apply_anisotropic(&pi, &pj, kernel_radius, anisotropy);
+                c.gradient = KernelGradient::points_apply_anisotropic_diff1(
+                    &pi,
+                    &pj,
+                    kernel_radius,
+                    anisotropy,
+                );
+                c.distance = na::distance(&pi, &pj);
             }
         })
     }
@@ -36,8 +47,15 @@ pub fn update_fluid_contacts<KernelDensity: Kernel, KernelGradient: Kernel>(
                 let pi = fluid1.positions[c.i];
                 let pj = bound2.positions[c.j];
 
-                c.weight = KernelDensity::points_apply(&pi, &pj, kernel_radius);
-                c.gradient = KernelGradient::points_apply_diff1(&pi, &pj, kernel_radius);
+                c.weight =
+                    KernelDensity::points_apply_anisotropic(&pi, &pj, kernel_radius, anisotropy);
+                c.gradient = KernelGradient::points_apply_anisotropic_diff1(
+                    &pi,
+                    &pj,
+                    kernel_radius,
+                    anisotropy,
+                );
+                c.distance = na::distance(&pi, &pj);
             }
         })
     }
@@ -45,6 +63,7 @@ pub fn update_fluid_contacts<KernelDensity: Kernel, KernelGradient: Kernel>(
 
 pub fn update_boundary_contacts<KernelDensity: Kernel, KernelGradient: Kernel>(
     kernel_radius: Real,
+    anisotropy: &Vector<Real>,
     boundary_boundary_contacts: &mut [ParticlesContacts],
     boundaries: &[Boundary],
 ) {
@@ -57,9 +76,188 @@ pub fn update_boundary_contacts<KernelDensity: Kernel, KernelGradient: Kernel>(
                 let pi = bound1.positions[c.i];
                 let pj = bound2.positions[c.j];
 
-                c.weight = KernelDensity::points_apply(&pi, &pj, kernel_radius);
-                c.gradient = KernelGradient::points_apply_diff1(&pi, &pj, kernel_radius);
+                c.weight =
+                    KernelDensity::points_apply_anisotropic(&pi, &pj, kernel_radius, anisotropy);
+                c.gradient = KernelGradient::points_apply_anisotropic_diff1(
+                    &pi,
+                    &pj,
+                    kernel_radius,
+                    anisotropy,
+                );
+                c.distance = na::distance(&pi, &pj);
             }
         })
     }
 }
+
+/// Solves all the non-pressure forces of `fluid`, in order.
+///
+/// The forces are first initialized with `NonPressureForce::init` if forces were added or the
+/// number of particles changed since their last initialization.
+///
+/// With the `parallel` feature, consecutive independent forces are solved in parallel, each one
+/// adding its accelerations to one of the buffers of the fluid which are then summed into its
+/// accelerations. The buffers are kept from one call to the next to avoid reallocating them.
+///
+/// If the fluid has more than one non-pressure sub-substep, the forces are solved once per
+/// sub-substep, see `Fluid::set_nonpressure_substeps`.
+pub fn solve_nonpressure_forces(
+    timestep: &TimestepManager,
+    kernel_radius: Real,
+    fluid_fluid_contacts: &ParticlesContacts,
+    fluid_boundary_contacts: &ParticlesContacts,
+    fluid: &mut Fluid,
+    boundaries: &[Boundary],
+    densities: &[Real],
+) {
+    let num_substeps = fluid.nonpressure_substeps();
+
+    if num_substeps <= 1 {
+        solve_nonpressure_forces_once(
+            timestep,
+            kernel_radius,
+            fluid_fluid_contacts,
+            fluid_boundary_contacts,
+            fluid,
+            boundaries,
+            densities,
+        );
+        return;
+    }
+
+    // The accelerations already applied to the fluid, e.g., the gravity, are kept constant while the
+    // velocities are integrated over the sub-substeps. The fluid accelerations are then set to their
+    // mean, so integrating them over the whole substep gives the same velocities.
+    let sub_timestep = timestep.with_dt(timestep.dt() / num_substeps as Real);
+    let sub_dt = sub_timestep.dt();
+    let inv_num_substeps = na::one::<Real>() / num_substeps as Real;
+    let base_accelerations = fluid.accelerations.clone();
+    let velocities = fluid.velocities.clone();
+    let mut mean_accelerations = vec![Vector::zeros(); fluid.num_particles()];
+    let boundary_forces: Vec<_> = boundaries
+        .iter()
+        .map(|boundary| {
+            boundary
+                .forces
+                .as_ref()
+                .map(|forces| forces.read().unwrap().clone())
+        })
+        .collect();
+
+    for _ in 0..num_substeps {
+        fluid.accelerations.clone_from(&base_accelerations);
+        solve_nonpressure_forces_once(
+            &sub_timestep,
+            kernel_radius,
+            fluid_fluid_contacts,
+            fluid_boundary_contacts,
+            fluid,
+            boundaries,
+            densities,
+        );
+
+        par_iter_mut!(fluid.velocities)
+            .zip(par_iter_mut!(mean_accelerations))
+            .zip(par_iter!(fluid.accelerations))
+            .for_each(|((velocity, mean_acceleration), acceleration)| {
+                *velocity += acceleration * sub_dt;
+                *mean_acceleration += acceleration * inv_num_substeps;
+            });
+    }
+
+    fluid.velocities = velocities;
+    fluid.accelerations = mean_accelerations;
+
+    // The forces applied to the boundaries are averaged over the sub-substeps too.
+    for (boundary, forces0) in boundaries.iter().zip(boundary_forces.iter()) {
+        if let (Some(forces), Some(forces0)) = (&boundary.forces, forces0) {
+            let mut forces = forces.write().unwrap();
+
+            for (force, force0) in forces.iter_mut().zip(forces0.iter()) {
+                *force = force0 + (*force - force0) * inv_num_substeps;
+            }
+        }
+    }
+}
+
+fn solve_nonpressure_forces_once(
+    timestep: &TimestepManager,
+    kernel_radius: Real,
+    fluid_fluid_contacts: &ParticlesContacts,
+    fluid_boundary_contacts: &ParticlesContacts,
+    fluid: &mut Fluid,
+    boundaries: &[Boundary],
+    densities: &[Real],
+) {
+    fluid.init_nonpressure_forces();
+    let mut forces = std::mem::take(&mut fluid.nonpressure_forces);
+    let mut start = 0;
+
+    while start < forces.len() {
+        #[cfg(feature = "parallel")]
+        {
+            let num_independent = forces[start..]
+                .iter_mut()
+                .position(|force| force.as_independent_mut().is_none())
+                .unwrap_or(forces.len() - start);
+
+            if num_independent > 1 {
+                let mut buffers = std::mem::take(&mut fluid.independent_force_accelerations);
+
+                if buffers.len() < num_independent {
+                    buffers.resize(num_independent, Vec::new());
+                }
+
+                let inputs = IndependentForceInputs {
+                    timestep,
+                    kernel_radius,
+                    fluid_fluid_contacts,
+                    fluid_boundaries_contacts: fluid_boundary_contacts,
+                    fluid: &*fluid,
+                    boundaries,
+                    densities,
+                };
+
+                forces[start..start + num_independent]
+                    .par_iter_mut()
+                    .zip(buffers[..num_independent].par_iter_mut())
+                    .for_each(|(force, buffer)| {
+                        buffer.clear();
+                        buffer.resize(inputs.fluid.num_particles(), Vector::zeros());
+
+                        if let Some(force) = force.as_independent_mut() {
+                            force.solve_independently(&inputs, buffer);
+                        }
+                    });
+
+                let summed_buffers = &buffers[..num_independent];
+                fluid
+                    .accelerations
+                    .par_iter_mut()
+                    .enumerate()
+                    .for_each(|(i, acceleration)| {
+                        for buffer in summed_buffers {
+                            *acceleration += buffer[i];
+                        }
+                    });
+                fluid.independent_force_accelerations = buffers;
+
+                start += num_independent;
+                continue;
+            }
+        }
+
+        forces[start].solve(
+            timestep,
+            kernel_radius,
+            fluid_fluid_contacts,
+            fluid_boundary_contacts,
+            fluid,
+            boundaries,
+            densities,
+        );
+        start += 1;
+    }
+
+    fluid.nonpressure_forces = forces;
+}
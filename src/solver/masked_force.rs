@@ -0,0 +1,332 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::geometry::ParticlesContacts;
+
+use crate::math::{Real, Vector};
+use crate::object::{Boundary, Fluid};
+use crate::solver::{IndependentForce, IndependentForceInputs, NonPressureForce};
+use crate::TimestepManager;
+
+/// A non-pressure force only applied to a subset of the particles of a fluid.
+///
+/// The subset is either a fixed set of particle indices, or a predicate evaluated for each particle
+/// at the beginning of each solve, e.g., to only apply a surface tension near the surface of the
+/// fluid. The subset is stored as a mask, and the wrapped force only sees the contacts of the
+/// particles of this subset: the other particles have no neighbor, so their contributions are
+/// skipped, and their accelerations are left unchanged.
+///
+/// The cost of the wrapped force is thus mostly proportional to the size of the subset, and the
+/// parallel iterations of the wrapped force are still over all the particles, where the particles
+/// outside of the subset take almost no time, so the work stealing of the parallel iterations
+/// balances the load over the particles of the subset. Note that the particles outside of the
+/// subset look isolated to the wrapped force, so the per-particle quantities it computes from
+/// the neighbors of each particle, e.g., the normals of `Akinci2013SurfaceTension`, are wrong
+/// for them. The subset should include a margin of neighbors around the particles where the force
+/// matters if the wrapped force relies on such quantities.
+pub struct MaskedForce {
+    force: Box<dyn NonPressureForce>,
+    selection: Selection,
+    mask: Vec<bool>,
+    fluid_fluid_contacts: ParticlesContacts,
+    fluid_boundaries_contacts: ParticlesContacts,
+    accelerations: Vec<Vector<Real>>,
+}
+
+// Whether the particle of a fluid is selected.
+type ParticlePredicate = dyn Fn(&Fluid, usize) -> bool + Send + Sync;
+
+enum Selection {
+    Indices(Vec<usize>),
+    Predicate(Box<ParticlePredicate>),
+}
+
+impl MaskedForce {
+    /// Restricts `force` to the particles with the given indices.
+    ///
+    /// The indices follow the particles when they are reordered by `Fluid::z_sort` or when other
+    /// particles are removed, and the indices of the removed particles are dropped. The particles
+    /// added afterwards are not selected. The indices out of the bounds of the fluid are ignored.
+    pub fn with_indices(force: Box<dyn NonPressureForce>, indices: Vec<usize>) -> Self {
+        Self::new(force, Selection::Indices(indices))
+    }
+
+    /// Restricts `force` to the particles `i` for which `predicate(fluid, i)` is `true`.
+    ///
+    /// The predicate is evaluated once per particle at the beginning of each solve.
+    pub fn with_predicate(
+        force: Box<dyn NonPressureForce>,
+        predicate: impl Fn(&Fluid, usize) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Self::new(force, Selection::Predicate(Box::new(predicate)))
+    }
+
+    fn new(force: Box<dyn NonPressureForce>, selection: Selection) -> Self {
+        Self {
+            force,
+            selection,
+            mask: Vec::new(),
+            fluid_fluid_contacts: ParticlesContacts::new(),
+            fluid_boundaries_contacts: ParticlesContacts::new(),
+            accelerations: Vec::new(),
+        }
+    }
+
+    /// The wrapped force.
+    pub fn force(&self) -> &dyn NonPressureForce {
+        &*self.force
+    }
+
+    /// The mask of the particles the force was applied to during the last solve.
+    pub fn mask(&self) -> &[bool] {
+        &self.mask
+    }
+
+    fn update_mask(
+        &mut self,
+        fluid: &Fluid,
+        fluid_fluid_contacts: &ParticlesContacts,
+        fluid_boundaries_contacts: &ParticlesContacts,
+    ) {
+        self.mask.clear();
+        self.mask.resize(fluid.num_particles(), false);
+
+        match &self.selection {
+            Selection::Indices(indices) => {
+                for i in indices {
+                    if let Some(selected) = self.mask.get_mut(*i) {
+                        *selected = true;
+                    }
+                }
+            }
+            Selection::Predicate(predicate) => {
+                par_iter_mut!(self.mask)
+                    .enumerate()
+                    .for_each(|(i, selected)| *selected = predicate(fluid, i));
+            }
+        }
+
+        self.fluid_fluid_contacts
+            .copy_masked_from(fluid_fluid_contacts, &self.mask);
+        self.fluid_boundaries_contacts
+            .copy_masked_from(fluid_boundaries_contacts, &self.mask);
+    }
+}
+
+impl NonPressureForce for MaskedForce {
+    fn init(&mut self, fluid: &Fluid) {
+        self.force.init(fluid)
+    }
+
+    fn solve(
+        &mut self,
+        timestep: &TimestepManager,
+        kernel_radius: Real,
+        fluid_fluid_contacts: &ParticlesContacts,
+        fluid_boundaries_contacts: &ParticlesContacts,
+        fluid: &mut Fluid,
+        boundaries: &[Boundary],
+        densities: &[Real],
+    ) {
+        self.update_mask(fluid, fluid_fluid_contacts, fluid_boundaries_contacts);
+        self.accelerations.clone_from(&fluid.accelerations);
+        self.force.solve(
+            timestep,
+            kernel_radius,
+            &self.fluid_fluid_contacts,
+            &self.fluid_boundaries_contacts,
+            fluid,
+            boundaries,
+            densities,
+        );
+
+        // Forces not relying on the contacts may still affect the particles outside of the mask.
+        par_iter_mut!(fluid.accelerations)
+            .zip(par_iter!(self.accelerations))
+            .zip(par_iter!(self.mask))
+            .for_each(|((acceleration, initial), selected)| {
+                if !*selected {
+                    *acceleration = *initial;
+                }
+            })
+    }
+
+    fn as_independent_mut(&mut self) -> Option<&mut dyn IndependentForce> {
+        if self.force.as_independent_mut().is_some() {
+            Some(self)
+        } else {
+            None
+        }
+    }
+
+    fn apply_permutation(&mut self, permutation: &[usize]) {
+        if let Selection::Indices(indices) = &mut self.selection {
+            // The particle `permutation[i]` becomes the particle `i`.
+            let mut new_indices = vec![usize::MAX; permutation.len()];
+
+            for (new_index, old_index) in permutation.iter().enumerate() {
+                new_indices[*old_index] = new_index;
+            }
+
+            for i in indices.iter_mut() {
+                if let Some(new_index) = new_indices.get(*i) {
+                    *i = *new_index;
+                }
+            }
+        }
+
+        self.force.apply_permutation(permutation)
+    }
+
+    fn apply_removal(&mut self, removed: &[usize]) {
+        if let Selection::Indices(indices) = &mut self.selection {
+            // Each remaining particle moves back by the number of removed particles before it.
+            indices.retain_mut(|i| match removed.binary_search(i) {
+                Ok(_) => false,
+                Err(num_removed_before) => {
+                    *i -= num_removed_before;
+                    true
+                }
+            });
+        }
+
+        self.force.apply_removal(removed)
+    }
+
+    fn rescale(&mut self, factor: Real) {
+        self.force.rescale(factor)
+    }
+
+    fn export_state(&self) -> Vec<Real> {
+        self.force.export_state()
+    }
+
+    fn import_state(&mut self, state: &[Real]) {
+        self.force.import_state(state)
+    }
+}
+
+impl IndependentForce for MaskedForce {
+    fn solve_independently(
+        &mut self,
+        inputs: &IndependentForceInputs,
+        accelerations: &mut [Vector<Real>],
+    ) {
+        let fluid = inputs.fluid;
+        self.update_mask(
+            fluid,
+            inputs.fluid_fluid_contacts,
+            inputs.fluid_boundaries_contacts,
+        );
+        self.accelerations.clear();
+        self.accelerations
+            .resize(fluid.num_particles(), Vector::zeros());
+        let masked_inputs = IndependentForceInputs {
+            fluid_fluid_contacts: &self.fluid_fluid_contacts,
+            fluid_boundaries_contacts: &self.fluid_boundaries_contacts,
+            ..*inputs
+        };
+
+        if let Some(force) = self.force.as_independent_mut() {
+            force.solve_independently(&masked_inputs, &mut self.accelerations);
+        }
+
+        par_iter_mut!(accelerations)
+            .zip(par_iter!(self.accelerations))
+            .zip(par_iter!(self.mask))
+            .for_each(|((acceleration, masked), selected)| {
+                if *selected {
+                    *acceleration += *masked;
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kernel::CubicSplineKernel;
+    use crate::math::{Point, Real, Vector};
+    use crate::object::Fluid;
+    use crate::LiquidWorld;
+
+    #[test]
+    fn masked_forces_only_affect_the_selected_particles() {
+        use crate::solver::{Buoyancy, DFSPHSolver, MaskedForce, OverlapRepulsion};
+
+        let particle_radius = 0.05;
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        // An overlapping pair, and two isolated particles.
+        let positions = vec![
+            Point::origin(),
+            Point::from(Vector::x() * 0.01),
+            Point::from(Vector::x()),
+            Point::from(Vector::x() * 2.0),
+        ];
+        let mut fluid = Fluid::new(positions.clone(), particle_radius, 1000.0);
+        let repulsion = OverlapRepulsion::new(10.0);
+        fluid.add_nonpressure_force(Box::new(MaskedForce::with_indices(
+            Box::new(repulsion),
+            vec![0],
+        )));
+        // A uniform upward acceleration, only applied to the particle closest to `x = 1`.
+        let lift = Buoyancy::new(0.0, 1.0, Vector::y() * -1.0, |_, _| 1.0);
+        fluid.add_nonpressure_force(Box::new(MaskedForce::with_predicate(
+            Box::new(lift),
+            |fluid, i| (fluid.positions[i].x - 1.0).abs() < 0.5,
+        )));
+        let handle = world.add_fluid(fluid);
+
+        for _ in 0..3 {
+            world.step(0.01, &Vector::zeros());
+        }
+
+        let new_positions = &world.fluids()[handle].positions;
+        assert!(new_positions[0].x < 0.0);
+        assert_eq!(new_positions[1], positions[1]);
+        assert!(new_positions[2].y > 0.0);
+        assert_eq!(new_positions[3], positions[3]);
+    }
+
+    #[test]
+    fn masked_indices_follow_the_removed_particles() {
+        use crate::solver::{Buoyancy, DFSPHSolver, MaskedForce};
+
+        let particle_radius = 0.05;
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        // Isolated particles, with a uniform upward acceleration applied to the second and fourth ones.
+        let positions: Vec<_> = (0..5)
+            .map(|i| Point::from(Vector::x() * i as Real))
+            .collect();
+        let mut fluid = Fluid::new(positions, particle_radius, 1000.0);
+        let lift = Buoyancy::new(0.0, 1.0, Vector::y() * -1.0, |_, _| 1.0);
+        fluid.add_nonpressure_force(Box::new(MaskedForce::with_indices(
+            Box::new(lift),
+            vec![1, 3],
+        )));
+        let handle = world.add_fluid(fluid);
+
+        // The second particle becomes the first one, the fourth one is removed, and the new particle
+        // is not selected.
+        let fluid = &mut world.fluids_mut()[handle];
+        fluid.delete_particle_at_next_timestep(0);
+        fluid.delete_particle_at_next_timestep(3);
+        world.step(0.01, &Vector::zeros());
+        world.fluids_mut()[handle].add_particles(&[Point::from(Vector::x() * 5.0)], None);
+        world.step(0.01, &Vector::zeros());
+
+        let lifted: Vec<_> = world.fluids()[handle]
+            .positions
+            .iter()
+            .map(|pt| pt.y > 0.0)
+            .collect();
+        assert_eq!(lifted, [true, false, false, false]);
+    }
+}
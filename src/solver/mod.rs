@@ -1,14 +1,28 @@
 //! Algorithms for solving pressure, viscosity, surface tension, etc.
 
+pub use self::boundary_method::BoundaryMethod;
+pub use self::buoyancy::Buoyancy;
 pub use self::elasticity::*;
-pub use self::nonpressure_force::NonPressureForce;
+pub use self::masked_force::MaskedForce;
+pub use self::nonpressure_force::{IndependentForce, IndependentForceInputs, NonPressureForce};
+pub use self::overlap_repulsion::OverlapRepulsion;
 pub use self::pressure::*;
+pub use self::rotating_frame::RotatingFrame;
+pub use self::solver_state::{SolverState, SolverStateError};
+pub use self::summation::{CompensatedSum, Summation};
 pub use self::surface_tension::*;
 pub use self::viscosity::*;
 
+pub(crate) mod boundary_method;
+mod buoyancy;
 mod elasticity;
 pub(crate) mod helper;
+mod masked_force;
 mod nonpressure_force;
+mod overlap_repulsion;
 mod pressure;
+mod rotating_frame;
+mod solver_state;
+mod summation;
 mod surface_tension;
 mod viscosity;
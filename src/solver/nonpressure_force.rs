@@ -1,5 +1,5 @@
 use crate::geometry::ParticlesContacts;
-use crate::math::Real;
+use crate::math::{Real, Vector};
 use crate::object::{Boundary, Fluid};
 use crate::TimestepManager;
 
@@ -8,10 +8,22 @@ use crate::TimestepManager;
 /// This includes all non-pressure forces internal to a same fluid, or acting
 /// between a fluid and a boundary.
 pub trait NonPressureForce: Send + Sync {
+    /// Initializes this force for the given fluid, e.g., to size its internal buffers.
+    ///
+    /// This is called by `Fluid::add_nonpressure_force` when the force is added, and before the
+    /// next solve whenever the number of particles of `fluid` changed, e.g., after an emitter
+    /// added particles. It is always called at least once before the first solve, even for forces
+    /// pushed directly to `Fluid::nonpressure_forces`.
+    fn init(&mut self, _fluid: &Fluid) {}
+
     /// Compute and applies the non-pressure forces to the given fluid.
     ///
     /// The force application should result in adding accelerations to the
     /// `fluid.accelerations` field.
+    ///
+    /// The `densities` of the particles of `fluid` are computed once per substep with the density
+    /// kernel of the pressure solver, before any non-pressure force is solved, and are shared by all
+    /// the non-pressure forces and the pressure solver. They must not be recomputed here.
     fn solve(
         &mut self,
         timestep: &TimestepManager,
@@ -23,8 +35,167 @@ pub trait NonPressureForce: Send + Sync {
         densities: &[Real],
     );
 
+    /// This force as an independent force, if it is one.
+    ///
+    /// Consecutive independent forces of a fluid are solved in parallel with
+    /// `IndependentForce::solve_independently` when the `parallel` feature is enabled. Forces reading
+    /// the accelerations of the fluid, e.g., to predict velocities, must return `None`, the default.
+    fn as_independent_mut(&mut self) -> Option<&mut dyn IndependentForce> {
+        None
+    }
+
+    /// The surface normals of the particles of the fluid computed during the last solve, if any.
+    ///
+    /// These are the gradients of the color field, `h Σ_j V_j ∇W_ij` over the neighbors `j` of the
+    /// same fluid, where `h` is the kernel radius, so they point toward the inside of the fluid
+    /// and vanish inside of it. This is used by `LiquidWorld::fluid_surface_curvature` to avoid
+    /// computing them again. Forces not computing such normals return `None`, the default.
+    fn surface_normals(&self) -> Option<&[Vector<Real>]> {
+        None
+    }
+
     /// Apply the given permutation to all relevant field of this non-pressure force.
     ///
-    /// This is currently not used so it can be left empty.
+    /// This is called by `Fluid::z_sort`, and can be left empty if this force does not refer to
+    /// specific particles from one solve to the next.
     fn apply_permutation(&mut self, _permutation: &[usize]) {}
+
+    /// Updates the per-particle state of this non-pressure force after particles were removed from the fluid.
+    ///
+    /// The `indices` of the removed particles are sorted in increasing order, and refer to the
+    /// particle indices before the removal, as for `FluidListener::on_particles_removed`. This can
+    /// be left empty if this force does not refer to specific particles from one solve to the next.
+    fn apply_removal(&mut self, _indices: &[usize]) {}
+
+    /// Rescales the internal state of this non-pressure force after all the lengths of the simulation were multiplied by `factor`.
+    ///
+    /// The particle masses are preserved by the rescaling. This is called by `LiquidWorld::rescale`
+    /// and can be left empty if this force does not keep any length-dependent state from one step
+    /// to the next. Its coefficients must not be modified.
+    fn rescale(&mut self, _factor: Real) {}
+
+    /// Exports the state this force keeps from one substep to the next, e.g., an elapsed time.
+    ///
+    /// This is called by `LiquidWorld` at the beginning of each substep while a stability guard is
+    /// set, and the state is given back to `self.import_state` if the substep is retried. This can
+    /// be left empty if this force does not keep any state from one substep to the next.
+    fn export_state(&self) -> Vec<Real> {
+        Vec::new()
+    }
+
+    /// Restores a state previously exported with `self.export_state`.
+    fn import_state(&mut self, _state: &[Real]) {}
+}
+
+/// The inputs of an independent non-pressure force.
+///
+/// These are the arguments of `NonPressureForce::solve`, with a fluid which cannot be modified.
+#[derive(Copy, Clone)]
+pub struct IndependentForceInputs<'a> {
+    /// The timestep of the current substep.
+    pub timestep: &'a TimestepManager,
+    /// The kernel radius of the liquid world.
+    pub kernel_radius: Real,
+    /// The contacts between the particles of the fluid and the particles of all the fluids.
+    pub fluid_fluid_contacts: &'a ParticlesContacts,
+    /// The contacts between the particles of the fluid and the boundary particles.
+    pub fluid_boundaries_contacts: &'a ParticlesContacts,
+    /// The fluid the force is applied to.
+    pub fluid: &'a Fluid,
+    /// All the boundaries of the liquid world.
+    pub boundaries: &'a [Boundary],
+    /// The densities of the particles of the fluid, see `NonPressureForce::solve`.
+    pub densities: &'a [Real],
+}
+
+/// Trait implemented by the non-pressure forces independent from the other non-pressure forces of a fluid.
+///
+/// An independent force only reads the fluid and adds its own contribution to the particle
+/// accelerations, and this contribution does not depend on the accelerations added by the other
+/// forces. Its `NonPressureForce::as_independent_mut` must return `Some(self)`.
+pub trait IndependentForce: NonPressureForce {
+    /// Computes the non-pressure forces of this force without modifying the fluid.
+    ///
+    /// This adds the accelerations due to this force to `accelerations`, which has one element per
+    /// particle of `inputs.fluid`, instead of adding them to the accelerations of the fluid. Note
+    /// that the accelerations of the fluid may not contain the accelerations of the other forces at
+    /// this point.
+    fn solve_independently(
+        &mut self,
+        inputs: &IndependentForceInputs,
+        accelerations: &mut [Vector<Real>],
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::math::Real;
+    use crate::object::{Boundary, Fluid};
+    use crate::TimestepManager;
+
+    #[test]
+    fn independent_forces_match_their_sequential_solve() {
+        use crate::geometry::ParticlesContacts;
+        use crate::solver::{NonPressureForce, WCSPHSurfaceTension, XSPHViscosity};
+
+        // Hides the independence of a force so that it is always solved sequentially.
+        struct Sequential<F>(F);
+
+        impl<F: NonPressureForce> NonPressureForce for Sequential<F> {
+            fn solve(
+                &mut self,
+                timestep: &TimestepManager,
+                kernel_radius: Real,
+                fluid_fluid_contacts: &ParticlesContacts,
+                fluid_boundaries_contacts: &ParticlesContacts,
+                fluid: &mut Fluid,
+                boundaries: &[Boundary],
+                densities: &[Real],
+            ) {
+                self.0.solve(
+                    timestep,
+                    kernel_radius,
+                    fluid_fluid_contacts,
+                    fluid_boundaries_contacts,
+                    fluid,
+                    boundaries,
+                    densities,
+                )
+            }
+        }
+
+        let gravity = crate::math::down_gravity(9.81);
+        let mut parallel = crate::testbed::generate_dam_break(0.05, 5);
+        let mut sequential = crate::testbed::generate_dam_break(0.05, 5);
+
+        for fluid in parallel.fluids_mut().as_mut_slice() {
+            fluid
+                .nonpressure_forces
+                .push(Box::new(XSPHViscosity::new(0.5, 0.0)));
+            fluid
+                .nonpressure_forces
+                .push(Box::new(WCSPHSurfaceTension::new(1.0, 1.0)));
+        }
+
+        for fluid in sequential.fluids_mut().as_mut_slice() {
+            fluid
+                .nonpressure_forces
+                .push(Box::new(Sequential(XSPHViscosity::new(0.5, 0.0))));
+            fluid
+                .nonpressure_forces
+                .push(Box::new(Sequential(WCSPHSurfaceTension::new(1.0, 1.0))));
+        }
+
+        for _ in 0..10 {
+            parallel.step(0.01, &gravity);
+            sequential.step(0.01, &gravity);
+        }
+
+        let parallel = &parallel.fluids().as_slice()[0];
+        let sequential = &sequential.fluids().as_slice()[0];
+
+        for (p1, p2) in parallel.positions.iter().zip(sequential.positions.iter()) {
+            assert!(approx::relative_eq!(p1, p2, epsilon = 1.0e-4));
+        }
+    }
 }
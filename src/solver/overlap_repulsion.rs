@@ -0,0 +1,207 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::geometry::ParticlesContacts;
+
+use crate::math::{Real, Vector};
+use crate::object::{Boundary, Fluid};
+use crate::solver::{IndependentForce, IndependentForceInputs, NonPressureForce};
+use crate::TimestepManager;
+
+/// A short-range repulsion separating overlapping particles, e.g., from an imperfect sampling.
+///
+/// Two particles closer than `cutoff * particle_radius` push each other apart with an acceleration
+/// growing linearly from zero at this distance to `stiffness` when they coincide. The fluid particles
+/// are repelled by the other fluid particles and by the boundary particles. With a cutoff well
+/// below one, the default, the particles at the usual spacing of twice the particle radius are not
+/// affected, so this does not interfere with the other forces, e.g., surface tension. The repulsion
+/// can be limited to the beginning of the simulation with `duration`, e.g., to relax a freshly loaded
+/// scene before the first pressure solves make it explode.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OverlapRepulsion {
+    /// The distance, as a multiple of the particle radius, below which particles are repelled.
+    pub cutoff: Real,
+    /// The acceleration of the repulsion between two coincident particles.
+    pub stiffness: Real,
+    /// The simulation time after which the repulsion stops, or `None` to keep it indefinitely.
+    pub duration: Option<Real>,
+    elapsed_time: Real,
+}
+
+impl OverlapRepulsion {
+    /// Initializes a repulsion with the given stiffness, a cutoff of half the particle radius, and no duration limit.
+    pub fn new(stiffness: Real) -> Self {
+        Self {
+            cutoff: na::convert::<_, Real>(0.5),
+            stiffness,
+            duration: None,
+            elapsed_time: na::zero::<Real>(),
+        }
+    }
+
+    /// Is the repulsion still applied?
+    pub fn is_active(&self) -> bool {
+        self.duration
+            .is_none_or(|duration| self.elapsed_time < duration)
+    }
+
+    // The acceleration pushing a particle away from a neighbor at the given distance, along the
+    // opposite of the kernel gradient of their contact.
+    fn acceleration(
+        &self,
+        cutoff_distance: Real,
+        distance: Real,
+        gradient: &Vector<Real>,
+    ) -> Vector<Real> {
+        if distance >= cutoff_distance {
+            return Vector::zeros();
+        }
+
+        match (-gradient).try_normalize(na::zero::<Real>()) {
+            Some(dir) => dir * (self.stiffness * (na::one::<Real>() - distance / cutoff_distance)),
+            None => Vector::zeros(),
+        }
+    }
+}
+
+impl NonPressureForce for OverlapRepulsion {
+    fn solve(
+        &mut self,
+        timestep: &TimestepManager,
+        kernel_radius: Real,
+        fluid_fluid_contacts: &ParticlesContacts,
+        fluid_boundaries_contacts: &ParticlesContacts,
+        fluid: &mut Fluid,
+        boundaries: &[Boundary],
+        densities: &[Real],
+    ) {
+        let mut accelerations = std::mem::take(&mut fluid.accelerations);
+        let inputs = IndependentForceInputs {
+            timestep,
+            kernel_radius,
+            fluid_fluid_contacts,
+            fluid_boundaries_contacts,
+            fluid,
+            boundaries,
+            densities,
+        };
+        self.solve_independently(&inputs, &mut accelerations);
+        fluid.accelerations = accelerations;
+    }
+
+    fn as_independent_mut(&mut self) -> Option<&mut dyn IndependentForce> {
+        Some(self)
+    }
+
+    fn export_state(&self) -> Vec<Real> {
+        vec![self.elapsed_time]
+    }
+
+    fn import_state(&mut self, state: &[Real]) {
+        self.elapsed_time = state[0];
+    }
+}
+
+impl IndependentForce for OverlapRepulsion {
+    fn solve_independently(
+        &mut self,
+        inputs: &IndependentForceInputs,
+        accelerations: &mut [Vector<Real>],
+    ) {
+        let IndependentForceInputs {
+            timestep,
+            fluid_fluid_contacts,
+            fluid_boundaries_contacts,
+            fluid,
+            boundaries,
+            ..
+        } = *inputs;
+        if !self.is_active() {
+            return;
+        }
+
+        self.elapsed_time += timestep.dt();
+
+        let this = &*self;
+        let cutoff_distance = self.cutoff * fluid.particle_radius();
+
+        par_iter_mut!(accelerations)
+            .enumerate()
+            .for_each(|(i, acceleration)| {
+                for c in fluid_fluid_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    if !c.is_same_particle_contact() {
+                        *acceleration +=
+                            this.acceleration(cutoff_distance, c.distance, &c.gradient);
+                    }
+                }
+
+                for c in fluid_boundaries_contacts
+                    .particle_contacts(i)
+                    .read()
+                    .unwrap()
+                    .iter()
+                {
+                    let boundary_acceleration =
+                        this.acceleration(cutoff_distance, c.distance, &c.gradient);
+
+                    if boundary_acceleration != Vector::zeros() {
+                        *acceleration += boundary_acceleration;
+                        boundaries[c.j_model]
+                            .apply_force(c.j, boundary_acceleration * -fluid.particle_mass(i));
+                    }
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kernel::CubicSplineKernel;
+    use crate::math::{Point, Vector};
+    use crate::object::Fluid;
+    use crate::LiquidWorld;
+
+    #[test]
+    fn overlap_repulsion_separates_overlapping_particles() {
+        use crate::solver::{DFSPHSolver, OverlapRepulsion};
+
+        let particle_radius = 0.05;
+        let mut world = LiquidWorld::new(
+            DFSPHSolver::<CubicSplineKernel>::new(),
+            particle_radius,
+            2.0,
+        );
+        // An overlapping pair, and a pair at the usual spacing far from it.
+        let positions = vec![
+            Point::origin(),
+            Point::from(Vector::x() * 0.01),
+            Point::from(Vector::y()),
+            Point::from(Vector::y() + Vector::x() * 0.1),
+        ];
+        let mut fluid = Fluid::new(positions, particle_radius, 1000.0);
+        let mut repulsion = OverlapRepulsion::new(10.0);
+        repulsion.duration = Some(0.05);
+        fluid.add_nonpressure_force(Box::new(repulsion));
+        let handle = world.add_fluid(fluid);
+
+        for _ in 0..3 {
+            world.step(0.01, &Vector::zeros());
+        }
+
+        let positions = &world.fluids()[handle].positions;
+        assert!(positions[1].x - positions[0].x > 0.01);
+        assert!((positions[1].y - positions[0].y).abs() < 1.0e-6);
+        assert_eq!(positions[2], Point::from(Vector::y()));
+        assert_eq!(positions[3], Point::from(Vector::y() + Vector::x() * 0.1));
+
+        // The repulsion stops after its duration.
+        assert!(repulsion.is_active());
+        repulsion.duration = Some(0.0);
+        assert!(!repulsion.is_active());
+    }
+}
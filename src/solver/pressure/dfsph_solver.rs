@@ -10,10 +10,38 @@ use crate::geometry::{ContactManager, ParticlesContacts};
 use crate::kernel::{CubicSplineKernel, Kernel};
 use crate::math::{Real, Vector, DIM};
 use crate::object::{Boundary, Fluid};
-use crate::solver::{helper, PressureSolver};
+use crate::solver::boundary_method::extrapolate_to_boundaries;
+use crate::solver::{
+    apply_pressure_reactions, helper, is_fluid_solved, max_error_or_nan, BoundaryMethod,
+    CompensatedSum, ConfigurablePressureSolver, IncompressibilityTolerance, IterationCallback,
+    IterationState, PartialPressureSolver, PressureSolver, SolvePhase, SolveStats, SolverState,
+    SolverStateError, StatefulPressureSolver, Summation,
+};
 use crate::TimestepManager;
 
+// The denominator of alpha below which a particle is considered to have no neighbor, relative to
+// `(rest_density / kernel_radius)²` so that it does not depend on the unit system.
+const MIN_RELATIVE_ALPHA_DENOMINATOR: f64 = 1.0e-12;
+// The largest factor applied to the boundary volumes by `DFSPHSolver::boundary_density_correction`.
+// Without this bound, the particles barely reaching the kernel support of a wall would be pushed
+// as hard as the ones touching it, and the fluid would float above the walls.
+const MAX_BOUNDARY_VOLUME_SCALE: f64 = 4.0;
+
+// The factor applied to the boundary volumes seen by the `i`-th particle of the fluid `fluid_id`.
+#[inline]
+fn boundary_volume_scale(scales: &[Vec<Real>], fluid_id: usize, i: usize) -> Real {
+    scales
+        .get(fluid_id)
+        .and_then(|scales| scales.get(i))
+        .copied()
+        .unwrap_or_else(na::one::<Real>)
+}
+
 /// A DFSPH (Divergence Free Smoothed Particle Hydrodynamics) pressure solver.
+///
+/// By default, the pressure of a boundary particle is mirrored from the fluid particle it interacts
+/// with, as in Akinci et al. 2012 "Versatile Rigid-Fluid Coupling for Incompressible SPH". The other
+/// methods of `BoundaryMethod` can be selected with `ConfigurablePressureSolver::set_boundary_method`.
 pub struct DFSPHSolver<
     KernelDensity: Kernel = CubicSplineKernel,
     KernelGradient: Kernel = CubicSplineKernel,
@@ -22,7 +50,7 @@ pub struct DFSPHSolver<
     pub min_pressure_iter: usize,
     /// Maximum number of iterations that must be executed for pressure resolution.
     pub max_pressure_iter: usize,
-    /// Maximum acceptable density error (in percents).
+    /// Maximum acceptable average relative density error, e.g., `0.05` for a compression of 5%.
     ///
     /// The pressure solver will continue iterating until the density error drops bellow this
     /// threshold, or until the maximum number of pressure iterations is reached.
@@ -36,12 +64,77 @@ pub struct DFSPHSolver<
     /// The pressure solver will continue iterating until the divergence error drops bellow this
     /// threshold, or until the maximum number of pressure iterations is reached.
     pub max_divergence_error: Real,
+    /// If `true`, gravity is applied through the pressure solve instead of being integrated explicitly.
+    ///
+    /// Gravity then only enters the source term of the density solve. A fluid is at hydrostatic
+    /// equilibrium once the resulting pressure balances gravity at each of its particles, and all
+    /// its particles are almost at rest. Its particles are then stopped, and the small residual of
+    /// gravity and pressure is dropped, so a settled fluid stays perfectly still instead of
+    /// jittering. The fluids with a particle falling or moving are not affected.
+    pub gravity_in_pressure_solve: bool,
+    /// Tolerance for detecting hydrostatic equilibrium when `gravity_in_pressure_solve` is enabled.
+    ///
+    /// A fluid is at equilibrium if, for each of its particles, the pressure pushes it against gravity,
+    /// and both the velocity change due to gravity and pressure and the velocity of the particle are
+    /// smaller than `hydrostatic_tolerance * |gravity * gravity_scale| * dt`, where `gravity_scale` is
+    /// the one of the fluid. Defaults to `0.01`. Larger values stop the fluids sooner, but with a
+    /// larger imbalance of gravity and pressure.
+    pub hydrostatic_tolerance: Real,
+    /// The maximum density the density solve allows, relative to the rest density of each particle.
+    ///
+    /// The default of 1 prevents any compression. Values slightly above 1, e.g., 1.02, let the fluid
+    /// compress a bit for a softer, springier look. Values below 1 enforce a density ceiling under
+    /// the rest density, and make the fluid expand until its particles are further apart.
+    pub max_density_ratio: Real,
+    /// The algorithm used to sum the contributions of the neighbors to the densities and the pressure forces.
+    ///
+    /// Defaults to `Summation::Naive`. `Summation::Compensated` is slower, but reduces the rounding
+    /// errors for particles with many neighbors.
+    pub summation: Summation,
+    /// The number of density computations between two full summations of the densities over the neighbors.
+    ///
+    /// The default of 1 sums the densities at every substep. With larger values, the densities of
+    /// the other substeps are advanced with the continuity equation
+    /// `dρ_i/dt = Σ_j m_j (v_i - v_j)·∇W_ij`, using the velocities of the previous substep. This
+    /// is cheaper but drifts over time, which is corrected by the full summation performed every
+    /// `density_resync_interval` computations. A full summation is also performed whenever
+    /// particles are added, removed or reordered, e.g., by `Fluid::z_sort`, and after the solver
+    /// is reset or rescaled. The densities and the number of computations since the last full
+    /// summation are part of the state exported by `StatefulPressureSolver::export_state`.
+    pub density_resync_interval: usize,
+    /// If `true`, the boundary volumes are scaled up so that the fluid particles near the walls
+    /// reach the rest density.
+    ///
+    /// The boundary particles of a wall sampled with a few layers do not cover the whole kernel
+    /// support of the fluid particles next to it, so these particles are under-dense and tend to
+    /// stick to the wall or to leave a gap. With this correction, the volumes of the boundary
+    /// neighbors of each fluid particle are multiplied by the factor needed to complete its density
+    /// up to its rest density, computed at each full summation of the densities. The factor is
+    /// between 1 and 4, and this only affects the boundary methods using the boundary volumes of
+    /// Akinci et al. 2012, see `BoundaryMethod::uses_boundary_volumes`. Defaults to `false`.
+    pub boundary_density_correction: bool,
+    boundary_method: BoundaryMethod,
+    solved_fluids: Option<Vec<bool>>,
+    // The number of density computations since the last full summation, zero if one is required.
+    densities_since_resync: usize,
+    // The permutation generation of each fluid at the last density computation.
+    permutation_generations: Vec<u64>,
+    // The time elapsed since the last density computation.
+    density_update_dt: Real,
     min_neighbors_for_divergence_solve: usize,
     alphas: Vec<Vec<Real>>,
     densities: Vec<Vec<Real>>,
     predicted_densities: Vec<Vec<Real>>,
     divergences: Vec<Vec<Real>>,
     velocity_changes: Vec<Vec<Vector<Real>>>,
+    boundary_stiffnesses: Vec<Vec<Real>>,
+    // The factors applied to the boundary volumes seen by each particle, see `boundary_density_correction`.
+    boundary_volume_scales: Vec<Vec<Real>>,
+    // The sum of the stiffnesses applied to each particle, for the reactions on the unsolved fluids.
+    pressure_coefficients: Vec<Vec<Real>>,
+    nonpressure_velocity_changes: Vec<Vec<Vector<Real>>>,
+    last_solve_stats: SolveStats,
+    iteration_callback: Option<Box<IterationCallback>>,
     phantoms: PhantomData<(KernelDensity, KernelGradient)>,
 }
 
@@ -59,26 +152,100 @@ where
             min_divergence_iter: 1,
             max_divergence_iter: 50,
             max_divergence_error: na::convert::<_, Real>(0.1),
+            gravity_in_pressure_solve: false,
+            hydrostatic_tolerance: na::convert::<_, Real>(0.01),
+            max_density_ratio: na::one::<Real>(),
+            summation: Summation::Naive,
+            density_resync_interval: 1,
+            boundary_density_correction: false,
+            boundary_method: BoundaryMethod::Akinci,
+            solved_fluids: None,
+            densities_since_resync: 0,
+            permutation_generations: Vec::new(),
+            density_update_dt: na::zero::<Real>(),
             min_neighbors_for_divergence_solve: if DIM == 2 { 6 } else { 20 },
             alphas: Vec::new(),
             densities: Vec::new(),
             predicted_densities: Vec::new(),
             divergences: Vec::new(),
             velocity_changes: Vec::new(),
+            boundary_stiffnesses: Vec::new(),
+            boundary_volume_scales: Vec::new(),
+            pressure_coefficients: Vec::new(),
+            nonpressure_velocity_changes: Vec::new(),
+            last_solve_stats: SolveStats::default(),
+            iteration_callback: None,
             phantoms: PhantomData,
         }
     }
 
+    // Advances the densities by `dt` with the continuity equation, instead of summing them.
+    fn advance_densities(
+        &mut self,
+        contact_manager: &ContactManager,
+        fluids: &[Fluid],
+        boundaries: &[Boundary],
+        dt: Real,
+    ) {
+        let summation = self.summation;
+        let boundary_method = self.boundary_method;
+        let boundary_volume_scales = &self.boundary_volume_scales;
+
+        for fluid_id in 0..fluids.len() {
+            let fluid_i = &fluids[fluid_id];
+
+            par_iter_mut!(self.densities[fluid_id])
+                .enumerate()
+                .for_each(|(i, density)| {
+                    let mut delta = CompensatedSum::new(summation);
+
+                    for c in contact_manager.fluid_fluid_contacts[fluid_id]
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .iter()
+                    {
+                        let fluid_j = &fluids[c.j_model];
+                        let dvel = fluid_i.velocities[c.i] - fluid_j.velocities[c.j];
+                        delta.add(fluid_j.particle_mass(c.j) * dvel.dot(&c.gradient));
+                    }
+
+                    for c in contact_manager.fluid_boundary_contacts[fluid_id]
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .iter()
+                    {
+                        let volume =
+                            boundary_method.boundary_volume(
+                                boundaries[c.j_model].volumes[c.j],
+                                fluid_i.volumes[c.i],
+                            ) * boundary_volume_scale(boundary_volume_scales, fluid_id, c.i);
+                        let dvel = fluid_i.velocities[c.i] - boundaries[c.j_model].velocities[c.j];
+                        delta.add(volume * fluid_i.rest_density(c.i) * dvel.dot(&c.gradient));
+                    }
+
+                    *density += delta.value() * dt;
+                })
+        }
+    }
+
     fn compute_boundary_volumes(
         &mut self,
         boundary_boundary_contacts: &[ParticlesContacts],
         boundaries: &mut [Boundary],
     ) {
+        let summation = self.summation;
+
         for boundary_id in 0..boundaries.len() {
+            if boundaries[boundary_id].fixed_volumes() {
+                continue;
+            }
+
             par_iter_mut!(boundaries[boundary_id].volumes)
                 .enumerate()
                 .for_each(|(i, volume)| {
-                    let mut denominator = na::zero::<Real>();
+                    let mut denominator = CompensatedSum::new(summation);
 
                     for c in boundary_boundary_contacts[boundary_id]
                         .particle_contacts(i)
@@ -86,9 +253,10 @@ where
                         .unwrap()
                         .iter()
                     {
-                        denominator += c.weight;
+                        denominator.add(c.weight);
                     }
 
+                    let denominator = denominator.value();
                     assert!(!denominator.is_zero());
                     *volume = na::one::<Real>() / denominator;
                 })
@@ -102,17 +270,27 @@ where
         fluid_boundary_contacts: &[ParticlesContacts],
         fluids: &[Fluid],
         boundaries: &[Boundary],
-    ) -> Real {
+        track_max_error: bool,
+    ) -> (Real, Real) {
         let velocity_changes = &self.velocity_changes;
         let densities = &self.densities;
+        let max_density_ratio = self.max_density_ratio;
+        let summation = self.summation;
+        let boundary_method = self.boundary_method;
+        let boundary_volume_scales = &self.boundary_volume_scales;
         let mut max_error = na::zero::<Real>();
+        let mut max_particle_error = na::zero::<Real>();
 
         for fluid_id in 0..fluids.len() {
+            if !is_fluid_solved(self.solved_fluids.as_deref(), fluid_id) {
+                continue;
+            }
+
             let it = par_iter_mut!(self.predicted_densities[fluid_id])
                 .enumerate()
                 .map(|(i, predicted_density)| {
                     let fluid_i = &fluids[fluid_id];
-                    let mut delta = na::zero::<Real>();
+                    let mut delta = CompensatedSum::new(summation);
 
                     for c in fluid_fluid_contacts[fluid_id]
                         .particle_contacts(i)
@@ -124,7 +302,8 @@ where
                         let vi = fluid_i.velocities[c.i] + velocity_changes[c.i_model][c.i];
                         let vj = fluid_j.velocities[c.j] + velocity_changes[c.j_model][c.j];
 
-                        delta += fluids[c.j_model].particle_mass(c.j) * (vi - vj).dot(&c.gradient);
+                        delta
+                            .add(fluids[c.j_model].particle_mass(c.j) * (vi - vj).dot(&c.gradient));
                     }
 
                     for c in fluid_boundary_contacts[fluid_id]
@@ -135,35 +314,45 @@ where
                     {
                         let vi = fluid_i.velocities[c.i] + velocity_changes[c.i_model][c.i];
                         let vj = boundaries[c.j_model].velocities[c.j];
+                        let volume = boundary_method.boundary_volume(
+                            boundaries[c.j_model].volumes[c.j],
+                            fluid_i.volumes[i],
+                        ) * boundary_volume_scale(boundary_volume_scales, fluid_id, i);
 
-                        delta += boundaries[c.j_model].volumes[c.j]
-                            * fluid_i.density0
-                            * (vi - vj).dot(&c.gradient);
+                        delta.add(volume * fluid_i.rest_density(i) * (vi - vj).dot(&c.gradient));
                     }
 
-                    *predicted_density = densities[fluid_id][i] + delta * timestep.dt();
+                    *predicted_density = densities[fluid_id][i] + delta.value() * timestep.dt();
                     assert!(!predicted_density.is_zero());
+                    let max_density = fluid_i.rest_density(i) * max_density_ratio;
 
-                    if *predicted_density < fluid_i.density0 {
+                    if *predicted_density < max_density {
                         na::zero::<Real>()
                     } else {
-                        *predicted_density / fluid_i.density0 - na::one::<Real>()
+                        *predicted_density / max_density - na::one::<Real>()
                     }
                 });
-            let err = par_reduce_sum!(na::zero::<Real>(), it);
+            let (err, particle_error) = if track_max_error {
+                par_reduce_sum_max!(na::zero::<Real>(), it)
+            } else {
+                (par_reduce_sum!(na::zero::<Real>(), it), na::zero::<Real>())
+            };
+            max_particle_error = max_particle_error.max(particle_error);
 
             let nparts = fluids[fluid_id].num_particles();
             if nparts != 0 {
-                max_error = max_error.max(err / na::convert::<_, Real>(nparts as f64));
+                max_error =
+                    max_error_or_nan(max_error, err / na::convert::<_, Real>(nparts as f64));
             }
         }
 
-        max_error
+        (max_error, max_particle_error)
     }
 
     // NOTE: this actually computes alpha_i / density_i
     fn compute_alphas(
         &mut self,
+        kernel_radius: Real,
         fluid_fluid_contacts: &[ParticlesContacts],
         fluid_boundary_contacts: &[ParticlesContacts],
         fluids: &[Fluid],
@@ -174,6 +363,14 @@ where
             let fluid_boundary_contacts = &fluid_boundary_contacts[fluid_id];
             let alphas_i = &mut self.alphas[fluid_id];
             let fluid_i = &fluids[fluid_id];
+            let boundary_method = self.boundary_method;
+            let boundary_volume_scales = &self.boundary_volume_scales;
+
+            // The unsolved fluids have no pressure: a zero alpha cancels their stiffnesses.
+            if !is_fluid_solved(self.solved_fluids.as_deref(), fluid_id) {
+                alphas_i.fill(na::zero::<Real>());
+                continue;
+            }
 
             par_iter_mut!(alphas_i)
                 .enumerate()
@@ -198,15 +395,22 @@ where
                         .unwrap()
                         .iter()
                     {
-                        let grad_i =
-                            c.gradient * boundaries[c.j_model].volumes[c.j] * fluid_i.density0;
+                        let volume = boundary_method.boundary_volume(
+                            boundaries[c.j_model].volumes[c.j],
+                            fluid_i.volumes[i],
+                        ) * boundary_volume_scale(boundary_volume_scales, fluid_id, i);
+                        let grad_i = c.gradient * volume * fluid_i.rest_density(i);
                         squared_grad_sum += grad_i.norm_squared();
                         grad_sum += grad_i;
                     }
 
                     let denominator = squared_grad_sum + grad_sum.norm_squared();
+                    let gradient_scale = fluid_i.rest_density(i) / kernel_radius;
+                    let min_denominator = gradient_scale
+                        * gradient_scale
+                        * na::convert::<_, Real>(MIN_RELATIVE_ALPHA_DENOMINATOR);
 
-                    if denominator <= na::convert::<_, Real>(1.0e-5) {
+                    if denominator <= min_denominator {
                         *alpha_i = na::zero::<Real>();
                     } else {
                         *alpha_i = na::one::<Real>() / denominator;
@@ -225,14 +429,42 @@ where
     ) {
         let alphas = &self.alphas;
         let predicted_densities = &self.predicted_densities;
+        let max_density_ratio = self.max_density_ratio;
+        let summation = self.summation;
+        let boundary_method = self.boundary_method;
+        let stiffness = |fluid_id: usize, i: usize| {
+            let fluid = &fluids[fluid_id];
+            (predicted_densities[fluid_id][i] - fluid.rest_density(i) * max_density_ratio)
+                * alphas[fluid_id][i]
+        };
+
+        if boundary_method.extrapolates_pressure() {
+            extrapolate_to_boundaries(
+                boundary_method,
+                fluid_boundary_contacts,
+                boundaries,
+                fluids,
+                |fluid_id, i| stiffness(fluid_id, i).max(na::zero::<Real>()),
+                &mut self.boundary_stiffnesses,
+            );
+        }
+
+        let boundary_stiffnesses = &self.boundary_stiffnesses;
+        let boundary_volume_scales = &self.boundary_volume_scales;
 
         for (fluid_id, _fluid1) in fluids.iter().enumerate() {
+            if !is_fluid_solved(self.solved_fluids.as_deref(), fluid_id) {
+                continue;
+            }
+
             par_iter_mut!(self.velocity_changes[fluid_id])
+                .zip(par_iter_mut!(self.pressure_coefficients[fluid_id]))
                 .enumerate()
-                .for_each(|(i, velocity_change)| {
+                .for_each(|(i, (velocity_change, coefficient))| {
                     let fluid1 = &fluids[fluid_id];
-                    let ki =
-                        (predicted_densities[fluid_id][i] - fluid1.density0) * alphas[fluid_id][i];
+                    let mut sum = CompensatedSum::starting_at(summation, *velocity_change);
+                    let ki = stiffness(fluid_id, i);
+                    *coefficient += ki.max(na::zero::<Real>()) * timestep.inv_dt();
 
                     for c in fluid_fluid_contacts[fluid_id]
                         .particle_contacts(i)
@@ -242,29 +474,43 @@ where
                     {
                         let fluid2 = &fluids[c.j_model];
 
-                        let kj = (predicted_densities[c.j_model][c.j] - fluid2.density0)
-                            * alphas[c.j_model][c.j];
+                        let kj = stiffness(c.j_model, c.j);
 
                         let kij = ki.max(na::zero::<Real>()) + kj.max(na::zero::<Real>());
 
                         // Compute velocity change.
                         if kij > na::zero::<Real>() {
                             let coeff = kij * fluid2.particle_mass(c.j);
-                            *velocity_change -= c.gradient * (coeff * timestep.inv_dt());
+                            sum.sub(c.gradient * (coeff * timestep.inv_dt()));
                         }
                     }
 
-                    if ki > na::zero::<Real>() {
+                    if ki > na::zero::<Real>() || boundary_method.extrapolates_pressure() {
                         for c in fluid_boundary_contacts[fluid_id]
                             .particle_contacts(i)
                             .read()
                             .unwrap()
                             .iter()
                         {
-                            let coeff = ki * boundaries[c.j_model].volumes[c.j] * fluid1.density0;
+                            let kb = if boundary_method.extrapolates_pressure() {
+                                boundary_stiffnesses[c.j_model][c.j]
+                            } else {
+                                ki
+                            };
+
+                            if kb <= na::zero::<Real>() {
+                                continue;
+                            }
+
+                            let volume =
+                                boundary_method.boundary_volume(
+                                    boundaries[c.j_model].volumes[c.j],
+                                    fluid1.volumes[i],
+                                ) * boundary_volume_scale(boundary_volume_scales, fluid_id, i);
+                            let coeff = kb * volume * fluid1.rest_density(i);
                             let delta = c.gradient * (coeff * timestep.inv_dt());
 
-                            *velocity_change -= delta;
+                            sum.sub(delta);
 
                             // Apply the force to the boundary too.
                             let particle_mass = fluid1.particle_mass(c.i);
@@ -272,6 +518,8 @@ where
                                 .apply_force(c.j, delta * (timestep.inv_dt() * particle_mass));
                         }
                     }
+
+                    *velocity_change = sum.value();
                 })
         }
     }
@@ -282,12 +530,20 @@ where
         fluid_boundary_contacts: &[ParticlesContacts],
         fluids: &[Fluid],
         boundaries: &[Boundary],
-    ) -> Real {
+        track_max_error: bool,
+    ) -> (Real, Real) {
         let velocity_changes = &self.velocity_changes;
         let min_neighbors_for_divergence_solve = self.min_neighbors_for_divergence_solve;
+        let boundary_method = self.boundary_method;
+        let boundary_volume_scales = &self.boundary_volume_scales;
         let mut max_error = na::zero::<Real>();
+        let mut max_particle_error = na::zero::<Real>();
 
         for fluid_id in 0..fluids.len() {
+            if !is_fluid_solved(self.solved_fluids.as_deref(), fluid_id) {
+                continue;
+            }
+
             let fluid_fluid_contacts = &fluid_fluid_contacts[fluid_id];
             let fluid_boundary_contacts = &fluid_boundary_contacts[fluid_id];
             let divergences_i = &mut self.divergences[fluid_id];
@@ -336,23 +592,34 @@ where
                         // FIXME: take the velocity of j too?
 
                         let dvel = v_i;
-                        *divergence_i += dvel.dot(&c.gradient)
-                            * boundaries[c.j_model].volumes[c.j]
-                            * fluid_i.density0;
+                        let volume = boundary_method.boundary_volume(
+                            boundaries[c.j_model].volumes[c.j],
+                            fluid_i.volumes[i],
+                        ) * boundary_volume_scale(boundary_volume_scales, fluid_id, i);
+                        *divergence_i += dvel.dot(&c.gradient) * volume * fluid_i.rest_density(i);
                     }
 
-                    *divergence_i = divergence_i.max(na::zero::<Real>());
-                    *divergence_i / fluid_i.density0
+                    // NOTE: don't use `max` which would hide NaNs.
+                    if *divergence_i < na::zero::<Real>() {
+                        *divergence_i = na::zero::<Real>();
+                    }
+                    *divergence_i / fluid_i.rest_density(i)
                 });
-            let err = par_reduce_sum!(na::zero::<Real>(), it);
+            let (err, particle_error) = if track_max_error {
+                par_reduce_sum_max!(na::zero::<Real>(), it)
+            } else {
+                (par_reduce_sum!(na::zero::<Real>(), it), na::zero::<Real>())
+            };
+            max_particle_error = max_particle_error.max(particle_error);
 
             let nparts = fluids[fluid_id].num_particles();
             if nparts != 0 {
-                max_error = max_error.max(err / na::convert::<_, Real>(nparts as f64));
+                max_error =
+                    max_error_or_nan(max_error, err / na::convert::<_, Real>(nparts as f64));
             }
         }
 
-        max_error
+        (max_error, max_particle_error)
     }
 
     fn compute_velocity_changes_for_divergence(
@@ -365,13 +632,23 @@ where
     ) {
         let alphas = &self.alphas;
         let divergences = &self.divergences;
+        let summation = self.summation;
+        let boundary_method = self.boundary_method;
+        let boundary_volume_scales = &self.boundary_volume_scales;
 
         for (fluid_id, _fluid1) in fluids.iter().enumerate() {
+            if !is_fluid_solved(self.solved_fluids.as_deref(), fluid_id) {
+                continue;
+            }
+
             par_iter_mut!(self.velocity_changes[fluid_id])
+                .zip(par_iter_mut!(self.pressure_coefficients[fluid_id]))
                 .enumerate()
-                .for_each(|(i, velocity_change)| {
+                .for_each(|(i, (velocity_change, coefficient))| {
                     let fluid1 = &fluids[fluid_id];
                     let ki = divergences[fluid_id][i] * alphas[fluid_id][i];
+                    *coefficient += ki;
+                    let mut sum = CompensatedSum::starting_at(summation, *velocity_change);
 
                     for c in fluid_fluid_contacts[fluid_id]
                         .particle_contacts(i)
@@ -384,7 +661,7 @@ where
 
                         // Compute velocity change.
                         let coeff = -(ki + kj) * fluid2.particle_mass(c.j);
-                        *velocity_change += c.gradient * coeff;
+                        sum.add(c.gradient * coeff);
                     }
 
                     for c in fluid_boundary_contacts[fluid_id]
@@ -394,41 +671,103 @@ where
                         .iter()
                     {
                         let boundary2 = &boundaries[c.j_model];
-
-                        // Compute velocity change.
-                        let coeff = -ki * boundaries[c.j_model].volumes[c.j] * fluid1.density0;
+                        let volume = boundary_method
+                            .boundary_volume(boundary2.volumes[c.j], fluid1.volumes[i])
+                            * boundary_volume_scale(boundary_volume_scales, fluid_id, i);
+
+                        // Compute velocity change. The stiffness is mirrored even for
+                        // `BoundaryMethod::FrozenFluid`: extrapolating it would push the particles
+                        // of the free surface along the walls.
+                        let coeff = -ki * volume * fluid1.rest_density(i);
                         let delta = c.gradient * coeff;
-                        *velocity_change += delta;
+                        sum.add(delta);
 
                         // Apply the force to the boundary too.
                         let particle_mass = fluid1.particle_mass(c.i);
                         boundary2.apply_force(c.j, delta * (-timestep.inv_dt() * particle_mass));
                     }
+
+                    *velocity_change = sum.value();
                 })
         }
     }
 
-    fn update_positions(&mut self, timestep: &TimestepManager, fluids: &mut [Fluid]) {
-        for (fluid, velocity_changes) in fluids.iter_mut().zip(self.velocity_changes.iter()) {
+    // Moves the particles of the solved fluids, except the frozen ones.
+    //
+    // NOTE: the positions must be advanced with the velocities at the end of the substep (semi-implicit
+    // Euler) because the pressure solve computes the velocity changes making the densities at these
+    // positions match the rest densities. A kick-drift-kick scheme would move the particles elsewhere.
+    fn update_positions(
+        &mut self,
+        timestep: &TimestepManager,
+        contact_manager: &ContactManager,
+        kernel_radius: Real,
+        fluids: &mut [Fluid],
+    ) {
+        for (fluid_id, (fluid, velocity_changes)) in fluids
+            .iter_mut()
+            .zip(self.velocity_changes.iter())
+            .enumerate()
+        {
+            if !is_fluid_solved(self.solved_fluids.as_deref(), fluid_id) {
+                continue;
+            }
+
             par_iter_mut!(fluid.positions)
                 .zip(par_iter!(fluid.velocities))
                 .zip(par_iter!(velocity_changes))
                 .for_each(|((pos, vel), delta)| {
-                    *pos += (*vel + delta) * timestep.dt();
+                    if !contact_manager.is_frozen(kernel_radius, pos) {
+                        *pos += (*vel + delta) * timestep.dt();
+                    }
                 })
         }
     }
 
-    fn update_velocities(&mut self, fluids: &mut [Fluid]) {
-        for (fluid, delta) in fluids.iter_mut().zip(self.velocity_changes.iter()) {
+    // Applies the velocity changes to the particles of the solved fluids, except the frozen ones.
+    fn update_velocities(
+        &mut self,
+        contact_manager: &ContactManager,
+        kernel_radius: Real,
+        fluids: &mut [Fluid],
+    ) {
+        for (fluid_id, (fluid, delta)) in fluids
+            .iter_mut()
+            .zip(self.velocity_changes.iter())
+            .enumerate()
+        {
+            if !is_fluid_solved(self.solved_fluids.as_deref(), fluid_id) {
+                continue;
+            }
+
             par_iter_mut!(fluid.velocities)
+                .zip(par_iter!(fluid.positions))
                 .zip(par_iter!(delta))
-                .for_each(|(vel, delta)| {
-                    *vel += delta;
+                .for_each(|((vel, pos), delta)| {
+                    if !contact_manager.is_frozen(kernel_radius, pos) {
+                        *vel += delta;
+                    }
                 })
         }
     }
 
+    fn report_iteration(
+        &mut self,
+        phase: SolvePhase,
+        iteration: usize,
+        average_error: Real,
+        max_error: Real,
+    ) {
+        if let Some(callback) = &mut self.iteration_callback {
+            callback(&IterationState {
+                phase,
+                iteration,
+                average_error,
+                max_error,
+            });
+        }
+    }
+
     fn pressure_solve(
         &mut self,
         timestep: &TimestepManager,
@@ -436,20 +775,27 @@ where
         fluids: &mut [Fluid],
         boundaries: &[Boundary],
     ) {
+        self.last_solve_stats.num_pressure_iterations = self.max_pressure_iter;
+
         for i in 0..self.max_pressure_iter {
-            let avg_err = self.compute_predicted_densities(
+            let (avg_err, max_particle_err) = self.compute_predicted_densities(
                 timestep,
                 &contact_manager.fluid_fluid_contacts,
                 &contact_manager.fluid_boundary_contacts,
                 fluids,
                 boundaries,
+                self.iteration_callback.is_some(),
             );
+            self.last_solve_stats.density_error = avg_err;
+            self.report_iteration(SolvePhase::Density, i, avg_err, max_particle_err);
 
             if avg_err <= self.max_density_error && i >= self.min_pressure_iter {
-                //                println!(
-                //                    "Average density error: {}, break after niters: {}",
-                //                    avg_err, i
-                //                );
+                self.last_solve_stats.num_pressure_iterations = i;
+                log::trace!(
+                    "DFSPH: average density error: {}, break after niters: {}",
+                    avg_err,
+                    i
+                );
                 break;
             }
 
@@ -461,6 +807,16 @@ where
                 boundaries,
             );
         }
+
+        if self.max_pressure_iter > 0
+            && self.last_solve_stats.density_error > self.max_density_error
+        {
+            log::warn!(
+                "DFSPH: the density solve did not converge after {} iterations (average density error: {}).",
+                self.max_pressure_iter,
+                self.last_solve_stats.density_error
+            );
+        }
     }
 
     fn divergence_solve(
@@ -471,21 +827,28 @@ where
         fluids: &mut [Fluid],
         boundaries: &[Boundary],
     ) {
+        self.last_solve_stats.num_divergence_iterations = self.max_divergence_iter;
+        let max_err = self.max_divergence_error * timestep.inv_dt() * na::convert::<_, Real>(0.01);
+
         for i in 0..self.max_divergence_iter {
-            let avg_err = self.compute_divergences(
+            let (avg_err, max_particle_err) = self.compute_divergences(
                 &contact_manager.fluid_fluid_contacts,
                 &contact_manager.fluid_boundary_contacts,
                 fluids,
                 boundaries,
+                self.iteration_callback.is_some(),
             );
+            self.last_solve_stats.divergence_error = avg_err;
+            self.report_iteration(SolvePhase::Divergence, i, avg_err, max_particle_err);
 
-            let max_err =
-                self.max_divergence_error * timestep.inv_dt() * na::convert::<_, Real>(0.01);
             if avg_err <= max_err && i >= self.min_divergence_iter {
-                //                println!(
-                //                    "Average divergence error: {} <= {}, break after niters: {}",
-                //                    avg_err, max_err, i
-                //                );
+                self.last_solve_stats.num_divergence_iterations = i;
+                log::trace!(
+                    "DFSPH: average divergence error: {} <= {}, break after niters: {}",
+                    avg_err,
+                    max_err,
+                    i
+                );
                 break;
             }
 
@@ -500,6 +863,14 @@ where
             );
             counters.custom.pause();
         }
+
+        if self.max_divergence_iter > 0 && self.last_solve_stats.divergence_error > max_err {
+            log::warn!(
+                "DFSPH: the divergence solve did not converge after {} iterations (average divergence error: {}).",
+                self.max_divergence_iter,
+                self.last_solve_stats.divergence_error
+            );
+        }
     }
 
     fn integrate_and_clear_accelerations(
@@ -507,7 +878,16 @@ where
         timestep: &TimestepManager,
         fluids: &mut [Fluid],
     ) {
-        for (velocity_changes, fluid) in self.velocity_changes.iter_mut().zip(fluids.iter_mut()) {
+        for (fluid_id, (velocity_changes, fluid)) in self
+            .velocity_changes
+            .iter_mut()
+            .zip(fluids.iter_mut())
+            .enumerate()
+        {
+            if !is_fluid_solved(self.solved_fluids.as_deref(), fluid_id) {
+                continue;
+            }
+
             par_iter_mut!(velocity_changes)
                 .zip(par_iter_mut!(fluid.accelerations))
                 .for_each(|(velocity_change, acceleration)| {
@@ -516,6 +896,85 @@ where
                 })
         }
     }
+
+    // Adds the gravity to the source term of the density solve, remembering the velocity
+    // changes due to non-pressure forces only.
+    fn add_gravity_to_pressure_source(
+        &mut self,
+        timestep: &TimestepManager,
+        gravity: &Vector<Real>,
+        fluids: &[Fluid],
+    ) {
+        self.nonpressure_velocity_changes
+            .resize(self.velocity_changes.len(), Vec::new());
+
+        for (fluid_id, ((velocity_changes, nonpressure_velocity_changes), fluid)) in self
+            .velocity_changes
+            .iter_mut()
+            .zip(self.nonpressure_velocity_changes.iter_mut())
+            .zip(fluids.iter())
+            .enumerate()
+        {
+            if !is_fluid_solved(self.solved_fluids.as_deref(), fluid_id) {
+                continue;
+            }
+
+            let gravity_change = gravity * (fluid.gravity_scale * timestep.dt());
+            nonpressure_velocity_changes.clear();
+            nonpressure_velocity_changes.extend_from_slice(velocity_changes);
+            velocity_changes
+                .iter_mut()
+                .for_each(|velocity_change| *velocity_change += gravity_change);
+        }
+    }
+
+    // Stops the fluids where the pressure balances gravity at every particle, and that are
+    // almost at rest.
+    fn apply_hydrostatic_equilibrium(
+        &mut self,
+        timestep: &TimestepManager,
+        gravity: &Vector<Real>,
+        fluids: &[Fluid],
+    ) {
+        for (fluid_id, ((velocity_changes, nonpressure_velocity_changes), fluid)) in self
+            .velocity_changes
+            .iter_mut()
+            .zip(self.nonpressure_velocity_changes.iter())
+            .zip(fluids.iter())
+            .enumerate()
+        {
+            if !is_fluid_solved(self.solved_fluids.as_deref(), fluid_id) {
+                continue;
+            }
+
+            let gravity_change = gravity * (fluid.gravity_scale * timestep.dt());
+            let threshold = gravity_change.norm() * self.hydrostatic_tolerance;
+
+            // The fluid is at equilibrium if the pressure pushes each particle against gravity, and
+            // every particle is almost at rest. Stopping only some particles, or keeping the
+            // velocities without the pressure correcting their drift, would disturb the fluid.
+            let at_equilibrium = velocity_changes
+                .iter()
+                .zip(nonpressure_velocity_changes.iter())
+                .zip(fluid.velocities.iter())
+                .all(
+                    |((velocity_change, nonpressure_velocity_change), velocity)| {
+                        let balance_change = *velocity_change - nonpressure_velocity_change;
+                        let pressure_change = balance_change - gravity_change;
+                        balance_change.norm() <= threshold
+                            && pressure_change.dot(&gravity_change) < na::zero::<Real>()
+                            && (velocity + nonpressure_velocity_change).norm() <= threshold
+                    },
+                );
+
+            if at_equilibrium {
+                velocity_changes
+                    .iter_mut()
+                    .zip(fluid.velocities.iter())
+                    .for_each(|(velocity_change, velocity)| *velocity_change = -velocity);
+            }
+        }
+    }
 }
 
 impl<KernelDensity, KernelGradient> PressureSolver for DFSPHSolver<KernelDensity, KernelGradient>
@@ -531,6 +990,17 @@ where
         self.divergences.resize(fluids.len(), Vec::new());
         self.velocity_changes.resize(fluids.len(), Vec::new());
 
+        // The densities of reordered particles cannot be advanced from their previous values.
+        let generations = fluids.iter().map(|fluid| fluid.permutation_generation());
+
+        if !self.permutation_generations.iter().copied().eq(generations) {
+            self.densities_since_resync = 0;
+            self.permutation_generations = fluids
+                .iter()
+                .map(|fluid| fluid.permutation_generation())
+                .collect();
+        }
+
         for (fluid, alphas, densities, predicted_densities, divergences, velocity_changes) in
             itertools::multizip((
                 fluids.iter(),
@@ -541,6 +1011,10 @@ where
                 self.velocity_changes.iter_mut(),
             ))
         {
+            if densities.len() != fluid.num_particles() || fluid.num_deleted_particles() != 0 {
+                self.densities_since_resync = 0;
+            }
+
             alphas.resize(fluid.num_particles(), na::zero::<Real>());
             densities.resize(fluid.num_particles(), na::zero::<Real>());
             predicted_densities.resize(fluid.num_particles(), na::zero::<Real>());
@@ -571,35 +1045,39 @@ where
         fluids: &mut [Fluid],
         boundaries: &[Boundary],
     ) {
-        for fluid in fluids.iter_mut() {
+        for (fluid_id, fluid) in fluids.iter_mut().enumerate() {
+            if !is_fluid_solved(self.solved_fluids.as_deref(), fluid_id) {
+                continue;
+            }
+
+            let fluid_gravity = gravity * fluid.gravity_scale;
             par_iter_mut!(fluid.accelerations).for_each(|acceleration| {
-                *acceleration += gravity;
+                *acceleration += fluid_gravity;
             })
         }
 
-        for (fluid, fluid_fluid_contacts, fluid_boundary_contacts, densities) in
+        for (fluid_id, (fluid, fluid_fluid_contacts, fluid_boundary_contacts, densities)) in
             itertools::multizip((
                 &mut *fluids,
                 &contact_manager.fluid_fluid_contacts,
                 &contact_manager.fluid_boundary_contacts,
                 &self.densities,
             ))
+            .enumerate()
         {
-            let mut forces = std::mem::replace(&mut fluid.nonpressure_forces, Vec::new());
-
-            for np_force in &mut forces {
-                np_force.solve(
-                    timestep,
-                    kernel_radius,
-                    fluid_fluid_contacts,
-                    fluid_boundary_contacts,
-                    fluid,
-                    boundaries,
-                    densities,
-                );
+            if !is_fluid_solved(self.solved_fluids.as_deref(), fluid_id) {
+                continue;
             }
 
-            fluid.nonpressure_forces = forces;
+            helper::solve_nonpressure_forces(
+                timestep,
+                kernel_radius,
+                fluid_fluid_contacts,
+                fluid_boundary_contacts,
+                fluid,
+                boundaries,
+                densities,
+            );
         }
     }
 
@@ -610,8 +1088,11 @@ where
         fluids: &[Fluid],
         boundaries: &[Boundary],
     ) {
+        let anisotropy = *contact_manager.kernel_anisotropy();
+
         helper::update_fluid_contacts::<KernelDensity, KernelGradient>(
             kernel_radius,
+            &anisotropy,
             &mut contact_manager.fluid_fluid_contacts,
             &mut contact_manager.fluid_boundary_contacts,
             fluids,
@@ -620,6 +1101,7 @@ where
 
         helper::update_boundary_contacts::<KernelDensity, KernelGradient>(
             kernel_radius,
+            &anisotropy,
             &mut contact_manager.boundary_boundary_contacts,
             boundaries,
         );
@@ -632,12 +1114,33 @@ where
         boundaries: &mut [Boundary],
     ) {
         self.compute_boundary_volumes(&contact_manager.boundary_boundary_contacts, boundaries);
+        let dt = std::mem::replace(&mut self.density_update_dt, na::zero::<Real>());
+
+        if self.densities_since_resync != 0
+            && self.densities_since_resync < self.density_resync_interval
+        {
+            self.densities_since_resync += 1;
+            self.advance_densities(contact_manager, fluids, boundaries, dt);
+            return;
+        }
+
+        self.densities_since_resync = 1;
+        let summation = self.summation;
+        let boundary_method = self.boundary_method;
+        let corrected = self.boundary_density_correction && boundary_method.uses_boundary_volumes();
+        self.boundary_volume_scales.resize(fluids.len(), Vec::new());
 
         for fluid_id in 0..fluids.len() {
+            let scales = &mut self.boundary_volume_scales[fluid_id];
+            scales.clear();
+            scales.resize(fluids[fluid_id].num_particles(), na::one::<Real>());
+
             par_iter_mut!(self.densities[fluid_id])
+                .zip(par_iter_mut!(scales))
                 .enumerate()
-                .for_each(|(i, density)| {
-                    *density = na::zero::<Real>();
+                .for_each(|(i, (density, scale))| {
+                    let mut fluid_sum = CompensatedSum::new(summation);
+                    let mut boundary_sum = CompensatedSum::new(summation);
 
                     for c in contact_manager.fluid_fluid_contacts[fluid_id]
                         .particle_contacts(i)
@@ -645,7 +1148,7 @@ where
                         .unwrap()
                         .iter()
                     {
-                        *density += fluids[c.j_model].particle_mass(c.j) * c.weight;
+                        fluid_sum.add(fluids[c.j_model].particle_mass(c.j) * c.weight);
                     }
 
                     for c in contact_manager.fluid_boundary_contacts[fluid_id]
@@ -654,11 +1157,26 @@ where
                         .unwrap()
                         .iter()
                     {
-                        *density += boundaries[c.j_model].volumes[c.j]
-                            * fluids[c.i_model].density0
-                            * c.weight;
+                        let fluid = &fluids[c.i_model];
+                        let volume = boundary_method.boundary_volume(
+                            boundaries[c.j_model].volumes[c.j],
+                            fluid.volumes[c.i],
+                        );
+                        boundary_sum.add(volume * fluid.rest_density(c.i) * c.weight);
+                    }
+
+                    let fluid_density = fluid_sum.value();
+                    let boundary_density = boundary_sum.value();
+
+                    // Complete the density of the particles near the walls up to their rest density.
+                    if corrected && boundary_density > na::zero::<Real>() {
+                        let missing = fluids[fluid_id].rest_density(i) - fluid_density;
+                        *scale = (missing / boundary_density)
+                            .max(na::one::<Real>())
+                            .min(na::convert::<_, Real>(MAX_BOUNDARY_VOLUME_SCALE));
                     }
 
+                    *density = fluid_density + boundary_density * *scale;
                     assert!(!density.is_zero());
                 })
         }
@@ -675,8 +1193,17 @@ where
         boundaries: &[Boundary],
     ) {
         counters.solver.pressure_resolution_time.resume();
+        self.density_update_dt += timestep.dt();
+
+        self.pressure_coefficients.resize(fluids.len(), Vec::new());
+
+        for (coefficients, fluid) in self.pressure_coefficients.iter_mut().zip(fluids.iter()) {
+            coefficients.clear();
+            coefficients.resize(fluid.num_particles(), na::zero::<Real>());
+        }
 
         self.compute_alphas(
+            kernel_radius,
             &contact_manager.fluid_fluid_contacts,
             &contact_manager.fluid_boundary_contacts,
             fluids,
@@ -685,16 +1212,22 @@ where
 
         self.divergence_solve(counters, timestep, contact_manager, fluids, boundaries);
 
-        self.update_velocities(fluids);
+        self.update_velocities(contact_manager, kernel_radius, fluids);
         self.velocity_changes
             .iter_mut()
             .for_each(|vs| vs.iter_mut().for_each(|v| v.fill(na::zero::<Real>())));
 
+        let explicit_gravity = if self.gravity_in_pressure_solve {
+            Vector::zeros()
+        } else {
+            *gravity
+        };
+
         self.predict_advection(
             timestep,
             kernel_radius,
             contact_manager,
-            gravity,
+            &explicit_gravity,
             fluids,
             boundaries,
         );
@@ -702,8 +1235,461 @@ where
         timestep.advance(fluids);
 
         self.integrate_and_clear_accelerations(timestep, fluids);
+
+        if self.gravity_in_pressure_solve {
+            self.add_gravity_to_pressure_source(timestep, gravity, fluids);
+        }
+
         self.pressure_solve(timestep, contact_manager, fluids, boundaries);
-        self.update_positions(timestep, fluids);
+
+        if self.gravity_in_pressure_solve {
+            self.apply_hydrostatic_equilibrium(timestep, gravity, fluids);
+        }
+
+        self.update_positions(timestep, contact_manager, kernel_radius, fluids);
+
+        let pressure_coefficients = &self.pressure_coefficients;
+        apply_pressure_reactions(
+            self.solved_fluids.as_deref(),
+            timestep,
+            &contact_manager.fluid_fluid_contacts,
+            |fluid_id, i| pressure_coefficients[fluid_id][i],
+            fluids,
+        );
         counters.solver.pressure_resolution_time.pause();
     }
+
+    fn densities(&self) -> Option<&[Vec<Real>]> {
+        Some(&self.densities)
+    }
+
+    fn as_stateful(&self) -> Option<&dyn StatefulPressureSolver> {
+        Some(self)
+    }
+
+    fn as_stateful_mut(&mut self) -> Option<&mut dyn StatefulPressureSolver> {
+        Some(self)
+    }
+
+    fn as_configurable(&self) -> Option<&dyn ConfigurablePressureSolver> {
+        Some(self)
+    }
+
+    fn as_configurable_mut(&mut self) -> Option<&mut dyn ConfigurablePressureSolver> {
+        Some(self)
+    }
+
+    fn as_partial_mut(&mut self) -> Option<&mut dyn PartialPressureSolver> {
+        Some(self)
+    }
+}
+
+impl<KernelDensity, KernelGradient> ConfigurablePressureSolver
+    for DFSPHSolver<KernelDensity, KernelGradient>
+where
+    KernelDensity: Kernel,
+    KernelGradient: Kernel,
+{
+    fn last_solve_stats(&self) -> SolveStats {
+        self.last_solve_stats
+    }
+
+    fn boundary_method(&self) -> BoundaryMethod {
+        self.boundary_method
+    }
+
+    fn set_boundary_method(&mut self, method: BoundaryMethod) {
+        self.boundary_method = method;
+    }
+
+    /// Sets `self.max_density_error` to the tolerance, as the density solve already measures the
+    /// average relative density error, and `self.max_divergence_error` to 100 times the tolerance,
+    /// as the divergence solve measures the relative density change over one substep in percent.
+    fn set_incompressibility_tolerance(&mut self, tolerance: IncompressibilityTolerance) {
+        self.max_density_error = tolerance.max_relative_density_error;
+        self.max_divergence_error =
+            tolerance.max_relative_density_error * na::convert::<_, Real>(100.0);
+    }
+
+    fn set_iteration_callback(&mut self, callback: Option<Box<IterationCallback>>) {
+        self.iteration_callback = callback;
+    }
+}
+
+impl<KernelDensity, KernelGradient> StatefulPressureSolver
+    for DFSPHSolver<KernelDensity, KernelGradient>
+where
+    KernelDensity: Kernel,
+    KernelGradient: Kernel,
+{
+    fn rescale(&mut self, factor: Real) {
+        for velocity_changes in self
+            .velocity_changes
+            .iter_mut()
+            .chain(self.nonpressure_velocity_changes.iter_mut())
+        {
+            velocity_changes.iter_mut().for_each(|v| *v *= factor);
+        }
+
+        // The rest densities changed.
+        self.densities_since_resync = 0;
+    }
+
+    fn damp_velocity_changes(&mut self, factor: Real) {
+        // The pressure velocity changes are only applied at the beginning of the next step.
+        for velocity_changes in &mut self.velocity_changes {
+            velocity_changes.iter_mut().for_each(|v| *v *= factor);
+        }
+    }
+
+    fn reset(&mut self) {
+        // Clear the buffers without releasing their memory.
+        self.alphas.iter_mut().for_each(Vec::clear);
+        self.densities.iter_mut().for_each(Vec::clear);
+        self.predicted_densities.iter_mut().for_each(Vec::clear);
+        self.divergences.iter_mut().for_each(Vec::clear);
+        self.velocity_changes.iter_mut().for_each(Vec::clear);
+        self.boundary_volume_scales.iter_mut().for_each(Vec::clear);
+        self.nonpressure_velocity_changes
+            .iter_mut()
+            .for_each(Vec::clear);
+        self.last_solve_stats = SolveStats::default();
+        self.densities_since_resync = 0;
+        self.density_update_dt = na::zero::<Real>();
+    }
+
+    fn shrink_to_fit(&mut self) {
+        for buffers in [
+            &mut self.alphas,
+            &mut self.densities,
+            &mut self.predicted_densities,
+            &mut self.divergences,
+            &mut self.boundary_stiffnesses,
+            &mut self.boundary_volume_scales,
+            &mut self.pressure_coefficients,
+        ] {
+            buffers.iter_mut().for_each(Vec::shrink_to_fit);
+        }
+
+        for buffers in [
+            &mut self.velocity_changes,
+            &mut self.nonpressure_velocity_changes,
+        ] {
+            buffers.iter_mut().for_each(Vec::shrink_to_fit);
+        }
+    }
+
+    fn export_state(&self) -> SolverState {
+        // The velocity change and the density of each particle.
+        let buffers = self
+            .velocity_changes
+            .iter()
+            .zip(self.densities.iter())
+            .map(|(changes, densities)| {
+                changes
+                    .iter()
+                    .zip(densities.iter())
+                    .flat_map(|(v, density)| v.iter().chain(std::iter::once(density)).copied())
+                    .collect()
+            })
+            .collect();
+        let globals = vec![self.densities_since_resync as Real, self.density_update_dt];
+        SolverState::new(DIM + 1, buffers).with_globals(globals)
+    }
+
+    fn import_state(
+        &mut self,
+        fluids: &[Fluid],
+        state: &SolverState,
+    ) -> Result<(), SolverStateError> {
+        state.validate(DIM + 1, fluids)?;
+
+        let [densities_since_resync, density_update_dt] = state.globals() else {
+            return Err(SolverStateError::LayoutMismatch);
+        };
+
+        self.densities_since_resync = *densities_since_resync as usize;
+        self.density_update_dt = *density_update_dt;
+        (self.velocity_changes, self.densities) = state
+            .fluid_buffers()
+            .iter()
+            .map(|buffer| {
+                buffer
+                    .chunks_exact(DIM + 1)
+                    .map(|chunk| (Vector::from_column_slice(&chunk[..DIM]), chunk[DIM]))
+                    .unzip()
+            })
+            .unzip();
+        Ok(())
+    }
+}
+
+impl<KernelDensity, KernelGradient> PartialPressureSolver
+    for DFSPHSolver<KernelDensity, KernelGradient>
+where
+    KernelDensity: Kernel,
+    KernelGradient: Kernel,
+{
+    fn set_solved_fluids(&mut self, solved_fluids: Option<Vec<bool>>) {
+        self.solved_fluids = solved_fluids;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DFSPHSolver;
+    use crate::kernel::CubicSplineKernel;
+    use crate::math::{Point, Real, Vector};
+    use crate::object::{Boundary, Fluid};
+    use crate::solver::XSPHViscosity;
+    use crate::testbed::particle_block;
+    use crate::{LiquidWorld, TimestepManager};
+
+    #[test]
+    fn continuity_densities_resync_periodically() {
+        use crate::math::DIM;
+        // A block of fluid falling into a tank.
+        let particle_radius = 0.05;
+        let spacing = particle_radius * 2.0;
+        let size: usize = 4;
+        let positions: Vec<_> = (0..size * size * if DIM == 2 { 1 } else { size })
+            .map(|i| {
+                let coords = Vector::from_fn(|k, _| (i / size.pow(k as u32) % size) as Real + 1.0);
+                Point::from(coords * spacing)
+            })
+            .collect();
+        let half_extents = Vector::repeat(spacing * (size + 1) as Real / 2.0);
+        let make_world = |interval| {
+            let mut solver = DFSPHSolver::<CubicSplineKernel>::new();
+            solver.density_resync_interval = interval;
+            let mut world = LiquidWorld::new(solver, particle_radius, 2.0);
+            let fluid = world.add_fluid(Fluid::new(positions.clone(), particle_radius, 1000.0));
+            let _ = world.add_boundary(Boundary::cuboid(
+                &Point::from(half_extents),
+                &half_extents,
+                particle_radius,
+                2,
+            ));
+            (world, fluid)
+        };
+
+        let (mut world, fluid) = make_world(3);
+        // Sums the densities at the current positions of `world`.
+        let (mut reference, reference_fluid) = make_world(1);
+        reference.set_paused(true);
+        let mut num_advanced = 0;
+
+        for k in 0..9 {
+            let fluid_k = world.fluids().get(fluid).unwrap();
+            let reference_k = reference.fluids_mut().get_mut(reference_fluid).unwrap();
+            reference_k.positions.clone_from(&fluid_k.positions);
+            reference_k.velocities.clone_from(&fluid_k.velocities);
+            reference.step(0.01, &Vector::zeros());
+            world.step(0.01, &(Vector::y() * -9.81));
+
+            let densities = world.fluid_densities(fluid).unwrap();
+            let expected = reference.fluid_densities(reference_fluid).unwrap();
+
+            for (density, expected) in densities.iter().zip(expected.iter()) {
+                let error = (density - expected).abs() / expected;
+
+                if k % 3 == 0 {
+                    assert!(error < 1.0e-5);
+                } else {
+                    // The continuity equation drifts a bit between two summations.
+                    assert!(error < 0.05);
+                    num_advanced += (error > 1.0e-5) as usize;
+                }
+            }
+        }
+
+        assert!(num_advanced > 0);
+    }
+
+    #[test]
+    fn continuity_densities_resync_after_z_sort() {
+        use crate::math::DIM;
+        // A block of fluid given in reverse Morton order, so that `z_sort` reorders all its particles.
+        let particle_radius = 0.05;
+        let size: usize = 4;
+        let mut positions: Vec<_> = (0..size.pow(DIM as u32))
+            .map(|i| {
+                let coords = Vector::from_fn(|k, _| (i / size.pow(k as u32) % size) as Real);
+                Point::from(coords * particle_radius * 2.0)
+            })
+            .collect();
+        let order = crate::z_order::compute_points_z_order(&positions);
+        positions = order.iter().rev().map(|i| positions[*i]).collect();
+
+        let make_world = |interval| {
+            let mut solver = DFSPHSolver::<CubicSplineKernel>::new();
+            solver.density_resync_interval = interval;
+            let mut world = LiquidWorld::new(solver, particle_radius, 2.0);
+            let fluid = world.add_fluid(Fluid::new(positions.clone(), particle_radius, 1000.0));
+            (world, fluid)
+        };
+
+        let (mut world, fluid) = make_world(3);
+        let (mut reference, reference_fluid) = make_world(1);
+        reference.set_paused(true);
+        let gravity = Vector::y() * -9.81;
+        world.step(0.01, &gravity);
+
+        let fluid_mut = world.fluids_mut().get_mut(fluid).unwrap();
+        let generation = fluid_mut.permutation_generation();
+        fluid_mut.z_sort();
+        assert_eq!(fluid_mut.permutation_generation(), generation + 1);
+
+        // The densities are summed again instead of being advanced with the permuted velocities.
+        let fluid_k = world.fluids().get(fluid).unwrap();
+        let reference_k = reference.fluids_mut().get_mut(reference_fluid).unwrap();
+        reference_k.positions.clone_from(&fluid_k.positions);
+        reference_k.velocities.clone_from(&fluid_k.velocities);
+        reference.step(0.01, &Vector::zeros());
+        world.step(0.01, &gravity);
+
+        let densities = world.fluid_densities(fluid).unwrap();
+        let expected = reference.fluid_densities(reference_fluid).unwrap();
+
+        for (density, expected) in densities.iter().zip(expected.iter()) {
+            assert!((density - expected).abs() < 1.0e-5 * expected);
+        }
+    }
+
+    #[test]
+    fn boundary_density_correction_keeps_the_fluid_off_the_walls() {
+        let particle_radius = 0.05;
+        let spacing = particle_radius * 2.0;
+        // The height of the lowest particle of a shallow pool at rest.
+        let pool_bottom = |corrected: bool| {
+            let mut solver = DFSPHSolver::<CubicSplineKernel>::new();
+            solver.boundary_density_correction = corrected;
+            let mut world = LiquidWorld::new(solver, particle_radius, 2.0);
+            let half_extents = Vector::repeat(0.4);
+            let _ = world.add_boundary(Boundary::cuboid(
+                &Point::from(half_extents),
+                &half_extents,
+                particle_radius,
+                2,
+            ));
+            let positions = particle_block(6, 3, spacing, Vector::repeat(spacing * 1.5));
+            let fluid = world.add_fluid(Fluid::new(positions, particle_radius, 1000.0));
+
+            for _ in 0..50 {
+                world.step(0.01, &(Vector::y() * -9.81));
+            }
+
+            world.fluids()[fluid]
+                .positions
+                .iter()
+                .map(|pt| pt.y)
+                .fold(Real::MAX, Real::min)
+        };
+
+        // The under-dense particles sink toward the floor, unless their density is corrected.
+        assert!(pool_bottom(false) < spacing * 0.75);
+        assert!((pool_bottom(true) - spacing).abs() < spacing * 0.2);
+    }
+
+    // The velocities of particles with the given velocities and pressure velocity changes, once the
+    // gravity of a substep and the hydrostatic equilibrium of a DFSPH solver are applied.
+    fn velocities_at_hydrostatic_equilibrium(
+        velocities: &[Vector<Real>],
+        pressure_changes: &[Vector<Real>],
+    ) -> Vec<Vector<Real>> {
+        let particle_radius = 0.05;
+        let dt = 0.01;
+        let gravity = Vector::y() * -9.81;
+        let timestep = TimestepManager::new(particle_radius).with_dt(dt);
+        let mut fluid = Fluid::new(
+            vec![Point::origin(); velocities.len()],
+            particle_radius,
+            1000.0,
+        );
+        fluid.velocities = velocities.to_vec();
+
+        let mut solver = DFSPHSolver::<CubicSplineKernel>::new();
+        solver.gravity_in_pressure_solve = true;
+        solver.nonpressure_velocity_changes = vec![vec![Vector::zeros(); velocities.len()]];
+        solver.velocity_changes = vec![pressure_changes
+            .iter()
+            .map(|change| change + gravity * dt)
+            .collect()];
+        solver.apply_hydrostatic_equilibrium(&timestep, &gravity, std::slice::from_ref(&fluid));
+
+        velocities
+            .iter()
+            .zip(&solver.velocity_changes[0])
+            .map(|(velocity, change)| velocity + change)
+            .collect()
+    }
+
+    #[test]
+    fn hydrostatic_equilibrium_stops_the_supported_fluids() {
+        let gravity_change = Vector::y() * (-9.81 * 0.01);
+        // Particles almost at rest, supported by a pressure almost balancing gravity.
+        let slow = Vector::x() * 1.0e-4;
+        let pressure_changes = [-gravity_change * 0.995, -gravity_change * 1.005];
+        let velocities = velocities_at_hydrostatic_equilibrium(&[slow, -slow], &pressure_changes);
+        assert_eq!(velocities, [Vector::zeros(); 2]);
+
+        // The particles keep the residual of gravity and pressure if any of them is not supported.
+        let velocities = velocities_at_hydrostatic_equilibrium(
+            &[Vector::zeros(); 2],
+            &[-gravity_change * 0.995, -gravity_change * 0.5],
+        );
+        assert!((velocities[0] - gravity_change * 0.005).norm() < 1.0e-6);
+        assert!((velocities[1] - gravity_change * 0.5).norm() < 1.0e-6);
+    }
+
+    #[test]
+    fn hydrostatic_equilibrium_keeps_moving_fluids() {
+        let gravity_change = Vector::y() * (-9.81 * 0.01);
+        // A particle supported by the pressure, flowing slower than the gravity change.
+        let velocity = Vector::x() * 0.01;
+        let velocities =
+            velocities_at_hydrostatic_equilibrium(&[velocity], &[-gravity_change * 0.995]);
+        assert!((velocities[0] - velocity - gravity_change * 0.005).norm() < 1.0e-6);
+    }
+
+    #[test]
+    fn gravity_in_pressure_solve_stops_a_still_tank() {
+        // The largest speed of the particles of a shallow pool settling in a tank, during the last
+        // steps of its simulation.
+        let max_speed = |gravity_in_pressure_solve: bool| {
+            let particle_radius = 0.05;
+            let spacing = particle_radius * 2.0;
+            let mut solver = DFSPHSolver::<CubicSplineKernel>::new();
+            solver.gravity_in_pressure_solve = gravity_in_pressure_solve;
+            let mut world = LiquidWorld::new(solver, particle_radius, 2.0);
+            let half_extents = Vector::repeat(spacing * 2.0);
+            let _ = world.add_boundary(Boundary::cuboid(
+                &Point::from(half_extents),
+                &half_extents,
+                particle_radius,
+                2,
+            ));
+            let positions = particle_block(3, 2, spacing, Vector::repeat(spacing));
+            let mut fluid = Fluid::new(positions, particle_radius, 1000.0);
+            fluid.add_nonpressure_force(Box::new(XSPHViscosity::new(0.1, 0.1)));
+            let fluid = world.add_fluid(fluid);
+            let mut max_speed: Real = 0.0;
+
+            for k in 0..300 {
+                world.step(0.01, &(Vector::y() * -9.81));
+
+                if k >= 250 {
+                    let velocities = &world.fluids()[fluid].velocities;
+                    max_speed = velocities.iter().fold(max_speed, |m, v| m.max(v.norm()));
+                }
+            }
+
+            max_speed
+        };
+
+        // Without the hydrostatic equilibrium, the residual of gravity and pressure keeps the
+        // particles moving.
+        assert!(max_speed(true) < Real::EPSILON);
+        assert!(max_speed(false) > 1.0e-6);
+    }
 }
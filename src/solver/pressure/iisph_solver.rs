@@ -8,12 +8,22 @@ use num::Zero;
 use crate::counters::Counters;
 use crate::geometry::{ContactManager, ParticlesContacts};
 use crate::kernel::{CubicSplineKernel, Kernel};
-use crate::math::{Real, Vector};
+use crate::math::{Real, Vector, DIM};
 use crate::object::{Boundary, Fluid};
-use crate::solver::{helper, PressureSolver};
+use crate::solver::boundary_method::extrapolate_to_boundaries;
+use crate::solver::{
+    apply_pressure_reactions, helper, is_fluid_solved, max_error_or_nan, BoundaryMethod,
+    CompensatedSum, ConfigurablePressureSolver, IncompressibilityTolerance, IterationCallback,
+    IterationState, PartialPressureSolver, PressureSolver, SolvePhase, SolveStats, SolverState,
+    SolverStateError, StatefulPressureSolver, Summation,
+};
 use crate::TimestepManager;
 
 /// A IISPH (Implicit Incompressible Smoothed Particle Hydrodynamics) pressure solver.
+///
+/// By default, the pressure of a boundary particle is mirrored from the fluid particle it interacts
+/// with, as in Akinci et al. 2012 "Versatile Rigid-Fluid Coupling for Incompressible SPH". The other
+/// methods of `BoundaryMethod` can be selected with `ConfigurablePressureSolver::set_boundary_method`.
 pub struct IISPHSolver<
     KernelDensity: Kernel = CubicSplineKernel,
     KernelGradient: Kernel = CubicSplineKernel,
@@ -22,11 +32,18 @@ pub struct IISPHSolver<
     pub min_pressure_iter: usize,
     /// Maximum number of iterations that must be executed for pressure resolution.
     pub max_pressure_iter: usize,
-    /// Maximum acceptable density error (in percents).
+    /// Maximum acceptable average relative density error, e.g., `0.05` for a compression of 5%.
     ///
     /// The pressure solver will continue iterating until the density error drops bellow this
     /// threshold, or until the maximum number of pressure iterations is reached.
     pub max_density_error: Real,
+    /// The algorithm used to sum the contributions of the neighbors to the densities and the pressure forces.
+    ///
+    /// Defaults to `Summation::Naive`. `Summation::Compensated` is slower, but reduces the rounding
+    /// errors for particles with many neighbors.
+    pub summation: Summation,
+    boundary_method: BoundaryMethod,
+    solved_fluids: Option<Vec<bool>>,
     omega: Real,
     densities: Vec<Vec<Real>>,
     aii: Vec<Vec<Real>>,
@@ -34,8 +51,11 @@ pub struct IISPHSolver<
     dij_pjl: Vec<Vec<Vector<Real>>>,
     pressures: Vec<Vec<Real>>,
     next_pressures: Vec<Vec<Real>>,
+    boundary_pressures: Vec<Vec<Real>>,
     predicted_densities: Vec<Vec<Real>>,
     velocity_changes: Vec<Vec<Vector<Real>>>,
+    last_solve_stats: SolveStats,
+    iteration_callback: Option<Box<IterationCallback>>,
     phantoms: PhantomData<(KernelDensity, KernelGradient)>,
 }
 
@@ -50,6 +70,9 @@ where
             min_pressure_iter: 1,
             max_pressure_iter: 50,
             max_density_error: na::convert::<_, Real>(0.05),
+            summation: Summation::Naive,
+            boundary_method: BoundaryMethod::Akinci,
+            solved_fluids: None,
             omega: na::convert::<_, Real>(0.5),
             densities: Vec::new(),
             dii: Vec::new(),
@@ -57,8 +80,11 @@ where
             dij_pjl: Vec::new(),
             pressures: Vec::new(),
             next_pressures: Vec::new(),
+            boundary_pressures: Vec::new(),
             predicted_densities: Vec::new(),
             velocity_changes: Vec::new(),
+            last_solve_stats: SolveStats::default(),
+            iteration_callback: None,
             phantoms: PhantomData,
         }
     }
@@ -68,11 +94,17 @@ where
         boundary_boundary_contacts: &[ParticlesContacts],
         boundaries: &mut [Boundary],
     ) {
+        let summation = self.summation;
+
         for boundary_id in 0..boundaries.len() {
+            if boundaries[boundary_id].fixed_volumes() {
+                continue;
+            }
+
             par_iter_mut!(boundaries[boundary_id].volumes)
                 .enumerate()
                 .for_each(|(i, volume)| {
-                    let mut denominator = na::zero::<Real>();
+                    let mut denominator = CompensatedSum::new(summation);
 
                     for c in boundary_boundary_contacts[boundary_id]
                         .particle_contacts(i)
@@ -80,15 +112,36 @@ where
                         .unwrap()
                         .iter()
                     {
-                        denominator += c.weight;
+                        denominator.add(c.weight);
                     }
 
+                    let denominator = denominator.value();
                     assert!(!denominator.is_zero());
                     *volume = na::one::<Real>() / denominator;
                 })
         }
     }
 
+    // Extrapolates the current pressures to the boundary particles, if required by the boundary method.
+    fn extrapolate_boundary_pressures(
+        &mut self,
+        fluid_boundary_contacts: &[ParticlesContacts],
+        fluids: &[Fluid],
+        boundaries: &[Boundary],
+    ) {
+        if self.boundary_method.extrapolates_pressure() {
+            let pressures = &self.pressures;
+            extrapolate_to_boundaries(
+                self.boundary_method,
+                fluid_boundary_contacts,
+                boundaries,
+                fluids,
+                |fluid_id, i| pressures[fluid_id][i],
+                &mut self.boundary_pressures,
+            );
+        }
+    }
+
     fn compute_predicted_densities(
         &mut self,
         timestep: &TimestepManager,
@@ -99,14 +152,20 @@ where
     ) {
         let velocity_changes = &self.velocity_changes;
         let densities = &self.densities;
+        let summation = self.summation;
+        let boundary_method = self.boundary_method;
         let _max_error = na::zero::<Real>();
 
         for fluid_id in 0..fluids.len() {
+            if !is_fluid_solved(self.solved_fluids.as_deref(), fluid_id) {
+                continue;
+            }
+
             let _it = par_iter_mut!(self.predicted_densities[fluid_id])
                 .enumerate()
                 .for_each(|(i, predicted_density)| {
                     let fluid_i = &fluids[fluid_id];
-                    let mut delta = na::zero::<Real>();
+                    let mut delta = CompensatedSum::new(summation);
 
                     for c in fluid_fluid_contacts[fluid_id]
                         .particle_contacts(i)
@@ -118,7 +177,8 @@ where
                         let vi = fluid_i.velocities[c.i] + velocity_changes[c.i_model][c.i];
                         let vj = fluid_j.velocities[c.j] + velocity_changes[c.j_model][c.j];
 
-                        delta += fluids[c.j_model].particle_mass(c.j) * (vi - vj).dot(&c.gradient);
+                        delta
+                            .add(fluids[c.j_model].particle_mass(c.j) * (vi - vj).dot(&c.gradient));
                     }
 
                     for c in fluid_boundary_contacts[fluid_id]
@@ -129,13 +189,15 @@ where
                     {
                         let vi = fluid_i.velocities[c.i] + velocity_changes[c.i_model][c.i];
                         let vj = boundaries[c.j_model].velocities[c.j];
+                        let volume = boundary_method.boundary_volume(
+                            boundaries[c.j_model].volumes[c.j],
+                            fluid_i.volumes[i],
+                        );
 
-                        delta += boundaries[c.j_model].volumes[c.j]
-                            * fluid_i.density0
-                            * (vi - vj).dot(&c.gradient);
+                        delta.add(volume * fluid_i.rest_density(i) * (vi - vj).dot(&c.gradient));
                     }
 
-                    *predicted_density = densities[fluid_id][i] + delta * timestep.dt();
+                    *predicted_density = densities[fluid_id][i] + delta.value() * timestep.dt();
                     assert!(!predicted_density.is_zero());
                 });
         }
@@ -149,12 +211,19 @@ where
         fluids: &[Fluid],
         boundaries: &[Boundary],
     ) {
+        let solved_fluids = self.solved_fluids.as_deref();
+
         for fluid_id in 0..fluids.len() {
+            if !is_fluid_solved(solved_fluids, fluid_id) {
+                continue;
+            }
+
             let fluid_fluid_contacts = &fluid_fluid_contacts[fluid_id];
             let fluid_boundary_contacts = &fluid_boundary_contacts[fluid_id];
             let dii = &mut self.dii[fluid_id];
             let fluid_i = &fluids[fluid_id];
             let densities = &self.densities;
+            let boundary_method = self.boundary_method;
 
             par_iter_mut!(dii).enumerate().for_each(|(i, dii)| {
                 dii.fill(na::zero::<Real>());
@@ -178,7 +247,9 @@ where
                     .unwrap()
                     .iter()
                 {
-                    let mj = boundaries[c.j_model].volumes[c.j] * fluid_i.density0;
+                    let volume = boundary_method
+                        .boundary_volume(boundaries[c.j_model].volumes[c.j], fluid_i.volumes[i]);
+                    let mj = volume * fluid_i.rest_density(i);
                     *dii += c.gradient * (mj * factor);
                 }
             })
@@ -193,13 +264,20 @@ where
         fluids: &[Fluid],
         boundaries: &[Boundary],
     ) {
+        let solved_fluids = self.solved_fluids.as_deref();
+
         for fluid_id in 0..fluids.len() {
+            if !is_fluid_solved(solved_fluids, fluid_id) {
+                continue;
+            }
+
             let fluid_fluid_contacts = &fluid_fluid_contacts[fluid_id];
             let fluid_boundary_contacts = &fluid_boundary_contacts[fluid_id];
             let aii = &mut self.aii[fluid_id];
             let dii = &self.dii[fluid_id];
             let fluid_i = &fluids[fluid_id];
             let densities = &self.densities;
+            let boundary_method = self.boundary_method;
 
             par_iter_mut!(aii).enumerate().for_each(|(i, aii)| {
                 *aii = na::zero::<Real>();
@@ -214,7 +292,12 @@ where
                     .iter()
                 {
                     let mj = fluids[c.j_model].particle_mass(c.j);
-                    let dji = c.gradient * factor;
+                    // The particles of the unsolved fluids are not displaced by this pressure.
+                    let dji = if is_fluid_solved(solved_fluids, c.j_model) {
+                        c.gradient * factor
+                    } else {
+                        Vector::zeros()
+                    };
                     *aii += mj * (dii[c.i] - dji).dot(&c.gradient);
                 }
 
@@ -224,7 +307,9 @@ where
                     .unwrap()
                     .iter()
                 {
-                    let mj = boundaries[c.j_model].volumes[c.j] * fluid_i.density0;
+                    let volume = boundary_method
+                        .boundary_volume(boundaries[c.j_model].volumes[c.j], fluid_i.volumes[i]);
+                    let mj = volume * fluid_i.rest_density(i);
                     let dji = c.gradient * factor;
                     *aii += mj * (dii[c.i] - dji).dot(&c.gradient);
                 }
@@ -238,14 +323,25 @@ where
         fluid_fluid_contacts: &[ParticlesContacts],
         fluid_boundary_contacts: &[ParticlesContacts],
         fluids: &[Fluid],
+        boundaries: &[Boundary],
     ) {
+        let boundary_method = self.boundary_method;
+        self.extrapolate_boundary_pressures(fluid_boundary_contacts, fluids, boundaries);
+
         for fluid_id in 0..fluids.len() {
-            let fluid_fluid_contacts = &fluid_fluid_contacts[fluid_id];
-            let _fluid_boundary_contacts = &fluid_boundary_contacts[fluid_id];
             let dij_pjl = &mut self.dij_pjl[fluid_id];
-            let _fluid_i = &fluids[fluid_id];
+
+            if !is_fluid_solved(self.solved_fluids.as_deref(), fluid_id) {
+                dij_pjl.fill(Vector::zeros());
+                continue;
+            }
+
+            let fluid_fluid_contacts = &fluid_fluid_contacts[fluid_id];
+            let fluid_boundary_contacts = &fluid_boundary_contacts[fluid_id];
+            let fluid_i = &fluids[fluid_id];
             let densities = &self.densities;
             let pressures = &self.pressures;
+            let boundary_pressures = &self.boundary_pressures;
 
             par_iter_mut!(dij_pjl).enumerate().for_each(|(i, dij_pjl)| {
                 dij_pjl.fill(na::zero::<Real>());
@@ -262,6 +358,24 @@ where
                     *dij_pjl += c.gradient * (-mj * p_jl / (rhoj * rhoj));
                 }
 
+                // `dii` assumes that the boundary pressures are mirrored, so only their deviation from
+                // the pressure of this particle is accounted for here.
+                if boundary_method.extrapolates_pressure() {
+                    let rhoi = densities[fluid_id][i];
+                    let pi = pressures[fluid_id][i];
+
+                    for c in fluid_boundary_contacts
+                        .particle_contacts(i)
+                        .read()
+                        .unwrap()
+                        .iter()
+                    {
+                        let mj = fluid_i.volumes[i] * fluid_i.rest_density(i);
+                        let p_b = boundary_pressures[c.j_model][c.j];
+                        *dij_pjl += c.gradient * (-mj * (p_b - pi) / (rhoi * rhoi));
+                    }
+                }
+
                 *dij_pjl *= timestep.dt() * timestep.dt();
             })
         }
@@ -274,13 +388,24 @@ where
         fluid_boundary_contacts: &[ParticlesContacts],
         fluids: &[Fluid],
         boundaries: &[Boundary],
-    ) -> Real {
+        track_max_error: bool,
+    ) -> (Real, Real) {
         let mut max_error = na::zero::<Real>();
+        let mut max_particle_error = na::zero::<Real>();
+
+        let solved_fluids = self.solved_fluids.as_deref();
 
         for fluid_id in 0..fluids.len() {
+            let next_pressures = &mut self.next_pressures[fluid_id];
+
+            // The unsolved fluids have no pressure.
+            if !is_fluid_solved(solved_fluids, fluid_id) {
+                next_pressures.fill(na::zero::<Real>());
+                continue;
+            }
+
             let fluid_fluid_contacts = &fluid_fluid_contacts[fluid_id];
             let fluid_boundary_contacts = &fluid_boundary_contacts[fluid_id];
-            let next_pressures = &mut self.next_pressures[fluid_id];
             let pressures = &self.pressures;
             let fluid_i = &fluids[fluid_id];
             let densities = &self.densities;
@@ -289,6 +414,7 @@ where
             let aii = &self.aii[fluid_id];
             let dij_pjl = &self.dij_pjl;
             let dii = &self.dii;
+            let boundary_method = self.boundary_method;
 
             let it = par_iter_mut!(next_pressures)
                 .enumerate()
@@ -298,7 +424,7 @@ where
                         let pi = pressures[fluid_id][i];
                         let mi = fluid_i.particle_mass(i);
                         let rhoi = densities[fluid_id][i];
-                        let derr = fluid_i.density0 - predicted_densities[fluid_id][i];
+                        let derr = fluid_i.rest_density(i) - predicted_densities[fluid_id][i];
 
                         for c in fluid_fluid_contacts
                             .particle_contacts(i)
@@ -307,6 +433,13 @@ where
                             .iter()
                         {
                             let mj = fluids[c.j_model].particle_mass(c.j);
+
+                            if !is_fluid_solved(solved_fluids, c.j_model) {
+                                // The particles of the unsolved fluids are not displaced by the pressures.
+                                sum += mj * dij_pjl[c.i_model][c.i].dot(&c.gradient);
+                                continue;
+                            }
+
                             let dji =
                                 c.gradient * (timestep.dt() * timestep.dt() * mi / (rhoi * rhoi));
                             let factor = dij_pjl[c.i_model][c.i]
@@ -321,16 +454,23 @@ where
                             .unwrap()
                             .iter()
                         {
-                            let mj = boundaries[c.j_model].volumes[c.j] * fluid_i.density0;
+                            let volume = boundary_method.boundary_volume(
+                                boundaries[c.j_model].volumes[c.j],
+                                fluid_i.volumes[i],
+                            );
+                            let mj = volume * fluid_i.rest_density(i);
                             sum += mj * dij_pjl[c.i_model][c.i].dot(&c.gradient);
                         }
 
                         *next_pressure =
                             (na::one::<Real>() - omega) * pi + omega * (derr - sum) / aii[i];
 
-                        if *next_pressure > na::zero::<Real>() {
-                            *next_pressure = next_pressure.max(na::zero::<Real>());
-                            (-sum - aii[i] * *next_pressure) / fluid_i.density0
+                        if next_pressure.is_nan() {
+                            // Report the NaNs instead of clamping them.
+                            Real::NAN
+                        } else if *next_pressure > na::zero::<Real>() {
+                            ((aii[i] * *next_pressure + sum - derr) / fluid_i.rest_density(i))
+                                .max(na::zero::<Real>())
                         } else {
                             // Clamp negative pressures.
                             *next_pressure = na::zero::<Real>();
@@ -341,15 +481,21 @@ where
                         na::zero::<Real>()
                     }
                 });
-            let err = par_reduce_sum!(na::zero::<Real>(), it);
+            let (err, particle_error) = if track_max_error {
+                par_reduce_sum_max!(na::zero::<Real>(), it)
+            } else {
+                (par_reduce_sum!(na::zero::<Real>(), it), na::zero::<Real>())
+            };
+            max_particle_error = max_particle_error.max(particle_error);
 
             let nparts = fluids[fluid_id].num_particles();
             if nparts != 0 {
-                max_error = max_error.max(err / na::convert::<_, Real>(nparts as f64));
+                max_error =
+                    max_error_or_nan(max_error, err / na::convert::<_, Real>(nparts as f64));
             }
         }
 
-        max_error
+        (max_error, max_particle_error)
     }
 
     fn compute_velocity_changes(
@@ -360,16 +506,27 @@ where
         fluids: &[Fluid],
         boundaries: &[Boundary],
     ) {
+        self.extrapolate_boundary_pressures(fluid_boundary_contacts, fluids, boundaries);
+
         let densities = &self.densities;
         let pressures = &self.pressures;
+        let boundary_pressures = &self.boundary_pressures;
+        let summation = self.summation;
+        let boundary_method = self.boundary_method;
+        let solved_fluids = self.solved_fluids.as_deref();
 
         for (fluid_id, _fluid1) in fluids.iter().enumerate() {
+            if !is_fluid_solved(solved_fluids, fluid_id) {
+                continue;
+            }
+
             par_iter_mut!(self.velocity_changes[fluid_id])
                 .enumerate()
                 .for_each(|(i, velocity_change)| {
                     let fluid_i = &fluids[fluid_id];
                     let pi = pressures[fluid_id][i];
                     let rhoi = densities[fluid_id][i];
+                    let mut sum = CompensatedSum::starting_at(summation, *velocity_change);
 
                     for c in fluid_fluid_contacts[fluid_id]
                         .particle_contacts(i)
@@ -381,8 +538,10 @@ where
                         let pj = pressures[c.j_model][c.j];
                         let rhoj = densities[c.j_model][c.j];
 
-                        *velocity_change -= c.gradient
-                            * (timestep.dt() * mj * (pi / (rhoi * rhoi) + pj / (rhoj * rhoj)));
+                        sum.sub(
+                            c.gradient
+                                * (timestep.dt() * mj * (pi / (rhoi * rhoi) + pj / (rhoj * rhoj))),
+                        );
                     }
 
                     for c in fluid_boundary_contacts[fluid_id]
@@ -391,30 +550,54 @@ where
                         .unwrap()
                         .iter()
                     {
-                        let mj = boundaries[c.j_model].volumes[c.j] * fluid_i.density0;
-                        let acc = c.gradient * (mj * pi / (rhoi * rhoi));
-                        *velocity_change -= acc * timestep.dt();
+                        let volume = boundary_method.boundary_volume(
+                            boundaries[c.j_model].volumes[c.j],
+                            fluid_i.volumes[i],
+                        );
+                        let mj = volume * fluid_i.rest_density(i);
+                        let pb = if boundary_method.extrapolates_pressure() {
+                            boundary_pressures[c.j_model][c.j]
+                        } else {
+                            pi
+                        };
+                        let acc = c.gradient * (mj * pb / (rhoi * rhoi));
+                        sum.sub(acc * timestep.dt());
 
                         // Apply the force to the boundary too.
                         let mi = fluid_i.particle_mass(c.i);
                         boundaries[c.j_model].apply_force(c.j, acc * mi);
                     }
+
+                    *velocity_change = sum.value();
                 })
         }
     }
 
+    // Moves the particles of the solved fluids, except the frozen ones.
     fn update_velocities_and_positions(
         &mut self,
         timestep: &TimestepManager,
+        contact_manager: &ContactManager,
+        kernel_radius: Real,
         fluids: &mut [Fluid],
     ) {
-        for (fluid, delta) in fluids.iter_mut().zip(self.velocity_changes.iter()) {
+        for (fluid_id, (fluid, delta)) in fluids
+            .iter_mut()
+            .zip(self.velocity_changes.iter())
+            .enumerate()
+        {
+            if !is_fluid_solved(self.solved_fluids.as_deref(), fluid_id) {
+                continue;
+            }
+
             par_iter_mut!(fluid.positions)
                 .zip(par_iter_mut!(fluid.velocities))
                 .zip(par_iter!(delta))
                 .for_each(|((pos, vel), delta)| {
-                    *vel += delta;
-                    *pos += *vel * timestep.dt();
+                    if !contact_manager.is_frozen(kernel_radius, pos) {
+                        *vel += delta;
+                        *pos += *vel * timestep.dt();
+                    }
                 })
         }
     }
@@ -427,32 +610,58 @@ where
         fluids: &mut [Fluid],
         boundaries: &[Boundary],
     ) {
+        self.last_solve_stats.num_pressure_iterations = self.max_pressure_iter;
+
         for i in 0..self.max_pressure_iter {
             self.compute_dij_pjl(
                 timestep,
                 &contact_manager.fluid_fluid_contacts,
                 &contact_manager.fluid_boundary_contacts,
                 fluids,
+                boundaries,
             );
 
-            let avg_err = self.compute_next_pressures(
+            let (avg_err, max_particle_err) = self.compute_next_pressures(
                 timestep,
                 &contact_manager.fluid_fluid_contacts,
                 &contact_manager.fluid_boundary_contacts,
                 fluids,
                 boundaries,
+                self.iteration_callback.is_some(),
             );
 
             std::mem::swap(&mut self.pressures, &mut self.next_pressures);
+            self.last_solve_stats.density_error = avg_err;
+
+            if let Some(callback) = &mut self.iteration_callback {
+                callback(&IterationState {
+                    phase: SolvePhase::Density,
+                    iteration: i,
+                    average_error: avg_err,
+                    max_error: max_particle_err,
+                });
+            }
 
             if avg_err <= self.max_density_error && i >= self.min_pressure_iter {
-                //                println!(
-                //                    "Average density error: {}, break after niters: {}",
-                //                    avg_err, i
-                //                );
+                self.last_solve_stats.num_pressure_iterations = i + 1;
+                log::trace!(
+                    "IISPH: average density error: {}, break after niters: {}",
+                    avg_err,
+                    i
+                );
                 break;
             }
         }
+
+        if self.max_pressure_iter > 0
+            && self.last_solve_stats.density_error > self.max_density_error
+        {
+            log::warn!(
+                "IISPH: the density solve did not converge after {} iterations (average density error: {}).",
+                self.max_pressure_iter,
+                self.last_solve_stats.density_error
+            );
+        }
     }
 
     fn integrate_and_clear_accelerations(
@@ -460,7 +669,16 @@ where
         timestep: &TimestepManager,
         fluids: &mut [Fluid],
     ) {
-        for (velocity_changes, fluid) in self.velocity_changes.iter_mut().zip(fluids.iter_mut()) {
+        for (fluid_id, (velocity_changes, fluid)) in self
+            .velocity_changes
+            .iter_mut()
+            .zip(fluids.iter_mut())
+            .enumerate()
+        {
+            if !is_fluid_solved(self.solved_fluids.as_deref(), fluid_id) {
+                continue;
+            }
+
             par_iter_mut!(velocity_changes)
                 .zip(par_iter_mut!(fluid.accelerations))
                 .for_each(|(velocity_change, acceleration)| {
@@ -547,35 +765,39 @@ where
         fluids: &mut [Fluid],
         boundaries: &[Boundary],
     ) {
-        for fluid in fluids.iter_mut() {
+        for (fluid_id, fluid) in fluids.iter_mut().enumerate() {
+            if !is_fluid_solved(self.solved_fluids.as_deref(), fluid_id) {
+                continue;
+            }
+
+            let fluid_gravity = gravity * fluid.gravity_scale;
             par_iter_mut!(fluid.accelerations).for_each(|acceleration| {
-                *acceleration += gravity;
+                *acceleration += fluid_gravity;
             })
         }
 
-        for (fluid, fluid_fluid_contacts, fluid_boundary_contacts, densities) in
+        for (fluid_id, (fluid, fluid_fluid_contacts, fluid_boundary_contacts, densities)) in
             itertools::multizip((
                 &mut *fluids,
                 &contact_manager.fluid_fluid_contacts,
                 &contact_manager.fluid_boundary_contacts,
                 &self.densities,
             ))
+            .enumerate()
         {
-            let mut forces = std::mem::replace(&mut fluid.nonpressure_forces, Vec::new());
-
-            for np_force in &mut forces {
-                np_force.solve(
-                    timestep,
-                    kernel_radius,
-                    fluid_fluid_contacts,
-                    fluid_boundary_contacts,
-                    fluid,
-                    boundaries,
-                    densities,
-                );
+            if !is_fluid_solved(self.solved_fluids.as_deref(), fluid_id) {
+                continue;
             }
 
-            fluid.nonpressure_forces = forces;
+            helper::solve_nonpressure_forces(
+                timestep,
+                kernel_radius,
+                fluid_fluid_contacts,
+                fluid_boundary_contacts,
+                fluid,
+                boundaries,
+                densities,
+            );
         }
     }
 
@@ -586,8 +808,11 @@ where
         fluids: &[Fluid],
         boundaries: &[Boundary],
     ) {
+        let anisotropy = *contact_manager.kernel_anisotropy();
+
         helper::update_fluid_contacts::<KernelDensity, KernelGradient>(
             kernel_radius,
+            &anisotropy,
             &mut contact_manager.fluid_fluid_contacts,
             &mut contact_manager.fluid_boundary_contacts,
             fluids,
@@ -596,6 +821,7 @@ where
 
         helper::update_boundary_contacts::<KernelDensity, KernelGradient>(
             kernel_radius,
+            &anisotropy,
             &mut contact_manager.boundary_boundary_contacts,
             boundaries,
         );
@@ -608,12 +834,13 @@ where
         boundaries: &mut [Boundary],
     ) {
         self.compute_boundary_volumes(&contact_manager.boundary_boundary_contacts, boundaries);
+        let summation = self.summation;
 
         for fluid_id in 0..fluids.len() {
             par_iter_mut!(self.densities[fluid_id])
                 .enumerate()
                 .for_each(|(i, density)| {
-                    *density = na::zero::<Real>();
+                    let mut sum = CompensatedSum::new(summation);
 
                     for c in contact_manager.fluid_fluid_contacts[fluid_id]
                         .particle_contacts(i)
@@ -621,7 +848,7 @@ where
                         .unwrap()
                         .iter()
                     {
-                        *density += fluids[c.j_model].particle_mass(c.j) * c.weight;
+                        sum.add(fluids[c.j_model].particle_mass(c.j) * c.weight);
                     }
 
                     for c in contact_manager.fluid_boundary_contacts[fluid_id]
@@ -630,11 +857,14 @@ where
                         .unwrap()
                         .iter()
                     {
-                        *density += boundaries[c.j_model].volumes[c.j]
-                            * fluids[c.i_model].density0
-                            * c.weight;
+                        sum.add(
+                            boundaries[c.j_model].volumes[c.j]
+                                * fluids[c.i_model].rest_density(c.i)
+                                * c.weight,
+                        );
                     }
 
+                    *density = sum.value();
                     assert!(!density.is_zero());
                 })
         }
@@ -702,11 +932,147 @@ where
             boundaries,
         );
 
-        self.update_velocities_and_positions(timestep, fluids);
+        self.update_velocities_and_positions(timestep, contact_manager, kernel_radius, fluids);
+
+        let (pressures, densities) = (&self.pressures, &self.densities);
+        apply_pressure_reactions(
+            self.solved_fluids.as_deref(),
+            timestep,
+            &contact_manager.fluid_fluid_contacts,
+            |fluid_id, i| {
+                let rhoi = densities[fluid_id][i];
+                timestep.dt() * pressures[fluid_id][i] / (rhoi * rhoi)
+            },
+            fluids,
+        );
 
         self.velocity_changes
             .iter_mut()
             .for_each(|vs| vs.iter_mut().for_each(|v| v.fill(na::zero::<Real>())));
         counters.solver.pressure_resolution_time.pause();
     }
+
+    fn densities(&self) -> Option<&[Vec<Real>]> {
+        Some(&self.densities)
+    }
+
+    fn as_stateful(&self) -> Option<&dyn StatefulPressureSolver> {
+        Some(self)
+    }
+
+    fn as_stateful_mut(&mut self) -> Option<&mut dyn StatefulPressureSolver> {
+        Some(self)
+    }
+
+    fn as_configurable(&self) -> Option<&dyn ConfigurablePressureSolver> {
+        Some(self)
+    }
+
+    fn as_configurable_mut(&mut self) -> Option<&mut dyn ConfigurablePressureSolver> {
+        Some(self)
+    }
+
+    fn as_partial_mut(&mut self) -> Option<&mut dyn PartialPressureSolver> {
+        Some(self)
+    }
+}
+
+impl<KernelDensity, KernelGradient> ConfigurablePressureSolver
+    for IISPHSolver<KernelDensity, KernelGradient>
+where
+    KernelDensity: Kernel,
+    KernelGradient: Kernel,
+{
+    fn last_solve_stats(&self) -> SolveStats {
+        self.last_solve_stats
+    }
+
+    fn boundary_method(&self) -> BoundaryMethod {
+        self.boundary_method
+    }
+
+    fn set_boundary_method(&mut self, method: BoundaryMethod) {
+        self.boundary_method = method;
+    }
+
+    /// Sets `self.max_density_error` to the tolerance: the pressure iterations stop once the average
+    /// relative compression predicted with the current pressures is below it.
+    fn set_incompressibility_tolerance(&mut self, tolerance: IncompressibilityTolerance) {
+        self.max_density_error = tolerance.max_relative_density_error;
+    }
+
+    fn set_iteration_callback(&mut self, callback: Option<Box<IterationCallback>>) {
+        self.iteration_callback = callback;
+    }
+}
+
+impl<KernelDensity, KernelGradient> StatefulPressureSolver
+    for IISPHSolver<KernelDensity, KernelGradient>
+where
+    KernelDensity: Kernel,
+    KernelGradient: Kernel,
+{
+    fn rescale(&mut self, factor: Real) {
+        // With constant masses, pressures scale like a force per unit area.
+        let pressure_factor = factor.powi(2 - DIM as i32);
+
+        for pressures in &mut self.pressures {
+            pressures.iter_mut().for_each(|p| *p *= pressure_factor);
+        }
+    }
+
+    fn reset(&mut self) {
+        // Clear the buffers without releasing their memory.
+        self.densities.iter_mut().for_each(Vec::clear);
+        self.predicted_densities.iter_mut().for_each(Vec::clear);
+        self.velocity_changes.iter_mut().for_each(Vec::clear);
+        self.aii.iter_mut().for_each(Vec::clear);
+        self.dii.iter_mut().for_each(Vec::clear);
+        self.dij_pjl.iter_mut().for_each(Vec::clear);
+        self.pressures.iter_mut().for_each(Vec::clear);
+        self.next_pressures.iter_mut().for_each(Vec::clear);
+        self.last_solve_stats = SolveStats::default();
+    }
+
+    fn shrink_to_fit(&mut self) {
+        for buffers in [
+            &mut self.densities,
+            &mut self.predicted_densities,
+            &mut self.aii,
+            &mut self.pressures,
+            &mut self.next_pressures,
+            &mut self.boundary_pressures,
+        ] {
+            buffers.iter_mut().for_each(Vec::shrink_to_fit);
+        }
+
+        for buffers in [&mut self.velocity_changes, &mut self.dii, &mut self.dij_pjl] {
+            buffers.iter_mut().for_each(Vec::shrink_to_fit);
+        }
+    }
+
+    fn export_state(&self) -> SolverState {
+        SolverState::new(1, self.pressures.clone())
+    }
+
+    fn import_state(
+        &mut self,
+        fluids: &[Fluid],
+        state: &SolverState,
+    ) -> Result<(), SolverStateError> {
+        state.validate(1, fluids)?;
+        self.pressures = state.fluid_buffers().to_vec();
+        Ok(())
+    }
+}
+
+impl<KernelDensity, KernelGradient> PartialPressureSolver
+    for IISPHSolver<KernelDensity, KernelGradient>
+where
+    KernelDensity: Kernel,
+    KernelGradient: Kernel,
+{
+    fn set_solved_fluids(&mut self, solved_fluids: Option<Vec<bool>>) {
+        self.solved_fluids = solved_fluids;
+    }
 }
@@ -1,6 +1,12 @@
 pub use self::dfsph_solver::DFSPHSolver;
 pub use self::iisph_solver::IISPHSolver;
-pub use self::pressure_solver::PressureSolver;
+pub(crate) use self::pressure_solver::{
+    apply_pressure_reactions, is_fluid_solved, max_error_or_nan,
+};
+pub use self::pressure_solver::{
+    ConfigurablePressureSolver, IncompressibilityTolerance, IterationCallback, IterationState,
+    PartialPressureSolver, PressureSolver, SolvePhase, SolveStats, StatefulPressureSolver,
+};
 
 mod dfsph_solver;
 mod iisph_solver;
@@ -1,10 +1,87 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
 use crate::counters::Counters;
-use crate::geometry::ContactManager;
+use crate::geometry::{ContactManager, ParticlesContacts};
 use crate::math::{Real, Vector};
 use crate::object::{Boundary, Fluid};
+use crate::solver::{BoundaryMethod, SolverState, SolverStateError};
 use crate::TimestepManager;
 
+/// Convergence information about the last pressure resolution of a solver.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct SolveStats {
+    /// The number of iterations executed by the density solve.
+    pub num_pressure_iterations: usize,
+    /// The average density error measured at the end of the density solve.
+    ///
+    /// This is expressed in the same unit as the `max_density_error` of the solver.
+    pub density_error: Real,
+    /// The number of iterations executed by the divergence solve, if any.
+    pub num_divergence_iterations: usize,
+    /// The average divergence error measured at the end of the divergence solve, if any.
+    pub divergence_error: Real,
+}
+
+/// The compression of the fluids tolerated by the pressure solvers, independently of the solver.
+///
+/// Each pressure solver translates this tolerance into its own stopping criteria, as documented
+/// by its implementation of `ConfigurablePressureSolver::set_incompressibility_tolerance`, so
+/// that switching solvers with the same tolerance gives comparably incompressible fluids.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct IncompressibilityTolerance {
+    /// The maximum average relative density error `(density - density0) / density0` of the fluid
+    /// particles at the end of the density solve, e.g., `0.001` for a compression of 0.1%.
+    pub max_relative_density_error: Real,
+}
+
+impl IncompressibilityTolerance {
+    /// Initializes a tolerance for the given average relative density error.
+    pub fn new(max_relative_density_error: Real) -> Self {
+        assert!(
+            max_relative_density_error > na::zero::<Real>(),
+            "The incompressibility tolerance must be positive."
+        );
+        Self {
+            max_relative_density_error,
+        }
+    }
+}
+
+/// The resolution of a pressure solver an iteration belongs to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SolvePhase {
+    /// The density solve, correcting the compression of the fluids.
+    Density,
+    /// The divergence solve, correcting the divergence of the velocity field.
+    Divergence,
+}
+
+/// The convergence state of a pressure solver after one of its iterations.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct IterationState {
+    /// The resolution this iteration belongs to.
+    pub phase: SolvePhase,
+    /// The index of this iteration within its resolution, starting at 0.
+    pub iteration: usize,
+    /// The error compared by the solver to its maximum acceptable error to detect convergence.
+    ///
+    /// This is the greatest average particle error among all the fluids, in the same unit as
+    /// the `SolveStats` of the solver.
+    pub average_error: Real,
+    /// The greatest error of a single particle among all the fluids.
+    pub max_error: Real,
+}
+
+/// A function called by a pressure solver after each of its iterations, e.g., to plot convergence curves.
+pub type IterationCallback = dyn FnMut(&IterationState) + Send + Sync;
+
 /// Trait implemented by pressure solvers.
+///
+/// The optional capabilities of a pressure solver are grouped into the extension traits
+/// `ConfigurablePressureSolver`, `StatefulPressureSolver`, and `PartialPressureSolver`. A solver
+/// exposes the ones it implements through the `as_*` methods of this trait, which return `None`
+/// by default.
 pub trait PressureSolver {
     /// Initialize this solver with the given fluids.
     fn init_with_fluids(&mut self, fluids: &[Fluid]);
@@ -54,4 +131,222 @@ pub trait PressureSolver {
         fluids: &mut [Fluid],
         boundaries: &[Boundary],
     );
+
+    /// The densities of the particles of each fluid computed during the last substep.
+    ///
+    /// Returns `None` if this solver does not keep track of particle densities.
+    fn densities(&self) -> Option<&[Vec<Real>]> {
+        None
+    }
+
+    /// This solver as a solver keeping a state from one step to the next, if it is one.
+    fn as_stateful(&self) -> Option<&dyn StatefulPressureSolver> {
+        None
+    }
+
+    /// This solver as a mutable solver keeping a state from one step to the next, if it is one.
+    fn as_stateful_mut(&mut self) -> Option<&mut dyn StatefulPressureSolver> {
+        None
+    }
+
+    /// This solver as a configurable iterative solver, if it is one.
+    fn as_configurable(&self) -> Option<&dyn ConfigurablePressureSolver> {
+        None
+    }
+
+    /// This solver as a mutable configurable iterative solver, if it is one.
+    fn as_configurable_mut(&mut self) -> Option<&mut dyn ConfigurablePressureSolver> {
+        None
+    }
+
+    /// This solver as a solver that can be restricted to a subset of the fluids, if it is one.
+    fn as_partial_mut(&mut self) -> Option<&mut dyn PartialPressureSolver> {
+        None
+    }
+}
+
+/// Trait implemented by iterative pressure solvers with configurable stopping criteria and boundary handling.
+pub trait ConfigurablePressureSolver: PressureSolver {
+    /// Convergence information about the last call to `self.step`.
+    fn last_solve_stats(&self) -> SolveStats;
+
+    /// The method used by this solver to account for the boundaries in the densities and pressure forces.
+    fn boundary_method(&self) -> BoundaryMethod;
+
+    /// Sets the method used by this solver to account for the boundaries in the densities and pressure forces.
+    fn set_boundary_method(&mut self, method: BoundaryMethod);
+
+    /// Sets the stopping criteria of the iterative resolutions of this solver from a tolerance shared by all the solvers.
+    ///
+    /// This is called by `LiquidWorld::set_incompressibility_tolerance` and
+    /// `LiquidWorld::set_fluid_solver`.
+    fn set_incompressibility_tolerance(&mut self, tolerance: IncompressibilityTolerance);
+
+    /// Sets the function called after each iteration of this solver, or disables it if `None`.
+    ///
+    /// The errors of each iteration are only tracked while a callback is set, so this has no cost
+    /// otherwise.
+    fn set_iteration_callback(&mut self, callback: Option<Box<IterationCallback>>);
+}
+
+/// Trait implemented by pressure solvers keeping a state from one step to the next, e.g., warm-start buffers.
+pub trait StatefulPressureSolver: PressureSolver {
+    /// Rescales the internal warm-start buffers of this solver after all the lengths of the simulation were multiplied by `factor`.
+    ///
+    /// The particle masses are preserved by the rescaling. This is called by `LiquidWorld::rescale`.
+    fn rescale(&mut self, factor: Real);
+
+    /// Damps the velocity changes this solver keeps for the next step, after the velocities of the fluids were multiplied by `factor`.
+    ///
+    /// This is called by `LiquidWorld` after each substep when a global damping is set, so that
+    /// the velocity changes not applied yet to the velocities of the fluids are damped too. This
+    /// can be left empty if this solver applies all its velocity changes during its step.
+    fn damp_velocity_changes(&mut self, _factor: Real) {}
+
+    /// Resets the state this solver keeps from one step to the next, e.g., its warm-start buffers.
+    ///
+    /// The solver then behaves as if it had never been stepped, but keeps its parameters. This is
+    /// called by `LiquidWorld::reset`.
+    fn reset(&mut self);
+
+    /// Releases the capacity of the internal buffers of this solver that exceeds the number of particles.
+    ///
+    /// This is called by `LiquidWorld` after the number of particles dropped below its compaction
+    /// threshold. It must not modify the content of the buffers.
+    fn shrink_to_fit(&mut self) {}
+
+    /// Exports the internal warm-start buffers of this solver.
+    fn export_state(&self) -> SolverState;
+
+    /// Imports warm-start buffers previously exported with `self.export_state()`.
+    ///
+    /// Returns an error, leaving this solver unchanged, if `state` is not compatible with `fluids`.
+    fn import_state(
+        &mut self,
+        fluids: &[Fluid],
+        state: &SolverState,
+    ) -> Result<(), SolverStateError>;
+}
+
+/// Trait implemented by pressure solvers that can be restricted to a subset of the fluids.
+///
+/// This is required by `LiquidWorld::set_fluid_solver`.
+pub trait PartialPressureSolver: PressureSolver {
+    /// Restricts the fluids corrected and integrated by this solver, e.g., to solve different fluids with different solvers.
+    ///
+    /// If `solved_fluids` is `Some`, its `i`-th element indicates if the `i`-th fluid of the slices
+    /// given to this solver is solved by this solver. The other fluids are neither affected by
+    /// gravity, nor by their non-pressure forces, nor integrated. Their particles still contribute
+    /// to the densities of the solved fluids, but as neighbors moving at their current velocity
+    /// and without any pressure of their own. The reaction of the pressure forces exerted on the
+    /// solved fluids must be added to the `accelerations` of the unsolved fluids, to be integrated
+    /// by their own solver. If `solved_fluids` is `None` (the default), all the fluids are solved.
+    ///
+    /// This is called by `LiquidWorld` before each substep when `LiquidWorld::set_fluid_solver`
+    /// assigned solvers to specific fluids, or when some fluids are inactive.
+    fn set_solved_fluids(&mut self, solved_fluids: Option<Vec<bool>>);
+}
+
+// Checks if the fluid `fluid_id` is solved, given the mask set by `PartialPressureSolver::set_solved_fluids`.
+pub(crate) fn is_fluid_solved(solved_fluids: Option<&[bool]>, fluid_id: usize) -> bool {
+    solved_fluids.is_none_or(|solved| solved.get(fluid_id).copied().unwrap_or(true))
+}
+
+// The maximum of two errors, or NaN if any of them is NaN, unlike `Real::max`, so that the
+// non-finite errors of a diverging solve are reported, e.g., to the stability guard.
+pub(crate) fn max_error_or_nan(a: Real, b: Real) -> Real {
+    if a.is_nan() || b.is_nan() {
+        Real::NAN
+    } else {
+        a.max(b)
+    }
+}
+
+// Applies to the particles of the unsolved fluids the reaction of the pressure forces they exerted
+// on the particles of the solved fluids, as accelerations integrated later by their own solver.
+//
+// The velocity change of a solved particle `i` due to an unsolved neighbor `j` must be
+// `-coefficient(fluid_i, i) * m_j * ∇W_ij`.
+pub(crate) fn apply_pressure_reactions(
+    solved_fluids: Option<&[bool]>,
+    timestep: &TimestepManager,
+    fluid_fluid_contacts: &[ParticlesContacts],
+    coefficient: impl Fn(usize, usize) -> Real + Sync,
+    fluids: &mut [Fluid],
+) {
+    if solved_fluids.is_none() {
+        return;
+    }
+
+    for fluid_id in 0..fluids.len() {
+        if is_fluid_solved(solved_fluids, fluid_id) {
+            continue;
+        }
+
+        let reactions: Vec<Vector<Real>> = {
+            let fluids = &*fluids;
+            par_iter!(fluid_fluid_contacts[fluid_id].contacts())
+                .map(|contacts| {
+                    let mut reaction = Vector::zeros();
+
+                    for c in contacts.read().unwrap().iter() {
+                        if is_fluid_solved(solved_fluids, c.j_model) {
+                            let mj = fluids[c.j_model].particle_mass(c.j);
+                            reaction -= c.gradient * (coefficient(c.j_model, c.j) * mj);
+                        }
+                    }
+
+                    reaction * timestep.inv_dt()
+                })
+                .collect()
+        };
+
+        for (acceleration, reaction) in fluids[fluid_id].accelerations.iter_mut().zip(reactions) {
+            *acceleration += reaction;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::math::Vector;
+    use crate::solver::{IterationState, SolvePhase};
+    use crate::testbed::generate_dam_break;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn iteration_callback_reports_each_solver_iteration() {
+        let gravity = Vector::y() * -9.81;
+        let mut world = generate_dam_break(0.05, 4);
+        let states = Arc::new(Mutex::new(Vec::<IterationState>::new()));
+        let recorded = states.clone();
+        world.set_pressure_iteration_callback(Some(Box::new(move |state: &IterationState| {
+            recorded.lock().unwrap().push(*state)
+        })));
+
+        world.step(0.01, &gravity);
+
+        {
+            let states = states.lock().unwrap();
+            assert!(!states.is_empty());
+
+            for phase in [SolvePhase::Density, SolvePhase::Divergence] {
+                let iterations: Vec<_> = states
+                    .iter()
+                    .filter(|s| s.phase == phase)
+                    .map(|s| s.iteration)
+                    .collect();
+                assert!(!iterations.is_empty());
+                assert!(iterations.iter().enumerate().all(|(i, it)| i == *it));
+            }
+
+            for state in states.iter() {
+                assert!(state.max_error >= state.average_error);
+            }
+        }
+
+        // Removing the callback drops it.
+        world.set_pressure_iteration_callback(None);
+        assert_eq!(Arc::strong_count(&states), 1);
+    }
 }
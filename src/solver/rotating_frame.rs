@@ -0,0 +1,129 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::geometry::ParticlesContacts;
+
+use crate::math::{AngularVector, Point, Real, Vector};
+use crate::object::{Boundary, Fluid};
+use crate::solver::{IndependentForce, IndependentForceInputs, NonPressureForce};
+use crate::TimestepManager;
+
+/// The fictitious forces of a reference frame rotating at a constant angular velocity.
+///
+/// Simulating the fluid in the rotating frame, e.g., attached to a spinning bucket, adds the
+/// centrifugal acceleration `-Ω × (Ω × r)` and the Coriolis acceleration `-2 Ω × v` to each particle,
+/// where `Ω` is the angular velocity of the frame, `r` the position of the particle relative to
+/// the center of rotation, and `v` its velocity in the rotating frame. In 2D, the angular velocity
+/// is a scalar about the axis orthogonal to the plane, so both accelerations remain in the plane.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RotatingFrame {
+    /// A point of the axis of rotation of the frame.
+    pub center: Point<Real>,
+    /// The angular velocity of the frame, in radians per second.
+    pub angular_velocity: AngularVector<Real>,
+}
+
+impl RotatingFrame {
+    /// Initializes the fictitious forces of a frame rotating about `center` at `angular_velocity`.
+    pub fn new(center: Point<Real>, angular_velocity: AngularVector<Real>) -> Self {
+        Self {
+            center,
+            angular_velocity,
+        }
+    }
+
+    /// The sum of the centrifugal and Coriolis accelerations of a particle at `point` moving at `velocity`.
+    pub fn acceleration(&self, point: &Point<Real>, velocity: &Vector<Real>) -> Vector<Real> {
+        let omega = &self.angular_velocity;
+        let centrifugal = -cross(omega, &cross(omega, &(point - self.center)));
+        let coriolis = cross(omega, velocity) * na::convert::<_, Real>(-2.0);
+        centrifugal + coriolis
+    }
+}
+
+#[cfg(feature = "dim2")]
+fn cross(omega: &AngularVector<Real>, v: &Vector<Real>) -> Vector<Real> {
+    Vector::new(-v.y, v.x) * omega.x
+}
+
+#[cfg(feature = "dim3")]
+fn cross(omega: &AngularVector<Real>, v: &Vector<Real>) -> Vector<Real> {
+    omega.cross(v)
+}
+
+impl NonPressureForce for RotatingFrame {
+    fn solve(
+        &mut self,
+        timestep: &TimestepManager,
+        kernel_radius: Real,
+        fluid_fluid_contacts: &ParticlesContacts,
+        fluid_boundaries_contacts: &ParticlesContacts,
+        fluid: &mut Fluid,
+        boundaries: &[Boundary],
+        densities: &[Real],
+    ) {
+        let mut accelerations = std::mem::take(&mut fluid.accelerations);
+        let inputs = IndependentForceInputs {
+            timestep,
+            kernel_radius,
+            fluid_fluid_contacts,
+            fluid_boundaries_contacts,
+            fluid,
+            boundaries,
+            densities,
+        };
+        self.solve_independently(&inputs, &mut accelerations);
+        fluid.accelerations = accelerations;
+    }
+
+    fn as_independent_mut(&mut self) -> Option<&mut dyn IndependentForce> {
+        Some(self)
+    }
+
+    fn rescale(&mut self, factor: Real) {
+        self.center.coords *= factor;
+    }
+}
+
+impl IndependentForce for RotatingFrame {
+    fn solve_independently(
+        &mut self,
+        inputs: &IndependentForceInputs,
+        accelerations: &mut [Vector<Real>],
+    ) {
+        let IndependentForceInputs { fluid, .. } = *inputs;
+        let frame = *self;
+
+        par_iter_mut!(accelerations)
+            .enumerate()
+            .for_each(|(i, acceleration)| {
+                *acceleration += frame.acceleration(&fluid.positions[i], &fluid.velocities[i]);
+            })
+    }
+}
+
+#[test]
+fn rotating_frame_accelerations() {
+    let center = Point::from(Vector::x());
+    let omega = 2.0;
+    #[cfg(feature = "dim2")]
+    let frame = RotatingFrame::new(center, AngularVector::new(omega));
+    #[cfg(feature = "dim3")]
+    let frame = RotatingFrame::new(center, Vector::z() * omega);
+
+    // A particle at rest is pushed away from the axis.
+    let offset = Vector::y() * 0.5;
+    let centrifugal = frame.acceleration(&(center + offset), &Vector::zeros());
+    assert!((centrifugal - offset * (omega * omega)).norm() < 1.0e-5);
+
+    // A particle moving away from the axis is deflected clockwise by a counterclockwise rotation.
+    let coriolis = frame.acceleration(&center, &Vector::y());
+    assert!((coriolis - Vector::x() * (2.0 * omega)).norm() < 1.0e-5);
+
+    #[cfg(feature = "dim3")]
+    {
+        // The positions and velocities along the axis are not affected.
+        let on_axis = frame.acceleration(&(center + Vector::z()), &Vector::z());
+        assert!(on_axis.norm() < 1.0e-5);
+    }
+}
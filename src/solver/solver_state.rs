@@ -0,0 +1,223 @@
+use crate::math::Real;
+use crate::object::Fluid;
+use std::fmt;
+
+/// Errors that can occur when importing a solver state.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SolverStateError {
+    /// The state was exported with a different number of fluids.
+    FluidCountMismatch {
+        /// The number of fluids of the liquid world.
+        expected: usize,
+        /// The number of fluids recorded in the state.
+        found: usize,
+    },
+    /// The state was exported with a different number of particles for one fluid.
+    ParticleCountMismatch {
+        /// The contiguous index of the fluid with mismatching particle count.
+        fluid: usize,
+        /// The number of particles of this fluid.
+        expected: usize,
+        /// The number of particles recorded in the state.
+        found: usize,
+    },
+    /// The state was exported by a different kind of solver.
+    LayoutMismatch,
+    /// The serialized state is truncated or malformed.
+    Corrupted,
+}
+
+impl fmt::Display for SolverStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SolverStateError::FluidCountMismatch { expected, found } => write!(
+                f,
+                "solver state has {} fluids but the world has {}",
+                found, expected
+            ),
+            SolverStateError::ParticleCountMismatch {
+                fluid,
+                expected,
+                found,
+            } => write!(
+                f,
+                "solver state has {} particles for fluid {} but this fluid has {}",
+                found, fluid, expected
+            ),
+            SolverStateError::LayoutMismatch => {
+                write!(f, "solver state was exported by a different solver")
+            }
+            SolverStateError::Corrupted => write!(f, "solver state is corrupted"),
+        }
+    }
+}
+
+impl std::error::Error for SolverStateError {}
+
+/// An opaque snapshot of the internal warm-start buffers of a pressure solver.
+///
+/// It can be converted to and from bytes with `SolverState::to_bytes` and `SolverState::from_bytes`
+/// in order to be saved alongside the particle data of a checkpoint.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct SolverState {
+    // Number of reals stored per particle.
+    stride: usize,
+    // The buffers of each fluid, with `stride` reals per particle.
+    fluids: Vec<Vec<Real>>,
+    // The values not attached to any particle, e.g., counters.
+    globals: Vec<Real>,
+}
+
+impl SolverState {
+    /// Creates a solver state storing `stride` reals per particle for each fluid.
+    pub fn new(stride: usize, fluids: Vec<Vec<Real>>) -> Self {
+        Self {
+            stride,
+            fluids,
+            globals: Vec::new(),
+        }
+    }
+
+    /// Sets the values of this state that are not attached to any particle, e.g., counters.
+    pub fn with_globals(mut self, globals: Vec<Real>) -> Self {
+        self.globals = globals;
+        self
+    }
+
+    /// The values of this state that are not attached to any particle.
+    pub fn globals(&self) -> &[Real] {
+        &self.globals
+    }
+
+    /// The number of reals stored per particle.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// The buffers stored for each fluid.
+    pub fn fluid_buffers(&self) -> &[Vec<Real>] {
+        &self.fluids
+    }
+
+    /// Checks that this state was exported with the given stride and is compatible with `fluids`.
+    pub fn validate(&self, stride: usize, fluids: &[Fluid]) -> Result<(), SolverStateError> {
+        if self.stride != stride {
+            return Err(SolverStateError::LayoutMismatch);
+        }
+
+        if self.fluids.len() != fluids.len() {
+            return Err(SolverStateError::FluidCountMismatch {
+                expected: fluids.len(),
+                found: self.fluids.len(),
+            });
+        }
+
+        for (i, (buffer, fluid)) in self.fluids.iter().zip(fluids.iter()).enumerate() {
+            if buffer.len() != fluid.num_particles() * stride {
+                return Err(SolverStateError::ParticleCountMismatch {
+                    fluid: i,
+                    expected: fluid.num_particles(),
+                    found: buffer.len() / stride.max(1),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Serializes this state into bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.extend_from_slice(&(self.stride as u64).to_le_bytes());
+        result.extend_from_slice(&(self.fluids.len() as u64).to_le_bytes());
+
+        for buffer in self.fluids.iter().chain(std::iter::once(&self.globals)) {
+            result.extend_from_slice(&(buffer.len() as u64).to_le_bytes());
+
+            for val in buffer {
+                result.extend_from_slice(&val.to_le_bytes());
+            }
+        }
+
+        result
+    }
+
+    /// Deserializes a state from bytes produced by `SolverState::to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SolverStateError> {
+        let mut cursor = bytes;
+        let stride = read_u64(&mut cursor)?;
+        let num_fluids = read_u64(&mut cursor)?;
+        let mut fluids = Vec::new();
+
+        for _ in 0..num_fluids {
+            fluids.push(read_reals(&mut cursor)?);
+        }
+
+        let globals = read_reals(&mut cursor)?;
+
+        if !cursor.is_empty() {
+            return Err(SolverStateError::Corrupted);
+        }
+
+        Ok(Self {
+            stride,
+            fluids,
+            globals,
+        })
+    }
+}
+
+fn read_reals(cursor: &mut &[u8]) -> Result<Vec<Real>, SolverStateError> {
+    let len = read_u64(cursor)?;
+    let nbytes = len
+        .checked_mul(size_of::<Real>())
+        .ok_or(SolverStateError::Corrupted)?;
+    let (head, tail) = cursor
+        .split_at_checked(nbytes)
+        .ok_or(SolverStateError::Corrupted)?;
+    *cursor = tail;
+    Ok(head
+        .chunks_exact(size_of::<Real>())
+        .map(|b| Real::from_le_bytes(b.try_into().unwrap()))
+        .collect())
+}
+
+fn read_u64(cursor: &mut &[u8]) -> Result<usize, SolverStateError> {
+    let (head, tail) = cursor
+        .split_at_checked(8)
+        .ok_or(SolverStateError::Corrupted)?;
+    *cursor = tail;
+    Ok(u64::from_le_bytes(head.try_into().unwrap()) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Point;
+
+    #[test]
+    fn bytes_roundtrip() {
+        let state =
+            SolverState::new(2, vec![vec![1.0, 2.0, 3.0, 4.0], vec![]]).with_globals(vec![5.0]);
+        let bytes = state.to_bytes();
+        assert_eq!(SolverState::from_bytes(&bytes), Ok(state));
+        assert_eq!(
+            SolverState::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(SolverStateError::Corrupted)
+        );
+    }
+
+    #[test]
+    fn particle_count_mismatch() {
+        let fluid = Fluid::new(vec![Point::origin(); 3], 0.1, 1000.0);
+        let state = SolverState::new(1, vec![vec![0.0; 2]]);
+        assert_eq!(
+            state.validate(1, &[fluid]),
+            Err(SolverStateError::ParticleCountMismatch {
+                fluid: 0,
+                expected: 3,
+                found: 2
+            })
+        );
+    }
+}
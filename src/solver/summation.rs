@@ -0,0 +1,138 @@
+use std::ops::{Add, Sub};
+
+use num::Zero;
+
+/// The algorithm used by the pressure solvers to sum the contributions of the neighbors of a particle.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Summation {
+    /// Contributions are added one after the other.
+    ///
+    /// This is the fastest, but the rounding errors accumulate with the number of neighbors.
+    #[default]
+    Naive,
+    /// Contributions are added with Kahan compensated summation.
+    ///
+    /// The rounding error of each addition is kept aside and fed back into the next one, so the
+    /// error of the sum no longer grows with the number of neighbors. This is slower, but improves
+    /// the accuracy of long-running simulations with many neighbors per particle.
+    /// The contributions are still added in the order of the contacts of each particle, so the
+    /// results are exactly as reproducible as with `Summation::Naive`.
+    Compensated,
+}
+
+/// An accumulator summing values with the given `Summation` algorithm.
+///
+/// This works for any type supporting additions and subtractions, e.g., scalars and vectors,
+/// in which case the compensation is applied to each component independently.
+#[derive(Copy, Clone, Debug)]
+pub struct CompensatedSum<T> {
+    sum: T,
+    compensation: T,
+    summation: Summation,
+}
+
+impl<T> CompensatedSum<T>
+where
+    T: Copy + Zero + Add<Output = T> + Sub<Output = T>,
+{
+    /// Creates an accumulator with a sum initialized to zero.
+    pub fn new(summation: Summation) -> Self {
+        Self::starting_at(summation, T::zero())
+    }
+
+    /// Creates an accumulator with a sum initialized to `value`.
+    pub fn starting_at(summation: Summation, value: T) -> Self {
+        Self {
+            sum: value,
+            compensation: T::zero(),
+            summation,
+        }
+    }
+
+    /// Adds `value` to the sum.
+    #[inline]
+    pub fn add(&mut self, value: T) {
+        match self.summation {
+            Summation::Naive => self.sum = self.sum + value,
+            Summation::Compensated => {
+                let compensated = value - self.compensation;
+                let sum = self.sum + compensated;
+                self.compensation = (sum - self.sum) - compensated;
+                self.sum = sum;
+            }
+        }
+    }
+
+    /// Subtracts `value` from the sum.
+    #[inline]
+    pub fn sub(&mut self, value: T) {
+        self.add(T::zero() - value)
+    }
+
+    /// The current value of the sum.
+    #[inline]
+    pub fn value(&self) -> T {
+        self.sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::math::{Point, Real, Vector};
+    use crate::object::Fluid;
+    use crate::LiquidWorld;
+
+    #[test]
+    fn compensated_summation_reduces_the_density_rounding_error() {
+        use crate::math::DIM;
+        use crate::solver::{DFSPHSolver, Summation};
+
+        let particle_radius = 0.05;
+        let spacing = particle_radius * 2.0;
+        let n: usize = if DIM == 2 { 45 } else { 13 };
+        let extent = spacing * (n - 1) as Real;
+        let positions: Vec<_> = (0..n.pow(DIM as u32))
+            .map(|k| {
+                Point::from(Vector::from_fn(|a, _| {
+                    ((k / n.pow(a as u32)) % n) as Real * spacing
+                }))
+            })
+            .collect();
+
+        let density_error = |summation| {
+            let mut solver: DFSPHSolver = DFSPHSolver::new();
+            solver.summation = summation;
+            // The kernel support covers the whole block, so that the probe particle at its center has
+            // thousands of neighbors.
+            let mut world = LiquidWorld::new(solver, particle_radius, extent / spacing);
+            // Only keep the contacts of the probe particle to keep the test fast.
+            world
+                .contact_manager_mut()
+                .set_contact_predicate(Some(Box::new(|i_model, _, _, _| i_model == 0)));
+            let probe = world.add_fluid(Fluid::new(
+                vec![Point::from(Vector::repeat(extent / 2.0))],
+                particle_radius,
+                1000.0,
+            ));
+            let _ = world.add_fluid(Fluid::new(positions.clone(), particle_radius, 1000.0));
+            world.set_paused(true);
+            world.step(0.01, &Vector::zeros());
+
+            let contacts = &world.contact_manager().fluid_fluid_contacts[0];
+            assert!(contacts.iter_particle(0).count() > 1000);
+            let exact: f64 = contacts
+                .iter_particle(0)
+                .map(|c| {
+                    world.fluids().as_slice()[c.j_model].particle_mass(c.j) as f64 * c.weight as f64
+                })
+                .sum();
+            let density = world.fluid_densities(probe).unwrap()[0];
+            (density as f64 - exact).abs() / exact
+        };
+
+        let naive_error = density_error(Summation::Naive);
+        let compensated_error = density_error(Summation::Compensated);
+        assert!(compensated_error <= Real::EPSILON as f64);
+        assert!(compensated_error * 10.0 < naive_error);
+    }
+}
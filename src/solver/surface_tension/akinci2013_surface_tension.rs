@@ -8,7 +8,7 @@ use crate::geometry::ParticlesContacts;
 
 use crate::math::{Real, Vector};
 use crate::object::{Boundary, Fluid};
-use crate::solver::NonPressureForce;
+use crate::solver::{IndependentForce, IndependentForceInputs, NonPressureForce};
 use crate::TimestepManager;
 
 #[derive(Clone)]
@@ -19,6 +19,7 @@ use crate::TimestepManager;
 pub struct Akinci2013SurfaceTension {
     fluid_tension_coefficient: Real,
     boundary_adhesion_coefficient: Real,
+    contact_angle: Option<Real>,
     normals: Vec<Vector<Real>>,
 }
 
@@ -30,10 +31,60 @@ impl Akinci2013SurfaceTension {
         Self {
             fluid_tension_coefficient,
             boundary_adhesion_coefficient,
+            contact_angle: None,
             normals: Vec::new(),
         }
     }
 
+    /// Initializes a surface tension with the given surface tension coefficient and target contact angle.
+    ///
+    /// See `Self::set_contact_angle` for details.
+    pub fn with_contact_angle(fluid_tension_coefficient: Real, contact_angle: Real) -> Self {
+        let mut result = Self::new(fluid_tension_coefficient, na::zero::<Real>());
+        result.set_contact_angle(Some(contact_angle));
+        result
+    }
+
+    /// The target contact angle between the fluid and the boundaries, in radians, if any.
+    pub fn contact_angle(&self) -> Option<Real> {
+        self.contact_angle
+    }
+
+    /// Sets the target contact angle between the fluid and the boundaries, in radians.
+    ///
+    /// If set, the boundary adhesion coefficient is derived from the fluid tension coefficient so
+    /// that the ratio between the adhesion and cohesion strengths is `(1 + cos(contact_angle)) / 2`,
+    /// following the Young-Dupré equation. The strengths are compared through the integrals of the
+    /// adhesion and cohesion kernels. Small angles result in a hydrophilic surface the fluid spreads
+    /// on, while angles close to `PI` result in a hydrophobic surface with no adhesion at all.
+    /// Set this to `None` to use the boundary adhesion coefficient given at construction instead.
+    pub fn set_contact_angle(&mut self, contact_angle: Option<Real>) {
+        if let Some(angle) = contact_angle {
+            assert!(
+                angle >= na::zero::<Real>() && angle <= Real::pi(),
+                "The contact angle must be in [0, PI]."
+            );
+        }
+
+        self.contact_angle = contact_angle;
+    }
+
+    /// The adhesion coefficient actually applied to fluid/boundary interactions for the given kernel radius.
+    pub fn effective_boundary_adhesion_coefficient(&self, kernel_radius: Real) -> Real {
+        match self.contact_angle {
+            Some(angle) => {
+                let half: Real = na::convert::<_, Real>(0.5f64);
+                let strength_ratio = kernel_integral(cohesion_kernel, kernel_radius)
+                    / kernel_integral(adhesion_kernel, kernel_radius);
+                self.fluid_tension_coefficient
+                    * strength_ratio
+                    * (na::one::<Real>() + angle.cos())
+                    * half
+            }
+            None => self.boundary_adhesion_coefficient,
+        }
+    }
+
     fn init(&mut self, fluid: &Fluid) {
         if self.normals.len() != fluid.num_particles() {
             self.normals.resize(fluid.num_particles(), Vector::zeros());
@@ -87,6 +138,20 @@ fn cohesion_kernel(r: Real, h: Real) -> Real {
     normalizer * coeff
 }
 
+// Integrates the given radial kernel over its support of radius `h`, with the midpoint rule.
+fn kernel_integral(kernel: fn(Real, Real) -> Real, h: Real) -> Real {
+    const NUM_SAMPLES: usize = 64;
+    let dr = h / (NUM_SAMPLES as Real);
+    let half: Real = na::convert::<_, Real>(0.5f64);
+
+    (0..NUM_SAMPLES)
+        .map(|i| {
+            let r = dr * ((i as Real) + half);
+            kernel(r, h) * r.powi(crate::math::DIM as i32 - 1) * dr
+        })
+        .sum()
+}
+
 fn adhesion_kernel(r: Real, h: Real) -> Real {
     let _2: Real = na::convert::<_, Real>(2.0f64);
 
@@ -113,7 +178,7 @@ fn adhesion_kernel(r: Real, h: Real) -> Real {
 impl NonPressureForce for Akinci2013SurfaceTension {
     fn solve(
         &mut self,
-        _timestep: &TimestepManager,
+        timestep: &TimestepManager,
         kernel_radius: Real,
         fluid_fluid_contacts: &ParticlesContacts,
         fluid_boundaries_contacts: &ParticlesContacts,
@@ -121,6 +186,50 @@ impl NonPressureForce for Akinci2013SurfaceTension {
         boundaries: &[Boundary],
         densities: &[Real],
     ) {
+        let mut accelerations = std::mem::take(&mut fluid.accelerations);
+        let inputs = IndependentForceInputs {
+            timestep,
+            kernel_radius,
+            fluid_fluid_contacts,
+            fluid_boundaries_contacts,
+            fluid,
+            boundaries,
+            densities,
+        };
+        self.solve_independently(&inputs, &mut accelerations);
+        fluid.accelerations = accelerations;
+    }
+
+    fn surface_normals(&self) -> Option<&[Vector<Real>]> {
+        Some(&self.normals)
+    }
+
+    fn as_independent_mut(&mut self) -> Option<&mut dyn IndependentForce> {
+        Some(self)
+    }
+
+    fn apply_permutation(&mut self, permutation: &[usize]) {
+        if self.normals.len() == permutation.len() {
+            self.normals = crate::z_order::apply_permutation(permutation, &self.normals);
+        }
+    }
+}
+
+impl IndependentForce for Akinci2013SurfaceTension {
+    fn solve_independently(
+        &mut self,
+        inputs: &IndependentForceInputs,
+        accelerations: &mut [Vector<Real>],
+    ) {
+        let IndependentForceInputs {
+            kernel_radius,
+            fluid_fluid_contacts,
+            fluid_boundaries_contacts,
+            fluid,
+            boundaries,
+            densities,
+            ..
+        } = *inputs;
         self.init(fluid);
         let _2: Real = na::convert::<_, Real>(2.0f64);
 
@@ -129,12 +238,13 @@ impl NonPressureForce for Akinci2013SurfaceTension {
         // Compute and apply forces.
         let normals = &self.normals;
         let fluid_tension_coefficient = self.fluid_tension_coefficient;
-        let boundary_adhesion_coefficient = self.boundary_adhesion_coefficient;
-        let volumes = &mut fluid.volumes;
+        let boundary_adhesion_coefficient =
+            self.effective_boundary_adhesion_coefficient(kernel_radius);
+        let volumes = &fluid.volumes;
         let density0 = fluid.density0;
         let positions = &fluid.positions;
 
-        par_iter_mut!(fluid.accelerations)
+        par_iter_mut!(accelerations)
             .enumerate()
             .for_each(|(i, acceleration_i)| {
                 if self.fluid_tension_coefficient != na::zero::<Real>() {
@@ -190,6 +300,102 @@ impl NonPressureForce for Akinci2013SurfaceTension {
                 }
             })
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Akinci2013SurfaceTension;
+    use crate::kernel::CubicSplineKernel;
+    use crate::math::{Point, Real, Vector, DIM};
+    use crate::object::{Boundary, Fluid};
+    use crate::LiquidWorld;
+    use na::RealField;
 
-    fn apply_permutation(&mut self, _: &[usize]) {}
+    #[test]
+    fn contact_angles_curve_the_meniscus_in_a_narrow_channel() {
+        use crate::solver::DFSPHSolver;
+        use crate::testbed::particle_block;
+
+        let particle_radius = 0.05;
+        let spacing = particle_radius * 2.0;
+        let num_columns = 5;
+        let width = spacing * (num_columns + 1) as Real;
+
+        // The average height of the surface next to the walls of the channel, and at its center.
+        let meniscus = |contact_angle: Real| {
+            let mut world = LiquidWorld::new(
+                DFSPHSolver::<CubicSplineKernel>::new(),
+                particle_radius,
+                2.0,
+            );
+            // In 3D, the channel is a thin slab, to keep the test fast.
+            let mut half_extents = Vector::repeat(width / 2.0);
+            if DIM == 3 {
+                half_extents[DIM - 1] = spacing * 1.5;
+            }
+            let _ = world.add_boundary(Boundary::cuboid(
+                &Point::from(half_extents),
+                &half_extents,
+                particle_radius,
+                2,
+            ));
+            let positions =
+                particle_block(num_columns, num_columns, spacing, Vector::repeat(spacing))
+                    .into_iter()
+                    .filter(|pt| DIM == 2 || pt[DIM - 1] < half_extents[DIM - 1] * 2.0)
+                    .collect();
+            let mut fluid = Fluid::new(positions, particle_radius, 1000.0);
+            fluid.add_nonpressure_force(Box::new(Akinci2013SurfaceTension::with_contact_angle(
+                1.0,
+                contact_angle,
+            )));
+            let handle = world.add_fluid(fluid);
+
+            let num_steps = 40;
+            let (mut wall_height, mut center_height) = (0.0, 0.0);
+            for step in 0..num_steps {
+                world.step(0.005, &(Vector::y() * -9.81));
+
+                if step >= num_steps / 2 {
+                    let positions = &world.fluids()[handle].positions;
+                    let highest = |distance_to_center: &dyn Fn(Real) -> bool| {
+                        positions
+                            .iter()
+                            .filter(|pt| distance_to_center((pt.x - width / 2.0).abs()))
+                            .map(|pt| pt.y)
+                            .fold(Real::MIN, Real::max)
+                    };
+                    wall_height += highest(&|d| d > width / 2.0 - spacing * 1.5);
+                    center_height += highest(&|d| d < spacing);
+                }
+            }
+
+            let num_samples = (num_steps - num_steps / 2) as Real;
+            (wall_height / num_samples, center_height / num_samples)
+        };
+
+        // A hydrophilic surface pulls the fluid up the walls, while a hydrophobic one pushes it down
+        // and makes the meniscus convex.
+        let (hydrophilic_wall, hydrophilic_center) = meniscus(0.0);
+        let (hydrophobic_wall, hydrophobic_center) = meniscus(Real::pi());
+        assert!(hydrophilic_wall > hydrophobic_wall + spacing);
+        assert!(
+            hydrophilic_wall - hydrophilic_center
+                > hydrophobic_wall - hydrophobic_center + spacing * 0.3
+        );
+        assert!(hydrophobic_wall < hydrophobic_center);
+
+        let h = particle_radius * 4.0;
+        let tension = |angle| Akinci2013SurfaceTension::with_contact_angle(1.0, angle);
+        assert_eq!(
+            tension(Real::pi()).effective_boundary_adhesion_coefficient(h),
+            0.0
+        );
+        assert!(
+            (tension(Real::frac_pi_2()).effective_boundary_adhesion_coefficient(h) * 2.0
+                - tension(0.0).effective_boundary_adhesion_coefficient(h))
+            .abs()
+                < 1.0e-3 * tension(0.0).effective_boundary_adhesion_coefficient(h)
+        );
+    }
 }
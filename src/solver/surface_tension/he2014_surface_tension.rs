@@ -5,7 +5,7 @@ use crate::geometry::ParticlesContacts;
 
 use crate::math::{Real, Vector};
 use crate::object::{Boundary, Fluid};
-use crate::solver::NonPressureForce;
+use crate::solver::{IndependentForce, IndependentForceInputs, NonPressureForce};
 use crate::TimestepManager;
 
 // http://peridynamics.com/publications/2014-He-RSS.pdf
@@ -108,14 +108,49 @@ impl He2014SurfaceTension {
 impl NonPressureForce for He2014SurfaceTension {
     fn solve(
         &mut self,
-        _timestep: &TimestepManager,
-        _kernel_radius: Real,
+        timestep: &TimestepManager,
+        kernel_radius: Real,
         fluid_fluid_contacts: &ParticlesContacts,
         fluid_boundary_contacts: &ParticlesContacts,
         fluid: &mut Fluid,
         boundaries: &[Boundary],
         densities: &[Real],
     ) {
+        let mut accelerations = std::mem::take(&mut fluid.accelerations);
+        let inputs = IndependentForceInputs {
+            timestep,
+            kernel_radius,
+            fluid_fluid_contacts,
+            fluid_boundaries_contacts: fluid_boundary_contacts,
+            fluid,
+            boundaries,
+            densities,
+        };
+        self.solve_independently(&inputs, &mut accelerations);
+        fluid.accelerations = accelerations;
+    }
+
+    fn as_independent_mut(&mut self) -> Option<&mut dyn IndependentForce> {
+        Some(self)
+    }
+
+    fn apply_permutation(&mut self, _: &[usize]) {}
+}
+
+impl IndependentForce for He2014SurfaceTension {
+    fn solve_independently(
+        &mut self,
+        inputs: &IndependentForceInputs,
+        accelerations: &mut [Vector<Real>],
+    ) {
+        let IndependentForceInputs {
+            fluid_fluid_contacts,
+            fluid_boundaries_contacts: fluid_boundary_contacts,
+            fluid,
+            boundaries,
+            densities,
+            ..
+        } = *inputs;
         self.init(fluid);
         let _2: Real = na::convert::<_, Real>(2.0f64);
 
@@ -135,7 +170,7 @@ impl NonPressureForce for He2014SurfaceTension {
         let density0 = fluid.density0;
         let volumes = &fluid.volumes;
 
-        par_iter_mut!(fluid.accelerations)
+        par_iter_mut!(accelerations)
             .enumerate()
             .for_each(|(i, acceleration_i)| {
                 let mi = volumes[i] * density0;
@@ -178,6 +213,4 @@ impl NonPressureForce for He2014SurfaceTension {
                 }
             })
     }
-
-    fn apply_permutation(&mut self, _: &[usize]) {}
 }
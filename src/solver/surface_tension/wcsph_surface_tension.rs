@@ -3,9 +3,9 @@ use rayon::prelude::*;
 
 use crate::geometry::ParticlesContacts;
 
-use crate::math::Real;
+use crate::math::{Real, Vector};
 use crate::object::{Boundary, Fluid};
-use crate::solver::NonPressureForce;
+use crate::solver::{IndependentForce, IndependentForceInputs, NonPressureForce};
 use crate::TimestepManager;
 
 // Surface tension of water: 0.01
@@ -31,21 +31,55 @@ impl WCSPHSurfaceTension {
 impl NonPressureForce for WCSPHSurfaceTension {
     fn solve(
         &mut self,
-        _timestep: &TimestepManager,
-        _kernel_radius: Real,
+        timestep: &TimestepManager,
+        kernel_radius: Real,
         fluid_fluid_contacts: &ParticlesContacts,
-        _fluid_boundaries_contacts: &ParticlesContacts,
+        fluid_boundaries_contacts: &ParticlesContacts,
         fluid: &mut Fluid,
         boundaries: &[Boundary],
-        _densities: &[Real],
+        densities: &[Real],
     ) {
+        let mut accelerations = std::mem::take(&mut fluid.accelerations);
+        let inputs = IndependentForceInputs {
+            timestep,
+            kernel_radius,
+            fluid_fluid_contacts,
+            fluid_boundaries_contacts,
+            fluid,
+            boundaries,
+            densities,
+        };
+        self.solve_independently(&inputs, &mut accelerations);
+        fluid.accelerations = accelerations;
+    }
+
+    fn as_independent_mut(&mut self) -> Option<&mut dyn IndependentForce> {
+        Some(self)
+    }
+
+    fn apply_permutation(&mut self, _: &[usize]) {}
+}
+
+impl IndependentForce for WCSPHSurfaceTension {
+    fn solve_independently(
+        &mut self,
+        inputs: &IndependentForceInputs,
+        accelerations: &mut [Vector<Real>],
+    ) {
+        let IndependentForceInputs {
+            fluid_fluid_contacts,
+            fluid_boundaries_contacts,
+            fluid,
+            boundaries,
+            ..
+        } = *inputs;
         let fluid_tension_coefficient = self.fluid_tension_coefficient;
         let boundary_tension_coefficient = self.boundary_tension_coefficient;
         let positions = &fluid.positions;
         let volumes = &fluid.volumes;
         let density0 = fluid.density0;
 
-        par_iter_mut!(fluid.accelerations)
+        par_iter_mut!(accelerations)
             .enumerate()
             .for_each(|(i, acceleration_i)| {
                 if fluid_tension_coefficient != na::zero::<Real>() {
@@ -66,7 +100,7 @@ impl NonPressureForce for WCSPHSurfaceTension {
                 }
 
                 if boundary_tension_coefficient != na::zero::<Real>() {
-                    for c in fluid_fluid_contacts
+                    for c in fluid_boundaries_contacts
                         .particle_contacts(i)
                         .read()
                         .unwrap()
@@ -85,6 +119,4 @@ impl NonPressureForce for WCSPHSurfaceTension {
                 }
             })
     }
-
-    fn apply_permutation(&mut self, _: &[usize]) {}
 }
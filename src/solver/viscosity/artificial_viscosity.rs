@@ -1,11 +1,11 @@
 #[cfg(feature = "parallel")]
 use rayon::prelude::*;
 
-use crate::geometry::ParticlesContacts;
+use crate::geometry::{Contact, ParticlesContacts};
 
 use crate::math::{Real, Vector};
 use crate::object::{Boundary, Fluid};
-use crate::solver::NonPressureForce;
+use crate::solver::{IndependentForce, IndependentForceInputs, NonPressureForce};
 use crate::TimestepManager;
 
 // See http://www.astro.lu.se/~david/teaching/SPH/notes/annurev.aa.30.090192.pdf
@@ -22,6 +22,13 @@ pub struct ArtificialViscosity {
     pub fluid_viscosity_coefficient: Real,
     /// The viscosity coefficient when interacting with boundaries.
     pub boundary_viscosity_coefficient: Real,
+    /// The relative speed below which two approaching particles are not affected by this viscosity.
+    ///
+    /// This is the speed at which the distance between the two particles decreases, and it is
+    /// checked independently for each contact. Defaults to zero, i.e., all the approaching
+    /// particles are affected. Larger values only damp the violent collisions, e.g., shocks,
+    /// while preserving the gentle small-scale motions.
+    pub min_approach_speed: Real,
 }
 
 impl ArtificialViscosity {
@@ -33,6 +40,7 @@ impl ArtificialViscosity {
             speed_of_sound: na::convert::<_, Real>(10.0),
             fluid_viscosity_coefficient,
             boundary_viscosity_coefficient,
+            min_approach_speed: na::zero::<Real>(),
         }
     }
 }
@@ -40,7 +48,7 @@ impl ArtificialViscosity {
 impl NonPressureForce for ArtificialViscosity {
     fn solve(
         &mut self,
-        _timestep: &TimestepManager,
+        timestep: &TimestepManager,
         kernel_radius: Real,
         fluid_fluid_contacts: &ParticlesContacts,
         fluid_boundaries_contacts: &ParticlesContacts,
@@ -48,18 +56,57 @@ impl NonPressureForce for ArtificialViscosity {
         boundaries: &[Boundary],
         densities: &[Real],
     ) {
+        let mut accelerations = std::mem::take(&mut fluid.accelerations);
+        let inputs = IndependentForceInputs {
+            timestep,
+            kernel_radius,
+            fluid_fluid_contacts,
+            fluid_boundaries_contacts,
+            fluid,
+            boundaries,
+            densities,
+        };
+        self.solve_independently(&inputs, &mut accelerations);
+        fluid.accelerations = accelerations;
+    }
+
+    fn as_independent_mut(&mut self) -> Option<&mut dyn IndependentForce> {
+        Some(self)
+    }
+
+    fn apply_permutation(&mut self, _: &[usize]) {}
+}
+
+impl IndependentForce for ArtificialViscosity {
+    fn solve_independently(
+        &mut self,
+        inputs: &IndependentForceInputs,
+        accelerations: &mut [Vector<Real>],
+    ) {
+        let IndependentForceInputs {
+            kernel_radius,
+            fluid_fluid_contacts,
+            fluid_boundaries_contacts,
+            fluid,
+            boundaries,
+            densities,
+            ..
+        } = *inputs;
         let fluid_viscosity_coefficient = self.fluid_viscosity_coefficient;
         let boundary_viscosity_coefficient = self.boundary_viscosity_coefficient;
         let speed_of_sound = self.speed_of_sound;
         let alpha = self.alpha;
         let beta = self.beta;
+        let min_approach_speed = self.min_approach_speed;
+        // Checks if two particles approach each other fast enough, given `vr = r_ij.dot(&v_ij)`.
+        let approaching = |c: &Contact, vr: Real| vr < -min_approach_speed * c.distance();
         let density0 = fluid.density0;
         let volumes = &fluid.volumes;
         let positions = &fluid.positions;
         let velocities = &fluid.velocities;
         let _0_5: Real = na::convert::<_, Real>(0.5);
 
-        par_iter_mut!(fluid.accelerations)
+        par_iter_mut!(accelerations)
             .enumerate()
             .for_each(|(i, acceleration)| {
                 let mut fluid_acc = Vector::zeros();
@@ -77,7 +124,7 @@ impl NonPressureForce for ArtificialViscosity {
                             let v_ij = velocities[c.i] - velocities[c.j];
                             let vr = r_ij.dot(&v_ij);
 
-                            if vr < na::zero::<Real>() {
+                            if approaching(c, vr) {
                                 let density_average = (densities[c.i] + densities[c.j]) * _0_5;
                                 let eta2 =
                                     kernel_radius * kernel_radius * na::convert::<_, Real>(0.01);
@@ -103,7 +150,7 @@ impl NonPressureForce for ArtificialViscosity {
                         let v_ij = velocities[c.i] - boundaries[c.j_model].velocities[c.j];
                         let vr = r_ij.dot(&v_ij);
 
-                        if vr < na::zero::<Real>() {
+                        if approaching(c, vr) {
                             let density_average = densities[c.i];
                             let eta2 = kernel_radius * kernel_radius * na::convert::<_, Real>(0.01);
                             let mu_ij = kernel_radius * vr / (r_ij.norm_squared() + eta2);
@@ -122,6 +169,52 @@ impl NonPressureForce for ArtificialViscosity {
                 *acceleration += fluid_acc + boundary_acc;
             })
     }
+}
 
-    fn apply_permutation(&mut self, _: &[usize]) {}
+#[test]
+fn min_approach_speed_filters_gentle_approaches() {
+    use crate::kernel::CubicSplineKernel;
+    use crate::math::Point;
+    use crate::solver::DFSPHSolver;
+    use crate::LiquidWorld;
+
+    // Two particles approaching each other at 0.2m/s.
+    let particle_radius = 0.05;
+    let positions = vec![Point::origin(), Point::from(Vector::x() * 0.1)];
+    let mut fluid = Fluid::new(positions, particle_radius, 1000.0);
+    fluid.velocities = vec![Vector::x() * 0.1, Vector::x() * -0.1];
+    let mut world = LiquidWorld::new(
+        DFSPHSolver::<CubicSplineKernel>::new(),
+        particle_radius,
+        2.0,
+    );
+    let handle = world.add_fluid(fluid);
+    world.set_paused(true);
+    world.step(0.01, &Vector::zeros());
+
+    let timestep = TimestepManager::new(particle_radius);
+    let contacts = world.contact_manager();
+    let fluid = world.fluids().get(handle).unwrap();
+    let densities = world.fluid_densities(handle).unwrap();
+    let accelerations = |min_approach_speed: Real| {
+        let mut viscosity = ArtificialViscosity::new(1.0, 0.0);
+        viscosity.min_approach_speed = min_approach_speed;
+        let mut accelerations = vec![Vector::zeros(); 2];
+        let inputs = IndependentForceInputs {
+            timestep: &timestep,
+            kernel_radius: world.h(),
+            fluid_fluid_contacts: &contacts.fluid_fluid_contacts[0],
+            fluid_boundaries_contacts: &contacts.fluid_boundary_contacts[0],
+            fluid,
+            boundaries: &[],
+            densities,
+        };
+        viscosity.solve_independently(&inputs, &mut accelerations);
+        accelerations
+    };
+
+    // The particles are slowed down unless they approach slower than the threshold.
+    assert!(accelerations(0.0)[0].x < 0.0);
+    assert!(accelerations(0.19)[0].x < 0.0);
+    assert_eq!(accelerations(0.21), vec![Vector::zeros(); 2]);
 }
@@ -310,12 +310,11 @@ impl NonPressureForce for DFSPHViscosity {
                 self.compute_strain_rates(timestep, fluid_fluid_contacts, fluid, densities, true);
 
             if avg_err <= self.max_viscosity_error && i >= self.min_viscosity_iter {
-                //                println!(
-                //                    "Average viscosity error: {}, break after niters: {}, unstable: {}",
-                //                    avg_err,
-                //                    i,
-                //                    avg_err > last_err
-                //                );
+                log::trace!(
+                    "DFSPH viscosity: average viscosity error: {}, break after niters: {}",
+                    avg_err,
+                    i
+                );
                 break;
             }
 
@@ -5,7 +5,7 @@ use crate::geometry::ParticlesContacts;
 
 use crate::math::{Real, Vector};
 use crate::object::{Boundary, Fluid};
-use crate::solver::NonPressureForce;
+use crate::solver::{IndependentForce, IndependentForceInputs, NonPressureForce};
 use crate::TimestepManager;
 
 #[derive(Clone)]
@@ -31,20 +31,56 @@ impl NonPressureForce for XSPHViscosity {
     fn solve(
         &mut self,
         timestep: &TimestepManager,
-        _kernel_radius: Real,
+        kernel_radius: Real,
         fluid_fluid_contacts: &ParticlesContacts,
         fluid_boundaries_contacts: &ParticlesContacts,
         fluid: &mut Fluid,
         boundaries: &[Boundary],
         densities: &[Real],
     ) {
+        let mut accelerations = std::mem::take(&mut fluid.accelerations);
+        let inputs = IndependentForceInputs {
+            timestep,
+            kernel_radius,
+            fluid_fluid_contacts,
+            fluid_boundaries_contacts,
+            fluid,
+            boundaries,
+            densities,
+        };
+        self.solve_independently(&inputs, &mut accelerations);
+        fluid.accelerations = accelerations;
+    }
+
+    fn as_independent_mut(&mut self) -> Option<&mut dyn IndependentForce> {
+        Some(self)
+    }
+
+    fn apply_permutation(&mut self, _: &[usize]) {}
+}
+
+impl IndependentForce for XSPHViscosity {
+    fn solve_independently(
+        &mut self,
+        inputs: &IndependentForceInputs,
+        accelerations: &mut [Vector<Real>],
+    ) {
+        let IndependentForceInputs {
+            timestep,
+            fluid_fluid_contacts,
+            fluid_boundaries_contacts,
+            fluid,
+            boundaries,
+            densities,
+            ..
+        } = *inputs;
         let boundary_viscosity_coefficient = self.boundary_viscosity_coefficient;
         let fluid_viscosity_coefficient = self.fluid_viscosity_coefficient;
         let velocities = &fluid.velocities;
         let volumes = &fluid.volumes;
         let density0 = fluid.density0;
 
-        par_iter_mut!(fluid.accelerations)
+        par_iter_mut!(accelerations)
             .enumerate()
             .for_each(|(i, acceleration)| {
                 let mut added_fluid_vel = Vector::zeros();
@@ -93,6 +129,4 @@ impl NonPressureForce for XSPHViscosity {
                     added_fluid_vel * timestep.inv_dt() + added_boundary_vel * timestep.inv_dt();
             })
     }
-
-    fn apply_permutation(&mut self, _: &[usize]) {}
 }
@@ -0,0 +1,57 @@
+use crate::math::Real;
+use std::fmt;
+
+/// Parameters of the automatic substep reduction performed when the pressure solver diverges.
+///
+/// When a stability guard is set on the liquid world, each substep is retried with a halved
+/// length as long as the density error reported by the pressure solver exceeds `max_density_error`,
+/// or as long as the density or divergence error is not finite.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StabilityGuard {
+    /// The density error above which a substep is considered diverging.
+    ///
+    /// This is expressed in the same unit as the `max_density_error` of the pressure solver,
+    /// and should be greater than it.
+    pub max_density_error: Real,
+    /// The maximum number of times the substep length can be halved during a single timestep.
+    pub max_retries: usize,
+}
+
+impl StabilityGuard {
+    /// Initializes a new stability guard.
+    pub fn new(max_density_error: Real, max_retries: usize) -> Self {
+        Self {
+            max_density_error,
+            max_retries,
+        }
+    }
+}
+
+/// Errors that can occur while advancing the liquid world.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StepError {
+    /// The density error still exceeded the threshold of the stability guard, or was not finite, after the maximum number of retries.
+    Diverged {
+        /// The density error of the last attempt.
+        density_error: Real,
+        /// The number of retries performed during this timestep.
+        num_retries: usize,
+    },
+}
+
+impl fmt::Display for StepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StepError::Diverged {
+                density_error,
+                num_retries,
+            } => write!(
+                f,
+                "pressure solver diverged with a density error of {} after {} retries",
+                density_error, num_retries
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StepError {}
@@ -0,0 +1,123 @@
+//! Reproducible scenes for benchmarking and comparing pressure solvers.
+
+use crate::math::{Point, Real, Vector, DIM};
+use crate::object::{Boundary, Fluid};
+use crate::solver::{DFSPHSolver, PressureSolver};
+use crate::LiquidWorld;
+
+/// Generates a dam break scene simulated with the default `DFSPHSolver`.
+///
+/// See `generate_dam_break_with_solver` for details.
+pub fn generate_dam_break(particle_radius: Real, size: usize) -> LiquidWorld {
+    let solver: DFSPHSolver = DFSPHSolver::new();
+    generate_dam_break_with_solver(solver, particle_radius, size)
+}
+
+/// Generates a dam break scene simulated with the given pressure solver.
+///
+/// The fluid, with a rest density of 1000, is a block of exactly `size^DIM` particles spaced by
+/// `2 * particle_radius`, at rest in a corner of a closed tank made of two layers of boundary
+/// particles. The tank is four times longer than the block along the `x` axis, and twice as high
+/// along the `y` axis. In 3D, it has the same depth as the block along the `z` axis. The kernel
+/// radius is `4 * particle_radius`.
+///
+/// The scene only depends on `particle_radius` and `size`, and its particles are always generated
+/// in the same order, so that benchmark results remain comparable across runs and versions.
+pub fn generate_dam_break_with_solver(
+    solver: impl PressureSolver + Send + Sync + 'static,
+    particle_radius: Real,
+    size: usize,
+) -> LiquidWorld {
+    let spacing = particle_radius * na::convert::<_, Real>(2.0);
+    // The interior size of the tank along the axes the block fills, leaving one spacing between
+    // the block and the walls.
+    let extent = spacing * ((size + 1) as Real);
+
+    let mut positions = Vec::with_capacity(size.pow(DIM as u32));
+
+    #[cfg(feature = "dim2")]
+    for i in 0..size {
+        for j in 0..size {
+            positions.push(Point::new(
+                spacing * ((i + 1) as Real),
+                spacing * ((j + 1) as Real),
+            ));
+        }
+    }
+
+    #[cfg(feature = "dim3")]
+    for i in 0..size {
+        for j in 0..size {
+            for k in 0..size {
+                positions.push(Point::new(
+                    spacing * ((i + 1) as Real),
+                    spacing * ((j + 1) as Real),
+                    spacing * ((k + 1) as Real),
+                ));
+            }
+        }
+    }
+
+    #[cfg(feature = "dim2")]
+    let half_extents = Vector::new(extent * na::convert::<_, Real>(2.0), extent);
+    #[cfg(feature = "dim3")]
+    let half_extents = Vector::new(
+        extent * na::convert::<_, Real>(2.0),
+        extent,
+        extent * na::convert::<_, Real>(0.5),
+    );
+
+    let tank = Boundary::cuboid(
+        &Point::from(half_extents),
+        &half_extents,
+        particle_radius,
+        2,
+    );
+
+    let mut world = LiquidWorld::new(solver, particle_radius, na::convert::<_, Real>(2.0));
+    let _ = world.add_fluid(Fluid::new(
+        positions,
+        particle_radius,
+        na::convert::<_, Real>(1000.0),
+    ));
+    let _ = world.add_boundary(tank);
+    world
+}
+
+// A block of particles on a regular lattice with the given spacing, with `height` particles along
+// the `y` axis and `width` particles along the other axes, starting at `origin`. The `x` coordinate
+// varies the fastest.
+#[cfg(test)]
+pub(crate) fn particle_block(
+    width: usize,
+    height: usize,
+    spacing: Real,
+    origin: Vector<Real>,
+) -> Vec<Point<Real>> {
+    let dims = Vector::from_fn(|k, _| if k == 1 { height } else { width });
+    let num_particles: usize = dims.iter().product();
+    (0..num_particles)
+        .map(|mut i| {
+            let coords = Vector::from_fn(|k, _| {
+                let c = i % dims[k];
+                i /= dims[k];
+                c as Real
+            });
+            Point::from(coords * spacing + origin)
+        })
+        .collect()
+}
+
+#[test]
+fn dam_break_is_reproducible() {
+    let world = generate_dam_break(0.05, 6);
+    let again = generate_dam_break(0.05, 6);
+
+    let fluid = &world.fluids().as_slice()[0];
+    assert_eq!(fluid.num_particles(), 6usize.pow(DIM as u32));
+    assert_eq!(fluid.positions, again.fluids().as_slice()[0].positions);
+    assert_eq!(
+        world.boundaries().as_slice()[0].positions,
+        again.boundaries().as_slice()[0].positions
+    );
+}
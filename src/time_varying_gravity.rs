@@ -0,0 +1,100 @@
+use crate::math::{Real, Vector};
+use na::RealField;
+
+/// A gravity that varies with the simulation time.
+///
+/// This is given to `LiquidWorld::step_with_time_varying_gravity`, which evaluates it at the
+/// beginning of each substep from the time elapsed since the creation of the world. This can
+/// also model any uniform acceleration of the frame of the simulation, e.g., to shake a container.
+pub struct TimeVaryingGravity {
+    gravity: Box<dyn Fn(Real) -> Vector<Real> + Send + Sync>,
+}
+
+impl TimeVaryingGravity {
+    /// Initializes a gravity given by `gravity(t)` at the simulation time `t`.
+    pub fn new(gravity: impl Fn(Real) -> Vector<Real> + Send + Sync + 'static) -> Self {
+        Self {
+            gravity: Box::new(gravity),
+        }
+    }
+
+    /// A gravity that does not vary with time.
+    pub fn constant(gravity: Vector<Real>) -> Self {
+        Self::new(move |_| gravity)
+    }
+
+    /// A gravity varying linearly from `from` at the time 0 to `to` at the time `duration`, and
+    /// equal to `to` afterwards.
+    pub fn ramp(from: Vector<Real>, to: Vector<Real>, duration: Real) -> Self {
+        assert!(
+            duration > na::zero::<Real>(),
+            "The ramp duration must be positive."
+        );
+        Self::new(move |t| from.lerp(&to, (t / duration).clamp(na::zero(), na::one())))
+    }
+
+    /// A gravity oscillating around `mean` with the given `amplitude` and `frequency` (in Hertz).
+    ///
+    /// This is equivalent to shaking the whole simulation sinusoidally, the acceleration of the
+    /// shaking being `-amplitude * sin(2 * pi * frequency * t)`.
+    pub fn sinusoidal(mean: Vector<Real>, amplitude: Vector<Real>, frequency: Real) -> Self {
+        Self::new(move |t| mean + amplitude * (Real::two_pi() * frequency * t).sin())
+    }
+
+    /// The gravity at the simulation time `t`.
+    pub fn at(&self, t: Real) -> Vector<Real> {
+        (self.gravity)(t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TimeVaryingGravity;
+    use crate::kernel::CubicSplineKernel;
+    use crate::math::{Point, Real, Vector};
+    use crate::object::Fluid;
+    use crate::LiquidWorld;
+
+    #[test]
+    fn time_varying_gravity_follows_the_elapsed_time() {
+        use crate::solver::DFSPHSolver;
+
+        let particle_radius = 0.1;
+        let new_world = || {
+            let mut world = LiquidWorld::new(
+                DFSPHSolver::<CubicSplineKernel>::new(),
+                particle_radius,
+                2.0,
+            );
+            let _ = world.add_fluid(Fluid::new(vec![Point::origin()], particle_radius, 1000.0));
+            world
+        };
+
+        // No gravity during the first five steps.
+        let gravity = TimeVaryingGravity::new(|t| {
+            if t < 0.09 {
+                Vector::zeros()
+            } else {
+                crate::math::down_gravity(9.81)
+            }
+        });
+        let mut world = new_world();
+        let mut expected = new_world();
+
+        for i in 0..10 {
+            world.step_with_time_varying_gravity(0.02, &gravity);
+            expected.step(0.02, &gravity.at(i as Real * 0.02));
+        }
+
+        assert!((world.elapsed_time() - 0.2).abs() < 1.0e-5);
+        assert_eq!(
+            world.fluids().as_slice()[0].velocities,
+            expected.fluids().as_slice()[0].velocities
+        );
+        assert!(world.fluids().as_slice()[0].velocities[0].y < 0.0);
+
+        world.set_elapsed_time(0.0);
+        world.step(0.02, &Vector::zeros());
+        assert!((world.elapsed_time() - 0.02).abs() < 1.0e-6);
+    }
+}
@@ -7,6 +7,7 @@ use crate::math::Real;
 use crate::object::Fluid;
 
 /// Structure responsible for regulating the timestep length of the simulation.
+#[derive(Clone)]
 pub struct TimestepManager {
     cfl_coeff: Real,
     min_num_substeps: u32,
@@ -16,6 +17,7 @@ pub struct TimestepManager {
     total_step_size: Real,
     remaining_time: Real,
     particle_radius: Real,
+    num_substep_halvings: u32,
 }
 
 impl TimestepManager {
@@ -30,6 +32,7 @@ impl TimestepManager {
             inv_dt: na::zero::<Real>(),
             total_step_size: na::zero::<Real>(),
             remaining_time: na::zero::<Real>(),
+            num_substep_halvings: 0,
         }
     }
 
@@ -49,6 +52,17 @@ impl TimestepManager {
     pub fn reset(&mut self, total_step_size: Real) {
         self.total_step_size = total_step_size;
         self.remaining_time = total_step_size;
+        self.num_substep_halvings = 0;
+    }
+
+    /// Halves the length of the next substeps, until the next call to `self.reset`.
+    pub(crate) fn halve_substep(&mut self) {
+        self.num_substep_halvings += 1;
+    }
+
+    /// Sets the particle radius used to compute the substep lengths.
+    pub(crate) fn set_particle_radius(&mut self, particle_radius: Real) {
+        self.particle_radius = particle_radius;
     }
 
     /// Checks if all the time of this timestep has been consumed.
@@ -71,6 +85,18 @@ impl TimestepManager {
         self.inv_dt
     }
 
+    // A copy of this timestep manager with a current substep of length `dt`.
+    pub(crate) fn with_dt(&self, dt: Real) -> Self {
+        let mut result = self.clone();
+        result.dt = dt;
+        result.inv_dt = if dt.is_zero() {
+            na::zero::<Real>()
+        } else {
+            na::one::<Real>() / dt
+        };
+        result
+    }
+
     /// Advance to the next substep.
     #[inline]
     pub fn advance(&mut self, fluids: &[Fluid]) {
@@ -84,8 +110,20 @@ impl TimestepManager {
         self.remaining_time -= self.dt;
     }
 
+    // The fraction of the step elapsed at the middle of the next substep.
+    pub(crate) fn next_substep_midpoint(&self) -> Real {
+        if self.total_step_size <= na::zero::<Real>() {
+            return na::zero::<Real>();
+        }
+
+        let substep = self.compute_substep(&[]);
+        let elapsed = self.total_step_size - self.remaining_time;
+        (elapsed + substep * na::convert::<_, Real>(0.5)) / self.total_step_size
+    }
+
     fn compute_substep(&self, _fluids: &[Fluid]) -> Real {
-        return self.total_step_size;
+        let divisor = na::convert::<_, Real>(2.0f64.powi(self.num_substep_halvings as i32));
+        return (self.total_step_size / divisor).min(self.remaining_time);
         // FIXME
         //        let min_substep = self.total_step_size / na::convert::<_, Real>(self.max_num_substeps as f64);
         //        let max_substep = self.total_step_size / na::convert::<_, Real>(self.min_num_substeps as f64);